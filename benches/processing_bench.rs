@@ -0,0 +1,194 @@
+//! Throughput benchmarks of the processing pipeline.
+//!
+//! Run with `cargo bench --bench processing_bench`. Results are reported in
+//! files per second; see the README for the baseline.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use exif::experimental::Writer;
+use exif::{Field, In, Rational, Tag, Value};
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+use photomap_processor::config::ProcessingConfig;
+use photomap_processor::database::Database;
+use photomap_processor::exif_parser::extract_metadata_from_jpeg;
+use photomap_processor::image_processing::{
+    create_scaled_image_in_memory, EncodeOptions, ImageType,
+};
+use photomap_processor::processing::{process_photos_with_stats, WalkOptions};
+
+const LIBRARY_SIZE: usize = 10_000;
+
+/// Temporary directory removed when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("photomap_bench_{name}"));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn rationals(degrees: f64) -> Value {
+    let minutes = degrees.fract() * 60.0;
+    let seconds = minutes.fract() * 60.0;
+    Value::Rational(vec![
+        Rational::from((degrees as u32, 1)),
+        Rational::from((minutes as u32, 1)),
+        Rational::from(((seconds * 100.0) as u32, 100)),
+    ])
+}
+
+/// Minimal TIFF block with a GPS position and capture time
+fn gps_exif(lat: f64, lng: f64) -> Vec<u8> {
+    let fields = [
+        Field {
+            tag: Tag::GPSLatitudeRef,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"N".to_vec()]),
+        },
+        Field {
+            tag: Tag::GPSLatitude,
+            ifd_num: In::PRIMARY,
+            value: rationals(lat),
+        },
+        Field {
+            tag: Tag::GPSLongitudeRef,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"E".to_vec()]),
+        },
+        Field {
+            tag: Tag::GPSLongitude,
+            ifd_num: In::PRIMARY,
+            value: rationals(lng),
+        },
+        Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2024:06:01 12:00:00".to_vec()]),
+        },
+    ];
+
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = std::io::Cursor::new(Vec::new());
+    writer.write(&mut tiff, false).unwrap();
+    tiff.into_inner()
+}
+
+/// Encodes a JPEG of the given size with the EXIF block in an APP1 segment
+fn jpeg_with_gps(width: u32, height: u32, lat: f64, lng: f64) -> Vec<u8> {
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+    })
+    .write_to(&mut encoded, image::ImageFormat::Jpeg)
+    .unwrap();
+    let encoded = encoded.into_inner();
+
+    let tiff = gps_exif(lat, lng);
+    let segment_len = (2 + 6 + tiff.len()) as u16;
+    let mut jpeg = Vec::with_capacity(encoded.len() + tiff.len() + 10);
+    jpeg.extend_from_slice(&encoded[..2]); // SOI
+    jpeg.extend_from_slice(&[0xFF, 0xE1]);
+    jpeg.extend_from_slice(&segment_len.to_be_bytes());
+    jpeg.extend_from_slice(b"Exif\0\0");
+    jpeg.extend_from_slice(&tiff);
+    jpeg.extend_from_slice(&encoded[2..]);
+    jpeg
+}
+
+fn write_library(dir: &Path) {
+    let jpeg = jpeg_with_gps(32, 24, 48.8566, 2.3522);
+    for i in 0..LIBRARY_SIZE {
+        let subdir = dir.join(format!("{:03}", i / 100));
+        if i % 100 == 0 {
+            std::fs::create_dir_all(&subdir).unwrap();
+        }
+        std::fs::write(subdir.join(format!("IMG_{i:05}.jpg")), &jpeg).unwrap();
+    }
+}
+
+fn bench_processing(c: &mut Criterion) {
+    let library = TempDir::new("library");
+    write_library(library.path());
+
+    let mut group = c.benchmark_group("processing");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(30));
+    group.throughput(Throughput::Elements(LIBRARY_SIZE as u64));
+
+    group.bench_function(
+        BenchmarkId::new("process_photos_with_stats", LIBRARY_SIZE),
+        |b| {
+            let cancel = AtomicBool::new(false);
+            let walk = WalkOptions::default();
+            b.iter(|| {
+                let db = Database::new().unwrap();
+                let stats = process_photos_with_stats(
+                    &db,
+                    library.path(),
+                    true,
+                    false,
+                    &walk,
+                    &ProcessingConfig::default(),
+                    &cancel,
+                    None,
+                )
+                .unwrap();
+                assert_eq!(stats.processed, LIBRARY_SIZE);
+            })
+        },
+    );
+
+    let single = library.path().join("000").join("IMG_00000.jpg");
+    group.bench_function(
+        BenchmarkId::new("extract_metadata_from_jpeg", LIBRARY_SIZE),
+        |b| {
+            b.iter(|| {
+                for _ in 0..LIBRARY_SIZE {
+                    black_box(extract_metadata_from_jpeg(black_box(&single)).unwrap());
+                }
+            })
+        },
+    );
+    group.finish();
+}
+
+fn bench_marker(c: &mut Criterion) {
+    let dir = TempDir::new("marker");
+    let photo = dir.path().join("12mp.jpg");
+    std::fs::write(&photo, jpeg_with_gps(4000, 3000, 48.8566, 2.3522)).unwrap();
+
+    let mut group = c.benchmark_group("images");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("create_scaled_image_in_memory/marker_12mp", |b| {
+        b.iter(|| {
+            black_box(
+                create_scaled_image_in_memory(&photo, ImageType::Marker, EncodeOptions::default())
+                    .unwrap(),
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_processing, bench_marker);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+//! Photo search: a linear scan against the trigram index of the memory store.
+//!
+//! Run with `cargo bench --bench search_bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+use photomap_processor::database::{Database, PhotoMetadata};
+
+const LIBRARY_SIZE: usize = 100_000;
+
+fn photo(i: usize) -> PhotoMetadata {
+    let relative_path = format!("{}/IMG_{i:06}.jpg", 2010 + i % 15);
+    let datetime = format!(
+        "{}-{:02}-{:02} {:02}:{:02}:00",
+        2010 + i % 15,
+        1 + i % 12,
+        1 + i % 28,
+        i % 24,
+        i % 60
+    );
+    PhotoMetadata::sample(
+        &relative_path,
+        &datetime,
+        48.0 + (i % 1000) as f64 * 0.001,
+        2.0,
+    )
+}
+
+/// What a search costs without an index: every photo is checked
+fn scan(photos: &[PhotoMetadata], query: &str) -> Vec<PhotoMetadata> {
+    let query = query.to_lowercase();
+    let mut found: Vec<PhotoMetadata> = photos
+        .iter()
+        .filter(|p| {
+            p.filename.to_lowercase().contains(&query) || p.datetime.to_lowercase().contains(&query)
+        })
+        .cloned()
+        .collect();
+    found.sort_by(|a, b| b.datetime.cmp(&a.datetime));
+    found.truncate(100);
+    found
+}
+
+fn bench_search(c: &mut Criterion) {
+    let photos: Vec<PhotoMetadata> = (0..LIBRARY_SIZE).map(photo).collect();
+    let db = Database::new().unwrap();
+    db.insert_photos_batch(&photos).unwrap();
+
+    let mut group = c.benchmark_group("search_100k");
+    // One photo, a month of ~1 700 photos and a filename prefix of 100 photos
+    for query in ["img_012345", "2019-07", "_0123"] {
+        group.bench_with_input(BenchmarkId::new("scan", query), query, |b, q| {
+            b.iter(|| scan(black_box(&photos), q))
+        });
+        group.bench_with_input(BenchmarkId::new("trigram_index", query), query, |b, q| {
+            b.iter(|| db.search_photos(black_box(q), 0, 100).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_search);
+criterion_main!(benches);
@@ -2,6 +2,18 @@
 // 1. GLOBAL CONSTANTS & MAP INITIALIZATION
 // ==========================================
 
+// With LAN access on, the page is opened as /?token=...; the token is kept in a
+// cookie so that fetches, images and event streams all carry it
+(function storeAccessToken() {
+    const params = new URLSearchParams(window.location.search);
+    const token = params.get('token');
+    if (!token) return;
+    document.cookie = `photomap_token=${encodeURIComponent(token)}; path=/; max-age=31536000; SameSite=Strict`;
+    params.delete('token');
+    const query = params.toString();
+    history.replaceState(null, '', window.location.pathname + (query ? `?${query}` : '') + window.location.hash);
+})();
+
 // Initialize map with OS-specific scroll wheel zoom settings
 const isMac = navigator.platform.toUpperCase().indexOf('MAC') >= 0;
 
@@ -34,14 +46,42 @@ const API = {
     SHUTDOWN: '/api/shutdown',
     THUMBNAIL: '/api/thumbnail',
     MARKER: '/api/marker',
+    MARKER_BATCH: '/api/markers/batch',
     GALLERY: '/api/gallery',
-    SELECT_FOLDER: '/api/select-folder',
+    SELECT_FOLDER: '/api/select-folder-dialog',
+    INITIATE_PROCESSING: '/api/initiate-processing',
     SET_FOLDER: '/api/set-folder',
     EVENTS: '/api/events',
-    REPROCESS: '/api/reprocess'
+    WS: '/api/ws',
+    REPROCESS: '/api/reprocess',
+    CANCEL: '/api/cancel',
+    PROCESSING_STATUS: '/api/processing-status',
+    STATS: '/api/stats',
+    FOLDERS: '/api/folders',
+    SEARCH_PLACE: '/api/search-place',
+    PLACES: '/api/places',
+    ALBUMS: '/api/albums',
+    TRIPS: '/api/trips',
+    PHOTOS_NEAR: '/api/photos/near',
+    YEARS: '/api/photos/years'
 };
 
+// Photos whose GPS dilution of precision exceeds this get an orange marker dot
+const GPS_DOP_WARNING = 5;
+
+// Popups list other photos taken within this many metres
+const NEARBY_RADIUS_M = 200;
+const NEARBY_LIMIT = 6;
+
 let photoData = [];
+// Album shown on the map; empty for the whole library
+let selectedAlbum = '';
+// Capture year shown on the map; empty for all years
+let selectedYear = '';
+// Trip id of each photo, keyed by relative_path
+const tripOfPhoto = new Map();
+// Dominant colour of each photo as [r, g, b], keyed by relative_path
+const dominantColors = new Map();
 
 function encodePhotoPath(path) {
     return String(path || '').replace(/\\/g, '/').split('/').map(encodeURIComponent).join('/');
@@ -67,22 +107,408 @@ async function loadPhotos() {
         markerClusterGroup.clearLayers();
         photoData = [];
 
-        const response = await fetch(API.PHOTOS);
+        const params = new URLSearchParams();
+        if (selectedAlbum) {
+            params.set('album', selectedAlbum);
+        }
+        if (selectedYear) {
+            params.set('start_date', `${selectedYear}-01-01`);
+            params.set('end_date', `${selectedYear}-12-31`);
+        }
+        const query = params.toString();
+        const response = await fetch(query ? `${API.PHOTOS}?${query}` : API.PHOTOS);
         photoData = await response.json();
 
-        // Pre-calculate years for performance
+        // Pre-calculate years and cluster colours for performance
+        dominantColors.clear();
         photoData.forEach(photo => {
             photo.year = getYearFromDatetime(photo.datetime);
+            if (photo.dominant_color) {
+                const rgb = parseInt(photo.dominant_color.slice(1), 16);
+                dominantColors.set(photo.relative_path, [rgb >> 16, (rgb >> 8) & 255, rgb & 255]);
+            }
         });
 
         console.log(`Loaded ${photoData.length} photos from database`);
         addMarkers();
+        loadLibraryStats();
+        loadPlaces();
+        loadAlbums();
+        loadYears();
+        loadTrips();
+        loadFolders();
         return photoData; // Return the loaded data
     } catch (error) {
         console.error('Failed to load photos:', error);
     }
 }
 
+/**
+ * Formats a byte count with a binary unit, e.g. "1.5 GB".
+ * @param {number} bytes
+ * @returns {string}
+ */
+function formatBytes(bytes) {
+    const units = ['B', 'KB', 'MB', 'GB', 'TB'];
+    let value = bytes;
+    let unit = 0;
+    while (value >= 1024 && unit < units.length - 1) {
+        value /= 1024;
+        unit++;
+    }
+    return `${unit === 0 ? value : value.toFixed(1)} ${units[unit]}`;
+}
+
+/**
+ * Loads the library statistics, shows them in the collapsible panel
+ * and fills the country filter.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function loadLibraryStats() {
+    try {
+        const response = await fetch(API.STATS);
+        const stats = await response.json();
+
+        const rows = [
+            ['Photos', stats.total_photos],
+            ['With GPS', stats.photos_with_gps],
+            ['Without GPS', stats.photos_without_gps],
+            ['HEIC', stats.heic_count],
+            ['Disk size', formatBytes(stats.total_size_bytes)],
+            ['Dates', stats.date_range ? `${stats.date_range.earliest} – ${stats.date_range.latest}` : '—'],
+            ['Per year', stats.per_year.map(y => `${y.year} (${y.count})`).join(', ') || '—'],
+            ['Per day', stats.average_photos_per_day.toFixed(2)],
+            ['Busiest day', stats.most_active_day ? `${stats.most_active_day.date} (${stats.most_active_day.count})` : '—'],
+            ['Top places', stats.top_locations.map(l => `${l.name} (${l.count})`).join(', ') || '—']
+        ];
+        const list = document.getElementById('exp-library-stats-list');
+        list.replaceChildren(...rows.flatMap(([label, value]) => {
+            const dt = document.createElement('dt');
+            dt.textContent = label;
+            const dd = document.createElement('dd');
+            dd.textContent = value;
+            return [dt, dd];
+        }));
+
+        const select = document.getElementById('exp-country-filter');
+        const selected = select.value;
+        select.replaceChildren(select.options[0]);
+        stats.countries.forEach(({ country, count }) => {
+            select.add(new Option(`${country} (${count})`, country));
+        });
+        select.value = stats.countries.some(c => c.country === selected) ? selected : '';
+    } catch (error) {
+        console.error('Failed to load library statistics:', error);
+    }
+}
+
+/**
+ * Loads the photos grouped by location; clicking a place fits the map to its photos.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function loadPlaces() {
+    try {
+        const response = await fetch(API.PLACES);
+        const places = await response.json();
+
+        const list = document.getElementById('exp-places-list');
+        list.replaceChildren(...places.map(place => {
+            const item = document.createElement('li');
+            item.className = 'place-item';
+            item.title = place.date_range
+                ? `${place.date_range.earliest} – ${place.date_range.latest}`
+                : '';
+
+            const name = document.createElement('span');
+            name.className = 'folder-path';
+            name.textContent = place.name;
+
+            const badge = document.createElement('span');
+            badge.className = 'folder-badge';
+            badge.textContent = place.count;
+
+            item.append(name, badge);
+            item.addEventListener('click', () => {
+                map.fitBounds(
+                    [[place.min_lat, place.min_lng], [place.max_lat, place.max_lng]],
+                    { padding: [40, 40], maxZoom: 15 }
+                );
+            });
+            return item;
+        }));
+    } catch (error) {
+        console.error('Failed to load places:', error);
+    }
+}
+
+/**
+ * Loads the albums as cards with their cover photo; clicking one shows only
+ * its photos, clicking it again shows the whole library.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function loadAlbums() {
+    try {
+        const response = await fetch(API.ALBUMS);
+        const albums = await response.json();
+
+        const list = document.getElementById('exp-albums-list');
+        list.replaceChildren(...albums.map(album => {
+            const item = document.createElement('li');
+            item.className = 'album-card';
+            item.classList.toggle('active', album.name === selectedAlbum);
+
+            const cover = document.createElement('img');
+            cover.loading = 'lazy';
+            cover.alt = '';
+            cover.src = `${API.THUMBNAIL}/${encodePhotoPath(album.cover_photo_relative_path)}`;
+
+            const name = document.createElement('span');
+            name.className = 'folder-path';
+            name.textContent = album.name;
+
+            const badge = document.createElement('span');
+            badge.className = 'folder-badge';
+            badge.textContent = album.count;
+
+            item.append(cover, name, badge);
+            item.addEventListener('click', () => {
+                selectedAlbum = album.name === selectedAlbum ? '' : album.name;
+                loadPhotos();
+            });
+            return item;
+        }));
+    } catch (error) {
+        console.error('Failed to load albums:', error);
+    }
+}
+
+/**
+ * Loads the years as a row of badges; clicking one shows only the photos of
+ * that year and centers the map on one of them, clicking it again shows all years.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function loadYears() {
+    try {
+        const response = await fetch(API.YEARS);
+        const years = await response.json();
+
+        const row = document.getElementById('exp-years-list');
+        row.replaceChildren(...years.map(entry => {
+            const year = String(entry.year);
+            const badge = document.createElement('button');
+            badge.type = 'button';
+            badge.className = 'folder-badge year-badge';
+            badge.classList.toggle('active', year === selectedYear);
+            badge.textContent = year;
+            badge.title = `${entry.count} photos`;
+            badge.addEventListener('click', () => {
+                selectedYear = year === selectedYear ? '' : year;
+                if (selectedYear) {
+                    map.setView([entry.example_lat, entry.example_lng]);
+                }
+                loadPhotos();
+            });
+            return badge;
+        }));
+    } catch (error) {
+        console.error('Failed to load years:', error);
+    }
+}
+
+/**
+ * Distinct colour of a trip; consecutive ids are far apart on the colour wheel.
+ * @param {number} id - The trip id.
+ * @returns {string} A CSS colour.
+ */
+function tripColor(id) {
+    return `hsl(${Math.round((id * 137.508) % 360)}, 70%, 45%)`;
+}
+
+/**
+ * Whether clusters are coloured by trip instead of by photo colour.
+ * @returns {boolean}
+ */
+function isTripMode() {
+    const toggle = document.getElementById('exp-trip-toggle');
+    return Boolean(toggle && toggle.checked);
+}
+
+/**
+ * Loads the trips into the timeline and remembers the trip of each photo;
+ * clicking a trip fits the map to it.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function loadTrips() {
+    try {
+        const response = await fetch(API.TRIPS);
+        const trips = await response.json();
+
+        tripOfPhoto.clear();
+        trips.forEach(trip => trip.photos.forEach(path => tripOfPhoto.set(path, trip.id)));
+
+        const list = document.getElementById('exp-trips-list');
+        list.replaceChildren(...trips.map(trip => {
+            const item = document.createElement('li');
+            item.className = 'place-item trip-item';
+            item.title = trip.visited_locations.join(', ');
+
+            const swatch = document.createElement('span');
+            swatch.className = 'trip-swatch';
+            swatch.style.background = tripColor(trip.id);
+
+            const name = document.createElement('span');
+            name.className = 'folder-path';
+            const dates = trip.start
+                ? (trip.start.slice(0, 10) === trip.end.slice(0, 10)
+                    ? trip.start.slice(0, 10)
+                    : `${trip.start.slice(0, 10)} – ${trip.end.slice(0, 10)}`)
+                : 'Undated';
+            name.textContent = trip.location ? `${dates} · ${trip.location}` : dates;
+
+            const badge = document.createElement('span');
+            badge.className = 'folder-badge';
+            badge.textContent = trip.photo_count;
+
+            item.append(swatch, name, badge);
+            item.addEventListener('click', () => {
+                map.fitBounds(
+                    [[trip.min_lat, trip.min_lng], [trip.max_lat, trip.max_lng]],
+                    { padding: [40, 40], maxZoom: 15 }
+                );
+            });
+            return item;
+        }));
+
+        if (isTripMode()) {
+            markerClusterGroup.refreshClusters();
+        }
+    } catch (error) {
+        console.error('Failed to load trips:', error);
+    }
+}
+
+/**
+ * Loads the configured folders and shows each with a status badge
+ * and a remove button.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function loadFolders() {
+    try {
+        const response = await fetch(API.FOLDERS);
+        const folders = await response.json();
+
+        const list = document.getElementById('exp-folder-list');
+        list.replaceChildren(...folders.map(folder => {
+            const item = document.createElement('li');
+
+            const path = document.createElement('span');
+            path.className = 'folder-path';
+            path.textContent = displayFilePath(folder.path);
+            path.title = displayFilePath(folder.path);
+
+            const enabled = document.createElement('input');
+            enabled.type = 'checkbox';
+            enabled.className = 'folder-enabled';
+            enabled.checked = folder.enabled;
+            enabled.title = folder.enabled ? 'Hide its photos from the map' : 'Show its photos on the map';
+            enabled.addEventListener('change', () => setFolderEnabled(folder, enabled.checked));
+            if (!folder.enabled) {
+                item.classList.add('disabled');
+            }
+
+            const badge = document.createElement('span');
+            if (!folder.exists) {
+                badge.className = 'folder-badge missing';
+                badge.textContent = 'missing';
+            } else {
+                badge.className = 'folder-badge';
+                badge.textContent = `${folder.photo_count} photos`;
+            }
+            badge.title = folder.last_processed
+                ? `Last processed ${folder.last_processed} UTC`
+                : 'Not processed yet';
+
+            const remove = document.createElement('button');
+            remove.className = 'folder-remove';
+            remove.textContent = '✕';
+            remove.title = 'Remove folder and its photos';
+            remove.addEventListener('click', () => removeFolder(folder));
+
+            item.append(enabled, path, badge, remove);
+            return item;
+        }));
+    } catch (error) {
+        console.error('Failed to load folders:', error);
+    }
+}
+
+/**
+ * Shows or hides the photos of a folder; its cache is kept while hidden.
+ * @async
+ * @param {Object} folder - Entry returned by /api/folders
+ * @param {boolean} enabled - Whether the photos are shown
+ * @returns {Promise<void>}
+ */
+async function setFolderEnabled(folder, enabled) {
+    try {
+        const response = await fetch(`${API.FOLDERS}/${folder.index}/enabled`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ enabled })
+        });
+        const result = await response.json().catch(() => ({}));
+        if (!response.ok) {
+            throw new Error(result.message || `HTTP ${response.status}`);
+        }
+        if (result.status === 'started') {
+            showNotification(`🔄 Processing changed files of ${displayFilePath(folder.path)}`, 'info');
+        }
+        loadFolders();
+        loadPhotos().then(() => {
+            initializeYearControls();
+        });
+        updateStatistics();
+    } catch (error) {
+        console.error('Failed to switch folder:', error);
+        showNotification(`❌ Failed to switch folder: ${error.message}`, 'error');
+        loadFolders();
+    }
+}
+
+/**
+ * Removes a folder from the settings and its photos from the map.
+ * @async
+ * @param {Object} folder - Entry returned by /api/folders
+ * @returns {Promise<void>}
+ */
+async function removeFolder(folder) {
+    if (!confirm(`Remove ${displayFilePath(folder.path)} and its photos from the map?`)) {
+        return;
+    }
+    try {
+        const response = await fetch(`${API.FOLDERS}/${folder.index}`, { method: 'DELETE' });
+        if (!response.ok) {
+            const result = await response.json().catch(() => ({}));
+            throw new Error(result.message || `HTTP ${response.status}`);
+        }
+        window.selectedFolders = (window.selectedFolders || []).filter(f => f !== folder.path);
+        showNotification(`🗑️ Removed ${displayFilePath(folder.path)}`, 'success');
+        loadPhotos().then(() => {
+            initializeYearControls();
+        });
+        updateStatistics();
+    } catch (error) {
+        console.error('Failed to remove folder:', error);
+        showNotification(`❌ Failed to remove folder: ${error.message}`, 'error');
+    }
+}
+
 /**
  * Loads user settings from the API and applies them to the UI.
  * Handles folder selection, toggles, and panel positioning.
@@ -96,7 +522,7 @@ async function loadSettings() {
 
         // Load folders from settings (new multi-folder support)
         if (settings.folders && Array.isArray(settings.folders)) {
-            const folders = settings.folders.filter(f => f !== null && f !== "");
+            const folders = settings.folders.map(folder => folder.path);
 
             if (folders.length > 0) {
                 const input = document.getElementById('exp-folder-input');
@@ -128,6 +554,9 @@ async function loadSettings() {
             browserAutostartToggle.checked = settings.start_browser !== undefined ? settings.start_browser : true;
         }
 
+        // Thumbnail warm-up keeps the event stream open after processing
+        window.pregenerateThumbnails = settings.pregenerate_thumbnails === true;
+
         // Set map coordinates toggle
         const mapCoordsToggle = document.getElementById('exp-map-coords-toggle');
         if (mapCoordsToggle) {
@@ -235,9 +664,7 @@ function createPhotoMetadataFragment(photo) {
 
     const metadataElement = document.createElement('div');
     metadataElement.className = 'popup-metadata';
-    metadataElement.textContent = photo.location
-        ? `📍 ${photo.location}   📅 ${formattedDateTime || ''}`
-        : `📅 ${formattedDateTime || ''}`;
+    metadataElement.textContent = `📍 ${photo.location || 'Unknown location'}   📅 ${formattedDateTime || ''}`;
 
     fragment.append(filenameElement, metadataElement);
     return fragment;
@@ -321,46 +748,39 @@ async function shutdownApp() {
             left = Math.round(rect.left);
         }
 
-        // 2. Fetch current settings to avoid overwriting other fields
-        const settingsResponse = await fetch(API.SETTINGS);
-        if (settingsResponse.ok) {
-            const currentSettings = await settingsResponse.json();
-
-            // 3. Update panel position
-            currentSettings.top = top;
-            currentSettings.left = left;
-
-            // 4. Update toggle states
-            const mapCoordsToggle = document.getElementById('exp-map-coords-toggle');
-            const routesToggle = document.getElementById('exp-routes-toggle');
-            const heatmapToggle = document.getElementById('exp-heatmap-toggle');
-            const browserAutostartToggle = document.getElementById('exp-browser-autostart-toggle');
-
-            if (mapCoordsToggle) currentSettings.map_coords = mapCoordsToggle.checked;
-            if (routesToggle) currentSettings.routes = routesToggle.checked;
-            if (heatmapToggle) currentSettings.heatmap = heatmapToggle.checked;
-            if (browserAutostartToggle) currentSettings.start_browser = browserAutostartToggle.checked;
+        // 2. Send only the fields owned by the panel; the rest keep their values
+        const changes = {
+            top: Math.max(0, top),
+            left: Math.max(0, left)
+        };
+        const toggles = {
+            map_coords: 'exp-map-coords-toggle',
+            routes: 'exp-routes-toggle',
+            heatmap: 'exp-heatmap-toggle',
+            start_browser: 'exp-browser-autostart-toggle'
+        };
+        for (const [field, id] of Object.entries(toggles)) {
+            const toggle = document.getElementById(id);
+            if (toggle) changes[field] = toggle.checked;
+        }
 
-            // 5. Save settings
-            const updateResponse = await fetch(API.UPDATE_SETTINGS, {
-                method: 'POST',
-                headers: {
-                    'Content-Type': 'application/json'
-                },
-                body: JSON.stringify(currentSettings)
-            });
+        // 3. Save settings
+        const updateResponse = await fetch(API.UPDATE_SETTINGS, {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json'
+            },
+            body: JSON.stringify(changes)
+        });
 
-            if (updateResponse.ok) {
-                showNotification('💾 Saved', 'success');
-            } else {
-                console.error('Failed to save settings before shutdown');
-                showNotification('⚠️ Failed to save settings', 'error');
-            }
+        if (updateResponse.ok) {
+            showNotification('💾 Saved', 'success');
         } else {
-            console.error('Failed to fetch current settings');
+            console.error('Failed to save settings before shutdown');
+            showNotification('⚠️ Failed to save settings', 'error');
         }
 
-        // 5. Wait 300ms before shutdown so you can see logs
+        // 4. Wait 300ms before shutdown so you can see logs
         showNotification('👋 Shutting down...', 'info');
 
         setTimeout(async () => {
@@ -492,6 +912,52 @@ function updateMapCoordinates() {
 map.on('move', updateMapCoordinates);
 map.on('zoom', updateMapCoordinates);
 
+/**
+ * Averages the dominant colours of the photos in a cluster.
+ * @param {Object} cluster - The Leaflet marker cluster.
+ * @returns {string|null} A CSS colour, or null when no photo has a colour.
+ */
+function clusterColor(cluster) {
+    if (isTripMode()) {
+        return clusterTripColor(cluster);
+    }
+    const sum = [0, 0, 0];
+    let count = 0;
+    cluster.getAllChildMarkers().forEach(marker => {
+        const photo = marker.options.photoData;
+        const color = photo && dominantColors.get(photo.relative_path);
+        if (color) {
+            sum[0] += color[0];
+            sum[1] += color[1];
+            sum[2] += color[2];
+            count++;
+        }
+    });
+    if (count === 0) return null;
+    return `rgb(${sum.map(c => Math.round(c / count)).join(', ')})`;
+}
+
+/**
+ * Colour of the trip most photos in a cluster belong to.
+ * @param {Object} cluster - The Leaflet marker cluster.
+ * @returns {string|null} A CSS colour, or null when no photo is in a trip.
+ */
+function clusterTripColor(cluster) {
+    const counts = new Map();
+    cluster.getAllChildMarkers().forEach(marker => {
+        const photo = marker.options.photoData;
+        const trip = photo && tripOfPhoto.get(photo.relative_path);
+        if (trip !== undefined) {
+            counts.set(trip, (counts.get(trip) || 0) + 1);
+        }
+    });
+    let best = null;
+    counts.forEach((count, trip) => {
+        if (best === null || count > counts.get(best)) best = trip;
+    });
+    return best === null ? null : tripColor(best);
+}
+
 // Initialize marker cluster group
 const markerClusterGroup = L.markerClusterGroup({
     iconCreateFunction: function (cluster) {
@@ -508,8 +974,11 @@ const markerClusterGroup = L.markerClusterGroup({
             large: 50
         };
 
+        const color = clusterColor(cluster);
+
         return L.divIcon({
             html: '<div class="cluster-icon-inner" style="' +
+                (color ? 'background: ' + color + '; ' : '') +
                 'width: ' + sizes[size] + 'px; ' +
                 'height: ' + sizes[size] + 'px; ' +
                 'line-height: ' + sizes[size] + 'px; ' +
@@ -533,9 +1002,11 @@ let heatLayer = null;
 
 /**
  * Creates a Leaflet icon for a photo marker.
+ * Map markers are painted from sprite sheets once they are on screen, see
+ * paintMarkerTile; larger thumbnails load as images of their own.
  * @param {Object} photo - The photo object.
  * @param {boolean} [useThumbnail=false] - Whether to use a larger thumbnail size.
- * @returns {L.Icon} The Leaflet icon instance.
+ * @returns {L.DivIcon} The Leaflet icon instance.
  */
 function createPhotoIcon(photo, useThumbnail = false) {
   const iconSize = useThumbnail ? 60 : 40;
@@ -544,15 +1015,152 @@ function createPhotoIcon(photo, useThumbnail = false) {
       ? `${apiUrl}/${encodePhotoPath(photo.relative_path)}`
       : (photo.marker_icon || `${apiUrl}/${encodePhotoPath(photo.relative_path)}`);
 
-  return L.icon({
-    iconUrl,
+  // Rough GPS fix: mark the photo with an orange dot.
+  // Low-quality or suspicious position: give the thumbnail an orange border.
+  // Known compass heading: draw a cone pointing where the camera faced.
+  const roughFix = photo.gps_dop > GPS_DOP_WARNING;
+  const lowQuality = photo.gps_quality === 'low' || photo.gps_quality === 'suspicious';
+  const hasDirection = typeof photo.direction === 'number';
+  const html = document.createElement('div');
+  if (hasDirection) {
+    const cone = document.createElement('span');
+    cone.className = 'direction-cone';
+    cone.style.transform = `rotate(${photo.direction}deg)`;
+    cone.title = `Facing ${Math.round(photo.direction)}°`;
+    html.append(cone);
+  }
+  const img = document.createElement(useThumbnail ? 'img' : 'div');
+  img.className = lowQuality ? 'thumbnail-icon gps-low-quality' : 'thumbnail-icon';
+  if (useThumbnail) {
+    img.src = iconUrl;
+  } else {
+    img.classList.add('sprite-marker');
+    img.dataset.path = photo.relative_path;
+    img.dataset.url = iconUrl;
+  }
+  img.style.width = img.style.height = `${iconSize}px`;
+  if (lowQuality) {
+    img.title = `Unreliable GPS position (${photo.gps_quality})`;
+  }
+  html.append(img);
+  if (roughFix) {
+    const dot = document.createElement('span');
+    dot.className = 'gps-dop-dot';
+    dot.title = `Low GPS accuracy (DOP ${photo.gps_dop.toFixed(1)})`;
+    html.append(dot);
+  }
+  return L.divIcon({
+    html: html.innerHTML,
     iconSize: [iconSize, iconSize],
     iconAnchor: [iconSize / 2, iconSize / 2],
     popupAnchor: [0, -iconSize / 2],
-    className: 'thumbnail-icon'
+    className: 'photo-marker-icon'
   });
 }
 
+// Sprite tiles of the markers by relative path, as CSS background properties.
+// Markers on screen are fetched together from /api/markers/batch, so a busy
+// view costs one request instead of one per marker.
+const markerTiles = new Map();
+const queuedMarkerPaths = new Set();
+let markerBatchTimer = null;
+// MAX_SPRITE_TILES on the server
+const MARKER_BATCH_SIZE = 1000;
+
+/**
+ * Paints a marker element with its sprite tile.
+ * @param {HTMLElement} element - A `.sprite-marker` element.
+ * @returns {boolean} Whether the tile was known.
+ */
+function paintMarkerTile(element) {
+  const tile = markerTiles.get(element.dataset.path);
+  if (!tile) {
+    return false;
+  }
+  Object.assign(element.style, tile);
+  return true;
+}
+
+/**
+ * Queues a marker element that has no tile yet for the next batch request.
+ * @param {HTMLElement} element - A `.sprite-marker` element.
+ * @param {number} [delay=50] - Milliseconds to wait for more markers.
+ */
+function queueMarkerTile(element, delay = 50) {
+  queuedMarkerPaths.add(element.dataset.path);
+  clearTimeout(markerBatchTimer);
+  markerBatchTimer = setTimeout(loadQueuedMarkerTiles, delay);
+}
+
+/**
+ * Fetches the sprite sheets of the queued markers and paints every marker on
+ * screen that waits for one. Markers the server could not render, or all of a
+ * failed batch, fall back to their single marker URL; a rate-limited batch is
+ * retried after `Retry-After`.
+ */
+async function loadQueuedMarkerTiles() {
+  const paths = [...queuedMarkerPaths].filter(path => !markerTiles.has(path));
+  queuedMarkerPaths.clear();
+  const fallback = new Set();
+  for (let start = 0; start < paths.length; start += MARKER_BATCH_SIZE) {
+    const batch = paths.slice(start, start + MARKER_BATCH_SIZE);
+    try {
+      const response = await fetch(API.MARKER_BATCH, {
+        method: 'POST',
+        headers: {
+          'Content-Type': 'application/json'
+        },
+        body: JSON.stringify(batch)
+      });
+      if (response.status === 429) {
+        const retryAfter = Number(response.headers.get('Retry-After')) || 1;
+        batch.forEach(path => queuedMarkerPaths.add(path));
+        clearTimeout(markerBatchTimer);
+        markerBatchTimer = setTimeout(loadQueuedMarkerTiles, retryAfter * 1000);
+        continue;
+      }
+      if (!response.ok) {
+        throw new Error(`HTTP ${response.status}`);
+      }
+      const form = await response.formData();
+      const manifest = JSON.parse(await form.get('manifest').text());
+      const sprite = URL.createObjectURL(form.get('sprite'));
+      for (const [path, tile] of Object.entries(manifest.tiles)) {
+        markerTiles.set(path, {
+          backgroundImage: `url(${sprite})`,
+          backgroundPosition: `-${tile.x}px -${tile.y}px`,
+          backgroundSize: `${manifest.width}px ${manifest.height}px`
+        });
+      }
+      manifest.missing.forEach(path => fallback.add(path));
+    } catch (error) {
+      console.error('Failed to load marker sprite:', error);
+      batch.forEach(path => fallback.add(path));
+    }
+  }
+
+  document.querySelectorAll('.sprite-marker').forEach(element => {
+    if (fallback.has(element.dataset.path)) {
+      markerTiles.set(element.dataset.path, {
+        backgroundImage: `url("${element.dataset.url}")`,
+        backgroundSize: 'cover'
+      });
+    }
+    paintMarkerTile(element);
+  });
+}
+
+// Leaflet builds a new icon element whenever a marker enters the map, e.g.
+// when a cluster splits; paint it from a known tile or queue it
+map.on('layeradd', (event) => {
+  const element = event.layer instanceof L.Marker && event.layer.getElement()
+      ? event.layer.getElement().querySelector('.sprite-marker')
+      : null;
+  if (element && !paintMarkerTile(element)) {
+    queueMarkerTile(element);
+  }
+});
+
 /**
  * Adds markers or heatmap to the map based on current settings.
  * Handles clustering, popups, and initial map fitting.
@@ -724,7 +1332,7 @@ function drawPolylines() {
 }
 
 /**
- * Filters map markers based on the selected year range.
+ * Filters map markers based on the selected year range and country.
  * Updates the map, clusters, and statistics.
  */
 function filterMarkers() {
@@ -733,6 +1341,7 @@ function filterMarkers() {
 
     const fromYear = parseInt(yearFromInput.value);
     const toYear = parseInt(yearToInput.value);
+    const country = document.getElementById('exp-country-filter').value;
 
     if (isNaN(fromYear) || isNaN(toYear)) return;
 
@@ -749,7 +1358,8 @@ function filterMarkers() {
     // Filter photos
     const filteredPhotos = photoData.filter(photo => {
         // Use pre-calculated year
-        return photo.year !== null && photo.year >= fromYear && photo.year <= toYear;
+        return photo.year !== null && photo.year >= fromYear && photo.year <= toYear &&
+            (!country || photo.country === country);
     });
 
     console.log(`Found ${filteredPhotos.length} photos in range`);
@@ -769,7 +1379,10 @@ function filterMarkers() {
         // Add filtered markers
         filteredPhotos.forEach(photo => {
             const icon = createPhotoIcon(photo, false);
-            const marker = L.marker([photo.lat, photo.lng], { icon: icon });
+            const marker = L.marker([photo.lat, photo.lng], {
+                icon: icon,
+                photoData: photo
+            });
 
             marker.bindPopup(() => createPopupContent(photo));
             markerClusterGroup.addLayer(marker);
@@ -783,6 +1396,83 @@ function filterMarkers() {
     }
 }
 
+// Places offered by the search box, keyed by their label
+const placeResults = new Map();
+let placeSearchTimer = null;
+
+function placeLabel(place) {
+    return [place.name, place.admin1, place.country].filter(Boolean).join(', ');
+}
+
+/**
+ * Flies the map to a place found by the search box.
+ * @param {Object} place - Entry returned by /api/search-place
+ */
+function goToPlace(place) {
+    map.setView([place.lat, place.lon], 12);
+}
+
+/**
+ * Looks up places matching the query and offers them in the search box.
+ * Retries after the server's Retry-After delay while the geocoder is loading.
+ * @async
+ * @param {string} query - Text typed into the search box
+ * @returns {Promise<void>}
+ */
+async function searchPlaces(query) {
+    if (query.trim().length < 2) return;
+    try {
+        const response = await fetch(`${API.SEARCH_PLACE}?q=${encodeURIComponent(query)}&limit=10`);
+        if (response.status === 503) {
+            const retryAfter = parseInt(response.headers.get('Retry-After'), 10);
+            if (retryAfter > 0) {
+                placeSearchTimer = setTimeout(() => searchPlaces(query), retryAfter * 1000);
+            }
+            return;
+        }
+        const places = await response.json();
+
+        placeResults.clear();
+        const options = document.getElementById('exp-place-options');
+        options.replaceChildren(...places.map(place => {
+            const label = placeLabel(place);
+            placeResults.set(label, place);
+            const option = document.createElement('option');
+            option.value = label;
+            return option;
+        }));
+    } catch (error) {
+        console.error('Failed to search places:', error);
+    }
+}
+
+/**
+ * Wires the place search box: suggestions while typing, Enter jumps to the best match.
+ */
+function initPlaceSearch() {
+    const input = document.getElementById('exp-place-search');
+    if (!input) return;
+
+    input.addEventListener('input', () => {
+        const chosen = placeResults.get(input.value);
+        if (chosen) {
+            goToPlace(chosen);
+            return;
+        }
+        clearTimeout(placeSearchTimer);
+        placeSearchTimer = setTimeout(() => searchPlaces(input.value), 250);
+    });
+    input.addEventListener('keydown', (e) => {
+        if (e.key !== 'Enter') return;
+        const best = placeResults.get(input.value) || placeResults.values().next().value;
+        if (best) {
+            goToPlace(best);
+        } else {
+            showNotification('⚠️ No matching place found', 'warning');
+        }
+    });
+}
+
 /**
  * Pans the map to the user's current location.
  * specific location if available, otherwise requests location access.
@@ -855,6 +1545,84 @@ markerClusterGroup.on('clusterclick', function (a) {
 // ==========================================
 // Handles DOM manipulation, panels, gallery, and user interaction
 
+const BLURHASH_DIGITS = '0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~';
+
+function decodeBase83(str) {
+    let value = 0;
+    for (const char of str) {
+        value = value * 83 + BLURHASH_DIGITS.indexOf(char);
+    }
+    return value;
+}
+
+function srgbToLinear(value) {
+    const v = value / 255;
+    return v <= 0.04045 ? v / 12.92 : Math.pow((v + 0.055) / 1.055, 2.4);
+}
+
+function linearToSrgb(value) {
+    const v = Math.max(0, Math.min(1, value));
+    return Math.round(v <= 0.0031308 ? v * 12.92 * 255 : (1.055 * Math.pow(v, 1 / 2.4) - 0.055) * 255);
+}
+
+function signPow(value, exp) {
+    return Math.sign(value) * Math.pow(Math.abs(value), exp);
+}
+
+/**
+ * Draws a BlurHash placeholder onto a small canvas.
+ * @param {string} hash - The BlurHash string from the API.
+ * @param {number} width - Canvas width in pixels.
+ * @param {number} height - Canvas height in pixels.
+ * @returns {HTMLCanvasElement|null} The canvas, or null for an invalid hash.
+ */
+function createBlurhashCanvas(hash, width, height) {
+    if (!hash || hash.length < 6) return null;
+    const sizeFlag = decodeBase83(hash[0]);
+    const numX = (sizeFlag % 9) + 1;
+    const numY = Math.floor(sizeFlag / 9) + 1;
+    if (hash.length !== 4 + 2 * numX * numY) return null;
+
+    const maxValue = (decodeBase83(hash[1]) + 1) / 166;
+    const dc = decodeBase83(hash.substring(2, 6));
+    const colors = [[srgbToLinear(dc >> 16), srgbToLinear((dc >> 8) & 255), srgbToLinear(dc & 255)]];
+    for (let i = 1; i < numX * numY; i++) {
+        const ac = decodeBase83(hash.substring(4 + i * 2, 6 + i * 2));
+        colors.push([
+            Math.floor(ac / (19 * 19)),
+            Math.floor(ac / 19) % 19,
+            ac % 19
+        ].map(q => signPow((q - 9) / 9, 2) * maxValue));
+    }
+
+    const canvas = document.createElement('canvas');
+    canvas.width = width;
+    canvas.height = height;
+    const ctx = canvas.getContext('2d');
+    const pixels = ctx.createImageData(width, height);
+    for (let y = 0; y < height; y++) {
+        for (let x = 0; x < width; x++) {
+            let r = 0, g = 0, b = 0;
+            for (let j = 0; j < numY; j++) {
+                for (let i = 0; i < numX; i++) {
+                    const basis = Math.cos(Math.PI * x * i / width) * Math.cos(Math.PI * y * j / height);
+                    const color = colors[i + j * numX];
+                    r += color[0] * basis;
+                    g += color[1] * basis;
+                    b += color[2] * basis;
+                }
+            }
+            const offset = 4 * (x + y * width);
+            pixels.data[offset] = linearToSrgb(r);
+            pixels.data[offset + 1] = linearToSrgb(g);
+            pixels.data[offset + 2] = linearToSrgb(b);
+            pixels.data[offset + 3] = 255;
+        }
+    }
+    ctx.putImageData(pixels, 0, 0);
+    return canvas;
+}
+
 /**
  * Generates HTML content for a photo popup.
  * @param {Object} photo - The photo object.
@@ -875,10 +1643,82 @@ function createPopupContent(photo) {
         }
     };
 
+    // Show the blurred placeholder until the photo itself has loaded
+    const placeholder = createBlurhashCanvas(photo.blurhash, 32, 24);
+    if (placeholder) {
+        placeholder.className = 'popup-placeholder';
+        img.classList.add('loading');
+        img.addEventListener('load', () => {
+            placeholder.remove();
+            img.classList.remove('loading');
+        }, { once: true });
+        popup.append(placeholder);
+    }
+
     popup.append(img, createPhotoMetadataFragment(photo));
+
+    if (photo.speed_kmh > 0) {
+        const speed = document.createElement('div');
+        speed.className = 'popup-metadata';
+        speed.textContent = `📍 ${photo.speed_kmh.toFixed(1)} km/h`;
+        popup.append(speed);
+    }
+
+    if (photo.is_motion_photo) {
+        const badge = document.createElement('span');
+        badge.className = 'motion-photo-badge';
+        badge.title = 'Motion photo';
+        badge.textContent = '▶';
+        popup.append(badge);
+    }
+
+    const nearby = document.createElement('div');
+    nearby.className = 'popup-nearby';
+    popup.append(nearby);
+    loadNearbyPhotos(photo, nearby);
+
     return popup;
 }
 
+/**
+ * Fills a popup row with other photos taken close to the given one; clicking a
+ * thumbnail centres the map on that photo.
+ * @async
+ * @param {Object} photo - The photo shown in the popup.
+ * @param {HTMLElement} container - The row to fill.
+ * @returns {Promise<void>}
+ */
+async function loadNearbyPhotos(photo, container) {
+    const params = new URLSearchParams({
+        lat: photo.lat,
+        lng: photo.lng,
+        radius_m: NEARBY_RADIUS_M,
+        limit: NEARBY_LIMIT,
+        exclude: photo.relative_path
+    });
+    try {
+        const response = await fetch(`${API.PHOTOS_NEAR}?${params}`);
+        const photos = await response.json();
+        if (!photos.length) return;
+
+        const label = document.createElement('div');
+        label.className = 'popup-metadata';
+        label.textContent = `📷 Nearby (${NEARBY_RADIUS_M} m)`;
+        container.append(label, ...photos.map(other => {
+            const thumb = document.createElement('img');
+            thumb.src = other.marker_icon;
+            thumb.alt = other.filename;
+            thumb.title = `${other.filename} · ${Math.round(other.distance_m)} m`;
+            thumb.addEventListener('click', () => {
+                map.setView([other.lat, other.lng], Math.max(map.getZoom(), 17));
+            });
+            return thumb;
+        }));
+    } catch (error) {
+        console.error('Failed to load nearby photos:', error);
+    }
+}
+
 /**
  * Updates the photo statistics (total and visible count) in the UI.
  * Calculates visible photos based on current map bounds.
@@ -1128,28 +1968,31 @@ async function openFolderDialog() {
         const result = await response.json();
 
         if (result.status === 'success') {
-            // Backend returns folder_paths array
-            const folders = result.folder_paths || [];
+            // The picked folders were added to the configured ones
+            const folders = (result.folders || []).map(folder => folder.path);
             if (folders.length > 1) {
                 folderInput.value = `Multiple folders (${folders.length})`;
                 // Add custom tooltip with all folder paths
                 folderInput.setAttribute('data-tooltip', folders.join('\n'));
-                showNotification(`✅ ${folders.length} folders selected`, 'success');
             } else if (folders.length === 1) {
                 folderInput.value = folders[0];
                 folderInput.removeAttribute('data-tooltip');
-                showNotification('✅ Folder selected', 'success');
             }
+            showNotification(`✅ ${result.message}`, 'success');
 
             // Store folders array for processing
             window.selectedFolders = folders;
+            loadFolders();
 
-            // Auto-start processing
-            console.log('Auto-starting processing...');
-            await processFolder();
+            // Process the new photos right away, keeping the stored ones
+            if (result.added.length) {
+                await startIncrementalProcessing();
+            }
         } else if (result.status === 'cancelled') {
             // User cancelled, do nothing
             console.log('Folder selection cancelled');
+        } else if (result.status === 'busy') {
+            showNotification('⏳ ' + result.message, 'info');
         } else {
             showNotification('❌ ' + (result.message || 'Error selecting folder'), 'error');
         }
@@ -1164,13 +2007,205 @@ async function openFolderDialog() {
     }
 }
 
+/**
+ * Processes the new photos of the configured folders with live updates.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function startIncrementalProcessing() {
+    openProcessingEvents(async (eventSource) => {
+        const response = await fetch(API.INITIATE_PROCESSING, { method: 'POST' });
+        const result = await response.json();
+        if (result.status !== 'started') {
+            eventSource.close();
+            showNotification('❌ ' + (result.message || 'Error starting processing'), 'error');
+            return;
+        }
+        setProcessingActive(true);
+        showNotification('✅ ' + result.message, 'success');
+    }, () => {
+        setProcessingActive(false);
+        showNotification('❌ Error connecting to the server for updates.', 'error');
+    });
+}
+
+/**
+ * Switches the Process button between starting and cancelling a run.
+ * @param {boolean} active - Whether a processing run is in progress.
+ */
+function setProcessingActive(active) {
+    window.processingActive = active;
+    const button = document.getElementById('exp-process-button');
+    if (!button) return;
+    button.title = active ? 'Cancel Processing' : 'Process Current Path';
+    button.lastChild.textContent = active ? ' Cancel ' : ' Process ';
+}
+
+/**
+ * Asks the server to stop the running processing job.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function cancelProcessing() {
+    try {
+        await fetch(API.CANCEL, { method: 'POST' });
+        showNotification('⏹️ Cancelling processing...', 'info');
+    } catch (error) {
+        showNotification('❌ Error: ' + error.message, 'error');
+    }
+}
+
+// Minimum delay between map refreshes while a run is still inserting photos
+const PROCESSING_REFRESH_INTERVAL_MS = 3000;
+let lastProcessingRefresh = 0;
+
+/**
+ * Reloads photos while processing runs so the map fills up incrementally.
+ * Throttled, since batches arrive faster than the map can redraw.
+ */
+function refreshPhotosDuringProcessing() {
+    const now = Date.now();
+    if (now - lastProcessingRefresh < PROCESSING_REFRESH_INTERVAL_MS) return;
+    lastProcessingRefresh = now;
+    loadPhotos();
+}
+
+/**
+ * Opens the processing event stream over a WebSocket, falling back to SSE when the
+ * WebSocket cannot connect (e.g. a proxy without upgrade support).
+ * @param {Function} onOpen - Called with the connection once it is ready.
+ * @param {Function} onError - Called when an open connection fails.
+ */
+function openProcessingEvents(onOpen, onError) {
+    const useSse = () => {
+        const eventSource = new EventSource(API.EVENTS);
+        eventSource.onopen = () => onOpen(eventSource);
+        eventSource.onmessage = (event) => handleProcessingEvent(eventSource, event);
+        eventSource.onerror = function () {
+            eventSource.close();
+            onError();
+        };
+    };
+    if (!('WebSocket' in window)) {
+        useSse();
+        return;
+    }
+
+    const protocol = location.protocol === 'https:' ? 'wss:' : 'ws:';
+    const socket = new WebSocket(`${protocol}//${location.host}${API.WS}`);
+    let opened = false;
+    let closedByClient = false;
+    const close = socket.close.bind(socket);
+    socket.close = () => {
+        closedByClient = true;
+        close();
+    };
+    socket.onopen = () => {
+        opened = true;
+        onOpen(socket);
+    };
+    socket.onmessage = (event) => handleProcessingEvent(socket, event);
+    socket.onclose = () => {
+        if (!opened) {
+            console.warn('WebSocket unavailable, using SSE for processing updates');
+            useSse();
+        } else if (!closedByClient) {
+            onError();
+        }
+    };
+}
+
+/**
+ * Reacts to processing and warm-up events received over WebSocket or SSE.
+ * @param {WebSocket|EventSource} eventSource - The connection to close once the run is over.
+ * @param {MessageEvent} event - The message with the JSON event in `data`.
+ */
+function handleProcessingEvent(eventSource, event) {
+    const data = JSON.parse(event.data);
+    if (data.event_type === 'processing_complete') {
+        setProcessingActive(false);
+        if (!window.pregenerateThumbnails) {
+            eventSource.close();
+        }
+        loadPhotos().then(() => {
+            initializeYearControls(); // Re-initialize year controls with new data
+        }); // Refresh map
+        updateStatistics();
+        showNotification(`🎉 Processing completed! Found ${data.data.processed || 0} photos`, 'success');
+    } else if (data.event_type === 'processing_cancelled') {
+        eventSource.close();
+        setProcessingActive(false);
+        loadPhotos().then(() => {
+            initializeYearControls();
+        });
+        updateStatistics();
+        showNotification(`⏹️ ${data.data.message}`, 'info');
+    } else if (data.event_type === 'processing_error') {
+        eventSource.close();
+        setProcessingActive(false);
+        showNotification(`❌ Error: ${data.data.message}`, 'error');
+    } else if (data.event_type === 'photos_available') {
+        refreshPhotosDuringProcessing();
+    } else if (data.event_type === 'warmup_progress') {
+        showNotification(`🔥 ${data.data.message}`, 'info');
+    } else if (data.event_type === 'warmup_complete') {
+        eventSource.close();
+        showNotification(`✅ ${data.data.message}`, 'success');
+    } else {
+        // Handle other events like progress updates
+        // Optional: Show progress in notification or console
+        // console.log('Processing progress:', data.data.message);
+    }
+}
+
+/**
+ * Restores the Process button state after a page reload while a run is active.
+ * @async
+ * @returns {Promise<void>}
+ */
+async function resumeProcessingState() {
+    try {
+        const response = await fetch(API.PROCESSING_STATUS);
+        const status = await response.json();
+        if (status.status !== 'running') return;
+
+        setProcessingActive(true);
+        openProcessingEvents(() => {}, () => setProcessingActive(false));
+    } catch (error) {
+        console.error('Failed to load processing status:', error);
+    }
+}
+
+/**
+ * Keeps a connection to the event stream for the whole session, so that
+ * folders added or removed in another tab (or through the API) show up here.
+ * The browser reconnects the EventSource by itself after an error.
+ */
+function watchFolderChanges() {
+    const eventSource = new EventSource(API.EVENTS);
+    eventSource.onmessage = (event) => {
+        const data = JSON.parse(event.data);
+        if (data.event_type !== 'folders_changed') return;
+        loadFolders();
+        loadPhotos().then(() => {
+            initializeYearControls();
+        });
+        updateStatistics();
+    };
+}
+
 /**
  * Initiates the photo processing workflow for the selected folder.
- * Connects to SSE for progress updates and reloads data upon completion.
+ * Listens for progress updates and reloads data upon completion.
  * @async
  * @returns {Promise<void>}
  */
 async function processFolder() {
+    if (window.processingActive) {
+        await cancelProcessing();
+        return;
+    }
+
     const folderInput = document.getElementById('exp-folder-input');
     const folderPath = folderInput ? folderInput.value.trim() : '';
 
@@ -1203,11 +2238,9 @@ async function processFolder() {
 
         showNotification(`✅ Folder set: ${folderPath}`, 'success');
 
-        // Step 2: Start listening for SSE events
-        const eventSource = new EventSource(API.EVENTS);
-
-        eventSource.onopen = async function () {
-            showNotification('✅ SSE connection established', 'success');
+        // Step 2: Start listening for processing events
+        openProcessingEvents(async (eventSource) => {
+            showNotification('✅ Live updates connected', 'success');
             // Step 3: Reprocess (clears DB and processes folders)
             const processResponse = await fetch(API.REPROCESS, {
                 method: 'POST',
@@ -1219,35 +2252,17 @@ async function processFolder() {
             const processResult = await processResponse.json();
 
             if (processResult.status !== 'started') {
-                throw new Error(processResult.message || 'Error starting processing');
-            }
-
-            showNotification('✅ Processing initiated: ' + folderPath, 'success');
-        };
-
-        eventSource.onmessage = function (event) {
-            const data = JSON.parse(event.data);
-            if (data.event_type === 'processing_complete') {
-                eventSource.close();
-                loadPhotos().then(() => {
-                    initializeYearControls(); // Re-initialize year controls with new data
-                }); // Refresh map
-                updateStatistics();
-                showNotification(`🎉 Processing completed! Found ${data.data.processed || 0} photos`, 'success');
-            } else if (data.event_type === 'processing_error') {
                 eventSource.close();
-                showNotification(`❌ Error: ${data.data.message}`, 'error');
-            } else {
-                // Handle other events like progress updates
-                // Optional: Show progress in notification or console
-                // console.log('Processing progress:', data.data.message);
+                showNotification('❌ ' + (processResult.message || 'Error starting processing'), 'error');
+                return;
             }
-        };
 
-        eventSource.onerror = function () {
-            eventSource.close();
+            setProcessingActive(true);
+            showNotification('✅ Processing initiated: ' + folderPath, 'success');
+        }, () => {
+            setProcessingActive(false);
             showNotification('❌ Error connecting to the server for updates.', 'error');
-        };
+        });
 
     } catch (error) {
         // Handle errors
@@ -1653,6 +2668,9 @@ document.addEventListener('keydown', function (e) {
 document.addEventListener('DOMContentLoaded', () => {
     // 1. Initialize Tooltips
     initFolderTooltip();
+    initPlaceSearch();
+    resumeProcessingState();
+    watchFolderChanges();
 
     // 2. Initialize Draggable Panel
     const panel = document.getElementById('experimental-panel');
@@ -1708,21 +2726,25 @@ document.addEventListener('DOMContentLoaded', () => {
         });
     }
 
+    // Trip overlay toggle: recolour clusters by trip
+    const expTripToggle = document.getElementById('exp-trip-toggle');
+    if (expTripToggle) {
+        expTripToggle.addEventListener('change', () => {
+            markerClusterGroup.refreshClusters();
+        });
+    }
+
     // Browser Autostart Toggle
     const expAutostartToggle = document.getElementById('exp-browser-autostart-toggle');
     if (expAutostartToggle) {
         expAutostartToggle.addEventListener('change', async (e) => {
             const startBrowser = e.target.checked;
             try {
-                const getResponse = await fetch(API.SETTINGS);
-                const currentSettings = await getResponse.json();
-                const newSettings = { ...currentSettings, start_browser: startBrowser };
-
-  const response = await fetch(API.UPDATE_SETTINGS, {
-    method: 'POST',
-    headers: { 'Content-Type': 'application/json' },
-    body: JSON.stringify(newSettings)
-  });
+                const response = await fetch(API.UPDATE_SETTINGS, {
+                    method: 'POST',
+                    headers: { 'Content-Type': 'application/json' },
+                    body: JSON.stringify({ start_browser: startBrowser })
+                });
 
                 if (response.ok) {
                     showNotification('✅ Settings saved', 'success');
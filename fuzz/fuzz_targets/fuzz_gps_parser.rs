@@ -0,0 +1,25 @@
+//! Fuzzes the fallback GPS parser for files with broken IFD chains.
+//!
+//! Workflow (needs nightly and `cargo install cargo-fuzz`):
+//!   make fuzz-seed                 # copy samples from tools/exif_parser_test/JPG for checks
+//!   make fuzz                      # run every target for FUZZ_TIME seconds
+//!   cd fuzz && cargo +nightly fuzz run fuzz_gps_parser corpus/fuzz_gps_parser
+//!
+//! The parser walks JPEG segments and TIFF offsets by hand; AddressSanitizer
+//! (enabled by cargo-fuzz) catches any out-of-bounds access.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use photomap_processor::exif_parser::gps_parser::extract_gps_from_malformed_exif;
+
+fuzz_target!(|data: &[u8]| {
+    let Some(path) = photomap_fuzz::write_input(data) else {
+        return;
+    };
+
+    if let Some(fix) = extract_gps_from_malformed_exif(&path) {
+        assert!(fix.lat.is_finite() && fix.lat.abs() <= 90.0);
+        assert!(fix.lng.is_finite() && fix.lng.abs() <= 180.0);
+    }
+});
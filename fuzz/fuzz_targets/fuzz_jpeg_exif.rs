@@ -0,0 +1,26 @@
+//! Fuzzes `extract_metadata_from_jpeg` with arbitrary file contents.
+//!
+//! Workflow (needs nightly and `cargo install cargo-fuzz`):
+//!   make fuzz-seed                 # copy samples from tools/exif_parser_test/JPG for checks
+//!   make fuzz                      # run every target for FUZZ_TIME seconds
+//!   cd fuzz && cargo +nightly fuzz run fuzz_jpeg_exif corpus/fuzz_jpeg_exif
+//!
+//! cargo-fuzz builds with AddressSanitizer by default, so any read outside the
+//! input buffer aborts the run. Crashes are saved to `fuzz/artifacts/` and can
+//! be replayed with `cargo +nightly fuzz run fuzz_jpeg_exif <artifact>`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use photomap_processor::exif_parser::{extract_metadata_from_jpeg, ExtractedMetadata};
+
+fuzz_target!(|data: &[u8]| {
+    let Some(path) = photomap_fuzz::write_input(data) else {
+        return;
+    };
+
+    if let Ok(ExtractedMetadata { lat, lng, .. }) = extract_metadata_from_jpeg(&path) {
+        assert!(lat.is_finite() && lat.abs() <= 90.0);
+        assert!(lng.is_finite() && lng.abs() <= 180.0);
+    }
+});
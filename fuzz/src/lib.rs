@@ -0,0 +1,13 @@
+//! Shared code for the fuzz targets.
+//!
+//! The parsers come from the `photomap_processor` library, so the fuzzers
+//! always run the shipped code.
+
+use std::path::PathBuf;
+
+/// Writes fuzz input to a per-process temp file for the path-based parsers
+pub fn write_input(data: &[u8]) -> Option<PathBuf> {
+    let path = std::env::temp_dir().join(format!("photomap_fuzz_{}.jpg", std::process::id()));
+    std::fs::write(&path, data).ok()?;
+    Some(path)
+}
@@ -0,0 +1,212 @@
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Command line options; each one overrides the matching setting for this run
+#[derive(Debug, Parser)]
+#[command(
+    name = "photomap_processor",
+    version,
+    about = "Parallel photo processing, EXIF metadata extraction and interactive map server."
+)]
+pub struct CliArgs {
+    /// Port for the HTTP server [default: the `port` setting, 3001]
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// Photo folder to use instead of the saved ones for this run; repeat for
+    /// several folders
+    #[arg(short, long, value_name = "PATH")]
+    pub folder: Vec<PathBuf>,
+
+    /// Store the `--folder` list in the settings file
+    #[arg(long, requires = "folder")]
+    pub save: bool,
+
+    /// Ignore the caches and process every folder again
+    #[arg(long)]
+    pub rescan: bool,
+
+    /// Process the folders, write the photos to a file and exit without
+    /// starting the server (e.g. `geojson:photos.geojson`)
+    #[arg(long, value_name = "FORMAT:FILE")]
+    pub export: Option<ExportTarget>,
+
+    /// Settings file to use instead of photomap.ini in the data directory
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Do not open the browser on startup
+    #[arg(long)]
+    pub no_browser: bool,
+
+    /// Log verbosity (overrides RUST_LOG)
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+
+    /// Directory for settings and caches instead of the platform data directory
+    #[arg(long, value_name = "PATH")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Where `--export` writes the library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    Geojson(PathBuf),
+}
+
+impl FromStr for ExportTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        // Split at the first colon only, so Windows paths such as C:\out keep theirs
+        let Some((format, file)) = value.split_once(':') else {
+            return Err("expected FORMAT:FILE, e.g. geojson:photos.geojson".to_string());
+        };
+        if file.is_empty() {
+            return Err("missing the file to export to".to_string());
+        }
+        match format.to_lowercase().as_str() {
+            "geojson" => Ok(ExportTarget::Geojson(PathBuf::from(file))),
+            other => Err(format!(
+                "unsupported export format `{other}`, expected geojson"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+/// Sets up the log pipeline; `--log-level` wins over RUST_LOG, which defaults to info
+pub fn init_logging(level: Option<LogLevel>) {
+    use tracing_subscriber::EnvFilter;
+
+    let filter = match level {
+        Some(level) => EnvFilter::new(level.as_str()),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let _ = tracing_subscriber::fmt().with_env_filter(filter).try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_all_flags() {
+        let args = CliArgs::try_parse_from([
+            "photomap_processor",
+            "--folder",
+            "/photos",
+            "--no-browser",
+            "--log-level",
+            "debug",
+            "--cache-dir",
+            "/tmp/photomap",
+        ])
+        .unwrap();
+
+        assert_eq!(args.port, None);
+        assert_eq!(args.folder, vec![PathBuf::from("/photos")]);
+        assert!(args.no_browser);
+        assert_eq!(args.log_level, Some(LogLevel::Debug));
+        assert_eq!(args.cache_dir, Some(PathBuf::from("/tmp/photomap")));
+    }
+
+    #[test]
+    fn parses_headless_flags() {
+        let args = CliArgs::try_parse_from([
+            "photomap_processor",
+            "--folder",
+            "/photos/2023",
+            "-f",
+            "/photos/2024",
+            "--save",
+            "--rescan",
+            "--export",
+            "geojson:C:\\out\\photos.geojson",
+            "--config",
+            "/etc/photomap.ini",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            args.folder,
+            vec![PathBuf::from("/photos/2023"), PathBuf::from("/photos/2024")]
+        );
+        assert!(args.save);
+        assert!(args.rescan);
+        assert_eq!(
+            args.export,
+            Some(ExportTarget::Geojson(PathBuf::from(
+                "C:\\out\\photos.geojson"
+            )))
+        );
+        assert_eq!(args.config, Some(PathBuf::from("/etc/photomap.ini")));
+    }
+
+    #[test]
+    fn no_arguments_keep_the_saved_settings() {
+        let args = CliArgs::try_parse_from(["photomap_processor"]).unwrap();
+
+        assert!(args.folder.is_empty());
+        assert!(!args.save && !args.rescan && !args.no_browser);
+        assert_eq!(args.export, None);
+        assert_eq!(args.config, None);
+    }
+
+    #[test]
+    fn rejects_bad_export_targets_and_save_without_folders() {
+        for export in ["kml:photos.kml", "photos.geojson", "geojson:"] {
+            assert!(
+                CliArgs::try_parse_from(["photomap_processor", "--export", export]).is_err(),
+                "{export}"
+            );
+        }
+        assert_eq!(
+            "GeoJSON:out.json".parse::<ExportTarget>(),
+            Ok(ExportTarget::Geojson(PathBuf::from("out.json")))
+        );
+        assert!(CliArgs::try_parse_from(["photomap_processor", "--save"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_log_level() {
+        assert!(CliArgs::try_parse_from(["photomap_processor", "--log-level", "loud"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn port_flag_sets_listening_port() {
+        let args = CliArgs::try_parse_from(["photomap_processor", "--port", "8080"]).unwrap();
+        assert_eq!(args.port, Some(8080));
+
+        // Port 0 lets the OS pick a free one, so the test never collides with a running app
+        let args = CliArgs::try_parse_from(["photomap_processor", "--port", "0"]).unwrap();
+        assert_eq!(args.port, Some(0));
+        let listener = crate::server::bind(args.port.unwrap(), false)
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_ne!(addr.port(), 0);
+    }
+}
@@ -0,0 +1,127 @@
+//! Tuning knobs for photo processing and image generation
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::DEFAULT_IMAGE_QUALITY;
+
+/// Concurrency and quality settings, stored in the `processing` part of `Settings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(default)]
+pub struct ProcessingConfig {
+    /// Worker threads for processing runs; None uses the global pool (one per core)
+    pub rayon_threads: Option<usize>,
+    /// JPEG quality of thumbnail and gallery images
+    pub thumbnail_jpeg_quality: u8,
+    /// JPEG quality of map markers
+    pub marker_jpeg_quality: u8,
+    /// Decode JPEGs with turbojpeg at a reduced scale instead of the image crate
+    pub use_turbojpeg_fast_path: bool,
+    /// Tag photos that have no tags yet with their EXIF/XMP keywords
+    pub import_keywords: bool,
+    /// Keep photos whose GPS position looks like a firmware bug, e.g. (0, 0)
+    pub include_suspicious_gps: bool,
+}
+
+impl Default for ProcessingConfig {
+    fn default() -> Self {
+        Self {
+            rayon_threads: None,
+            thumbnail_jpeg_quality: DEFAULT_IMAGE_QUALITY,
+            marker_jpeg_quality: DEFAULT_IMAGE_QUALITY,
+            use_turbojpeg_fast_path: true,
+            import_keywords: false,
+            include_suspicious_gps: false,
+        }
+    }
+}
+
+impl ProcessingConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (name, quality) in [
+            ("thumbnail_quality", self.thumbnail_jpeg_quality),
+            ("marker_quality", self.marker_jpeg_quality),
+        ] {
+            if !(1..=100).contains(&quality) {
+                bail!("{} must be between 1 and 100, got {}", name, quality);
+            }
+        }
+        if self.rayon_threads == Some(0) {
+            bail!("rayon_threads must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// Dedicated pool for a processing run when the configured thread count differs
+    /// from the global pool, which stays at one thread per core for everything else
+    pub fn thread_pool(&self) -> Option<rayon::ThreadPool> {
+        let threads = self.rayon_threads?;
+        if threads == rayon::current_num_threads() {
+            return None;
+        }
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| eprintln!("⚠️ Failed to create processing thread pool: {}", e))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProcessingConfig;
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = ProcessingConfig::default();
+        assert_eq!(config.rayon_threads, None);
+        assert_eq!(
+            (config.thumbnail_jpeg_quality, config.marker_jpeg_quality),
+            (85, 85)
+        );
+        assert!(config.use_turbojpeg_fast_path);
+        assert!(!config.import_keywords);
+        assert!(!config.include_suspicious_gps);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let invalid = [
+            ProcessingConfig {
+                thumbnail_jpeg_quality: 0,
+                ..Default::default()
+            },
+            ProcessingConfig {
+                marker_jpeg_quality: 101,
+                ..Default::default()
+            },
+            ProcessingConfig {
+                rayon_threads: Some(0),
+                ..Default::default()
+            },
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err(), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn thread_pool_is_only_built_for_a_different_size() {
+        assert!(ProcessingConfig::default().thread_pool().is_none());
+        let current = rayon::current_num_threads();
+        let same = ProcessingConfig {
+            rayon_threads: Some(current),
+            ..Default::default()
+        };
+        assert!(same.thread_pool().is_none());
+        let other = ProcessingConfig {
+            rayon_threads: Some(current + 1),
+            ..Default::default()
+        };
+        assert_eq!(
+            other.thread_pool().unwrap().current_num_threads(),
+            current + 1
+        );
+    }
+}
@@ -4,16 +4,66 @@ pub const MARKER_SIZE: u32 = 40;
 pub const THUMBNAIL_SIZE: u32 = 120; // For map markers and spiderweb (2x for HiDPI)
 pub const GALLERY_SIZE: u32 = 240; // For gallery modal
 pub const POPUP_SIZE: u32 = 1400;
+pub const TINY_SIZE: u32 = 32; // Source of blurhash placeholders
+
+pub const DEFAULT_IMAGE_QUALITY: u8 = 85;
+
+/// Port of the HTTP server unless the settings or `--port` say otherwise
+pub const DEFAULT_PORT: u16 = 3001;
+
+/// Default memory budget for generated images kept between requests, in MB
+pub const DEFAULT_IMAGE_CACHE_MB: u32 = 256;
+
+/// Default template of location names; `{admin1}` adds the state or region
+pub const DEFAULT_LOCATION_FORMAT: &str = "{name}, {country}";
+
+/// Photos farther than this from every known city get no location name
+pub const DEFAULT_MAX_GEOCODE_DISTANCE_KM: f64 = 100.0;
+
+/// Image requests per second a client may send before getting 429
+pub const DEFAULT_RATE_LIMIT_RPS: u32 = 20;
+/// Image requests a client may send at once, e.g. while the map loads markers
+pub const DEFAULT_RATE_LIMIT_BURST: u32 = 50;
+
+/// Photos one ZIP export may hold; the archive is built in memory
+pub const DEFAULT_EXPORT_MAX_PHOTOS: usize = 500;
+/// Largest `export_max_photos` accepted from the settings API
+pub const MAX_EXPORT_MAX_PHOTOS: usize = 100_000;
+/// Largest `image_cache_mb` accepted from the settings API
+pub const MAX_IMAGE_CACHE_MB: u32 = 16 * 1024;
+/// Size the disk thumbnail cache is pruned back to, oldest files first, in MB
+pub const DISK_CACHE_MAX_MB: u64 = 2048;
+
+/// Photos inserted into the database at a time while a folder is processed
+pub const INSERT_BATCH_SIZE: usize = 500;
+
+/// Most markers returned in one sprite sheet by `/api/markers/batch`
+pub const MAX_SPRITE_TILES: usize = 1000;
+
+/// Width of a server-side cluster cell in screen pixels (map tiles are 256 px)
+pub const CLUSTER_CELL_PX: u32 = 64;
+/// Cells with at most this many photos are returned as individual photos
+pub const CLUSTER_MAX_POINTS: usize = 3;
+/// Deepest zoom level accepted for clustering
+pub const MAX_CLUSTER_ZOOM: u8 = 22;
+
+/// GPS fixes with a larger dilution of precision are rough
+pub const GPS_DOP_ROUGH: f64 = 5.0;
+/// GPS fixes with a larger dilution of precision are poor
+pub const GPS_DOP_POOR: f64 = 20.0;
+
+/// A longer pause between consecutive photos starts a new trip
+pub const DEFAULT_TRIP_MAX_GAP_DAYS: f64 = 3.0;
+/// A longer jump between consecutive photos starts a new trip
+pub const DEFAULT_TRIP_MAX_JUMP_KM: f64 = 300.0;
+
+/// Per-folder file with gitignore-style patterns excluded from processing
+pub const IGNORE_FILE_NAME: &str = ".photomapignore";
 
 /// Checks if a file extension is a supported image format (case-insensitive)
 pub fn is_supported_image(ext: &str) -> bool {
     matches!(
         ext.to_lowercase().as_str(),
-        "jpg" | "jpeg" | "heic" | "heif" | "avif"
+        "jpg" | "jpeg" | "heic" | "heif" | "avif" | "png" | "tif" | "tiff" | "webp"
     )
 }
-
-/// Checks if a file extension is a HEIC/HEIF format (case-insensitive)
-pub fn is_heic_format(ext: &str) -> bool {
-    matches!(ext.to_lowercase().as_str(), "heic" | "heif" | "avif")
-}
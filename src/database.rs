@@ -1,45 +1,716 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+use crate::constants::{CLUSTER_CELL_PX, CLUSTER_MAX_POINTS, MAX_CLUSTER_ZOOM};
+use crate::geocoding::{self, Place};
+use crate::processing::{folder_file_stamps, WalkOptions};
+use crate::trips::{self, Trip};
+use crate::types::{BoundingBox, GpsCoordinate};
+
+mod albums;
+mod duplicates;
+mod places;
+mod search;
+mod sqlite;
+mod stats;
+mod tags;
+
+pub use crate::exif_parser::GpsQuality;
+pub use albums::Album;
+pub use duplicates::{verify as verify_duplicates, DuplicateReason};
+pub use places::PlaceGroup;
+use sqlite::SqliteStore;
+pub use stats::{
+    CountryCount, DateRange, DayCount, LibraryStats, LocationCount, YearCount, YearSummary,
+};
+use tags::TagStore;
+
+/// How long `get_year_summary` reuses its counts
+const YEAR_SUMMARY_TTL: Duration = Duration::from_secs(60);
+
+/// File name of the SQLite database in the app data directory
+const SQLITE_FILE: &str = "library.sqlite";
+
+/// Where the photo database is kept while the app runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// Everything in RAM, persisted as per-folder bincode caches
+    #[default]
+    Memory,
+    /// Rows in a SQLite file, updated in place
+    Sqlite,
+}
+
+impl StorageKind {
+    /// Parses a storage name as stored in settings (case-insensitive)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "memory" => Some(StorageKind::Memory),
+            "sqlite" => Some(StorageKind::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// Returns the name used in settings
+    pub fn name(&self) -> &'static str {
+        match self {
+            StorageKind::Memory => "memory",
+            StorageKind::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// Stable photo identifier: hex-encoded XXH3 of the normalized relative path
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct PhotoId(String);
+
+impl PhotoId {
+    /// Computes the id of a relative path; either path separator gives the same id
+    pub fn from_relative_path(relative_path: &str) -> Self {
+        PhotoId(format!(
+            "{:016x}",
+            xxh3_64(normalize_relative_path(relative_path).as_bytes())
+        ))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&PhotoMetadata> for PhotoId {
+    fn from(photo: &PhotoMetadata) -> Self {
+        PhotoId::from_relative_path(&photo.relative_path)
+    }
+}
+
+impl std::fmt::Display for PhotoId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Where a photo's GPS position and timestamp were read from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub enum ExifSource {
+    #[default]
+    Exif,
+    XmpSidecar,
+    GoogleTakeoutJson,
+    /// Position set by hand through the API
+    Manual,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PhotoMetadata {
+    pub id: PhotoId,
     pub filename: String,
     pub relative_path: String,
     pub datetime: String,
-    pub lat: f64,
-    pub lng: f64,
+    pub coords: GpsCoordinate,
     pub file_path: String,
     pub is_heic: bool,
+    pub is_motion_photo: bool,
+    pub source: ExifSource,
+    /// Speed of the GPS receiver in km/h, recorded by some action cameras
+    pub speed_kmh: Option<f64>,
+    /// GPS dilution of precision; above 5 the position is rough
+    pub gps_dop: Option<f64>,
+    /// Compass heading of the camera in degrees (GPSImgDirection), for view cones
+    pub direction: Option<f64>,
+    /// BlurHash placeholder shown while the photo loads
+    pub blurhash: Option<String>,
+    /// Nearest city, looked up once when the photo is processed
+    pub location: Option<String>,
+    /// City, region and country `location` was formatted from
+    pub place: Option<Place>,
+    /// Mean RGB colour of the photo, used to tint cluster icons
+    pub dominant_color: Option<[u8; 3]>,
+    /// Size of the original in bytes when it was processed; 0 if unknown
+    pub file_size: u64,
+    /// How far the GPS position can be trusted, judged when the photo is processed
+    pub gps_quality: GpsQuality,
+}
+
+/// Album of a photo that sits directly in a watched folder
+pub const ROOT_ALBUM: &str = "/";
+
+impl PhotoMetadata {
+    /// First folder of `relative_path`, or `ROOT_ALBUM` for photos in the watched folder itself
+    pub fn album(&self) -> &str {
+        self.relative_path
+            .split_once('/')
+            .map_or(ROOT_ALBUM, |(album, _)| album)
+    }
+
+    /// Photo under `/photos` with only its path, date and position set, for tests
+    /// and benchmarks; they change the other fields with struct update syntax.
+    /// Not `cfg(test)` because benchmarks build the library without it.
+    #[doc(hidden)]
+    pub fn sample(relative_path: &str, datetime: &str, lat: f64, lng: f64) -> Self {
+        PhotoMetadata {
+            id: PhotoId::from_relative_path(relative_path),
+            filename: relative_path
+                .rsplit('/')
+                .next()
+                .unwrap_or(relative_path)
+                .to_string(),
+            relative_path: relative_path.to_string(),
+            datetime: datetime.to_string(),
+            coords: GpsCoordinate::new(lat, lng).expect("sample position is valid"),
+            file_path: format!("/photos/{relative_path}"),
+            is_heic: false,
+            is_motion_photo: false,
+            source: ExifSource::Exif,
+            speed_kmh: None,
+            gps_dop: None,
+            direction: None,
+            blurhash: None,
+            location: None,
+            place: None,
+            dominant_color: None,
+            file_size: 0,
+            gps_quality: GpsQuality::High,
+        }
+    }
+}
+
+/// `datetime` of photos whose capture time could not be read
+pub const UNKNOWN_DATE: &str = "Unknown Date";
+
+/// Order of the photos of `/api/photos`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortOrder {
+    /// Newest first
+    #[default]
+    DateDesc,
+    DateAsc,
+    /// By filename, ignoring case
+    Name,
+    /// Nearest to the point first
+    Distance {
+        lat: f64,
+        lng: f64,
+    },
+}
+
+impl SortOrder {
+    /// Photos without a capture date come last in both date orders; ties are
+    /// broken by relative path so the order is stable across backends.
+    pub fn compare(&self, a: &PhotoMetadata, b: &PhotoMetadata) -> std::cmp::Ordering {
+        let undated = |photo: &PhotoMetadata| photo.datetime == UNKNOWN_DATE;
+        let order = match *self {
+            SortOrder::DateDesc => undated(a)
+                .cmp(&undated(b))
+                .then_with(|| b.datetime.cmp(&a.datetime)),
+            SortOrder::DateAsc => undated(a)
+                .cmp(&undated(b))
+                .then_with(|| a.datetime.cmp(&b.datetime)),
+            SortOrder::Name => a.filename.to_lowercase().cmp(&b.filename.to_lowercase()),
+            SortOrder::Distance { lat, lng } => {
+                let distance = |photo: &PhotoMetadata| {
+                    geocoding::haversine_km(lat, lng, photo.coords.lat(), photo.coords.lng())
+                };
+                distance(a).total_cmp(&distance(b))
+            }
+        };
+        order.then_with(|| a.relative_path.cmp(&b.relative_path))
+    }
+}
+
+/// Photos walked by `for_each_photo_chunk`; unset fields match every photo
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhotoFilter<'a> {
+    /// See `PhotoMetadata::album`
+    pub album: Option<&'a str>,
+    /// Only photos carrying all of these tags
+    pub tags: &'a [String],
+    /// Inclusive "YYYY-MM-DD" bounds of the capture date; undated photos
+    /// never match a bound
+    pub start_date: Option<&'a str>,
+    pub end_date: Option<&'a str>,
 }
 
-#[derive(Serialize, Debug, Clone, Deserialize)]
+impl PhotoFilter<'_> {
+    fn has_dates(&self) -> bool {
+        self.start_date.is_some() || self.end_date.is_some()
+    }
+
+    fn matches_date(&self, photo: &PhotoMetadata) -> bool {
+        if !self.has_dates() {
+            return true;
+        }
+        let Some(date) = stats::date_of(&photo.datetime) else {
+            return false;
+        };
+        self.start_date.is_none_or(|start| date >= start)
+            && self.end_date.is_none_or(|end| date <= end)
+    }
+}
+
+/// Whether `value` is a "YYYY-MM-DD" date as accepted by `PhotoFilter`
+pub fn is_valid_date(value: &str) -> bool {
+    stats::date_of(value) == Some(value)
+}
+
+#[derive(Serialize, Debug, Clone, Deserialize, utoipa::ToSchema)]
 pub struct ImageMetadata {
+    #[schema(value_type = String, example = "3f2a9c0d1b7e4a56")]
+    pub id: PhotoId,
+    #[schema(example = "IMG_0042.jpg")]
     pub filename: String,
+    #[schema(example = "2024/IMG_0042.jpg")]
     pub relative_path: String,
+    /// See `PhotoMetadata::album`
+    #[schema(example = "2024")]
+    pub album: String,
     pub url: String,
     pub fallback_url: String,
     pub marker_icon: String,
+    #[schema(example = 48.8566)]
     pub lat: f64,
+    #[schema(example = 2.3522)]
     pub lng: f64,
+    #[schema(example = "2024-06-01 12:00:00")]
     pub datetime: String,
     pub file_path: String,
     pub is_heic: bool,
+    pub is_motion_photo: bool,
+    pub source: ExifSource,
+    pub speed_kmh: Option<f64>,
+    pub gps_dop: Option<f64>,
+    #[serde(default)]
+    pub gps_quality: GpsQuality,
+    pub direction: Option<f64>,
+    pub blurhash: Option<String>,
+    pub location: Option<String>,
+    pub city: Option<String>,
+    pub admin1: Option<String>,
+    pub country: Option<String>,
+    /// `#rrggbb`
+    pub dominant_color: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A photo found around a position, see `Database::photos_near`
+#[derive(Debug, Clone)]
+pub struct NearbyPhoto {
+    pub photo: PhotoMetadata,
+    /// Great-circle distance from the queried position
+    pub distance_m: f64,
+}
+
+/// A processed file that has no usable location
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct NoGpsEntry {
+    pub path: String,
+    /// Same form as `PhotoMetadata::relative_path`, used to geotag the file
+    pub relative_path: String,
+    /// Error from the extractor chain, e.g. no GPS tags vs. unparseable GPS tags
+    pub reason: String,
+}
+
+/// One entry of a clustered map view
+#[derive(Debug, Clone)]
+pub enum ClusterEntry {
+    /// Several photos in one grid cell, placed at their mean position
+    Cluster {
+        lat: f64,
+        lng: f64,
+        count: usize,
+    },
+    Photo(Box<PhotoMetadata>),
+}
+
+/// Bumped whenever `PhotoMetadata` changes shape so stale caches are rebuilt
+const CACHE_VERSION: u32 = 17;
+
+/// Last version written as plain bincode with full `PhotoMetadata` records; still read
+const UNCOMPRESSED_CACHE_VERSION: u32 = 8;
+
+/// Last gzipped version without stored locations; its photos are geocoded on load
+const UNGEOCODED_CACHE_VERSION: u32 = 9;
+
+/// Limit of a decoded cache file, guarding against corrupted lengths
+const CACHE_SIZE_LIMIT: u64 = 50 * 1024 * 1024;
+
+/// Cheap fingerprint of the photos in a folder, to notice files added or
+/// deleted while the app was closed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FolderSignature {
+    pub file_count: usize,
+    /// Latest modification time in milliseconds since the Unix epoch
+    pub max_mtime_ms: u64,
+    /// XXH3 of the sorted relative paths, catches a deletion balanced by an addition
+    pub paths_hash: u64,
+}
+
+impl FolderSignature {
+    pub fn of(files: &FileStamps) -> Self {
+        let mut paths: Vec<&String> = files.keys().collect();
+        paths.sort_unstable();
+        let mut hasher = Xxh3::new();
+        for relative_path in paths {
+            hasher.update(relative_path.as_bytes());
+            hasher.update(&[0]);
+        }
+        FolderSignature {
+            file_count: files.len(),
+            max_mtime_ms: files.values().copied().max().unwrap_or(0),
+            paths_hash: hasher.digest(),
+        }
+    }
+}
+
+/// Modification time in milliseconds of each supported file of a folder, by relative path.
+/// Lets a folder whose signature changed keep the cached photos of untouched files.
+pub type FileStamps = HashMap<String, u64>;
+
+/// `PhotoMetadata` as stored in a folder cache. The id and the absolute path
+/// are rebuilt from the folder and the relative path on load.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CachedPhoto {
+    pub filename: String,
+    pub relative_path: String,
+    pub datetime: String,
+    pub coords: GpsCoordinate,
+    /// Only set when the file is not at `source_path/relative_path`
+    pub file_path: Option<String>,
+    pub is_heic: bool,
+    pub is_motion_photo: bool,
+    pub source: ExifSource,
+    pub speed_kmh: Option<f64>,
+    pub gps_dop: Option<f64>,
+    pub direction: Option<f64>,
+    pub blurhash: Option<String>,
     pub location: Option<String>,
+    pub place: Option<Place>,
+    pub dominant_color: Option<[u8; 3]>,
+    pub file_size: u64,
+    pub gps_quality: GpsQuality,
+}
+
+impl CachedPhoto {
+    fn new(photo: &PhotoMetadata, folder: &str) -> Self {
+        let file_path = (Path::new(folder).join(&photo.relative_path)
+            != Path::new(&photo.file_path))
+        .then(|| photo.file_path.clone());
+        CachedPhoto {
+            filename: photo.filename.clone(),
+            relative_path: photo.relative_path.clone(),
+            datetime: photo.datetime.clone(),
+            coords: photo.coords,
+            file_path,
+            is_heic: photo.is_heic,
+            is_motion_photo: photo.is_motion_photo,
+            source: photo.source,
+            speed_kmh: photo.speed_kmh,
+            gps_dop: photo.gps_dop,
+            direction: photo.direction,
+            blurhash: photo.blurhash.clone(),
+            location: photo.location.clone(),
+            place: photo.place.clone(),
+            dominant_color: photo.dominant_color,
+            file_size: photo.file_size,
+            gps_quality: photo.gps_quality,
+        }
+    }
+
+    fn into_photo(self, folder: &str) -> PhotoMetadata {
+        let file_path = self.file_path.unwrap_or_else(|| {
+            Path::new(folder)
+                .join(&self.relative_path)
+                .to_string_lossy()
+                .to_string()
+        });
+        normalized_photo(&PhotoMetadata {
+            id: PhotoId::default(),
+            filename: self.filename,
+            relative_path: self.relative_path,
+            datetime: self.datetime,
+            coords: self.coords,
+            file_path,
+            is_heic: self.is_heic,
+            is_motion_photo: self.is_motion_photo,
+            source: self.source,
+            speed_kmh: self.speed_kmh,
+            gps_dop: self.gps_dop,
+            direction: self.direction,
+            blurhash: self.blurhash,
+            location: self.location,
+            place: self.place,
+            dominant_color: self.dominant_color,
+            file_size: self.file_size,
+            gps_quality: self.gps_quality,
+        })
+    }
 }
 
+/// Cache of one photo folder, stored gzipped in its own file so that adding
+/// or removing a folder does not invalidate the others
 #[derive(Serialize, Deserialize, Clone)]
-pub struct CachedDatabase {
+pub struct CachedFolder {
     pub version: u32,
-    pub source_paths: Vec<String>,
-    pub photos: Vec<PhotoMetadata>,
+    pub source_path: String,
+    pub signature: FolderSignature,
+    pub file_stamps: FileStamps,
+    pub photos: Vec<CachedPhoto>,
+    pub no_gps: Vec<NoGpsEntry>,
+}
+
+impl CachedFolder {
+    /// Photos with their ids and absolute paths restored
+    pub fn into_photos(self) -> (Vec<PhotoMetadata>, Vec<NoGpsEntry>) {
+        let folder = self.source_path;
+        let photos = self
+            .photos
+            .into_iter()
+            .map(|photo| photo.into_photo(&folder))
+            .collect();
+        (photos, self.no_gps)
+    }
+}
+
+/// `PhotoMetadata` as it was in `UNCOMPRESSED_CACHE_VERSION`
+#[derive(Serialize, Deserialize)]
+struct UncompressedPhoto {
+    id: PhotoId,
+    filename: String,
+    relative_path: String,
+    datetime: String,
+    coords: GpsCoordinate,
+    file_path: String,
+    is_heic: bool,
+    is_motion_photo: bool,
+    source: ExifSource,
+    speed_kmh: Option<f64>,
+    gps_dop: Option<f64>,
+}
+
+/// Looks up the place of a photo from a cache that did not store it
+fn geocode(coords: GpsCoordinate) -> (Option<String>, Option<Place>) {
+    let place = geocoding::get_place(coords.lat(), coords.lng());
+    (place.as_ref().map(geocoding::location_name), place)
+}
+
+/// GPS quality of a photo read from a cache written before it was recorded
+fn graded_gps(coords: GpsCoordinate, gps_dop: Option<f64>) -> GpsQuality {
+    crate::exif_parser::validate_gps_coordinate(coords.lat(), coords.lng())
+        .with_dop(gps_dop)
+        .quality
+}
+
+impl From<UncompressedPhoto> for PhotoMetadata {
+    fn from(photo: UncompressedPhoto) -> Self {
+        let (location, place) = geocode(photo.coords);
+        PhotoMetadata {
+            id: photo.id,
+            filename: photo.filename,
+            relative_path: photo.relative_path,
+            datetime: photo.datetime,
+            coords: photo.coords,
+            file_path: photo.file_path,
+            is_heic: photo.is_heic,
+            is_motion_photo: photo.is_motion_photo,
+            source: photo.source,
+            speed_kmh: photo.speed_kmh,
+            gps_dop: photo.gps_dop,
+            direction: None,
+            blurhash: None,
+            location,
+            place,
+            dominant_color: None,
+            file_size: 0,
+            gps_quality: graded_gps(photo.coords, photo.gps_dop),
+        }
+    }
+}
+
+/// `CachedPhoto` as it was in `UNGEOCODED_CACHE_VERSION`
+#[derive(Serialize, Deserialize)]
+struct UngeocodedPhoto {
+    filename: String,
+    relative_path: String,
+    datetime: String,
+    coords: GpsCoordinate,
+    file_path: Option<String>,
+    is_heic: bool,
+    is_motion_photo: bool,
+    source: ExifSource,
+    speed_kmh: Option<f64>,
+    gps_dop: Option<f64>,
+}
+
+impl From<UngeocodedPhoto> for CachedPhoto {
+    fn from(photo: UngeocodedPhoto) -> Self {
+        let (location, place) = geocode(photo.coords);
+        CachedPhoto {
+            filename: photo.filename,
+            relative_path: photo.relative_path,
+            datetime: photo.datetime,
+            coords: photo.coords,
+            file_path: photo.file_path,
+            is_heic: photo.is_heic,
+            is_motion_photo: photo.is_motion_photo,
+            source: photo.source,
+            speed_kmh: photo.speed_kmh,
+            gps_dop: photo.gps_dop,
+            direction: None,
+            blurhash: None,
+            location,
+            place,
+            dominant_color: None,
+            file_size: 0,
+            gps_quality: graded_gps(photo.coords, photo.gps_dop),
+        }
+    }
+}
+
+/// Gzipped cache layout of `UNGEOCODED_CACHE_VERSION`
+#[derive(Serialize, Deserialize)]
+struct UngeocodedCachedFolder {
+    version: u32,
+    source_path: String,
+    signature: FolderSignature,
+    photos: Vec<UngeocodedPhoto>,
+    no_gps: Vec<NoGpsEntry>,
+}
+
+impl From<UngeocodedCachedFolder> for CachedFolder {
+    fn from(cache: UngeocodedCachedFolder) -> Self {
+        println!(
+            "🌍 Geocoding {} cached photos of {}",
+            cache.photos.len(),
+            cache.source_path
+        );
+        CachedFolder {
+            version: cache.version,
+            source_path: cache.source_path,
+            signature: cache.signature,
+            file_stamps: FileStamps::new(),
+            photos: cache.photos.into_iter().map(CachedPhoto::from).collect(),
+            no_gps: cache.no_gps,
+        }
+    }
+}
+
+/// Uncompressed cache layout of `UNCOMPRESSED_CACHE_VERSION`
+#[derive(Serialize, Deserialize)]
+struct UncompressedCachedFolder {
+    version: u32,
+    source_path: String,
+    signature: FolderSignature,
+    photos: Vec<UncompressedPhoto>,
+    no_gps: Vec<NoGpsEntry>,
+}
+
+impl From<UncompressedCachedFolder> for CachedFolder {
+    fn from(cache: UncompressedCachedFolder) -> Self {
+        CachedFolder {
+            version: cache.version,
+            photos: cache
+                .photos
+                .into_iter()
+                .map(|photo| CachedPhoto::new(&photo.into(), &cache.source_path))
+                .collect(),
+            source_path: cache.source_path,
+            signature: cache.signature,
+            file_stamps: FileStamps::new(),
+            no_gps: cache.no_gps,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct MemoryStore {
+    photos: Arc<RwLock<HashMap<PhotoId, PhotoMetadata>>>,
+    no_gps: Arc<RwLock<Vec<NoGpsEntry>>>,
+    /// Kept in step with `photos`; always locked after it
+    search_index: Arc<RwLock<search::TrigramIndex>>,
+}
+
+impl MemoryStore {
+    fn insert_photos(&self, new_photos: impl IntoIterator<Item = PhotoMetadata>) {
+        let mut photos = self.photos.write().unwrap();
+        let mut index = self.search_index.write().unwrap();
+        for photo in new_photos {
+            index.insert(&photo);
+            photos.insert(photo.id.clone(), photo);
+        }
+    }
+
+    fn retain_photos(&self, keep: impl Fn(&PhotoMetadata) -> bool) {
+        let mut photos = self.photos.write().unwrap();
+        let mut index = self.search_index.write().unwrap();
+        photos.retain(|id, photo| {
+            let kept = keep(photo);
+            if !kept {
+                index.remove(id);
+            }
+            kept
+        });
+        if index.needs_rebuild() {
+            index.rebuild(photos.values());
+        }
+    }
+
+    fn clear_photos(&self) {
+        let mut photos = self.photos.write().unwrap();
+        photos.clear();
+        self.search_index.write().unwrap().clear();
+    }
+
+    fn search(&self, query_lower: &str) -> Vec<PhotoMetadata> {
+        let photos = self.photos.read().unwrap();
+        let index = self.search_index.read().unwrap();
+        match index.candidates(query_lower) {
+            Some(ids) => ids
+                .into_iter()
+                .filter_map(|id| photos.get(id))
+                .filter(|photo| search::matches(photo, query_lower))
+                .cloned()
+                .collect(),
+            None => photos
+                .values()
+                .filter(|photo| search::matches(photo, query_lower))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum DatabaseBackend {
+    Memory(MemoryStore),
+    Sqlite(SqliteStore),
 }
 
 #[derive(Clone)]
 pub struct Database {
-    photos: Arc<RwLock<HashMap<String, PhotoMetadata>>>,
+    backend: DatabaseBackend,
+    // Incremented whenever the database is cleared for a rebuild
+    generation: Arc<AtomicU64>,
+    // Statistics of the current photo set, dropped on every change
+    stats: Arc<Mutex<Option<LibraryStats>>>,
+    // Photos per year with the time they were counted, see `get_year_summary`
+    years: Arc<Mutex<Option<(Instant, Vec<YearSummary>)>>>,
+    // Incremented on every change to the photos, see `revision`
+    revision: Arc<AtomicU64>,
+    // Stored apart from the photos so they survive reprocessing
+    tags: Arc<TagStore>,
 }
 
 fn source_path_cache_key(path: &str) -> String {
@@ -66,6 +737,15 @@ fn normalize_relative_path(path: &str) -> String {
     path.replace('\\', "/")
 }
 
+/// Normalizes paths of a photo and recomputes its id from the normalized path
+fn normalized_photo(photo: &PhotoMetadata) -> PhotoMetadata {
+    let mut photo = photo.clone();
+    photo.relative_path = normalize_relative_path(&photo.relative_path);
+    photo.file_path = normalize_file_path(&photo.file_path);
+    photo.id = PhotoId::from(&photo);
+    photo
+}
+
 fn normalize_file_path(path: &str) -> String {
     #[cfg(windows)]
     {
@@ -80,23 +760,84 @@ fn normalize_file_path(path: &str) -> String {
 
 impl Database {
     pub fn new() -> Result<Self> {
-        Ok(Database {
-            photos: Arc::new(RwLock::new(HashMap::new())),
-        })
+        Ok(Self::with_backend(DatabaseBackend::Memory(
+            MemoryStore::default(),
+        )))
+    }
+
+    /// Opens the database kind chosen in settings; SQLite and the photo tags
+    /// live in the app data directory
+    pub fn open(storage: StorageKind) -> Result<Self> {
+        let app_dir = crate::utils::get_app_data_dir();
+        crate::utils::ensure_directory_exists(&app_dir)?;
+        let db = match storage {
+            StorageKind::Memory => Self::new()?,
+            StorageKind::Sqlite => Self::open_sqlite(&app_dir.join(SQLITE_FILE))?,
+        };
+        Ok(db.with_tags_file(&app_dir.join(tags::TAGS_FILE)))
+    }
+
+    pub fn open_sqlite(path: &Path) -> Result<Self> {
+        Ok(Self::with_backend(DatabaseBackend::Sqlite(
+            SqliteStore::open(path)?,
+        )))
+    }
+
+    fn with_backend(backend: DatabaseBackend) -> Self {
+        Database {
+            backend,
+            generation: Arc::new(AtomicU64::new(0)),
+            stats: Arc::new(Mutex::new(None)),
+            years: Arc::new(Mutex::new(None)),
+            // Starting at the current time keeps validators of an earlier run from matching
+            revision: Arc::new(AtomicU64::new(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64),
+            )),
+            tags: Arc::default(),
+        }
+    }
+
+    /// Keeps the photo tags in `path` instead of only in memory
+    fn with_tags_file(mut self, path: &Path) -> Self {
+        self.tags = Arc::new(TagStore::load(path));
+        self
+    }
+
+    /// Bumps the revision and drops the cached statistics after the photos or
+    /// no-GPS entries changed
+    fn photos_changed(&self) {
+        self.revision.fetch_add(1, Ordering::SeqCst);
+        *self.stats.lock().unwrap() = None;
     }
 
     pub fn clear_all_photos(&self) -> Result<()> {
-        let mut photos = self.photos.write().unwrap();
-        photos.clear();
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                store.clear_photos();
+                store.no_gps.write().unwrap().clear();
+            }
+            DatabaseBackend::Sqlite(store) => store.clear()?,
+        }
+        self.photos_changed();
+        self.generation.fetch_add(1, Ordering::SeqCst);
         Ok(())
     }
 
+    /// Returns the rebuild counter used to invalidate HTTP validators
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Changes whenever photos are added, updated or removed; validators of photo
+    /// lists are built from it
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
     pub fn insert_photo(&self, photo: &PhotoMetadata) -> Result<()> {
-        let mut photos = self.photos.write().unwrap();
-        let mut photo = photo.clone();
-        photo.relative_path = normalize_relative_path(&photo.relative_path);
-        photo.file_path = normalize_file_path(&photo.file_path);
-        photos.insert(photo.relative_path.clone(), photo);
+        self.insert_photos_batch(std::slice::from_ref(photo))?;
         Ok(())
     }
 
@@ -104,144 +845,944 @@ impl Database {
         if new_photos.is_empty() {
             return Ok(0);
         }
-        let mut photos = self.photos.write().unwrap();
-        for photo in new_photos {
-            let mut photo = photo.clone();
-            photo.relative_path = normalize_relative_path(&photo.relative_path);
-            photo.file_path = normalize_file_path(&photo.file_path);
-            photos.insert(photo.relative_path.clone(), photo);
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                store.insert_photos(new_photos.iter().map(normalized_photo));
+            }
+            DatabaseBackend::Sqlite(store) => {
+                let photos: Vec<_> = new_photos.iter().map(normalized_photo).collect();
+                store.insert_photos(&photos)?;
+            }
         }
+        self.photos_changed();
         Ok(new_photos.len())
     }
 
     pub fn get_all_photos(&self) -> Result<Vec<PhotoMetadata>> {
-        let photos = self.photos.read().unwrap();
-        let mut result: Vec<_> = photos.values().cloned().collect();
-        result.sort_by(|a, b| b.datetime.cmp(&a.datetime));
-        Ok(result)
+        self.get_photos_in_album(None)
     }
 
-    pub fn get_photos_count(&self) -> Result<usize> {
-        let photos = self.photos.read().unwrap();
-        Ok(photos.len())
+    /// Photos newest first, only those of `album` when given
+    pub fn get_photos_in_album(&self, album: Option<&str>) -> Result<Vec<PhotoMetadata>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let photos = store.photos.read().unwrap();
+                let mut result: Vec<_> = photos
+                    .values()
+                    .filter(|photo| album.is_none_or(|album| photo.album() == album))
+                    .cloned()
+                    .collect();
+                result.sort_by(|a, b| SortOrder::DateDesc.compare(a, b));
+                Ok(result)
+            }
+            DatabaseBackend::Sqlite(store) => match album {
+                Some(album) => store.album_photos(album),
+                None => store.all_photos(),
+            },
+        }
     }
 
-    pub fn get_photo_by_relative_path(&self, relative_path: &str) -> Result<Option<PhotoMetadata>> {
-        let photos = self.photos.read().unwrap();
-        Ok(photos
-            .get(relative_path)
-            .or_else(|| photos.get(&normalize_relative_path(relative_path)))
-            .cloned())
+    /// All photos in the given order
+    pub fn get_all_sorted(&self, order: SortOrder) -> Result<Vec<PhotoMetadata>> {
+        let mut photos = self.get_all_photos()?;
+        if order != SortOrder::DateDesc {
+            photos.sort_by(|a, b| order.compare(a, b));
+        }
+        Ok(photos)
     }
 
-    pub fn save_to_disk(&self, source_paths: &[String]) -> Result<()> {
-        let photos = self.photos.read().unwrap();
-        let cache = CachedDatabase {
-            version: 1,
-            source_paths: source_paths.to_vec(),
-            photos: photos.values().cloned().collect(),
+    /// Calls `f` with the photos matching `filter` in chunks of `chunk_size`,
+    /// until it returns false. The photos are looked up again for every chunk, so
+    /// writers are not blocked for the whole walk; photos removed meanwhile are skipped.
+    pub fn for_each_photo_chunk(
+        &self,
+        filter: &PhotoFilter,
+        order: SortOrder,
+        chunk_size: usize,
+        mut f: impl FnMut(Vec<PhotoMetadata>) -> bool,
+    ) -> Result<()> {
+        let album = filter.album;
+        let mut ids: Vec<PhotoId> = match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let photos = store.photos.read().unwrap();
+                let mut sorted: Vec<&PhotoMetadata> = photos
+                    .values()
+                    .filter(|photo| album.is_none_or(|album| photo.album() == album))
+                    .filter(|photo| filter.matches_date(photo))
+                    .collect();
+                sorted.sort_by(|a, b| order.compare(a, b));
+                sorted.into_iter().map(|photo| photo.id.clone()).collect()
+            }
+            DatabaseBackend::Sqlite(store)
+                if order == SortOrder::DateDesc && !filter.has_dates() =>
+            {
+                store.album_photo_ids(album)?
+            }
+            // Other orders and the dates are applied after fetching the album
+            DatabaseBackend::Sqlite(_) => {
+                let mut photos = self.get_photos_in_album(album)?;
+                photos.retain(|photo| filter.matches_date(photo));
+                photos.sort_by(|a, b| order.compare(a, b));
+                photos.into_iter().map(|photo| photo.id).collect()
+            }
         };
-        let app_dir = crate::utils::get_app_data_dir();
-        crate::utils::ensure_directory_exists(&app_dir)?;
-        let cache_path = app_dir.join("photos_v1.bin");
-        let file = std::fs::File::create(cache_path)?;
-        bincode::serialize_into(file, &cache)?;
+        let tags = tags::normalize_tags(filter.tags);
+        if !tags.is_empty() {
+            ids.retain(|id| self.tags.has_all(id, &tags));
+        }
+
+        for chunk in ids.chunks(chunk_size.max(1)) {
+            let photos = match &self.backend {
+                DatabaseBackend::Memory(store) => {
+                    let photos = store.photos.read().unwrap();
+                    chunk
+                        .iter()
+                        .filter_map(|id| photos.get(id).cloned())
+                        .collect()
+                }
+                DatabaseBackend::Sqlite(store) => store.photos_by_ids(chunk)?,
+            };
+            if !f(photos) {
+                break;
+            }
+        }
         Ok(())
     }
 
-    pub fn load_from_disk(&self, expected_paths: &[String]) -> Result<bool> {
-        let app_dir = crate::utils::get_app_data_dir();
-        let old_cache_path = app_dir.join("photos.bin");
-        if old_cache_path.exists() {
-            eprintln!("🗑️ Removing old cache format (photos.bin)");
-            let _ = std::fs::remove_file(&old_cache_path);
-        }
-        let old_db_path = app_dir.join("photos.db");
-        if old_db_path.exists() {
-            eprintln!("🗑️ Removing old SQLite database (photos.db)");
-            let _ = std::fs::remove_file(&old_db_path);
-        }
-        let cache_path = app_dir.join("photos_v1.bin");
-        if !cache_path.exists() {
-            return Ok(false);
-        }
-        let file = std::fs::File::open(&cache_path)?;
-        use bincode::Options;
-        let cache: CachedDatabase = match bincode::options()
-            .with_limit(50 * 1024 * 1024)
-            .with_fixint_encoding()
-            .deserialize_from(file)
-        {
-            Ok(c) => c,
-            Err(_) => {
-                eprintln!("⚠️ Cache format incompatible or corrupted");
-                eprintln!("🗑️ Deleting invalid cache file");
-                let _ = std::fs::remove_file(&cache_path);
-                return Ok(false);
-            }
-        };
-        if cache.version != 1 {
-            eprintln!(
-                "⚠️ Cache version mismatch (found {}, expected 1)",
-                cache.version
-            );
-            eprintln!("🗑️ Deleting outdated cache file");
-            let _ = std::fs::remove_file(&cache_path);
-            return Ok(false);
+    pub fn get_photos_count(&self) -> Result<usize> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => Ok(store.photos.read().unwrap().len()),
+            DatabaseBackend::Sqlite(store) => store.count(),
         }
-        let cached_paths: Vec<String> = cache
-            .source_paths
-            .iter()
-            .map(|path| source_path_cache_key(path))
-            .collect();
-        let expected_paths: Vec<String> = expected_paths
-            .iter()
-            .map(|path| source_path_cache_key(path))
-            .collect();
+    }
 
-        if cached_paths != expected_paths {
-            return Ok(false);
+    pub fn get_photo_by_id(&self, id: &PhotoId) -> Result<Option<PhotoMetadata>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => Ok(store.photos.read().unwrap().get(id).cloned()),
+            DatabaseBackend::Sqlite(store) => store.photo_by_id(id),
         }
-        let mut photos = self.photos.write().unwrap();
-        *photos = cache
-            .photos
+    }
+
+    /// Tags of a photo, sorted
+    pub fn photo_tags(&self, id: &PhotoId) -> Vec<String> {
+        self.tags.get(id)
+    }
+
+    /// Replaces the tags of a photo and returns them trimmed, lowercased and deduplicated
+    pub fn set_photo_tags(&self, id: &PhotoId, tags: &[String]) -> Result<Vec<String>> {
+        let tags = tags::normalize_tags(tags);
+        self.tags.set(id, tags.clone())?;
+        self.photos_changed();
+        Ok(tags)
+    }
+
+    /// Uses keywords read from the files as the tags of photos that were never tagged.
+    /// Returns the number of photos that got tags.
+    pub fn import_photo_tags(&self, keywords: Vec<(PhotoId, Vec<String>)>) -> Result<usize> {
+        let tagged = keywords
             .into_iter()
-            .map(|mut p| {
-                p.relative_path = normalize_relative_path(&p.relative_path);
-                p.file_path = normalize_file_path(&p.file_path);
-                (p.relative_path.clone(), p)
-            })
+            .map(|(id, keywords)| (id, tags::normalize_tags(keywords)))
             .collect();
-        Ok(true)
+        let imported = self.tags.import(tagged)?;
+        if imported > 0 {
+            self.photos_changed();
+        }
+        Ok(imported)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{normalize_file_path, normalize_relative_path, source_path_cache_key};
+    /// Number of photos per tag, counting only photos in the database
+    pub fn tag_counts(&self) -> Result<BTreeMap<String, usize>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let photos = store.photos.read().unwrap();
+                Ok(self.tags.counts(|id| photos.contains_key(id)))
+            }
+            DatabaseBackend::Sqlite(store) => {
+                let ids: HashSet<PhotoId> = store.album_photo_ids(None)?.into_iter().collect();
+                Ok(self.tags.counts(|id| ids.contains(id)))
+            }
+        }
+    }
 
-    #[test]
-    fn windows_cache_key_accepts_either_separator() {
-        #[cfg(windows)]
-        assert_eq!(source_path_cache_key("D:/Photo/"), "d:\\photo");
+    pub fn add_no_gps_entries(&self, entries: Vec<NoGpsEntry>) -> Result<()> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => store.no_gps.write().unwrap().extend(entries),
+            DatabaseBackend::Sqlite(store) => store.add_no_gps(&entries)?,
+        }
+        self.photos_changed();
+        Ok(())
+    }
 
-        #[cfg(not(windows))]
-        assert_eq!(
-            source_path_cache_key("/home/user/photos/"),
-            "/home/user/photos"
-        );
+    /// Finds a no-GPS file by the id its photo will get once it has a location
+    pub fn get_no_gps_entry(&self, id: &PhotoId) -> Result<Option<NoGpsEntry>> {
+        let matches = |e: &NoGpsEntry| PhotoId::from_relative_path(&e.relative_path) == *id;
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let entries = store.no_gps.read().unwrap();
+                Ok(entries.iter().find(|e| matches(e)).cloned())
+            }
+            DatabaseBackend::Sqlite(store) => Ok(store.no_gps_entries()?.into_iter().find(matches)),
+        }
     }
 
-    #[test]
-    fn relative_paths_always_use_url_separators() {
-        assert_eq!(
-            normalize_relative_path("Folder\\Nested\\image.jpg"),
-            "Folder/Nested/image.jpg"
-        );
+    /// Stores a photo that now has a location and drops it from the no-GPS list
+    pub fn upsert_geotagged_photo(&self, photo: &PhotoMetadata) -> Result<()> {
+        let photo = normalized_photo(photo);
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                store
+                    .no_gps
+                    .write()
+                    .unwrap()
+                    .retain(|e| e.relative_path != photo.relative_path);
+                store.insert_photos([photo]);
+            }
+            DatabaseBackend::Sqlite(store) => store.upsert_geotagged(&photo)?,
+        }
+        self.photos_changed();
+        Ok(())
     }
 
-    #[test]
-    fn file_paths_use_native_separators() {
+    /// Totals, per-year and per-country counts, date range and disk size of the
+    /// library, computed once per photo set
+    pub fn get_statistics(&self) -> Result<LibraryStats> {
+        if let Some(stats) = self.stats.lock().unwrap().clone() {
+            return Ok(stats);
+        }
+        let stats = self.compute_stats()?;
+        *self.stats.lock().unwrap() = Some(stats.clone());
+        Ok(stats)
+    }
+
+    /// Dated photos per year, newest year first, each with the position of
+    /// one of its photos. Counted in one pass and reused for `YEAR_SUMMARY_TTL`.
+    pub fn get_year_summary(&self) -> Result<Vec<YearSummary>> {
+        if let Some((counted, years)) = self.years.lock().unwrap().as_ref() {
+            if counted.elapsed() < YEAR_SUMMARY_TTL {
+                return Ok(years.clone());
+            }
+        }
+        let years = match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                YearSummary::from_photos(store.photos.read().unwrap().values())
+            }
+            DatabaseBackend::Sqlite(store) => YearSummary::from_photos(&store.all_photos()?),
+        };
+        *self.years.lock().unwrap() = Some((Instant::now(), years.clone()));
+        Ok(years)
+    }
+
+    fn compute_stats(&self) -> Result<LibraryStats> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let photos = store.photos.read().unwrap();
+                let no_gps = store.no_gps.read().unwrap().len();
+                Ok(LibraryStats::from_photos(photos.values(), no_gps))
+            }
+            DatabaseBackend::Sqlite(store) => Ok(LibraryStats::from_photos(
+                &store.all_photos()?,
+                store.no_gps_entries()?.len(),
+            )),
+        }
+    }
+
+    /// Photos grouped by location name, largest places first
+    pub fn places(&self) -> Result<Vec<PlaceGroup>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => Ok(places::group_by_place(
+                store.photos.read().unwrap().values(),
+            )),
+            DatabaseBackend::Sqlite(store) => Ok(places::group_by_place(&store.all_photos()?)),
+        }
+    }
+
+    /// Photos whose filename or datetime contains the query, ignoring case; newest
+    /// first, ties by relative path
+    pub fn search_photos(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<PhotoMetadata>> {
+        let query = query.trim().to_lowercase();
+        let mut found = match &self.backend {
+            DatabaseBackend::Memory(store) => store.search(&query),
+            DatabaseBackend::Sqlite(store) => {
+                let mut photos = store.photos_matching(&query)?;
+                photos.retain(|photo| search::matches(photo, &query));
+                photos
+            }
+        };
+        found.sort_by(|a, b| {
+            b.datetime
+                .cmp(&a.datetime)
+                .then_with(|| a.relative_path.cmp(&b.relative_path))
+        });
+        Ok(found.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// Photos within `radius_m` metres of `center`, nearest first (ties by relative
+    /// path), without the `exclude` photo
+    pub fn photos_near(
+        &self,
+        center: GpsCoordinate,
+        radius_m: f64,
+        limit: usize,
+        exclude: Option<&PhotoId>,
+    ) -> Result<Vec<NearbyPhoto>> {
+        let (lat, lng) = (center.lat(), center.lng());
+        let band = geocoding::latitude_degrees(radius_m / 1000.0);
+        let (south, north) = (lat - band, lat + band);
+        let nearby = |photo: &PhotoMetadata| {
+            let in_band = (south..=north).contains(&photo.coords.lat());
+            if !in_band || exclude == Some(&photo.id) {
+                return None;
+            }
+            let distance_m =
+                geocoding::haversine_km(lat, lng, photo.coords.lat(), photo.coords.lng()) * 1000.0;
+            (distance_m <= radius_m).then(|| NearbyPhoto {
+                photo: photo.clone(),
+                distance_m,
+            })
+        };
+        let mut found: Vec<NearbyPhoto> = match &self.backend {
+            DatabaseBackend::Memory(store) => store
+                .photos
+                .read()
+                .unwrap()
+                .values()
+                .filter_map(nearby)
+                .collect(),
+            DatabaseBackend::Sqlite(store) => store
+                .photos_in_latitudes(south, north)?
+                .iter()
+                .filter_map(nearby)
+                .collect(),
+        };
+        found.sort_by(|a, b| {
+            a.distance_m
+                .total_cmp(&b.distance_m)
+                .then_with(|| a.photo.relative_path.cmp(&b.photo.relative_path))
+        });
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    /// Groups of photos taken in the same second at the same position (rounded to
+    /// 0.0001°), oldest first; check them with `verify_duplicates`
+    pub fn find_duplicates(&self) -> Result<Vec<Vec<PhotoMetadata>>> {
+        Ok(duplicates::candidate_groups(self.get_all_photos()?))
+    }
+
+    /// Removes the photos with the given ids and returns how many there were
+    pub fn remove_photos(&self, ids: &[PhotoId]) -> Result<usize> {
+        let removed = match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let ids: std::collections::HashSet<&PhotoId> = ids.iter().collect();
+                let before = store.photos.read().unwrap().len();
+                store.retain_photos(|photo| !ids.contains(&photo.id));
+                before - store.photos.read().unwrap().len()
+            }
+            DatabaseBackend::Sqlite(store) => store.remove_photos(ids)?,
+        };
+        self.photos_changed();
+        Ok(removed)
+    }
+
+    /// Albums by name, each with its photo count and newest photo
+    pub fn albums(&self) -> Result<Vec<Album>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => Ok(albums::group_by_album(
+                store.photos.read().unwrap().values(),
+            )),
+            DatabaseBackend::Sqlite(store) => Ok(albums::group_by_album(&store.all_photos()?)),
+        }
+    }
+
+    /// Photos split into trips, oldest first: a new trip starts after a time gap
+    /// longer than `time_gap` or a jump farther than `dist_gap_km`
+    pub fn group_into_trips(&self, time_gap: Duration, dist_gap_km: f64) -> Result<Vec<Trip>> {
+        let max_gap_days = time_gap.as_secs_f64() / 86_400.0;
+        match &self.backend {
+            DatabaseBackend::Memory(store) => Ok(trips::detect_trips(
+                store.photos.read().unwrap().values(),
+                max_gap_days,
+                dist_gap_km,
+            )),
+            DatabaseBackend::Sqlite(store) => Ok(trips::detect_trips(
+                &store.all_photos()?,
+                max_gap_days,
+                dist_gap_km,
+            )),
+        }
+    }
+
+    /// Number of photos stored under each of the given folders
+    pub fn folder_photo_counts(&self, folders: &[String]) -> Result<Vec<usize>> {
+        let mut counts = vec![0; folders.len()];
+        let mut count = |path: &str| {
+            if let Some(i) = folders.iter().position(|f| is_in_folder(path, f)) {
+                counts[i] += 1;
+            }
+        };
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                for photo in store.photos.read().unwrap().values() {
+                    count(&photo.file_path);
+                }
+            }
+            DatabaseBackend::Sqlite(store) => {
+                for path in store.photo_paths()? {
+                    count(&path);
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Relative paths of the photos and no-GPS entries stored under a folder
+    pub fn folder_relative_paths(&self, folder: &str) -> Result<HashSet<String>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let photos = store.photos.read().unwrap();
+                let no_gps = store.no_gps.read().unwrap();
+                Ok(photos
+                    .values()
+                    .filter(|p| is_in_folder(&p.file_path, folder))
+                    .map(|p| p.relative_path.clone())
+                    .chain(
+                        no_gps
+                            .iter()
+                            .filter(|e| is_in_folder(&e.path, folder))
+                            .map(|e| e.relative_path.clone()),
+                    )
+                    .collect())
+            }
+            DatabaseBackend::Sqlite(store) => store.folder_relative_paths(folder),
+        }
+    }
+
+    /// Unix time in seconds when the photos of a folder were last stored
+    pub fn folder_processed_at(&self, folder: &str) -> Result<Option<u64>> {
+        match &self.backend {
+            DatabaseBackend::Memory(_) => {
+                let cache_path = folder_cache_path(&crate::utils::get_app_data_dir(), folder);
+                Ok(std::fs::metadata(cache_path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|age| age.as_secs()))
+            }
+            DatabaseBackend::Sqlite(store) => store.folder_processed_at(folder),
+        }
+    }
+
+    /// Drops the photos, no-GPS entries and cache of a folder removed from the settings
+    pub fn remove_folder(&self, folder: &str) -> Result<()> {
+        self.remove_folder_in(&crate::utils::get_app_data_dir(), folder)
+    }
+
+    fn remove_folder_in(&self, cache_dir: &Path, folder: &str) -> Result<()> {
+        self.remove_photos_under(folder)?;
+        let cache_path = folder_cache_path(cache_dir, folder);
+        for path in [backup_path(&cache_path), cache_path] {
+            if path.exists() {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("Failed to delete cache {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the photos and no-GPS entries stored under a folder; caches on
+    /// disk are left alone. Returns the number of photos removed.
+    pub fn remove_photos_under(&self, folder: &str) -> Result<usize> {
+        self.photos_changed();
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let before = store.photos.read().unwrap().len();
+                store.retain_photos(|p| !is_in_folder(&p.file_path, folder));
+                store
+                    .no_gps
+                    .write()
+                    .unwrap()
+                    .retain(|e| !is_in_folder(&e.path, folder));
+                Ok(before - store.photos.read().unwrap().len())
+            }
+            DatabaseBackend::Sqlite(store) => {
+                let count = self.folder_photo_counts(&[folder.to_string()])?[0];
+                store.remove_folder(folder)?;
+                Ok(count)
+            }
+        }
+    }
+
+    /// Takes the photos of a disabled folder off the map. Its cache stays, so
+    /// enabling the folder again loads it through `load_from_disk`.
+    pub fn unload_folder(&self, folder: &str) -> Result<()> {
+        let app_dir = crate::utils::get_app_data_dir();
+        crate::utils::ensure_directory_exists(&app_dir)?;
+        self.unload_folder_in(&app_dir, folder)
+    }
+
+    fn unload_folder_in(&self, cache_dir: &Path, folder: &str) -> Result<()> {
+        match &self.backend {
+            DatabaseBackend::Memory(_) => {
+                self.remove_photos_under(folder)?;
+            }
+            DatabaseBackend::Sqlite(store) => {
+                // The rows are the only copy, so they go to a bincode cache that
+                // `load_sqlite_folders` imports again
+                if let Some(signature) = store.folder_signature(folder)? {
+                    let cache = CachedFolder {
+                        version: CACHE_VERSION,
+                        source_path: folder.to_string(),
+                        signature,
+                        file_stamps: store.folder_file_stamps(folder)?,
+                        photos: store
+                            .all_photos()?
+                            .iter()
+                            .filter(|p| is_in_folder(&p.file_path, folder))
+                            .map(|p| CachedPhoto::new(p, folder))
+                            .collect(),
+                        no_gps: store
+                            .no_gps_entries()?
+                            .into_iter()
+                            .filter(|e| is_in_folder(&e.path, folder))
+                            .collect(),
+                    };
+                    write_folder_cache(&folder_cache_path(cache_dir, folder), &cache)?;
+                }
+                self.remove_photos_under(folder)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Files without a usable location, sorted by path
+    pub fn get_no_gps_entries(&self) -> Result<Vec<NoGpsEntry>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let mut entries = store.no_gps.read().unwrap().clone();
+                entries.sort_by(|a, b| a.path.cmp(&b.path));
+                Ok(entries)
+            }
+            DatabaseBackend::Sqlite(store) => store.no_gps_entries(),
+        }
+    }
+
+    /// Groups the photos inside `bbox` into grid cells about `CLUSTER_CELL_PX`
+    /// pixels wide at `zoom`. Cells with few photos return the photos themselves.
+    pub fn cluster(&self, bbox: &BoundingBox, zoom: u8) -> Result<Vec<ClusterEntry>> {
+        match &self.backend {
+            DatabaseBackend::Memory(store) => {
+                let photos = store.photos.read().unwrap();
+                Ok(cluster_photos(
+                    photos.values().filter(|p| bbox.contains(p.coords)),
+                    zoom,
+                ))
+            }
+            DatabaseBackend::Sqlite(store) => {
+                let photos = store.photos_in_latitudes(bbox.south, bbox.north)?;
+                Ok(cluster_photos(
+                    photos.iter().filter(|p| bbox.contains(p.coords)),
+                    zoom,
+                ))
+            }
+        }
+    }
+
+    /// Writes one cache file per given folder; caches of other folders are left as they are.
+    /// SQLite already holds the photos, so only the folder signatures are recorded.
+    pub fn save_to_disk(&self, folders: &[String], walk: &WalkOptions) -> Result<()> {
+        let app_dir = crate::utils::get_app_data_dir();
+        crate::utils::ensure_directory_exists(&app_dir)?;
+        self.save_folders(&app_dir, folders, walk)
+    }
+
+    fn save_folders(&self, cache_dir: &Path, folders: &[String], walk: &WalkOptions) -> Result<()> {
+        let store = match &self.backend {
+            DatabaseBackend::Memory(store) => store,
+            DatabaseBackend::Sqlite(store) => {
+                for folder in folders {
+                    let stamps = folder_file_stamps(Path::new(folder), walk);
+                    store.save_folder_signature(folder, &FolderSignature::of(&stamps), &stamps)?;
+                }
+                return Ok(());
+            }
+        };
+        let photos = store.photos.read().unwrap();
+        let no_gps = store.no_gps.read().unwrap();
+        for folder in folders {
+            let file_stamps = folder_file_stamps(Path::new(folder), walk);
+            let cache = CachedFolder {
+                version: CACHE_VERSION,
+                source_path: folder.clone(),
+                signature: FolderSignature::of(&file_stamps),
+                file_stamps,
+                photos: photos
+                    .values()
+                    .filter(|p| is_in_folder(&p.file_path, folder))
+                    .map(|p| CachedPhoto::new(p, folder))
+                    .collect(),
+                no_gps: no_gps
+                    .iter()
+                    .filter(|e| is_in_folder(&e.path, folder))
+                    .cloned()
+                    .collect(),
+            };
+            write_folder_cache(&folder_cache_path(cache_dir, folder), &cache)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the cached folders into the database and returns the folders
+    /// without a valid cache or whose files changed, which need to be processed
+    pub fn load_from_disk(&self, folders: &[String], walk: &WalkOptions) -> Result<Vec<String>> {
+        let app_dir = crate::utils::get_app_data_dir();
+        for old_file in ["photos.bin", "photos_v1.bin"] {
+            let old_cache_path = app_dir.join(old_file);
+            if old_cache_path.exists() {
+                eprintln!("🗑️ Removing old cache format ({})", old_file);
+                let _ = std::fs::remove_file(&old_cache_path);
+            }
+        }
+        let old_db_path = app_dir.join("photos.db");
+        if old_db_path.exists() {
+            eprintln!("🗑️ Removing old SQLite database (photos.db)");
+            let _ = std::fs::remove_file(&old_db_path);
+        }
+        self.load_folders(&app_dir, folders, walk)
+    }
+
+    /// Like `load_from_disk`, but SQLite keeps the rows of folders not listed.
+    /// Used when command line folders replace the saved ones for one run.
+    pub fn load_from_disk_keeping_others(
+        &self,
+        folders: &[String],
+        walk: &WalkOptions,
+    ) -> Result<Vec<String>> {
+        let app_dir = crate::utils::get_app_data_dir();
+        self.add_cached_folders(&app_dir, folders, walk)
+    }
+
+    /// Loads the cache of a folder enabled again next to the photos already
+    /// stored. Returns whether the folder has files to process.
+    pub fn load_folder(&self, folder: &str, walk: &WalkOptions) -> Result<bool> {
+        let app_dir = crate::utils::get_app_data_dir();
+        let missing = self.add_cached_folders(&app_dir, &[folder.to_string()], walk)?;
+        Ok(!missing.is_empty())
+    }
+
+    fn load_folders(
+        &self,
+        cache_dir: &Path,
+        folders: &[String],
+        walk: &WalkOptions,
+    ) -> Result<Vec<String>> {
+        if let DatabaseBackend::Sqlite(store) = &self.backend {
+            store.retain_folders(folders)?;
+        }
+        self.add_cached_folders(cache_dir, folders, walk)
+    }
+
+    fn add_cached_folders(
+        &self,
+        cache_dir: &Path,
+        folders: &[String],
+        walk: &WalkOptions,
+    ) -> Result<Vec<String>> {
+        self.photos_changed();
+        let store = match &self.backend {
+            DatabaseBackend::Memory(store) => store,
+            DatabaseBackend::Sqlite(store) => {
+                return load_sqlite_folders(store, cache_dir, folders, walk)
+            }
+        };
+        let mut missing = Vec::new();
+        for folder in folders {
+            let Some(mut cache) = read_folder_cache(cache_dir, folder)? else {
+                missing.push(folder.clone());
+                continue;
+            };
+            let stamps = folder_file_stamps(Path::new(folder), walk);
+            let changed = cache.signature != FolderSignature::of(&stamps);
+            let cached_stamps = std::mem::take(&mut cache.file_stamps);
+            let unchanged = unchanged_files(&cached_stamps, &stamps);
+            let (cached_photos, mut no_gps) = cache.into_photos();
+            if changed {
+                println!("🔄 Files changed since the last run: {}", folder);
+                no_gps.retain(|e| unchanged.contains(e.relative_path.as_str()));
+                missing.push(folder.clone());
+            }
+            store.insert_photos(
+                cached_photos
+                    .into_iter()
+                    .filter(|p| !changed || unchanged.contains(p.relative_path.as_str())),
+            );
+            store.no_gps.write().unwrap().extend(no_gps);
+        }
+        Ok(missing)
+    }
+}
+
+/// Relative paths of the files whose modification time is the same as when they were cached
+fn unchanged_files<'a>(cached: &'a FileStamps, current: &FileStamps) -> HashSet<&'a str> {
+    cached
+        .iter()
+        .filter(|(path, mtime)| current.get(*path) == Some(*mtime))
+        .map(|(path, _)| path.as_str())
+        .collect()
+}
+
+/// Checks the folders stored in SQLite against the files on disk. A folder
+/// not stored yet is imported from its bincode cache when that is still valid.
+fn load_sqlite_folders(
+    store: &SqliteStore,
+    cache_dir: &Path,
+    folders: &[String],
+    walk: &WalkOptions,
+) -> Result<Vec<String>> {
+    let mut missing = Vec::new();
+    for folder in folders {
+        let stamps = folder_file_stamps(Path::new(folder), walk);
+        let signature = FolderSignature::of(&stamps);
+        match store.folder_signature(folder)? {
+            Some(stored) if stored == signature => continue,
+            Some(_) => {
+                // Photos of untouched files stay; the rest, including whatever an
+                // interrupted run left behind, is processed again
+                println!("🔄 Files changed since the last run: {}", folder);
+                let cached = store.folder_file_stamps(folder)?;
+                let unchanged = unchanged_files(&cached, &stamps);
+                store.retain_folder_files(folder, |relative_path| {
+                    unchanged.contains(relative_path)
+                })?;
+                missing.push(folder.clone());
+                continue;
+            }
+            None => {
+                if let Some(cache) = read_folder_cache(cache_dir, folder)? {
+                    if cache.signature == signature {
+                        let (photos, no_gps) = cache.into_photos();
+                        store.replace_folder(folder, &photos, &no_gps, &signature, &stamps)?;
+                        println!("📥 Imported cache of {} into SQLite", folder);
+                        continue;
+                    }
+                }
+            }
+        }
+        // Drop whatever an interrupted run left behind
+        store.remove_folder(folder)?;
+        missing.push(folder.clone());
+    }
+    Ok(missing)
+}
+
+/// Grid clustering behind `Database::cluster`
+fn cluster_photos<'a>(
+    photos: impl Iterator<Item = &'a PhotoMetadata>,
+    zoom: u8,
+) -> Vec<ClusterEntry> {
+    // The world is 256 * 2^zoom pixels wide
+    let zoom = zoom.min(MAX_CLUSTER_ZOOM);
+    let cell_degrees = 360.0 * CLUSTER_CELL_PX as f64 / (256.0 * 2f64.powi(zoom as i32));
+
+    let mut cells: HashMap<(i64, i64), Vec<&PhotoMetadata>> = HashMap::new();
+    for photo in photos {
+        let cell = (
+            (photo.coords.lat() / cell_degrees).floor() as i64,
+            (photo.coords.lng() / cell_degrees).floor() as i64,
+        );
+        cells.entry(cell).or_default().push(photo);
+    }
+
+    let mut cells: Vec<_> = cells.into_iter().collect();
+    cells.sort_by_key(|(cell, _)| *cell);
+    let mut entries = Vec::new();
+    for (_, mut members) in cells {
+        if members.len() <= CLUSTER_MAX_POINTS {
+            members.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+            entries.extend(
+                members
+                    .into_iter()
+                    .map(|photo| ClusterEntry::Photo(Box::new(photo.clone()))),
+            );
+        } else {
+            let count = members.len();
+            let mean = |coord: fn(&GpsCoordinate) -> f64| {
+                members.iter().map(|p| coord(&p.coords)).sum::<f64>() / count as f64
+            };
+            entries.push(ClusterEntry::Cluster {
+                lat: mean(GpsCoordinate::lat),
+                lng: mean(GpsCoordinate::lng),
+                count,
+            });
+        }
+    }
+    entries
+}
+
+/// Cache file of a folder, named by the hash of its normalized path
+fn folder_cache_path(cache_dir: &Path, folder: &str) -> PathBuf {
+    let key = source_path_cache_key(folder);
+    cache_dir.join(format!("folder_{:016x}.bin", xxh3_64(key.as_bytes())))
+}
+
+fn is_in_folder(path: &str, folder: &str) -> bool {
+    Path::new(path).starts_with(Path::new(folder))
+}
+
+/// Previous version of a cache file, used when the current one is unreadable
+fn backup_path(cache_path: &Path) -> PathBuf {
+    cache_path.with_extension("bin.bak")
+}
+
+/// Writes a cache file through a synced temporary file renamed into place,
+/// keeping the previous file as a backup, so a crash never leaves only a truncated cache
+fn write_folder_cache(cache_path: &Path, cache: &CachedFolder) -> Result<()> {
+    let temp_path = cache_path.with_extension("bin.tmp");
+    let result = (|| -> Result<()> {
+        let writer = std::io::BufWriter::new(std::fs::File::create(&temp_path)?);
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        bincode::serialize_into(&mut encoder, cache)?;
+        encoder.finish()?.into_inner()?.sync_all()?;
+        if cache_path.exists() {
+            std::fs::rename(cache_path, backup_path(cache_path))?;
+        }
+        std::fs::rename(&temp_path, cache_path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Reads the cache of one folder, falling back to the backup when the
+/// current file is missing or unreadable
+fn read_folder_cache(cache_dir: &Path, folder: &str) -> Result<Option<CachedFolder>> {
+    let cache_path = folder_cache_path(cache_dir, folder);
+    if let Some(cache) = read_cache_file(&cache_path, folder)? {
+        return Ok(Some(cache));
+    }
+    let cache = read_cache_file(&backup_path(&cache_path), folder)?;
+    if cache.is_some() {
+        println!("♻️ Using backup cache of {}", folder);
+    }
+    Ok(cache)
+}
+
+/// Reads one cache file; outdated or corrupted files are deleted
+fn read_cache_file(cache_path: &Path, folder: &str) -> Result<Option<CachedFolder>> {
+    if !cache_path.exists() {
+        return Ok(None);
+    }
+    let raw = std::fs::read(cache_path)?;
+    use bincode::Options;
+    let options = bincode::options()
+        .with_limit(CACHE_SIZE_LIMIT)
+        .with_fixint_encoding();
+
+    // Current caches are gzipped; older ones are plain bincode
+    let compressed = raw.starts_with(&[0x1F, 0x8B]);
+    let data = if compressed {
+        let mut data = Vec::new();
+        let decoded = GzDecoder::new(raw.as_slice())
+            .take(CACHE_SIZE_LIMIT + 1)
+            .read_to_end(&mut data);
+        if decoded.is_err() || data.len() as u64 > CACHE_SIZE_LIMIT {
+            data.clear();
+        }
+        data
+    } else {
+        raw
+    };
+
+    // The version is the first field; check it before decoding photos whose layout may differ
+    let version: Option<u32> = options.allow_trailing_bytes().deserialize(&data).ok();
+    let expected = if compressed {
+        CACHE_VERSION
+    } else {
+        UNCOMPRESSED_CACHE_VERSION
+    };
+    let ungeocoded = compressed && version == Some(UNGEOCODED_CACHE_VERSION);
+    if version != Some(expected) && !ungeocoded {
+        eprintln!(
+            "⚠️ Cache version mismatch for {} in {} (found {}, expected {})",
+            folder,
+            cache_path.display(),
+            version.map_or_else(|| "none".to_string(), |v| v.to_string()),
+            expected
+        );
+        eprintln!("🗑️ Deleting outdated cache file");
+        let _ = std::fs::remove_file(cache_path);
+        return Ok(None);
+    }
+
+    let decoded = if ungeocoded {
+        options
+            .deserialize::<UngeocodedCachedFolder>(&data)
+            .map(CachedFolder::from)
+    } else if compressed {
+        options.deserialize::<CachedFolder>(&data)
+    } else {
+        options
+            .deserialize::<UncompressedCachedFolder>(&data)
+            .map(CachedFolder::from)
+    };
+    let cache = match decoded {
+        Ok(c) => c,
+        Err(_) => {
+            eprintln!(
+                "⚠️ Cache of {} incompatible or corrupted: {}",
+                folder,
+                cache_path.display()
+            );
+            eprintln!("🗑️ Deleting invalid cache file");
+            let _ = std::fs::remove_file(cache_path);
+            return Ok(None);
+        }
+    };
+    if source_path_cache_key(&cache.source_path) != source_path_cache_key(folder) {
+        return Ok(None);
+    }
+    Ok(Some(cache))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterEntry;
+    use super::{
+        normalize_file_path, normalize_relative_path, source_path_cache_key, Database, ExifSource,
+        PhotoFilter, PhotoId, PhotoMetadata, SortOrder, UNKNOWN_DATE,
+    };
+    use crate::processing::WalkOptions;
+    use crate::types::{BoundingBox, GpsCoordinate};
+    use std::time::Duration;
+
+    #[test]
+    fn windows_cache_key_accepts_either_separator() {
+        #[cfg(windows)]
+        assert_eq!(source_path_cache_key("D:/Photo/"), "d:\\photo");
+
+        #[cfg(not(windows))]
+        assert_eq!(
+            source_path_cache_key("/home/user/photos/"),
+            "/home/user/photos"
+        );
+    }
+
+    #[test]
+    fn relative_paths_always_use_url_separators() {
+        assert_eq!(
+            normalize_relative_path("Folder\\Nested\\image.jpg"),
+            "Folder/Nested/image.jpg"
+        );
+    }
+
+    #[test]
+    fn file_paths_use_native_separators() {
         #[cfg(windows)]
         assert_eq!(
             normalize_file_path("D:/Photo/Nested/image.jpg"),
@@ -254,4 +1795,906 @@ mod tests {
             "D:/Photo/Nested/image.jpg"
         );
     }
+
+    #[test]
+    fn photo_id_is_stable_hex_of_relative_path() {
+        let id = PhotoId::from_relative_path("2024/Trip/IMG_0001.jpg");
+        assert_eq!(id.as_str().len(), 16);
+        assert!(id.as_str().chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(id, PhotoId::from_relative_path("2024\\Trip\\IMG_0001.jpg"));
+        assert_ne!(id, PhotoId::from_relative_path("2024/Trip/IMG_0002.jpg"));
+        assert_eq!(id.to_string(), id.as_str());
+    }
+
+    /// One database per backend; the SQLite file lives in a fresh temp directory
+    fn backends(name: &str) -> Vec<Database> {
+        let dir = cache_dir(&format!("backend_{name}"));
+        vec![
+            Database::new().unwrap(),
+            Database::open_sqlite(&dir.join(super::SQLITE_FILE)).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn photos_are_looked_up_by_id() {
+        for db in backends("lookup") {
+            photos_are_looked_up_by_id_in(&db);
+        }
+    }
+
+    fn photos_are_looked_up_by_id_in(db: &Database) {
+        db.insert_photo(&PhotoMetadata {
+            // Stale ids are replaced by the id of the normalized path
+            id: PhotoId::default(),
+            relative_path: "2024\\IMG_0001.jpg".to_string(),
+            ..PhotoMetadata::sample("2024/IMG_0001.jpg", "2024-06-01 12:00:00", 48.8566, 2.3522)
+        })
+        .unwrap();
+
+        let id = PhotoId::from_relative_path("2024/IMG_0001.jpg");
+        let photo = db.get_photo_by_id(&id).unwrap().unwrap();
+        assert_eq!(photo.id, id);
+        assert_eq!(PhotoId::from(&photo), id);
+        assert!(db
+            .get_photo_by_id(&PhotoId::from_relative_path("missing.jpg"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn photos_are_searched_by_filename_and_date() {
+        for db in backends("search") {
+            photos_are_searched_by_filename_and_date_in(&db);
+        }
+    }
+
+    fn photos_are_searched_by_filename_and_date_in(db: &Database) {
+        let mut photos: Vec<_> = ["IMG_0001.jpg", "img_0002.JPG", "DSC_0003.jpg", "100%.jpg"]
+            .iter()
+            .map(|name| photo_at(name, 48.85, 2.35))
+            .collect();
+        photos[1].datetime = "2023-07-14 10:00:00".to_string();
+        photos[2].datetime = "2023-07-15 10:00:00".to_string();
+        db.insert_photos_batch(&photos).unwrap();
+
+        let names = |query: &str, offset: usize, limit: usize| -> Vec<String> {
+            db.search_photos(query, offset, limit)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.filename)
+                .collect()
+        };
+        assert_eq!(names("img_", 0, 10), ["IMG_0001.jpg", "img_0002.JPG"]);
+        assert_eq!(names("2023-07", 0, 10), ["DSC_0003.jpg", "img_0002.JPG"]);
+        assert_eq!(names("2023-07", 1, 10), ["img_0002.JPG"]);
+        assert_eq!(names("0", 0, 2).len(), 2);
+        assert_eq!(names("%", 0, 10), ["100%.jpg"]);
+        assert!(names("photos", 0, 10).is_empty());
+
+        db.remove_folder_in(&cache_dir("search_remove"), "/photos")
+            .unwrap();
+        assert!(names("img", 0, 10).is_empty());
+    }
+
+    #[test]
+    fn photos_are_grouped_into_trips() {
+        for db in backends("trips") {
+            let shot = |name: &str, datetime: &str, lat: f64, lng: f64| PhotoMetadata {
+                datetime: datetime.to_string(),
+                ..photo_at(name, lat, lng)
+            };
+            db.insert_photos_batch(&[
+                shot("morning.jpg", "2024-06-01 09:00:00", 48.85, 2.35),
+                shot("noon.jpg", "2024-06-01 12:00:00", 48.86, 2.34),
+                shot("evening.jpg", "2024-06-01 20:00:00", 48.86, 2.35),
+                shot("london.jpg", "2024-06-01 21:00:00", 51.51, -0.13),
+            ])
+            .unwrap();
+
+            let trips = |hours: u64, km: f64| -> Vec<Vec<String>> {
+                db.group_into_trips(Duration::from_secs(hours * 3600), km)
+                    .unwrap()
+                    .into_iter()
+                    .map(|trip| trip.photos)
+                    .collect()
+            };
+            assert_eq!(
+                trips(6, 300.0),
+                [
+                    vec!["morning.jpg", "noon.jpg"],
+                    vec!["evening.jpg"],
+                    vec!["london.jpg"]
+                ]
+            );
+            assert_eq!(trips(24, 1000.0).len(), 1);
+        }
+    }
+
+    #[test]
+    fn photos_are_filtered_by_album() {
+        for db in backends("album") {
+            let names = ["Paris/a.jpg", "Paris/2024/b.jpg", "Paris2/c.jpg", "d.jpg"];
+            let photos: Vec<_> = names.iter().map(|n| photo_at(n, 48.85, 2.35)).collect();
+            db.insert_photos_batch(&photos).unwrap();
+
+            let album = |name: &str| -> Vec<String> {
+                let mut paths: Vec<String> = db
+                    .get_photos_in_album(Some(name))
+                    .unwrap()
+                    .into_iter()
+                    .map(|p| p.relative_path)
+                    .collect();
+                paths.sort();
+                paths
+            };
+            assert_eq!(album("Paris"), ["Paris/2024/b.jpg", "Paris/a.jpg"]);
+            assert_eq!(album(super::ROOT_ALBUM), ["d.jpg"]);
+            assert!(album("paris").is_empty());
+            assert_eq!(db.get_photos_in_album(None).unwrap().len(), 4);
+            assert_eq!(db.albums().unwrap().len(), 3);
+
+            let chunked = |album: Option<&str>| {
+                let mut chunks = Vec::new();
+                let filter = PhotoFilter {
+                    album,
+                    ..PhotoFilter::default()
+                };
+                db.for_each_photo_chunk(&filter, SortOrder::DateDesc, 1, |photos| {
+                    chunks.push(photos.len());
+                    chunks.len() < 3
+                })
+                .unwrap();
+                chunks
+            };
+            assert_eq!(chunked(None), [1, 1, 1]);
+            assert_eq!(chunked(Some("Paris")), [1, 1]);
+            assert_eq!(chunked(Some(super::ROOT_ALBUM)), [1]);
+        }
+    }
+
+    #[test]
+    fn photos_are_sorted_with_undated_last() {
+        for db in backends("sort") {
+            let mut photos = vec![
+                photo_at("b.jpg", 48.8566, 2.3522),
+                photo_at("A.jpg", 52.5200, 13.4050),
+                photo_at("c.jpg", 41.9028, 12.4964),
+                photo_at("d.jpg", 40.4168, -3.7038),
+            ];
+            photos[0].datetime = "2024-06-01 12:00:00".to_string();
+            photos[1].datetime = "2024-07-01 12:00:00".to_string();
+            photos[2].datetime = UNKNOWN_DATE.to_string();
+            photos[3].datetime = "2024-05-01 12:00:00".to_string();
+            db.insert_photos_batch(&photos).unwrap();
+
+            let sorted = |order: SortOrder| -> Vec<String> {
+                db.get_all_sorted(order)
+                    .unwrap()
+                    .into_iter()
+                    .map(|p| p.filename)
+                    .collect()
+            };
+            assert_eq!(
+                sorted(SortOrder::DateDesc),
+                ["A.jpg", "b.jpg", "d.jpg", "c.jpg"]
+            );
+            assert_eq!(
+                sorted(SortOrder::DateAsc),
+                ["d.jpg", "b.jpg", "A.jpg", "c.jpg"]
+            );
+            assert_eq!(
+                sorted(SortOrder::Name),
+                ["A.jpg", "b.jpg", "c.jpg", "d.jpg"]
+            );
+            // Nearest to Brussels: Paris, Berlin, Rome, Madrid
+            let from_brussels = SortOrder::Distance {
+                lat: 50.8503,
+                lng: 4.3517,
+            };
+            assert_eq!(sorted(from_brussels), ["b.jpg", "A.jpg", "c.jpg", "d.jpg"]);
+
+            let mut streamed = Vec::new();
+            db.for_each_photo_chunk(&PhotoFilter::default(), from_brussels, 3, |chunk| {
+                streamed.extend(chunk.into_iter().map(|p| p.filename));
+                true
+            })
+            .unwrap();
+            assert_eq!(streamed, sorted(from_brussels));
+            let mut newest = Vec::new();
+            db.for_each_photo_chunk(&PhotoFilter::default(), SortOrder::DateDesc, 3, |chunk| {
+                newest.extend(chunk.into_iter().map(|p| p.filename));
+                true
+            })
+            .unwrap();
+            assert_eq!(newest, sorted(SortOrder::DateDesc));
+        }
+    }
+
+    #[test]
+    fn photos_are_filtered_by_capture_date() {
+        for db in backends("dates") {
+            let mut photos = vec![
+                photo_at("a.jpg", 48.85, 2.35),
+                photo_at("b.jpg", 48.85, 2.35),
+                photo_at("c.jpg", 48.85, 2.35),
+                photo_at("d.jpg", 48.85, 2.35),
+            ];
+            photos[0].datetime = "2023-12-31 23:59:59".to_string();
+            photos[1].datetime = "2024-01-01 00:00:00".to_string();
+            photos[2].datetime = "2024-12-31 18:00:00".to_string();
+            photos[3].datetime = UNKNOWN_DATE.to_string();
+            db.insert_photos_batch(&photos).unwrap();
+
+            let between = |start_date: Option<&str>, end_date: Option<&str>| {
+                let filter = PhotoFilter {
+                    start_date,
+                    end_date,
+                    ..PhotoFilter::default()
+                };
+                let mut names = Vec::new();
+                db.for_each_photo_chunk(&filter, SortOrder::DateDesc, 2, |chunk| {
+                    names.extend(chunk.into_iter().map(|p| p.filename));
+                    true
+                })
+                .unwrap();
+                names
+            };
+            assert_eq!(
+                between(Some("2024-01-01"), Some("2024-12-31")),
+                ["c.jpg", "b.jpg"]
+            );
+            assert_eq!(between(None, Some("2023-12-31")), ["a.jpg"]);
+            assert_eq!(between(None, None).len(), 4);
+
+            let years: Vec<_> = db
+                .get_year_summary()
+                .unwrap()
+                .into_iter()
+                .map(|y| (y.year, y.count))
+                .collect();
+            assert_eq!(years, [(2024, 2), (2023, 1)]);
+        }
+        assert!(super::is_valid_date("2024-02-29"));
+        assert!(!super::is_valid_date("2024-2-29"));
+        assert!(!super::is_valid_date("2024-02-29 12:00:00"));
+    }
+
+    #[test]
+    fn photos_are_filtered_by_all_tags() {
+        let tags_file = cache_dir("tags").join(super::tags::TAGS_FILE);
+        for db in backends("tags") {
+            let db = db.with_tags_file(&tags_file);
+            let photos: Vec<_> = ["a.jpg", "b.jpg", "c.jpg"]
+                .iter()
+                .map(|n| photo_at(n, 48.85, 2.35))
+                .collect();
+            db.insert_photos_batch(&photos).unwrap();
+            let tag = |photo: &PhotoMetadata, tags: &[&str]| {
+                let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+                db.set_photo_tags(&photo.id, &tags).unwrap()
+            };
+            assert_eq!(tag(&photos[0], &["Dog", " park "]), ["dog", "park"]);
+            tag(&photos[1], &["dog"]);
+
+            let tagged = |tags: &[&str]| {
+                let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+                let mut paths = Vec::new();
+                let filter = PhotoFilter {
+                    tags: &tags,
+                    ..PhotoFilter::default()
+                };
+                db.for_each_photo_chunk(&filter, SortOrder::DateDesc, 10, |chunk| {
+                    paths.extend(chunk.into_iter().map(|p| p.relative_path));
+                    true
+                })
+                .unwrap();
+                paths.sort();
+                paths
+            };
+            assert_eq!(tagged(&["dog"]), ["a.jpg", "b.jpg"]);
+            assert_eq!(tagged(&["DOG", "park"]), ["a.jpg"]);
+            assert!(tagged(&["dog", "cat"]).is_empty());
+            assert_eq!(tagged(&[]).len(), 3);
+
+            let counts = db.tag_counts().unwrap();
+            assert_eq!((counts["dog"], counts["park"]), (2, 1));
+
+            // Tags outlive the photos, e.g. while a folder is reprocessed
+            db.clear_all_photos().unwrap();
+            assert!(db.tag_counts().unwrap().is_empty());
+            let reopened = Database::new().unwrap().with_tags_file(&tags_file);
+            assert_eq!(reopened.photo_tags(&photos[0].id), ["dog", "park"]);
+            let _ = std::fs::remove_file(&tags_file);
+        }
+    }
+
+    fn photo_at(name: &str, lat: f64, lng: f64) -> PhotoMetadata {
+        PhotoMetadata::sample(name, "2024-06-01 12:00:00", lat, lng)
+    }
+
+    #[test]
+    fn clusters_depend_on_zoom_and_bbox() {
+        for db in backends("clusters") {
+            clusters_depend_on_zoom_and_bbox_in(&db);
+        }
+    }
+
+    fn clusters_depend_on_zoom_and_bbox_in(db: &Database) {
+        // Five photos within ~100 m in Paris and one in Berlin
+        let mut photos: Vec<_> = (0..5)
+            .map(|i| {
+                photo_at(
+                    &format!("paris{i}.jpg"),
+                    48.8566 + i as f64 * 0.0002,
+                    2.3522,
+                )
+            })
+            .collect();
+        photos.push(photo_at("berlin.jpg", 52.52, 13.405));
+        db.insert_photos_batch(&photos).unwrap();
+
+        let world = db.cluster(&BoundingBox::WORLD, 3).unwrap();
+        assert_eq!(world.len(), 2);
+        let ClusterEntry::Cluster { lat, count, .. } = &world[0] else {
+            panic!("expected the Paris photos to be clustered: {world:?}");
+        };
+        assert_eq!(*count, 5);
+        assert!((lat - 48.857).abs() < 1e-3);
+        assert!(matches!(&world[1], ClusterEntry::Photo(p) if p.filename == "berlin.jpg"));
+
+        // Zoomed in far enough every Paris photo gets its own cell
+        let street = db.cluster(&BoundingBox::WORLD, 20).unwrap();
+        assert_eq!(street.len(), 6);
+        assert!(street.iter().all(|e| matches!(e, ClusterEntry::Photo(_))));
+
+        let paris_only = BoundingBox::parse("2.0,48.0,3.0,49.0").unwrap();
+        let entries = db.cluster(&paris_only, 3).unwrap();
+        assert!(matches!(
+            entries[..],
+            [ClusterEntry::Cluster { count: 5, .. }]
+        ));
+    }
+
+    #[test]
+    fn nearby_photos_are_sorted_by_distance() {
+        for db in backends("near") {
+            db.insert_photos_batch(&[
+                photo_at("eiffel.jpg", 48.8584, 2.2945),
+                photo_at("louvre.jpg", 48.8606, 2.3376),
+                photo_at("notre_dame.jpg", 48.853, 2.3499),
+                photo_at("berlin.jpg", 52.52, 13.405),
+            ])
+            .unwrap();
+            let center = GpsCoordinate::new(48.8584, 2.2945).unwrap();
+
+            let near = db.photos_near(center, 5_000.0, 10, None).unwrap();
+            let names: Vec<&str> = near.iter().map(|n| n.photo.filename.as_str()).collect();
+            assert_eq!(names, ["eiffel.jpg", "louvre.jpg", "notre_dame.jpg"]);
+            assert_eq!(near[0].distance_m, 0.0);
+            assert!(
+                (near[1].distance_m - 3_160.0).abs() < 50.0,
+                "{}",
+                near[1].distance_m
+            );
+
+            let exclude = PhotoId::from_relative_path("eiffel.jpg");
+            let others = db.photos_near(center, 5_000.0, 1, Some(&exclude)).unwrap();
+            assert_eq!(others.len(), 1);
+            assert_eq!(others[0].photo.filename, "louvre.jpg");
+            assert!(db
+                .photos_near(center, 1.0, 10, Some(&exclude))
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn duplicates_are_found_and_removed() {
+        for db in backends("duplicates") {
+            db.insert_photos_batch(&[
+                photo_at("phone/a.jpg", 48.85, 2.35),
+                photo_at("camera/a.jpg", 48.85, 2.35),
+                photo_at("elsewhere.jpg", 52.52, 13.405),
+            ])
+            .unwrap();
+            let groups = db.find_duplicates().unwrap();
+            assert_eq!(groups.len(), 1);
+            let paths: Vec<&str> = groups[0].iter().map(|p| p.relative_path.as_str()).collect();
+            assert_eq!(paths, ["camera/a.jpg", "phone/a.jpg"]);
+
+            let copy = PhotoId::from_relative_path("phone/a.jpg");
+            let missing = PhotoId::from_relative_path("missing.jpg");
+            assert_eq!(db.remove_photos(&[copy.clone(), missing]).unwrap(), 1);
+            assert!(db.get_photo_by_id(&copy).unwrap().is_none());
+            assert_eq!(db.get_photos_count().unwrap(), 2);
+            assert!(db.find_duplicates().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn statistics_follow_library_changes() {
+        for db in backends("stats") {
+            assert_eq!(db.get_statistics().unwrap().total_photos, 0);
+
+            let mut photo = photo_at("a.jpg", 48.85, 2.35);
+            photo.file_size = 1_000;
+            db.insert_photos_batch(&[photo, photo_at("b.jpg", 52.52, 13.405)])
+                .unwrap();
+            let stats = db.get_statistics().unwrap();
+            assert_eq!(stats.photos_with_gps, 2);
+            assert_eq!(stats.total_size_bytes, 1_000);
+            assert_eq!(stats.per_year[0].year, "2024");
+
+            db.clear_all_photos().unwrap();
+            assert_eq!(db.get_statistics().unwrap().total_photos, 0);
+        }
+    }
+
+    fn photo_in(folder: &str, name: &str) -> PhotoMetadata {
+        let mut photo = photo_at(name, 48.85, 2.35);
+        photo.file_path = format!("{folder}/{name}");
+        photo
+    }
+
+    fn cache_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("photomap_folder_cache_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn cached_library(dir: &std::path::Path) -> Vec<String> {
+        let folders = vec!["/library/a".to_string(), "/library/b".to_string()];
+        let db = Database::new().unwrap();
+        db.insert_photos_batch(&[
+            photo_in("/library/a", "a1.jpg"),
+            photo_in("/library/a", "a2.jpg"),
+            photo_in("/library/b", "b1.jpg"),
+        ])
+        .unwrap();
+        db.save_folders(dir, &folders, &WalkOptions::default())
+            .unwrap();
+        folders
+    }
+
+    #[test]
+    fn adding_a_folder_keeps_the_other_caches() {
+        let dir = cache_dir("add");
+        let mut folders = cached_library(&dir);
+        folders.push("/library/c".to_string());
+
+        let db = Database::new().unwrap();
+        let missing = db
+            .load_folders(&dir, &folders, &WalkOptions::default())
+            .unwrap();
+        assert_eq!(missing, ["/library/c"]);
+        assert_eq!(db.get_photos_count().unwrap(), 3);
+
+        // Only the new folder is written after processing it
+        db.insert_photo(&photo_in("/library/c", "c1.jpg")).unwrap();
+        db.save_folders(&dir, &missing, &WalkOptions::default())
+            .unwrap();
+        let reloaded = Database::new().unwrap();
+        assert!(reloaded
+            .load_folders(&dir, &folders, &WalkOptions::default())
+            .unwrap()
+            .is_empty());
+        assert_eq!(reloaded.get_photos_count().unwrap(), 4);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn removing_a_folder_drops_only_its_photos() {
+        let dir = cache_dir("remove");
+        cached_library(&dir);
+
+        let db = Database::new().unwrap();
+        let missing = db
+            .load_folders(&dir, &["/library/b/".to_string()], &WalkOptions::default())
+            .unwrap();
+        assert!(missing.is_empty());
+        let photos = db.get_all_photos().unwrap();
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].relative_path, "b1.jpg");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn forgetting_a_folder_drops_its_photos_and_cache() {
+        let dir = cache_dir("forget");
+        let folders = cached_library(&dir);
+        for db in backends("forget") {
+            db.insert_photos_batch(&[
+                photo_in("/library/a", "a1.jpg"),
+                photo_in("/library/a", "a2.jpg"),
+                photo_in("/library/b", "b1.jpg"),
+            ])
+            .unwrap();
+            assert_eq!(db.folder_photo_counts(&folders).unwrap(), [2, 1]);
+
+            db.remove_folder_in(&dir, "/library/a/").unwrap();
+            assert_eq!(db.folder_photo_counts(&folders).unwrap(), [0, 1]);
+        }
+        assert!(!super::folder_cache_path(&dir, "/library/a").exists());
+        assert!(super::folder_cache_path(&dir, "/library/b").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn photos_under_a_folder_are_removed() {
+        for db in backends("remove_under") {
+            db.insert_photos_batch(&[
+                photo_in("/library/a", "a1.jpg"),
+                photo_in("/library/a", "nested/a2.jpg"),
+                photo_in("/library/ab", "ab1.jpg"),
+            ])
+            .unwrap();
+
+            assert_eq!(db.remove_photos_under("/library/a").unwrap(), 2);
+            let photos = db.get_all_photos().unwrap();
+            assert_eq!(photos.len(), 1);
+            assert_eq!(photos[0].relative_path, "ab1.jpg");
+            assert_eq!(db.remove_photos_under("/library/never").unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn disabled_folder_keeps_its_cache() {
+        let dir = cache_dir("disable");
+        let folders = cached_library(&dir);
+        let walk = WalkOptions::default();
+        let sqlite = Database::open_sqlite(&dir.join(super::SQLITE_FILE)).unwrap();
+        for db in [Database::new().unwrap(), sqlite] {
+            assert!(db.load_folders(&dir, &folders, &walk).unwrap().is_empty());
+            db.unload_folder_in(&dir, "/library/a").unwrap();
+            assert_eq!(db.folder_photo_counts(&folders).unwrap(), [0, 1]);
+            assert!(super::folder_cache_path(&dir, "/library/a").exists());
+
+            // Enabled again, the folder comes back without being processed
+            assert!(db.load_folders(&dir, &folders, &walk).unwrap().is_empty());
+            assert_eq!(db.folder_photo_counts(&folders).unwrap(), [2, 1]);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn renamed_folder_is_reprocessed() {
+        let dir = cache_dir("rename");
+        cached_library(&dir);
+
+        let db = Database::new().unwrap();
+        let folders = ["/library/a".to_string(), "/library/b-renamed".to_string()];
+        let missing = db
+            .load_folders(&dir, &folders, &WalkOptions::default())
+            .unwrap();
+        assert_eq!(missing, ["/library/b-renamed"]);
+        assert_eq!(db.get_photos_count().unwrap(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn outdated_folder_cache_is_deleted() {
+        let dir = cache_dir("outdated");
+        let folder = "/library/a".to_string();
+        let path = super::folder_cache_path(&dir, &folder);
+        std::fs::write(&path, (super::UNCOMPRESSED_CACHE_VERSION - 1).to_le_bytes()).unwrap();
+
+        let db = Database::new().unwrap();
+        let missing = db
+            .load_folders(&dir, std::slice::from_ref(&folder), &WalkOptions::default())
+            .unwrap();
+        assert_eq!(missing, [folder]);
+        assert!(!path.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn changed_folder_contents_invalidate_only_that_folder() {
+        let dir = cache_dir("contents");
+        let library = dir.join("library");
+        std::fs::create_dir_all(&library).unwrap();
+        std::fs::write(library.join("a.jpg"), b"jpeg").unwrap();
+        let folder = library.to_string_lossy().to_string();
+        let folders = [folder.clone(), "/library/b".to_string()];
+        let walk = WalkOptions::default();
+
+        let db = Database::new().unwrap();
+        db.insert_photos_batch(&[photo_in(&folder, "a.jpg"), photo_in("/library/b", "b1.jpg")])
+            .unwrap();
+        db.save_folders(&dir, &folders, &walk).unwrap();
+        let load = || {
+            Database::new()
+                .unwrap()
+                .load_folders(&dir, &folders, &walk)
+                .unwrap()
+        };
+        assert!(load().is_empty());
+
+        // Files other than photos do not matter
+        std::fs::write(library.join("notes.txt"), b"text").unwrap();
+        assert!(load().is_empty());
+
+        std::fs::write(library.join("new.jpg"), b"jpeg").unwrap();
+        assert_eq!(load(), std::slice::from_ref(&folder));
+        std::fs::remove_file(library.join("new.jpg")).unwrap();
+        assert!(load().is_empty());
+
+        std::fs::remove_file(library.join("a.jpg")).unwrap();
+        assert_eq!(load(), [folder]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn changed_folder_keeps_photos_of_unchanged_files() {
+        let dir = cache_dir("incremental");
+        let library = dir.join("library");
+        std::fs::create_dir_all(&library).unwrap();
+        for name in ["a.jpg", "b.jpg"] {
+            std::fs::write(library.join(name), b"jpeg").unwrap();
+        }
+        let folder = library.to_string_lossy().to_string();
+        let folders = std::slice::from_ref(&folder);
+        let walk = WalkOptions::default();
+
+        let db = Database::new().unwrap();
+        db.insert_photos_batch(&[photo_in(&folder, "a.jpg"), photo_in(&folder, "b.jpg")])
+            .unwrap();
+        db.save_folders(&dir, folders, &walk).unwrap();
+
+        let edited = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(library.join("b.jpg"))
+            .unwrap()
+            .set_modified(edited)
+            .unwrap();
+        std::fs::write(library.join("c.jpg"), b"jpeg").unwrap();
+
+        let db = Database::new().unwrap();
+        assert_eq!(db.load_folders(&dir, folders, &walk).unwrap(), folders);
+        let kept = db.folder_relative_paths(&folder).unwrap();
+        assert_eq!(kept, std::collections::HashSet::from(["a.jpg".to_string()]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn truncated_cache_is_recovered_from_backup() {
+        let dir = cache_dir("backup");
+        let folder = "/library/a".to_string();
+        let folders = std::slice::from_ref(&folder);
+        let walk = WalkOptions::default();
+        let db = Database::new().unwrap();
+        db.insert_photo(&photo_in(&folder, "a1.jpg")).unwrap();
+        db.save_folders(&dir, folders, &walk).unwrap();
+        db.insert_photo(&photo_in(&folder, "a2.jpg")).unwrap();
+        db.save_folders(&dir, folders, &walk).unwrap();
+
+        let path = super::folder_cache_path(&dir, &folder);
+        assert!(!path.with_extension("bin.tmp").exists());
+        let full = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &full[..full.len() / 2]).unwrap();
+
+        // The backup holds the state before the last save
+        let recovered = Database::new().unwrap();
+        assert!(recovered
+            .load_folders(&dir, folders, &walk)
+            .unwrap()
+            .is_empty());
+        assert_eq!(recovered.get_photos_count().unwrap(), 1);
+        assert!(!path.exists());
+
+        // A crash between the two renames leaves only the backup
+        std::fs::remove_file(super::backup_path(&path)).unwrap();
+        db.save_folders(&dir, folders, &walk).unwrap();
+        std::fs::rename(&path, super::backup_path(&path)).unwrap();
+        let recovered = Database::new().unwrap();
+        assert!(recovered
+            .load_folders(&dir, folders, &walk)
+            .unwrap()
+            .is_empty());
+        assert_eq!(recovered.get_photos_count().unwrap(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sqlite_imports_bincode_cache_and_keeps_it_across_restarts() {
+        let dir = cache_dir("sqlite");
+        let folders = cached_library(&dir);
+        let walk = WalkOptions::default();
+        let path = dir.join(super::SQLITE_FILE);
+
+        let db = Database::open_sqlite(&path).unwrap();
+        assert!(db.load_folders(&dir, &folders, &walk).unwrap().is_empty());
+        assert_eq!(db.get_photos_count().unwrap(), 3);
+        db.add_no_gps_entries(vec![super::NoGpsEntry {
+            path: "/library/b/scan.jpg".to_string(),
+            relative_path: "scan.jpg".to_string(),
+            reason: "No GPS data found".to_string(),
+        }])
+        .unwrap();
+        drop(db);
+
+        // Rows survive without the bincode caches
+        for folder in &folders {
+            std::fs::remove_file(super::folder_cache_path(&dir, folder)).unwrap();
+        }
+        let db = Database::open_sqlite(&path).unwrap();
+        assert!(db.load_folders(&dir, &folders, &walk).unwrap().is_empty());
+        assert_eq!(db.get_photos_count().unwrap(), 3);
+        assert!(db
+            .get_no_gps_entry(&PhotoId::from_relative_path("scan.jpg"))
+            .unwrap()
+            .is_some());
+
+        // Removing a folder drops its rows; a renamed one is processed again
+        let folders = ["/library/a-renamed".to_string(), "/library/b/".to_string()];
+        let missing = db.load_folders(&dir, &folders, &walk).unwrap();
+        assert_eq!(missing, ["/library/a-renamed"]);
+        let photos = db.get_all_photos().unwrap();
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].relative_path, "b1.jpg");
+        assert_eq!(db.get_no_gps_entries().unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn command_line_folders_leave_the_saved_sqlite_rows() {
+        let dir = cache_dir("sqlite_override");
+        let folders = cached_library(&dir);
+        let walk = WalkOptions::default();
+        let path = dir.join(super::SQLITE_FILE);
+        let db = Database::open_sqlite(&path).unwrap();
+        assert!(db.load_folders(&dir, &folders, &walk).unwrap().is_empty());
+
+        // A run on another folder only loads that one
+        let other = ["/library/trip".to_string()];
+        assert_eq!(db.add_cached_folders(&dir, &other, &walk).unwrap(), other);
+        drop(db);
+        for folder in &folders {
+            std::fs::remove_file(super::folder_cache_path(&dir, folder)).unwrap();
+        }
+
+        // The next normal run finds the saved folders' rows without their caches
+        let db = Database::open_sqlite(&path).unwrap();
+        assert!(db.load_folders(&dir, &folders, &walk).unwrap().is_empty());
+        assert_eq!(db.folder_photo_counts(&folders).unwrap(), [2, 1]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Folder with a long path as in real libraries, holding `count` photos
+    fn uncompressed_library(count: usize) -> super::UncompressedCachedFolder {
+        let folder = "/home/user/Pictures/Camera Uploads/Phone Backup".to_string();
+        let photos = (0..count)
+            .map(|i| {
+                let name = format!("{}/IMG_{:05}.jpg", 2015 + i % 10, i);
+                let mut photo = photo_in(&folder, &name);
+                photo.filename = format!("IMG_{:05}.jpg", i);
+                photo.datetime = format!(
+                    "{}-06-01 12:{:02}:{:02}",
+                    2015 + i % 10,
+                    i / 60 % 60,
+                    i % 60
+                );
+                photo.coords =
+                    GpsCoordinate::new(48.0 + i as f64 * 1e-4, 2.0 + i as f64 * 1e-4).unwrap();
+                super::UncompressedPhoto {
+                    id: photo.id,
+                    filename: photo.filename,
+                    relative_path: photo.relative_path,
+                    datetime: photo.datetime,
+                    coords: photo.coords,
+                    file_path: photo.file_path,
+                    is_heic: photo.is_heic,
+                    is_motion_photo: photo.is_motion_photo,
+                    source: photo.source,
+                    speed_kmh: photo.speed_kmh,
+                    gps_dop: photo.gps_dop,
+                }
+            })
+            .collect();
+        super::UncompressedCachedFolder {
+            version: super::UNCOMPRESSED_CACHE_VERSION,
+            source_path: folder,
+            signature: Default::default(),
+            photos,
+            no_gps: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compressed_cache_is_three_times_smaller() {
+        let dir = cache_dir("compressed");
+        let legacy = uncompressed_library(10_000);
+        let uncompressed_size = bincode::serialize(&legacy).unwrap().len();
+
+        let folder = legacy.source_path.clone();
+        let path = super::folder_cache_path(&dir, &folder);
+        let mut cache = super::CachedFolder::from(legacy);
+        cache.version = super::CACHE_VERSION;
+        super::write_folder_cache(&path, &cache).unwrap();
+        let compressed_size = std::fs::metadata(&path).unwrap().len() as usize;
+        assert!(
+            compressed_size * 3 <= uncompressed_size,
+            "{compressed_size} vs {uncompressed_size} bytes"
+        );
+
+        // Paths and ids are restored on load
+        let cache = super::read_folder_cache(&dir, &folder).unwrap().unwrap();
+        let (photos, _) = cache.into_photos();
+        assert_eq!(photos.len(), 10_000);
+        assert_eq!(photos[1].file_path, format!("{folder}/2016/IMG_00001.jpg"));
+        assert_eq!(
+            photos[1].id,
+            PhotoId::from_relative_path("2016/IMG_00001.jpg")
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn uncompressed_cache_of_previous_version_is_loaded() {
+        let dir = cache_dir("uncompressed");
+        let mut legacy = uncompressed_library(2);
+        // A file outside the folder keeps its own path
+        legacy.photos[1].file_path = "/elsewhere/IMG_00001.jpg".to_string();
+        let folder = legacy.source_path.clone();
+        let path = super::folder_cache_path(&dir, &folder);
+        std::fs::write(&path, bincode::serialize(&legacy).unwrap()).unwrap();
+
+        let db = Database::new().unwrap();
+        let folders = std::slice::from_ref(&folder);
+        assert!(db
+            .load_folders(&dir, folders, &WalkOptions::default())
+            .unwrap()
+            .is_empty());
+        let mut paths: Vec<_> = db
+            .get_all_photos()
+            .unwrap()
+            .into_iter()
+            .map(|p| p.file_path)
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "/elsewhere/IMG_00001.jpg".to_string(),
+                format!("{folder}/2015/IMG_00000.jpg")
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cache_without_locations_is_geocoded_on_load() {
+        let dir = cache_dir("ungeocoded");
+        let folder = "/photos/library".to_string();
+        let legacy = super::UngeocodedCachedFolder {
+            version: super::UNGEOCODED_CACHE_VERSION,
+            source_path: folder.clone(),
+            signature: Default::default(),
+            photos: vec![super::UngeocodedPhoto {
+                filename: "IMG_0001.jpg".to_string(),
+                relative_path: "IMG_0001.jpg".to_string(),
+                datetime: "2024-06-01 12:00:00".to_string(),
+                coords: GpsCoordinate::new(40.7128, -74.006).unwrap(),
+                file_path: None,
+                is_heic: false,
+                is_motion_photo: false,
+                source: ExifSource::Exif,
+                speed_kmh: None,
+                gps_dop: None,
+            }],
+            no_gps: Vec::new(),
+        };
+        let path = super::folder_cache_path(&dir, &folder);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, &bincode::serialize(&legacy).unwrap()).unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let cache = super::read_folder_cache(&dir, &folder).unwrap().unwrap();
+        let (photos, _) = cache.into_photos();
+        assert_eq!(photos[0].location.as_deref(), Some("New York City, US"));
+        assert_eq!(photos[0].place.as_ref().unwrap().country, "US");
+        assert_eq!(photos[0].file_path, format!("{folder}/IMG_0001.jpg"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,73 @@
+//! Photos grouped by the first folder below the watched folder, served at `/api/albums`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::places::recency;
+use super::PhotoMetadata;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Album {
+    pub name: String,
+    pub count: usize,
+    /// Relative path of the newest photo of the album
+    pub cover_photo_relative_path: String,
+}
+
+/// Groups photos by `PhotoMetadata::album`, sorted by name
+pub fn group_by_album<'a>(photos: impl IntoIterator<Item = &'a PhotoMetadata>) -> Vec<Album> {
+    let mut groups: HashMap<&str, (usize, &PhotoMetadata)> = HashMap::new();
+    for photo in photos {
+        let (count, cover) = groups.entry(photo.album()).or_insert((0, photo));
+        *count += 1;
+        if recency(photo) > recency(cover) {
+            *cover = photo;
+        }
+    }
+
+    let mut albums: Vec<Album> = groups
+        .into_iter()
+        .map(|(name, (count, cover))| Album {
+            name: name.to_string(),
+            count,
+            cover_photo_relative_path: cover.relative_path.clone(),
+        })
+        .collect();
+    albums.sort_by(|a, b| a.name.cmp(&b.name));
+    albums
+}
+
+#[cfg(test)]
+mod tests {
+    use super::group_by_album;
+    use crate::database::{PhotoMetadata, ROOT_ALBUM};
+
+    fn photo(relative_path: &str, datetime: &str) -> PhotoMetadata {
+        PhotoMetadata::sample(relative_path, datetime, 48.85, 2.35)
+    }
+
+    #[test]
+    fn photos_are_grouped_by_first_folder() {
+        let photos = [
+            photo("Holidays/2022/Paris/IMG_001.jpg", "2022-07-01 10:00:00"),
+            photo("Holidays/2023/IMG_002.jpg", "2023-08-01 10:00:00"),
+            photo("Holidays/IMG_003.jpg", "Unknown Date"),
+            photo("Family/IMG_004.jpg", "2021-01-01 10:00:00"),
+            photo("IMG_005.jpg", "2020-01-01 10:00:00"),
+        ];
+        assert_eq!(photos[0].album(), "Holidays");
+        assert_eq!(photos[4].album(), ROOT_ALBUM);
+
+        let albums = group_by_album(&photos);
+        let names: Vec<&str> = albums.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, [ROOT_ALBUM, "Family", "Holidays"]);
+        let holidays = &albums[2];
+        assert_eq!(holidays.count, 3);
+        assert_eq!(
+            holidays.cover_photo_relative_path,
+            "Holidays/2023/IMG_002.jpg"
+        );
+        assert!(group_by_album(&[]).is_empty());
+    }
+}
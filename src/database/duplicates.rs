@@ -0,0 +1,154 @@
+//! Photos that look like copies of each other: the same capture second and
+//! position, confirmed by identical file contents when the files can be read.
+//! Served at `/api/photos/duplicates`.
+
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+use xxhash_rust::xxh3::Xxh3;
+
+use super::PhotoMetadata;
+use crate::exif_parser::generic::parse_timestamp;
+
+/// Degrees per position bucket, about 11 m of latitude
+const POSITION_BUCKET_DEG: f64 = 0.0001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateReason {
+    /// Same capture second and position but different files, e.g. a burst
+    GpsDatetime,
+    /// Same capture second and position and identical file contents
+    Both,
+}
+
+/// Capture second and rounded position; None for undated photos
+fn bucket(photo: &PhotoMetadata) -> Option<(i64, i64, i64)> {
+    let time = parse_timestamp(&photo.datetime)?;
+    let cell = |degrees: f64| (degrees / POSITION_BUCKET_DEG).round() as i64;
+    Some((time, cell(photo.coords.lat()), cell(photo.coords.lng())))
+}
+
+/// Groups of two or more dated photos sharing a capture second and position bucket,
+/// oldest group first, each sorted by relative path
+pub(super) fn candidate_groups(photos: Vec<PhotoMetadata>) -> Vec<Vec<PhotoMetadata>> {
+    let mut keyed: Vec<_> = photos
+        .into_iter()
+        .filter_map(|photo| Some((bucket(&photo)?, photo)))
+        .collect();
+    keyed.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then_with(|| a.1.relative_path.cmp(&b.1.relative_path))
+    });
+
+    let mut groups = Vec::new();
+    let mut current: Vec<PhotoMetadata> = Vec::new();
+    let mut current_key = None;
+    for (key, photo) in keyed {
+        if current_key != Some(key) {
+            if current.len() > 1 {
+                groups.push(std::mem::take(&mut current));
+            }
+            current.clear();
+            current_key = Some(key);
+        }
+        current.push(photo);
+    }
+    if current.len() > 1 {
+        groups.push(current);
+    }
+    groups
+}
+
+/// XXH3 of a file's contents, None when it can't be read
+fn content_hash(path: &Path) -> Option<u64> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut hasher = Xxh3::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer).ok()? {
+            0 => return Some(hasher.digest()),
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+}
+
+/// `Both` when every photo of a candidate group has the same readable contents
+pub fn verify(group: &[PhotoMetadata]) -> DuplicateReason {
+    let mut hashes = group
+        .iter()
+        .map(|photo| content_hash(Path::new(&photo.file_path)));
+    let first = hashes.next().flatten();
+    if first.is_some() && hashes.all(|hash| hash == first) {
+        DuplicateReason::Both
+    } else {
+        DuplicateReason::GpsDatetime
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{candidate_groups, verify, DuplicateReason};
+    use crate::database::PhotoMetadata;
+
+    fn names(groups: &[Vec<PhotoMetadata>]) -> Vec<Vec<&str>> {
+        groups
+            .iter()
+            .map(|group| group.iter().map(|p| p.relative_path.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn same_second_and_position_are_grouped() {
+        let groups = candidate_groups(vec![
+            // A burst: same second, a few centimetres apart, sub-seconds differ
+            PhotoMetadata::sample("burst2.jpg", "2024-06-01 10:00:00.400", 48.858_40, 2.294_50),
+            PhotoMetadata::sample("burst1.jpg", "2024-06-01 10:00:00.100", 48.858_41, 2.294_50),
+            // The next second and another position are separate
+            PhotoMetadata::sample("later.jpg", "2024-06-01 10:00:01", 48.858_40, 2.294_50),
+            PhotoMetadata::sample("moved.jpg", "2024-06-01 10:00:00", 48.859_40, 2.294_50),
+            // A copy from another device, earlier in the day
+            PhotoMetadata::sample("phone/copy.jpg", "2024-06-01 08:00:00", 52.52, 13.405),
+            PhotoMetadata::sample("camera/copy.jpg", "2024-06-01 08:00:00", 52.52, 13.405),
+            // Undated photos are never duplicates
+            PhotoMetadata::sample("a.jpg", "Unknown Date", 0.0, 0.0),
+            PhotoMetadata::sample("b.jpg", "Unknown Date", 0.0, 0.0),
+        ]);
+        assert_eq!(
+            names(&groups),
+            [
+                vec!["camera/copy.jpg", "phone/copy.jpg"],
+                vec!["burst1.jpg", "burst2.jpg"],
+            ]
+        );
+        assert!(candidate_groups(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn identical_contents_confirm_a_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            let mut photo = PhotoMetadata::sample(name, "2024-06-01 10:00:00", 48.85, 2.35);
+            photo.file_path = path.to_string_lossy().into_owned();
+            photo
+        };
+        let original = file("original.jpg", b"same bytes");
+        let copy = file("copy.jpg", b"same bytes");
+        let burst = file("burst.jpg", b"other bytes");
+        let mut missing = original.clone();
+        missing.file_path = dir.path().join("gone.jpg").to_string_lossy().into_owned();
+
+        assert_eq!(
+            verify(&[original.clone(), copy.clone()]),
+            DuplicateReason::Both
+        );
+        assert_eq!(
+            verify(&[original.clone(), copy, burst]),
+            DuplicateReason::GpsDatetime
+        );
+        assert_eq!(verify(&[original, missing]), DuplicateReason::GpsDatetime);
+    }
+}
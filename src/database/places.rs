@@ -0,0 +1,205 @@
+//! Photos grouped by their location name, served at `/api/places`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::stats::{date_of, DateRange};
+use super::PhotoMetadata;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlaceGroup {
+    pub name: String,
+    pub count: usize,
+    /// Relative path of the newest dated photo of the place
+    pub representative: String,
+    pub date_range: Option<DateRange>,
+    /// Centroid of the photos
+    pub lat: f64,
+    pub lng: f64,
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lng: f64,
+    pub max_lng: f64,
+}
+
+/// Orders photos by capture date; undated ones ("Unknown Date") come before all dated ones
+pub(super) fn recency(photo: &PhotoMetadata) -> (bool, &str, &str) {
+    (
+        date_of(&photo.datetime).is_some(),
+        &photo.datetime,
+        &photo.relative_path,
+    )
+}
+
+/// Running totals of one place
+struct Group<'a> {
+    count: usize,
+    lat_sum: f64,
+    lng_sum: f64,
+    min_lat: f64,
+    max_lat: f64,
+    min_lng: f64,
+    max_lng: f64,
+    newest: &'a PhotoMetadata,
+    earliest: Option<&'a str>,
+    latest: Option<&'a str>,
+}
+
+impl<'a> Group<'a> {
+    fn new(photo: &'a PhotoMetadata) -> Self {
+        Group {
+            count: 0,
+            lat_sum: 0.0,
+            lng_sum: 0.0,
+            min_lat: f64::INFINITY,
+            max_lat: f64::NEG_INFINITY,
+            min_lng: f64::INFINITY,
+            max_lng: f64::NEG_INFINITY,
+            newest: photo,
+            earliest: None,
+            latest: None,
+        }
+    }
+
+    fn add(&mut self, photo: &'a PhotoMetadata) {
+        let (lat, lng) = (photo.coords.lat(), photo.coords.lng());
+        self.count += 1;
+        self.lat_sum += lat;
+        self.lng_sum += lng;
+        self.min_lat = self.min_lat.min(lat);
+        self.max_lat = self.max_lat.max(lat);
+        self.min_lng = self.min_lng.min(lng);
+        self.max_lng = self.max_lng.max(lng);
+        if recency(photo) > recency(self.newest) {
+            self.newest = photo;
+        }
+        if let Some(date) = date_of(&photo.datetime) {
+            self.earliest = Some(self.earliest.map_or(date, |d| d.min(date)));
+            self.latest = Some(self.latest.map_or(date, |d| d.max(date)));
+        }
+    }
+}
+
+/// Groups photos with a location name; the largest places come first, ties by name
+pub fn group_by_place<'a>(photos: impl IntoIterator<Item = &'a PhotoMetadata>) -> Vec<PlaceGroup> {
+    let mut groups: HashMap<&str, Group> = HashMap::new();
+    for photo in photos {
+        if let Some(name) = &photo.location {
+            groups
+                .entry(name)
+                .or_insert_with(|| Group::new(photo))
+                .add(photo);
+        }
+    }
+
+    let mut places: Vec<PlaceGroup> = groups
+        .into_iter()
+        .map(|(name, group)| PlaceGroup {
+            name: name.to_string(),
+            count: group.count,
+            representative: group.newest.relative_path.clone(),
+            date_range: group
+                .earliest
+                .zip(group.latest)
+                .map(|(earliest, latest)| DateRange {
+                    earliest: earliest.to_string(),
+                    latest: latest.to_string(),
+                }),
+            lat: group.lat_sum / group.count as f64,
+            lng: group.lng_sum / group.count as f64,
+            min_lat: group.min_lat,
+            max_lat: group.max_lat,
+            min_lng: group.min_lng,
+            max_lng: group.max_lng,
+        })
+        .collect();
+    places.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    places
+}
+
+#[cfg(test)]
+mod tests {
+    use super::group_by_place;
+    use crate::database::PhotoMetadata;
+
+    fn photo(
+        name: &str,
+        location: Option<&str>,
+        lat: f64,
+        lng: f64,
+        datetime: &str,
+    ) -> PhotoMetadata {
+        PhotoMetadata {
+            location: location.map(str::to_string),
+            ..PhotoMetadata::sample(name, datetime, lat, lng)
+        }
+    }
+
+    #[test]
+    fn photos_are_grouped_by_location() {
+        let photos = [
+            photo(
+                "rome1.jpg",
+                Some("Rome, IT"),
+                41.90,
+                12.50,
+                "2024-05-02 10:00:00",
+            ),
+            photo(
+                "paris1.jpg",
+                Some("Paris, FR"),
+                48.80,
+                2.30,
+                "2024-06-01 09:00:00",
+            ),
+            photo(
+                "paris2.jpg",
+                Some("Paris, FR"),
+                48.90,
+                2.40,
+                "2024-06-03 18:00:00",
+            ),
+            photo("paris3.jpg", Some("Paris, FR"), 48.85, 2.35, "Unknown Date"),
+            photo(
+                "rome2.jpg",
+                Some("Rome, IT"),
+                41.80,
+                12.40,
+                "2024-05-01 10:00:00",
+            ),
+            photo("sea.jpg", None, 30.0, -40.0, "2024-07-01 12:00:00"),
+        ];
+        let places = group_by_place(&photos);
+
+        assert_eq!(places.len(), 2);
+        let paris = &places[0];
+        assert_eq!((paris.name.as_str(), paris.count), ("Paris, FR", 3));
+        assert_eq!(paris.representative, "paris2.jpg");
+        assert!((paris.lat - 48.85).abs() < 1e-9);
+        assert!((paris.lng - 2.35).abs() < 1e-9);
+        assert_eq!((paris.min_lat, paris.max_lat), (48.80, 48.90));
+        assert_eq!((paris.min_lng, paris.max_lng), (2.30, 2.40));
+        let range = paris.date_range.as_ref().unwrap();
+        assert_eq!(
+            (range.earliest.as_str(), range.latest.as_str()),
+            ("2024-06-01", "2024-06-03")
+        );
+
+        let rome = &places[1];
+        assert_eq!(rome.count, 2);
+        assert_eq!(rome.representative, "rome1.jpg");
+    }
+
+    #[test]
+    fn ties_are_sorted_by_name() {
+        let photos = [
+            photo("b.jpg", Some("Berlin, DE"), 52.5, 13.4, "Unknown Date"),
+            photo("a.jpg", Some("Amsterdam, NL"), 52.4, 4.9, "Unknown Date"),
+        ];
+        let places = group_by_place(&photos);
+        assert_eq!(places[0].name, "Amsterdam, NL");
+        assert!(places[0].date_range.is_none());
+        assert!(group_by_place(&[]).is_empty());
+    }
+}
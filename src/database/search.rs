@@ -0,0 +1,176 @@
+//! Case-insensitive substring search over photo filenames and capture dates.
+//!
+//! The memory backend keeps a trigram index: every 3-byte window of the
+//! lowercased filename and datetime points to the photos containing it. A query
+//! of three bytes or more only checks the photos holding all of its trigrams.
+
+use std::collections::HashMap;
+
+use super::{PhotoId, PhotoMetadata};
+
+/// Whether the photo's filename or datetime contains the lowercased query
+pub(super) fn matches(photo: &PhotoMetadata, query_lower: &str) -> bool {
+    photo.filename.to_lowercase().contains(query_lower)
+        || photo.datetime.to_lowercase().contains(query_lower)
+}
+
+/// Distinct trigrams of the text, sorted
+fn trigrams(text: &str) -> Vec<[u8; 3]> {
+    let mut grams: Vec<[u8; 3]> = text
+        .as_bytes()
+        .windows(3)
+        .map(|w| [w[0], w[1], w[2]])
+        .collect();
+    grams.sort_unstable();
+    grams.dedup();
+    grams
+}
+
+/// Trigrams of both searchable fields of a photo
+fn photo_trigrams(photo: &PhotoMetadata) -> Vec<[u8; 3]> {
+    let mut grams = trigrams(&photo.filename.to_lowercase());
+    grams.extend(trigrams(&photo.datetime.to_lowercase()));
+    grams.sort_unstable();
+    grams.dedup();
+    grams
+}
+
+/// Intersection of ascending slot lists
+fn intersect(lists: &mut [&Vec<u32>]) -> Vec<u32> {
+    lists.sort_by_key(|list| list.len());
+    let Some((first, rest)) = lists.split_first() else {
+        return Vec::new();
+    };
+    first
+        .iter()
+        .copied()
+        .filter(|slot| rest.iter().all(|list| list.binary_search(slot).is_ok()))
+        .collect()
+}
+
+/// Trigram index keyed by slots handed out in insertion order. Removed photos
+/// only free their slot; the stale entries are dropped when the index is rebuilt.
+#[derive(Default)]
+pub(super) struct TrigramIndex {
+    ids: Vec<Option<PhotoId>>,
+    slots: HashMap<PhotoId, u32>,
+    /// Ascending slots per trigram
+    grams: HashMap<[u8; 3], Vec<u32>>,
+    stale: usize,
+}
+
+impl TrigramIndex {
+    pub(super) fn insert(&mut self, photo: &PhotoMetadata) {
+        self.remove(&photo.id);
+        let slot = self.ids.len() as u32;
+        self.ids.push(Some(photo.id.clone()));
+        self.slots.insert(photo.id.clone(), slot);
+        for gram in photo_trigrams(photo) {
+            self.grams.entry(gram).or_default().push(slot);
+        }
+    }
+
+    pub(super) fn remove(&mut self, id: &PhotoId) {
+        if let Some(slot) = self.slots.remove(id) {
+            self.ids[slot as usize] = None;
+            self.stale += 1;
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        *self = TrigramIndex::default();
+    }
+
+    /// True once most slots belong to removed photos
+    pub(super) fn needs_rebuild(&self) -> bool {
+        self.stale > 1024 && self.stale > self.slots.len()
+    }
+
+    pub(super) fn rebuild<'a>(&mut self, photos: impl IntoIterator<Item = &'a PhotoMetadata>) {
+        self.clear();
+        for photo in photos {
+            self.insert(photo);
+        }
+    }
+
+    /// Photos that may contain the lowercased query, or None when it is too short to
+    /// use the index and every photo has to be checked
+    pub(super) fn candidates(&self, query_lower: &str) -> Option<Vec<&PhotoId>> {
+        let grams = trigrams(query_lower);
+        if grams.is_empty() {
+            return None;
+        }
+        let mut lists = Vec::with_capacity(grams.len());
+        for gram in &grams {
+            match self.grams.get(gram) {
+                Some(list) => lists.push(list),
+                None => return Some(Vec::new()),
+            }
+        }
+        Some(
+            intersect(&mut lists)
+                .into_iter()
+                .filter_map(|slot| self.ids[slot as usize].as_ref())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches, TrigramIndex};
+    use crate::database::PhotoMetadata;
+
+    fn photo(name: &str, datetime: &str) -> PhotoMetadata {
+        PhotoMetadata::sample(name, datetime, 48.85, 2.35)
+    }
+
+    fn names(index: &TrigramIndex, query: &str) -> Vec<String> {
+        let mut found: Vec<String> = index
+            .candidates(query)
+            .unwrap()
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn candidates_contain_all_query_trigrams() {
+        let photos = [
+            photo("IMG_4001.jpg", "2023-07-14 10:00:00"),
+            photo("IMG_5001.jpg", "2023-08-01 10:00:00"),
+            photo("DSC_0042.JPG", "2024-07-02 10:00:00"),
+        ];
+        let mut index = TrigramIndex::default();
+        for p in &photos {
+            index.insert(p);
+        }
+
+        let id = |i: usize| photos[i].id.to_string();
+        assert_eq!(names(&index, "img_4"), [id(0)]);
+        assert_eq!(names(&index, "2023-07"), [id(0)]);
+        let mut dsc = names(&index, "dsc");
+        dsc.retain(|found| *found == id(2));
+        assert_eq!(dsc.len(), 1);
+        assert!(names(&index, "xyz").is_empty());
+        assert!(index.candidates("im").is_none());
+        assert!(matches(&photos[2], "dsc_00"));
+    }
+
+    #[test]
+    fn replaced_and_removed_photos_are_not_found() {
+        let mut index = TrigramIndex::default();
+        let mut p = photo("IMG_0001.jpg", "2023-07-14 10:00:00");
+        index.insert(&p);
+        p.datetime = "2019-01-01 00:00:00".to_string();
+        index.insert(&p);
+        assert!(names(&index, "2023").is_empty());
+        assert_eq!(names(&index, "2019").len(), 1);
+
+        index.remove(&p.id);
+        assert!(names(&index, "img").is_empty());
+        assert!(!index.needs_rebuild());
+    }
+}
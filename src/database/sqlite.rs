@@ -0,0 +1,517 @@
+//! SQLite storage of the photo database, selected with `storage = sqlite`.
+//! Rows are updated in place, so nothing has to be written on exit and only
+//! the queried photos are decoded.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{
+    is_in_folder, source_path_cache_key, FileStamps, FolderSignature, NoGpsEntry, PhotoId,
+    PhotoMetadata, CACHE_VERSION,
+};
+
+/// Newest first with undated photos last, like `SortOrder::DateDesc`
+macro_rules! newest_first {
+    () => {
+        "ORDER BY datetime = 'Unknown Date', datetime DESC, relative_path"
+    };
+}
+
+/// The full record is a bincode blob; the columns next to it exist for lookups
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS photos (
+    id TEXT PRIMARY KEY,
+    relative_path TEXT NOT NULL,
+    file_path TEXT NOT NULL,
+    datetime TEXT NOT NULL,
+    lat REAL NOT NULL,
+    lng REAL NOT NULL,
+    data BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS photos_relative_path ON photos (relative_path);
+CREATE INDEX IF NOT EXISTS photos_position ON photos (lat, lng);
+CREATE INDEX IF NOT EXISTS photos_datetime ON photos (datetime);
+CREATE TABLE IF NOT EXISTS no_gps (
+    path TEXT PRIMARY KEY,
+    relative_path TEXT NOT NULL,
+    reason TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS no_gps_relative_path ON no_gps (relative_path);
+CREATE TABLE IF NOT EXISTS folders (
+    cache_key TEXT PRIMARY KEY,
+    signature BLOB NOT NULL,
+    file_stamps BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS folder_runs (
+    cache_key TEXT PRIMARY KEY,
+    processed_at INTEGER NOT NULL
+);
+";
+
+#[derive(Clone)]
+pub(super) struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+fn decode(blob: &[u8]) -> Result<PhotoMetadata> {
+    bincode::deserialize(blob).context("Decoding photo row")
+}
+
+fn insert_photo(conn: &Connection, photo: &PhotoMetadata) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO photos (id, relative_path, file_path, datetime, lat, lng, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?
+    .execute(params![
+        photo.id.as_str(),
+        photo.relative_path,
+        photo.file_path,
+        photo.datetime,
+        photo.coords.lat(),
+        photo.coords.lng(),
+        bincode::serialize(photo)?,
+    ])?;
+    Ok(())
+}
+
+fn insert_no_gps(conn: &Connection, entry: &NoGpsEntry) -> Result<()> {
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO no_gps (path, relative_path, reason) VALUES (?1, ?2, ?3)",
+    )?
+    .execute(params![entry.path, entry.relative_path, entry.reason])?;
+    Ok(())
+}
+
+/// Stores the signature and file stamps of a folder along with the time they were stored
+fn record_folder(
+    conn: &Connection,
+    folder: &str,
+    signature: &FolderSignature,
+    stamps: &FileStamps,
+) -> Result<()> {
+    let key = source_path_cache_key(folder);
+    conn.execute(
+        "INSERT OR REPLACE INTO folders (cache_key, signature, file_stamps) VALUES (?1, ?2, ?3)",
+        params![
+            key,
+            bincode::serialize(signature)?,
+            bincode::serialize(stamps)?
+        ],
+    )?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    conn.execute(
+        "INSERT OR REPLACE INTO folder_runs (cache_key, processed_at) VALUES (?1, ?2)",
+        params![key, now as i64],
+    )?;
+    Ok(())
+}
+
+fn query_photos(
+    conn: &Connection,
+    sql: &str,
+    args: impl rusqlite::Params,
+) -> Result<Vec<PhotoMetadata>> {
+    let mut statement = conn.prepare_cached(sql)?;
+    let blobs = statement
+        .query_map(args, |row| row.get::<_, Vec<u8>>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    blobs.iter().map(|blob| decode(blob)).collect()
+}
+
+impl SqliteStore {
+    pub(super) fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite database {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+
+        // Rows hold bincode records, which change shape with the cache version
+        let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version != CACHE_VERSION {
+            if version != 0 {
+                eprintln!(
+                    "⚠️ SQLite database version mismatch (found {}, expected {})",
+                    version, CACHE_VERSION
+                );
+                eprintln!("🗑️ Dropping outdated tables");
+            }
+            conn.execute_batch(
+                "DROP TABLE IF EXISTS photos; DROP TABLE IF EXISTS no_gps;
+                 DROP TABLE IF EXISTS folders; DROP TABLE IF EXISTS folder_runs;",
+            )?;
+            conn.pragma_update(None, "user_version", CACHE_VERSION)?;
+        }
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    pub(super) fn clear(&self) -> Result<()> {
+        self.conn.lock().unwrap().execute_batch(
+            "DELETE FROM photos; DELETE FROM no_gps; DELETE FROM folders; DELETE FROM folder_runs;",
+        )?;
+        Ok(())
+    }
+
+    /// Inserts normalized photos in one transaction
+    pub(super) fn insert_photos(&self, photos: &[PhotoMetadata]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for photo in photos {
+            insert_photo(&tx, photo)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// All photos, newest first
+    pub(super) fn all_photos(&self) -> Result<Vec<PhotoMetadata>> {
+        let conn = self.conn.lock().unwrap();
+        query_photos(
+            &conn,
+            concat!("SELECT data FROM photos ", newest_first!()),
+            [],
+        )
+    }
+
+    /// Photos of an album (first folder of the relative path), newest first
+    pub(super) fn album_photos(&self, album: &str) -> Result<Vec<PhotoMetadata>> {
+        let conn = self.conn.lock().unwrap();
+        if album == super::ROOT_ALBUM {
+            return query_photos(
+                &conn,
+                concat!(
+                    "SELECT data FROM photos WHERE instr(relative_path, '/') = 0 ",
+                    newest_first!()
+                ),
+                [],
+            );
+        }
+        query_photos(
+            &conn,
+            concat!(
+                "SELECT data FROM photos WHERE substr(relative_path, 1, length(?1) + 1) = ?1 || '/' ",
+                newest_first!()
+            ),
+            params![album],
+        )
+    }
+
+    /// Ids of the photos of `album_photos`, or of all photos, newest first
+    pub(super) fn album_photo_ids(&self, album: Option<&str>) -> Result<Vec<PhotoId>> {
+        let conn = self.conn.lock().unwrap();
+        let ids = match album {
+            None => {
+                let mut statement =
+                    conn.prepare_cached(concat!("SELECT id FROM photos ", newest_first!()))?;
+                let ids = statement.query_map([], |row| row.get(0))?;
+                ids.collect::<rusqlite::Result<Vec<String>>>()?
+            }
+            Some(album) if album == super::ROOT_ALBUM => {
+                let mut statement = conn.prepare_cached(concat!(
+                    "SELECT id FROM photos WHERE instr(relative_path, '/') = 0 ",
+                    newest_first!()
+                ))?;
+                let ids = statement.query_map([], |row| row.get(0))?;
+                ids.collect::<rusqlite::Result<Vec<String>>>()?
+            }
+            Some(album) => {
+                let mut statement = conn.prepare_cached(
+                    concat!(
+                        "SELECT id FROM photos WHERE substr(relative_path, 1, length(?1) + 1) = ?1 || '/' ",
+                        newest_first!()
+                    ),
+                )?;
+                let ids = statement.query_map(params![album], |row| row.get(0))?;
+                ids.collect::<rusqlite::Result<Vec<String>>>()?
+            }
+        };
+        Ok(ids.into_iter().map(PhotoId).collect())
+    }
+
+    /// The photos with the given ids that still exist, in the same order
+    pub(super) fn photos_by_ids(&self, ids: &[PhotoId]) -> Result<Vec<PhotoMetadata>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare_cached("SELECT data FROM photos WHERE id = ?1")?;
+        let mut photos = Vec::with_capacity(ids.len());
+        for id in ids {
+            let blob: Option<Vec<u8>> = statement
+                .query_row(params![id.as_str()], |row| row.get(0))
+                .optional()?;
+            if let Some(blob) = blob {
+                photos.push(decode(&blob)?);
+            }
+        }
+        Ok(photos)
+    }
+
+    /// Deletes the photos with the given ids and returns how many existed
+    pub(super) fn remove_photos(&self, ids: &[PhotoId]) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut removed = 0;
+        {
+            let mut delete = tx.prepare("DELETE FROM photos WHERE id = ?1")?;
+            for id in ids {
+                removed += delete.execute(params![id.as_str()])?;
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Photos whose latitude lies in the given range, using the position index
+    pub(super) fn photos_in_latitudes(&self, south: f64, north: f64) -> Result<Vec<PhotoMetadata>> {
+        let conn = self.conn.lock().unwrap();
+        query_photos(
+            &conn,
+            "SELECT data FROM photos WHERE lat BETWEEN ?1 AND ?2",
+            params![south, north],
+        )
+    }
+
+    /// Photos whose relative path or datetime contains the text, ignoring ASCII case
+    pub(super) fn photos_matching(&self, text: &str) -> Result<Vec<PhotoMetadata>> {
+        let pattern = format!(
+            "%{}%",
+            text.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let conn = self.conn.lock().unwrap();
+        query_photos(
+            &conn,
+            "SELECT data FROM photos WHERE relative_path LIKE ?1 ESCAPE '\\' OR datetime LIKE ?1 ESCAPE '\\'",
+            params![pattern],
+        )
+    }
+
+    /// File paths of all photos, without decoding the records
+    pub(super) fn photo_paths(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare_cached("SELECT file_path FROM photos")?;
+        let paths = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(paths)
+    }
+
+    /// Relative paths of the photos and no-GPS entries stored under a folder
+    pub(super) fn folder_relative_paths(&self, folder: &str) -> Result<HashSet<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut relative_paths = HashSet::new();
+        for (table, column) in [("photos", "file_path"), ("no_gps", "path")] {
+            let mut statement =
+                conn.prepare_cached(&format!("SELECT {column}, relative_path FROM {table}"))?;
+            let rows = statement.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (path, relative_path) = row?;
+                if is_in_folder(&path, folder) {
+                    relative_paths.insert(relative_path);
+                }
+            }
+        }
+        Ok(relative_paths)
+    }
+
+    pub(super) fn count(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM photos", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    pub(super) fn photo_by_id(&self, id: &PhotoId) -> Result<Option<PhotoMetadata>> {
+        let conn = self.conn.lock().unwrap();
+        let blob: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT data FROM photos WHERE id = ?1",
+                params![id.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        blob.map(|blob| decode(&blob)).transpose()
+    }
+
+    pub(super) fn add_no_gps(&self, entries: &[NoGpsEntry]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for entry in entries {
+            insert_no_gps(&tx, entry)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Files without a usable location, sorted by path
+    pub(super) fn no_gps_entries(&self) -> Result<Vec<NoGpsEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare_cached("SELECT path, relative_path, reason FROM no_gps ORDER BY path")?;
+        let entries = statement
+            .query_map([], |row| {
+                Ok(NoGpsEntry {
+                    path: row.get(0)?,
+                    relative_path: row.get(1)?,
+                    reason: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Stores a normalized photo and drops it from the no-GPS list
+    pub(super) fn upsert_geotagged(&self, photo: &PhotoMetadata) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM no_gps WHERE relative_path = ?1",
+            params![photo.relative_path],
+        )?;
+        insert_photo(&tx, photo)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Signature of a folder when its photos were last stored
+    pub(super) fn folder_signature(&self, folder: &str) -> Result<Option<FolderSignature>> {
+        let conn = self.conn.lock().unwrap();
+        let blob: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT signature FROM folders WHERE cache_key = ?1",
+                params![source_path_cache_key(folder)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(blob.and_then(|blob| bincode::deserialize(&blob).ok()))
+    }
+
+    /// File stamps of a folder when its photos were last stored
+    pub(super) fn folder_file_stamps(&self, folder: &str) -> Result<FileStamps> {
+        let conn = self.conn.lock().unwrap();
+        let blob: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT file_stamps FROM folders WHERE cache_key = ?1",
+                params![source_path_cache_key(folder)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(blob
+            .and_then(|blob| bincode::deserialize(&blob).ok())
+            .unwrap_or_default())
+    }
+
+    pub(super) fn save_folder_signature(
+        &self,
+        folder: &str,
+        signature: &FolderSignature,
+        stamps: &FileStamps,
+    ) -> Result<()> {
+        record_folder(&self.conn.lock().unwrap(), folder, signature, stamps)
+    }
+
+    /// Unix time in seconds when the photos of a folder were last stored
+    pub(super) fn folder_processed_at(&self, folder: &str) -> Result<Option<u64>> {
+        let conn = self.conn.lock().unwrap();
+        let processed_at: Option<i64> = conn
+            .query_row(
+                "SELECT processed_at FROM folder_runs WHERE cache_key = ?1",
+                params![source_path_cache_key(folder)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(processed_at.map(|secs| secs as u64))
+    }
+
+    /// Replaces everything stored for a folder, e.g. when importing its bincode cache
+    pub(super) fn replace_folder(
+        &self,
+        folder: &str,
+        photos: &[PhotoMetadata],
+        no_gps: &[NoGpsEntry],
+        signature: &FolderSignature,
+        stamps: &FileStamps,
+    ) -> Result<()> {
+        self.remove_where(|path, _| is_in_folder(path, folder))?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for photo in photos {
+            insert_photo(&tx, photo)?;
+        }
+        for entry in no_gps {
+            insert_no_gps(&tx, entry)?;
+        }
+        record_folder(&tx, folder, signature, stamps)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Drops the photos, no-GPS entries and signature of a folder
+    pub(super) fn remove_folder(&self, folder: &str) -> Result<()> {
+        self.remove_where(|path, _| is_in_folder(path, folder))?;
+        let conn = self.conn.lock().unwrap();
+        let key = source_path_cache_key(folder);
+        conn.execute("DELETE FROM folders WHERE cache_key = ?1", params![key])?;
+        conn.execute("DELETE FROM folder_runs WHERE cache_key = ?1", params![key])?;
+        Ok(())
+    }
+
+    /// Drops photos and no-GPS entries outside all of the given folders
+    pub(super) fn retain_folders(&self, folders: &[String]) -> Result<()> {
+        let keys: Vec<String> = folders.iter().map(|f| source_path_cache_key(f)).collect();
+        self.remove_where(|path, _| !folders.iter().any(|folder| is_in_folder(path, folder)))?;
+
+        let conn = self.conn.lock().unwrap();
+        let stored = conn
+            .prepare("SELECT cache_key FROM folders")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for key in stored.iter().filter(|key| !keys.contains(key)) {
+            conn.execute("DELETE FROM folders WHERE cache_key = ?1", params![key])?;
+            conn.execute("DELETE FROM folder_runs WHERE cache_key = ?1", params![key])?;
+        }
+        Ok(())
+    }
+
+    /// Drops the photos and no-GPS entries of a folder whose relative path is not kept
+    pub(super) fn retain_folder_files(
+        &self,
+        folder: &str,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<()> {
+        self.remove_where(|path, relative_path| is_in_folder(path, folder) && !keep(relative_path))
+    }
+
+    /// Deletes the rows whose file path and relative path match; folder checks need `Path`, not SQL
+    fn remove_where(&self, matches: impl Fn(&str, &str) -> bool) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (table, column) in [("photos", "file_path"), ("no_gps", "path")] {
+            let paths = tx
+                .prepare(&format!("SELECT {column}, relative_path FROM {table}"))?
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            let mut delete = tx.prepare(&format!("DELETE FROM {table} WHERE {column} = ?1"))?;
+            for (path, _) in paths
+                .iter()
+                .filter(|(path, relative_path)| matches(path, relative_path))
+            {
+                delete.execute(params![path])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! Aggregate numbers about the photo library, served at `/api/stats`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::PhotoMetadata;
+
+/// Entries kept in each top list
+const TOP_ENTRIES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct DateRange {
+    pub earliest: String,
+    pub latest: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct CountryCount {
+    pub country: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct LocationCount {
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct YearCount {
+    pub year: String,
+    pub count: usize,
+}
+
+/// Photos of one year with where one of them was taken, served at `/api/photos/years`
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct YearSummary {
+    #[schema(example = 2024)]
+    pub year: u16,
+    #[schema(example = 312)]
+    pub count: usize,
+    /// Position of the first photo of the year met, to center the map on
+    pub example_lat: f64,
+    pub example_lng: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct DayCount {
+    pub date: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct LibraryStats {
+    /// Geotagged photos plus files without a usable location
+    pub total_photos: usize,
+    pub photos_with_gps: usize,
+    pub photos_without_gps: usize,
+    pub heic_count: usize,
+    /// Sum of the original file sizes of the geotagged photos
+    pub total_size_bytes: u64,
+    /// Capture dates ("YYYY-MM-DD") of the oldest and newest dated photo
+    pub date_range: Option<DateRange>,
+    /// Dated photos per year, oldest first
+    pub per_year: Vec<YearCount>,
+    /// Every country, largest first
+    pub countries: Vec<CountryCount>,
+    pub top_locations: Vec<LocationCount>,
+    /// Dated photos per calendar day between the oldest and newest one
+    pub average_photos_per_day: f64,
+    pub most_active_day: Option<DayCount>,
+}
+
+/// "YYYY-MM-DD" part of a photo datetime, None for "Unknown Date" and the like
+pub(super) fn date_of(datetime: &str) -> Option<&str> {
+    let date = datetime.get(..10)?;
+    let bytes = date.as_bytes();
+    let digits = [0, 1, 2, 3, 5, 6, 8, 9]
+        .iter()
+        .all(|&i| bytes[i].is_ascii_digit());
+    (digits && bytes[4] == b'-' && bytes[7] == b'-').then_some(date)
+}
+
+/// Days since 1970-01-01 of a "YYYY-MM-DD" date
+fn day_number(date: &str) -> i64 {
+    let year: i64 = date[..4].parse().unwrap_or(1970);
+    let month: i64 = date[5..7].parse().unwrap_or(1);
+    let day: i64 = date[8..10].parse().unwrap_or(1);
+    // Civil-from-days inverse with years starting in March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Largest counts first, ties by name so the lists are stable
+fn ranked(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut entries: Vec<_> = counts.into_iter().collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    entries
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect()
+}
+
+/// The `TOP_ENTRIES` largest counts
+fn top(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut entries = ranked(counts);
+    entries.truncate(TOP_ENTRIES);
+    entries
+}
+
+impl LibraryStats {
+    /// Aggregates the photos in one pass
+    pub fn from_photos<'a>(
+        photos: impl IntoIterator<Item = &'a PhotoMetadata>,
+        no_gps_count: usize,
+    ) -> Self {
+        let mut stats = LibraryStats::default();
+        let mut countries: HashMap<&str, usize> = HashMap::new();
+        let mut locations: HashMap<&str, usize> = HashMap::new();
+        let mut days: HashMap<&str, usize> = HashMap::new();
+
+        for photo in photos {
+            stats.photos_with_gps += 1;
+            stats.heic_count += photo.is_heic as usize;
+            stats.total_size_bytes += photo.file_size;
+            if let Some(place) = &photo.place {
+                *countries.entry(&place.country).or_default() += 1;
+            }
+            if let Some(location) = &photo.location {
+                *locations.entry(location).or_default() += 1;
+            }
+            if let Some(date) = date_of(&photo.datetime) {
+                *days.entry(date).or_default() += 1;
+            }
+        }
+        stats.photos_without_gps = no_gps_count;
+        stats.total_photos = stats.photos_with_gps + no_gps_count;
+
+        let mut years: HashMap<&str, usize> = HashMap::new();
+        for (date, count) in &days {
+            *years.entry(&date[..4]).or_default() += count;
+        }
+        let mut per_year: Vec<YearCount> = years
+            .into_iter()
+            .map(|(year, count)| YearCount {
+                year: year.to_string(),
+                count,
+            })
+            .collect();
+        per_year.sort_by(|a, b| a.year.cmp(&b.year));
+        stats.per_year = per_year;
+
+        let earliest = days.keys().min().copied();
+        let latest = days.keys().max().copied();
+        if let (Some(earliest), Some(latest)) = (earliest, latest) {
+            let dated: usize = days.values().sum();
+            let span = day_number(latest) - day_number(earliest) + 1;
+            stats.average_photos_per_day = dated as f64 / span as f64;
+            stats.date_range = Some(DateRange {
+                earliest: earliest.to_string(),
+                latest: latest.to_string(),
+            });
+        }
+        stats.most_active_day = top(days)
+            .into_iter()
+            .next()
+            .map(|(date, count)| DayCount { date, count });
+        stats.countries = ranked(countries)
+            .into_iter()
+            .map(|(country, count)| CountryCount { country, count })
+            .collect();
+        stats.top_locations = top(locations)
+            .into_iter()
+            .map(|(name, count)| LocationCount { name, count })
+            .collect();
+        stats
+    }
+}
+
+impl YearSummary {
+    /// Counts the photos per year in one pass, newest year first. Only the year
+    /// digits of the datetime are parsed; undated photos are left out.
+    pub fn from_photos<'a>(photos: impl IntoIterator<Item = &'a PhotoMetadata>) -> Vec<Self> {
+        let mut years: HashMap<u16, YearSummary> = HashMap::new();
+        for photo in photos {
+            let Some(year) = photo.datetime.get(..4).and_then(|y| y.parse().ok()) else {
+                continue;
+            };
+            years
+                .entry(year)
+                .or_insert_with(|| YearSummary {
+                    year,
+                    count: 0,
+                    example_lat: photo.coords.lat(),
+                    example_lng: photo.coords.lng(),
+                })
+                .count += 1;
+        }
+        let mut years: Vec<YearSummary> = years.into_values().collect();
+        years.sort_by(|a, b| b.year.cmp(&a.year));
+        years
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{date_of, day_number, LibraryStats, YearSummary};
+    use crate::database::{PhotoId, PhotoMetadata};
+    use crate::geocoding::Place;
+    use crate::types::GpsCoordinate;
+
+    fn photo(datetime: &str, country: Option<&str>, is_heic: bool) -> PhotoMetadata {
+        PhotoMetadata {
+            id: PhotoId::default(),
+            is_heic,
+            location: country.map(|c| format!("Capital, {c}")),
+            place: country.map(|c| Place {
+                city: "Capital".to_string(),
+                admin1: None,
+                country: c.to_string(),
+            }),
+            file_size: if is_heic { 2_000_000 } else { 5_000_000 },
+            ..PhotoMetadata::sample("photo.jpg", datetime, 48.8566, 2.3522)
+        }
+    }
+
+    #[test]
+    fn day_numbers_count_calendar_days() {
+        assert_eq!(day_number("1970-01-01"), 0);
+        assert_eq!(day_number("2024-03-01") - day_number("2024-02-28"), 2);
+        assert_eq!(day_number("2023-03-01") - day_number("2023-02-28"), 1);
+        assert_eq!(date_of("Unknown Date"), None);
+        assert_eq!(date_of("2024-06-01 12:00:00"), Some("2024-06-01"));
+    }
+
+    #[test]
+    fn library_stats_are_aggregated() {
+        let photos = [
+            photo("2024-06-01 09:00:00", Some("FR"), false),
+            photo("2024-06-01 18:00:00", Some("FR"), true),
+            photo("2024-06-10 12:00:00", Some("DE"), false),
+            photo("2023-12-31 23:59:59", Some("DE"), false),
+            photo("Unknown Date", None, true),
+        ];
+        let stats = LibraryStats::from_photos(&photos, 3);
+
+        assert_eq!(stats.total_photos, 8);
+        assert_eq!(stats.photos_with_gps, 5);
+        assert_eq!(stats.photos_without_gps, 3);
+        assert_eq!(stats.heic_count, 2);
+        assert_eq!(stats.total_size_bytes, 19_000_000);
+        let years: Vec<_> = stats
+            .per_year
+            .iter()
+            .map(|y| (y.year.as_str(), y.count))
+            .collect();
+        assert_eq!(years, [("2023", 1), ("2024", 3)]);
+        let range = stats.date_range.unwrap();
+        assert_eq!(
+            (range.earliest.as_str(), range.latest.as_str()),
+            ("2023-12-31", "2024-06-10")
+        );
+        assert_eq!(stats.countries[0].country, "DE");
+        assert_eq!(stats.countries[1].count, 2);
+        assert_eq!(stats.countries[1].country, "FR");
+        assert_eq!(stats.top_locations[0].name, "Capital, DE");
+        assert!((stats.average_photos_per_day - 4.0 / 163.0).abs() < 1e-9);
+        let busiest = stats.most_active_day.unwrap();
+        assert_eq!((busiest.date.as_str(), busiest.count), ("2024-06-01", 2));
+    }
+
+    #[test]
+    fn years_are_counted_newest_first() {
+        let mut photos = [
+            photo("2023-12-31 23:59:59", Some("DE"), false),
+            photo("2024-06-01 09:00:00", Some("FR"), false),
+            photo("2024-06-10 12:00:00", Some("FR"), false),
+            photo("Unknown Date", None, false),
+        ];
+        photos[0].coords = GpsCoordinate::new(52.52, 13.405).unwrap();
+        let years = YearSummary::from_photos(&photos);
+
+        let counts: Vec<_> = years.iter().map(|y| (y.year, y.count)).collect();
+        assert_eq!(counts, [(2024, 2), (2023, 1)]);
+        assert_eq!(
+            (years[1].example_lat, years[1].example_lng),
+            (52.52, 13.405)
+        );
+        assert_eq!(years[0].example_lat, 48.8566);
+        assert!(YearSummary::from_photos(&[]).is_empty());
+    }
+
+    #[test]
+    fn empty_library_has_no_dates() {
+        let stats = LibraryStats::from_photos(&[], 0);
+        assert_eq!(stats, LibraryStats::default());
+    }
+}
@@ -0,0 +1,173 @@
+//! Free-form photo tags. They live in `tags.json` next to the folder caches
+//! instead of in the photo records, so reprocessing a folder keeps them.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+
+use super::PhotoId;
+
+pub(super) const TAGS_FILE: &str = "tags.json";
+
+/// Tags by photo id, written to `path` after every change when set
+#[derive(Default)]
+pub(super) struct TagStore {
+    tags: RwLock<BTreeMap<PhotoId, Vec<String>>>,
+    path: Option<PathBuf>,
+}
+
+impl TagStore {
+    /// Reads the tags file; a missing or unreadable file starts an empty store
+    pub(super) fn load(path: &Path) -> Self {
+        let tags = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                eprintln!("⚠️ Ignoring unreadable {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        TagStore {
+            tags: RwLock::new(tags),
+            path: Some(path.to_path_buf()),
+        }
+    }
+
+    pub(super) fn get(&self, id: &PhotoId) -> Vec<String> {
+        self.tags
+            .read()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether the photo carries every one of the normalized `tags`
+    pub(super) fn has_all(&self, id: &PhotoId, tags: &[String]) -> bool {
+        if tags.is_empty() {
+            return true;
+        }
+        self.tags
+            .read()
+            .unwrap()
+            .get(id)
+            .is_some_and(|own| tags.iter().all(|tag| own.contains(tag)))
+    }
+
+    /// Replaces the tags of a photo. An empty list is kept as well, so keywords
+    /// removed by hand are not imported again.
+    pub(super) fn set(&self, id: &PhotoId, tags: Vec<String>) -> Result<()> {
+        self.tags.write().unwrap().insert(id.clone(), tags);
+        self.save()
+    }
+
+    /// Sets the tags of the photos that have no entry yet, e.g. keywords read during processing
+    pub(super) fn import(&self, tagged: Vec<(PhotoId, Vec<String>)>) -> Result<usize> {
+        let mut imported = 0;
+        {
+            let mut all = self.tags.write().unwrap();
+            for (id, tags) in tagged {
+                if !tags.is_empty() && !all.contains_key(&id) {
+                    all.insert(id, tags);
+                    imported += 1;
+                }
+            }
+        }
+        if imported > 0 {
+            self.save()?;
+        }
+        Ok(imported)
+    }
+
+    /// Number of photos per tag, counting only the photos for which `exists` holds
+    pub(super) fn counts(&self, exists: impl Fn(&PhotoId) -> bool) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for (id, tags) in self.tags.read().unwrap().iter() {
+            if exists(id) {
+                for tag in tags {
+                    *counts.entry(tag.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// Writes the tags file atomically, like the folder caches
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec_pretty(&*self.tags.read().unwrap())?;
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, data)
+            .and_then(|_| std::fs::rename(&temp_path, path))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Trimmed, lowercase tags without empties and duplicates, sorted
+pub(super) fn normalize_tags<S: AsRef<str>>(tags: impl IntoIterator<Item = S>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut normalized: Vec<String> = tags
+        .into_iter()
+        .map(|tag| tag.as_ref().trim().to_lowercase())
+        .filter(|tag| !tag.is_empty() && seen.insert(tag.clone()))
+        .collect();
+    normalized.sort();
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_tags, TagStore};
+    use crate::database::PhotoId;
+
+    #[test]
+    fn tags_are_trimmed_lowercased_and_deduplicated() {
+        assert_eq!(
+            normalize_tags([" Wedding", "dog", "", "wedding ", "DOG"]),
+            ["dog", "wedding"]
+        );
+    }
+
+    #[test]
+    fn tags_survive_a_reload() {
+        let path = std::env::temp_dir().join("photomap_tags_reload.json");
+        let _ = std::fs::remove_file(&path);
+        let a = PhotoId::from_relative_path("a.jpg");
+        let b = PhotoId::from_relative_path("b.jpg");
+
+        let store = TagStore::load(&path);
+        store.set(&a, normalize_tags(["dog", "park"])).unwrap();
+        assert_eq!(
+            store
+                .import(vec![
+                    (a.clone(), vec!["cat".into()]),
+                    (b.clone(), vec!["cat".into()])
+                ])
+                .unwrap(),
+            1
+        );
+
+        let reloaded = TagStore::load(&path);
+        assert_eq!(reloaded.get(&a), ["dog", "park"]);
+        assert_eq!(reloaded.get(&b), ["cat"]);
+        assert!(reloaded.has_all(&a, &normalize_tags(["dog", "park"])));
+        assert!(!reloaded.has_all(&a, &normalize_tags(["dog", "cat"])));
+        let counts: Vec<(String, usize)> = reloaded.counts(|id| *id == a).into_iter().collect();
+        assert_eq!(counts, [("dog".to_string(), 1), ("park".to_string(), 1)]);
+
+        // Cleared tags stay cleared instead of being imported again
+        reloaded.set(&a, Vec::new()).unwrap();
+        let reloaded = TagStore::load(&path);
+        assert_eq!(
+            reloaded
+                .import(vec![(a.clone(), vec!["cat".into()])])
+                .unwrap(),
+            0
+        );
+        assert!(reloaded.get(&a).is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -29,24 +29,241 @@ pub fn parse_exif_datetime(s: &[u8]) -> Option<String> {
     None
 }
 
-/// Extracts datetime string from EXIF data
+/// Formats Unix seconds as "YYYY-MM-DD HH:MM:SS" (UTC)
+pub fn format_unix_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let secs_of_day = seconds % 86_400;
+
+    // Civil-from-days conversion (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        (secs_of_day % 3_600) / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parses "YYYY-MM-DD HH:MM:SS" into seconds since the Unix epoch, the inverse of
+/// `format_unix_timestamp`. The time is taken as UTC; a ".mmm" fraction is ignored.
+pub fn parse_timestamp(datetime: &str) -> Option<i64> {
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        let part = datetime.get(range)?;
+        part.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| part.parse().ok())?
+    };
+    let bytes = datetime.as_bytes();
+    let fraction = bytes.get(19..).unwrap_or_default();
+    if !(fraction.is_empty() || fraction[0] == b'.' && fraction[1..].iter().all(u8::is_ascii_digit))
+    {
+        return None;
+    }
+    if bytes.len() < 19 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[13] != b':' {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+
+    // Days-from-civil conversion (proleptic Gregorian calendar)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Datetime tags in order of preference, each with its sub-second and UTC offset tag
+const DATETIME_TAGS: [(Tag, Tag, Tag); 2] = [
+    (
+        Tag::DateTimeOriginal,
+        Tag::SubSecTimeOriginal,
+        Tag::OffsetTimeOriginal,
+    ),
+    (Tag::DateTime, Tag::SubSecTime, Tag::OffsetTime),
+];
+
+/// First string of an ASCII field in the primary image
+pub(super) fn ascii_field(exif: &exif::Exif, tag: Tag) -> Option<&[u8]> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Ascii(vec) => vec.first().map(Vec::as_slice),
+        _ => None,
+    }
+}
+
+/// Parses an EXIF UTC offset ("+05:30", "-08:00") into seconds east of UTC
+fn parse_utc_offset(s: &[u8]) -> Option<i64> {
+    let s = std::str::from_utf8(s).ok()?.trim_end_matches('\0').trim();
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 3_600 + minutes * 60))
+}
+
+/// Milliseconds of an EXIF SubSecTime value: "4" is 400 ms, "123456" is 123 ms
+fn parse_subsec_millis(s: &[u8]) -> Option<u32> {
+    let s = std::str::from_utf8(s).ok()?.trim_end_matches('\0').trim();
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let digits: String = s.chars().chain("00".chars()).take(3).collect();
+    digits.parse().ok()
+}
+
+/// Extracts datetime string from EXIF data. With an offset tag the time is
+/// converted to UTC; sub-second digits are kept as a ".mmm" suffix.
 pub fn get_datetime_string(exif: &exif::Exif) -> Option<String> {
-    let try_tags = [Tag::DateTimeOriginal, Tag::DateTime];
-
-    for &tag in &try_tags {
-        if let Some(field) = exif.get_field(tag, In::PRIMARY) {
-            if let exif::Value::Ascii(ref vec) = field.value {
-                if let Some(datetime_bytes) = vec.first() {
-                    if let Some(dt) = parse_exif_datetime(datetime_bytes) {
-                        return Some(dt);
-                    }
-                }
-            }
+    for &(tag, subsec_tag, offset_tag) in &DATETIME_TAGS {
+        let Some(mut datetime) = ascii_field(exif, tag).and_then(parse_exif_datetime) else {
+            continue;
+        };
+        let offset = ascii_field(exif, offset_tag).and_then(parse_utc_offset);
+        let utc = offset
+            .zip(parse_timestamp(&datetime))
+            .and_then(|(offset, local)| u64::try_from(local - offset).ok());
+        if let Some(utc) = utc {
+            datetime = format_unix_timestamp(utc);
         }
+        if let Some(millis) = ascii_field(exif, subsec_tag).and_then(parse_subsec_millis) {
+            datetime.push_str(&format!(".{millis:03}"));
+        }
+        return Some(datetime);
     }
     None
 }
 
+/// Location and capture details read from a file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExtractedMetadata {
+    pub lat: f64,
+    pub lng: f64,
+    pub datetime: Option<String>,
+    /// Speed of the GPS receiver in km/h
+    pub speed_kmh: Option<f64>,
+    /// Dilution of precision of the GPS fix; larger values are less accurate
+    pub gps_dop: Option<f64>,
+    /// Compass heading of the camera in degrees, 0 to 360
+    pub direction: Option<f64>,
+    /// Whether the JPEG carries a Samsung/Google motion photo video
+    pub is_motion_photo: bool,
+}
+
+impl ExtractedMetadata {
+    pub fn new(lat: f64, lng: f64, datetime: Option<String>) -> Self {
+        Self {
+            lat,
+            lng,
+            datetime,
+            ..Default::default()
+        }
+    }
+
+    /// Adds the speed, DOP and heading recorded next to the position
+    pub fn with_gps_details(mut self, exif: &exif::Exif) -> Self {
+        self.speed_kmh = get_gps_speed(exif);
+        self.gps_dop = get_gps_dop(exif);
+        self.direction = get_gps_direction(exif);
+        self
+    }
+}
+
+/// First rational value of a tag, looked up in the primary IFD and then in any IFD
+pub(super) fn get_rational(exif: &exif::Exif, tag: Tag) -> Option<f64> {
+    let field = exif
+        .get_field(tag, In::PRIMARY)
+        .or_else(|| exif.fields().find(|f| f.tag == tag))?;
+    let value = match &field.value {
+        Value::Rational(vec) => vec.first()?.to_f64(),
+        Value::SRational(vec) => vec.first()?.to_f64(),
+        _ => return None,
+    };
+    (is_valid_float(value) && value >= 0.0).then_some(value)
+}
+
+/// Reads GPSSpeed and converts it to km/h using GPSSpeedRef
+/// (K = km/h, M = mph, N = knots; km/h when the reference is missing)
+pub fn get_gps_speed(exif: &exif::Exif) -> Option<f64> {
+    let speed = get_rational(exif, Tag::GPSSpeed)?;
+    let unit = exif
+        .get_field(Tag::GPSSpeedRef, In::PRIMARY)
+        .or_else(|| exif.fields().find(|f| f.tag == Tag::GPSSpeedRef))
+        .and_then(|f| match &f.value {
+            Value::Ascii(vec) => vec.first().and_then(|s| s.first()).copied(),
+            _ => None,
+        })
+        .unwrap_or(b'K');
+    let factor = match unit.to_ascii_uppercase() {
+        b'K' => 1.0,
+        b'M' => 1.609_344,
+        b'N' => 1.852,
+        _ => return None,
+    };
+    Some(speed * factor)
+}
+
+/// Reads GPSDOP, the dilution of precision of the GPS fix
+pub fn get_gps_dop(exif: &exif::Exif) -> Option<f64> {
+    get_rational(exif, Tag::GPSDOP)
+}
+
+/// Reads GPSImgDirection, the compass heading the camera pointed at, normalized to
+/// 0–360. GPSImgDirectionRef tells true (T) from magnetic (M) north; the few degrees
+/// of declination do not matter on a map, so both are kept as they are, while any
+/// other reference rejects the heading.
+pub fn get_gps_direction(exif: &exif::Exif) -> Option<f64> {
+    let direction = get_rational(exif, Tag::GPSImgDirection)?;
+    let reference = exif
+        .get_field(Tag::GPSImgDirectionRef, In::PRIMARY)
+        .or_else(|| exif.fields().find(|f| f.tag == Tag::GPSImgDirectionRef))
+        .and_then(|f| match &f.value {
+            Value::Ascii(vec) => vec.first().and_then(|s| s.first()).copied(),
+            _ => None,
+        })
+        .unwrap_or(b'T');
+    match reference.to_ascii_uppercase() {
+        b'T' | b'M' => Some(direction.rem_euclid(360.0)),
+        _ => None,
+    }
+}
+
+/// Runs the EXIF reader, keeping whatever it could parse of a damaged block
+pub(super) fn read_tolerant(
+    read: impl FnOnce(&mut exif::Reader) -> Result<exif::Exif, exif::Error>,
+) -> Option<exif::Exif> {
+    let mut reader = exif::Reader::new();
+    reader.continue_on_error(true);
+    match read(&mut reader) {
+        Ok(exif) => Some(exif),
+        Err(exif::Error::PartialResult(partial)) => Some(partial.into_inner().0),
+        Err(_) => None,
+    }
+}
+
 /// Applies EXIF orientation to the image
 pub fn apply_exif_orientation(
     source_path: &Path,
@@ -90,6 +307,49 @@ fn is_valid_float(value: f64) -> bool {
     !value.is_nan() && !value.is_infinite()
 }
 
+/// Largest valid magnitude for a coordinate tag: 90° latitude, 180° longitude
+fn max_degrees(coord_tag: Tag) -> f64 {
+    if coord_tag == Tag::GPSLongitude {
+        180.0
+    } else {
+        90.0
+    }
+}
+
+/// Converts degrees, minutes and seconds to decimal degrees. Writers may leave out
+/// trailing parts: one value is decimal degrees, two are degrees and decimal minutes.
+fn dms_to_degrees(parts: &[f64]) -> Option<f64> {
+    if parts.is_empty() || parts.len() > 3 || !parts.iter().all(|&p| is_valid_float(p)) {
+        return None;
+    }
+    let decimal = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, unit)| part / unit)
+        .sum::<f64>();
+    is_valid_float(decimal).then_some(decimal)
+}
+
+/// Reads a coordinate field with its N/S or E/W reference into signed decimal degrees
+fn coord_from_fields(coord: &exif::Field, reference: &exif::Field, coord_tag: Tag) -> Option<f64> {
+    // Some Samsung devices use SRational instead of Rational (e.g., SM-N900)
+    let parts: Vec<f64> = match &coord.value {
+        Value::Rational(vec) => vec.iter().map(|r| r.to_f64()).collect(),
+        Value::SRational(vec) => vec.iter().map(|r| r.to_f64()).collect(),
+        _ => return None,
+    };
+    let decimal = dms_to_degrees(&parts)?;
+    if decimal.abs() > max_degrees(coord_tag) {
+        return None;
+    }
+
+    // Apply reference (S/W are negative values)
+    match reference.display_value().to_string().chars().next() {
+        Some('S') | Some('W') => Some(-decimal),
+        _ => Some(decimal),
+    }
+}
+
 pub fn get_gps_coord(exif: &exif::Exif, coord_tag: Tag, ref_tag: Tag) -> Result<Option<f64>> {
     // Try PRIMARY IFD first (most common location)
     if let Some(result) = try_get_gps_from_ifd(exif, coord_tag, ref_tag, In::PRIMARY)? {
@@ -97,85 +357,57 @@ pub fn get_gps_coord(exif: &exif::Exif, coord_tag: Tag, ref_tag: Tag) -> Result<
     }
 
     // Fallback: Search through ALL fields to find GPS data
-    // Some cameras (like Samsung) may store GPS in different IFDs or use SRational instead of Rational
-    for field in exif.fields() {
-        if field.tag == coord_tag {
-            // Found coordinate field - now find its reference
-            for ref_field in exif.fields() {
-                if ref_field.tag == ref_tag && ref_field.ifd_num == field.ifd_num {
-                    // Found matching reference in same IFD
-
-                    // Try Rational (unsigned) first - most common
-                    if let Value::Rational(ref vec) = &field.value {
-                        if vec.len() == 3 {
-                            let d = vec[0].to_f64();
-                            let m = vec[1].to_f64();
-                            let s = vec[2].to_f64();
-
-                            // Validate all components before calculation
-                            if !is_valid_float(d) || !is_valid_float(m) || !is_valid_float(s) {
-                                continue;
-                            }
-
-                            let mut decimal = d + (m / 60.0) + (s / 3600.0);
-
-                            // Validate final result
-                            if !is_valid_float(decimal) {
-                                continue;
-                            }
-
-                            // Apply reference (S/W are negative values)
-                            if let Some(ref_val) =
-                                ref_field.display_value().to_string().chars().next()
-                            {
-                                if ref_val == 'S' || ref_val == 'W' {
-                                    decimal *= -1.0;
-                                }
-                            }
-                            return Ok(Some(decimal));
-                        }
-                    }
-
-                    // Try SRational (signed) - some Samsung devices use this (e.g., SM-N900)
-                    if let Value::SRational(ref vec) = &field.value {
-                        if vec.len() == 3 {
-                            let d = vec[0].to_f64();
-                            let m = vec[1].to_f64();
-                            let s = vec[2].to_f64();
-
-                            // Validate all components before calculation
-                            if !is_valid_float(d) || !is_valid_float(m) || !is_valid_float(s) {
-                                continue;
-                            }
-
-                            let mut decimal = d + (m / 60.0) + (s / 3600.0);
-
-                            // Validate final result
-                            if !is_valid_float(decimal) {
-                                continue;
-                            }
-
-                            // Apply reference (S/W are negative values)
-                            if let Some(ref_val) =
-                                ref_field.display_value().to_string().chars().next()
-                            {
-                                if ref_val == 'S' || ref_val == 'W' {
-                                    decimal *= -1.0;
-                                }
-                            }
-                            return Ok(Some(decimal));
-                        }
-                    }
-                }
-            }
+    // Some cameras (like Samsung) may store GPS in different IFDs
+    for field in exif.fields().filter(|f| f.tag == coord_tag) {
+        // The reference must come from the same IFD
+        let decimal = exif
+            .fields()
+            .filter(|r| r.tag == ref_tag && r.ifd_num == field.ifd_num)
+            .find_map(|ref_field| coord_from_fields(field, ref_field, coord_tag));
+        if decimal.is_some() {
+            return Ok(decimal);
         }
     }
 
     Ok(None)
 }
 
+/// Explains why the GPS position tags of an EXIF block give no coordinates.
+/// Returns None when the block has no GPS position tags at all.
+pub fn describe_unparseable_gps(exif: &exif::Exif) -> Option<String> {
+    let has_tag = |tag: Tag| exif.fields().any(|f| f.tag == tag);
+    if !has_tag(Tag::GPSLatitude) && !has_tag(Tag::GPSLongitude) {
+        return None;
+    }
+
+    let problems: Vec<String> = [
+        (Tag::GPSLatitude, Tag::GPSLatitudeRef),
+        (Tag::GPSLongitude, Tag::GPSLongitudeRef),
+    ]
+    .into_iter()
+    .filter_map(|(coord_tag, ref_tag)| {
+        let field = match exif.fields().find(|f| f.tag == coord_tag) {
+            Some(field) => field,
+            None => return Some(format!("{} missing", coord_tag)),
+        };
+        match get_gps_coord(exif, coord_tag, ref_tag) {
+            Ok(Some(_)) => None,
+            Err(e) => Some(format!("{}: {}", coord_tag, e)),
+            Ok(None) if !has_tag(ref_tag) => Some(format!("{} missing", ref_tag)),
+            Ok(None) => Some(format!(
+                "invalid {} value {}",
+                coord_tag,
+                field.display_value()
+            )),
+        }
+    })
+    .collect();
+
+    Some(problems.join(", "))
+}
+
 // Helper function to try GPS extraction from specific IFD
-fn try_get_gps_from_ifd(
+pub(super) fn try_get_gps_from_ifd(
     exif: &exif::Exif,
     coord_tag: Tag,
     ref_tag: Tag,
@@ -185,63 +417,7 @@ fn try_get_gps_from_ifd(
     let ref_field = exif.get_field(ref_tag, ifd);
 
     if let (Some(coord), Some(ref_val)) = (coord_field, ref_field) {
-        // Try Rational (unsigned) first - most common
-        if let Value::Rational(ref vec) = coord.value {
-            if vec.len() == 3 {
-                let d = vec[0].to_f64();
-                let m = vec[1].to_f64();
-                let s = vec[2].to_f64();
-
-                // Validate all components before calculation
-                if !is_valid_float(d) || !is_valid_float(m) || !is_valid_float(s) {
-                    return Ok(None);
-                }
-
-                let mut decimal = d + (m / 60.0) + (s / 3600.0);
-
-                // Validate final result
-                if !is_valid_float(decimal) {
-                    return Ok(None);
-                }
-
-                // Apply reference (S/W are negative values)
-                if let Some(ref_val) = ref_val.display_value().to_string().chars().next() {
-                    if ref_val == 'S' || ref_val == 'W' {
-                        decimal *= -1.0;
-                    }
-                }
-                return Ok(Some(decimal));
-            }
-        }
-
-        // Try SRational (signed) - some Samsung devices use this (e.g., SM-N900)
-        if let Value::SRational(ref vec) = coord.value {
-            if vec.len() == 3 {
-                let d = vec[0].to_f64();
-                let m = vec[1].to_f64();
-                let s = vec[2].to_f64();
-
-                // Validate all components before calculation
-                if !is_valid_float(d) || !is_valid_float(m) || !is_valid_float(s) {
-                    return Ok(None);
-                }
-
-                let mut decimal = d + (m / 60.0) + (s / 3600.0);
-
-                // Validate final result
-                if !is_valid_float(decimal) {
-                    return Ok(None);
-                }
-
-                // Apply reference (S/W are negative values)
-                if let Some(ref_val) = ref_val.display_value().to_string().chars().next() {
-                    if ref_val == 'S' || ref_val == 'W' {
-                        decimal *= -1.0;
-                    }
-                }
-                return Ok(Some(decimal));
-            }
-        }
+        return Ok(coord_from_fields(coord, ref_val, coord_tag));
     }
     Ok(None)
 }
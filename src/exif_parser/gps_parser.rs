@@ -11,43 +11,63 @@ enum ByteOrder {
     BigEndian,
 }
 
+/// GPS data read directly from a GPS IFD
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpsFix {
+    pub lat: f64,
+    pub lng: f64,
+    /// Meters relative to sea level (negative below it)
+    pub altitude: Option<f64>,
+    /// UTC date of the fix from GPSDateStamp, as "YYYY-MM-DD"
+    pub date_stamp: Option<String>,
+}
+
 /// Read GPS coordinates directly from EXIF data, bypassing broken IFD chains
-pub fn extract_gps_from_malformed_exif(path: &Path) -> Option<(f64, f64)> {
+pub fn extract_gps_from_malformed_exif(path: &Path) -> Option<GpsFix> {
     let mut file = File::open(path).ok()?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer).ok()?;
 
+    extract_gps_from_jpeg_bytes(&buffer)
+}
+
+/// Same as `extract_gps_from_malformed_exif` for JPEG data already in memory
+pub(super) fn extract_gps_from_jpeg_bytes(buffer: &[u8]) -> Option<GpsFix> {
     // Find EXIF marker in JPEG (0xFFE1)
-    let exif_start = find_exif_segment(&buffer)?;
+    let exif_start = find_exif_segment(buffer)?;
 
-    // Parse TIFF header
     // APP1 structure: FF E1 [2 bytes length] "Exif\0\0" [TIFF data]
     let tiff_start = exif_start + 4 + 6; // Skip marker (2) + length (2) + "Exif\0\0" (6)
-    if tiff_start + 8 > buffer.len() {
+    extract_gps_from_tiff(buffer.get(tiff_start..)?)
+}
+
+/// Reads GPS coordinates from a TIFF buffer (EXIF data after "Exif\0\0"),
+/// e.g. the EXIF block of a HEIC file, walking IFD0 and the GPS IFD directly
+pub fn extract_gps_from_tiff(tiff: &[u8]) -> Option<GpsFix> {
+    if tiff.len() < 8 {
         return None;
     }
 
     // Determine byte order
-    let byte_order = match &buffer[tiff_start..tiff_start + 2] {
+    let byte_order = match &tiff[0..2] {
         b"II" => ByteOrder::LittleEndian,
         b"MM" => ByteOrder::BigEndian,
         _ => return None,
     };
 
     // Verify TIFF magic number (42)
-    let magic = read_u16(&buffer[tiff_start + 2..tiff_start + 4], byte_order);
+    let magic = read_u16(&tiff[2..4], byte_order);
     if magic != 42 {
         return None;
     }
 
     // Read offset to first IFD
-    let ifd0_offset = read_u32(&buffer[tiff_start + 4..tiff_start + 8], byte_order) as usize;
+    let ifd0_offset = read_u32(&tiff[4..8], byte_order) as usize;
 
     // Try to find GPS IFD offset in IFD0
-    if let Some(gps_ifd_offset) = find_gps_ifd_offset(&buffer, tiff_start, ifd0_offset, byte_order)
-    {
+    if let Some(gps_ifd_offset) = find_gps_ifd_offset(tiff, ifd0_offset, byte_order) {
         // Read GPS data from GPS IFD
-        return parse_gps_ifd(&buffer, tiff_start, gps_ifd_offset, byte_order);
+        return parse_gps_ifd(tiff, gps_ifd_offset, byte_order);
     }
 
     None
@@ -80,13 +100,7 @@ fn find_exif_segment(data: &[u8]) -> Option<usize> {
 }
 
 /// Find GPS IFD offset in IFD0
-fn find_gps_ifd_offset(
-    data: &[u8],
-    tiff_start: usize,
-    ifd_offset: usize,
-    byte_order: ByteOrder,
-) -> Option<usize> {
-    let ifd_pos = tiff_start + ifd_offset;
+fn find_gps_ifd_offset(data: &[u8], ifd_pos: usize, byte_order: ByteOrder) -> Option<usize> {
     if ifd_pos + 2 > data.len() {
         return None;
     }
@@ -113,14 +127,8 @@ fn find_gps_ifd_offset(
     None
 }
 
-/// Parse GPS IFD and extract coordinates
-fn parse_gps_ifd(
-    data: &[u8],
-    tiff_start: usize,
-    gps_offset: usize,
-    byte_order: ByteOrder,
-) -> Option<(f64, f64)> {
-    let gps_pos = tiff_start + gps_offset;
+/// Parse GPS IFD and extract coordinates, altitude and date stamp
+fn parse_gps_ifd(data: &[u8], gps_pos: usize, byte_order: ByteOrder) -> Option<GpsFix> {
     if gps_pos + 2 > data.len() {
         return None;
     }
@@ -132,6 +140,9 @@ fn parse_gps_ifd(
     let mut lat_ref: Option<char> = None;
     let mut lon: Option<f64> = None;
     let mut lon_ref: Option<char> = None;
+    let mut below_sea_level = false;
+    let mut altitude: Option<f64> = None;
+    let mut date_stamp: Option<String> = None;
 
     for _ in 0..num_entries {
         if pos + 12 > data.len() {
@@ -148,17 +159,29 @@ fn parse_gps_ifd(
                 // GPSLatitudeRef
                 lat_ref = Some(data[pos + 8] as char);
             }
-            2 if format == 5 && count == 3 => {
+            2 if format == 5 && (1..=3).contains(&count) => {
                 // GPSLatitude
-                lat = read_gps_coordinate(data, tiff_start, value_offset as usize, byte_order);
+                lat = read_gps_coordinate(data, value_offset as usize, count, byte_order);
             }
             3 if format == 2 && count >= 1 => {
                 // GPSLongitudeRef
                 lon_ref = Some(data[pos + 8] as char);
             }
-            4 if format == 5 && count == 3 => {
+            4 if format == 5 && (1..=3).contains(&count) => {
                 // GPSLongitude
-                lon = read_gps_coordinate(data, tiff_start, value_offset as usize, byte_order);
+                lon = read_gps_coordinate(data, value_offset as usize, count, byte_order);
+            }
+            5 if format == 1 && count >= 1 => {
+                // GPSAltitudeRef: 1 means below sea level
+                below_sea_level = data[pos + 8] == 1;
+            }
+            6 if format == 5 && count == 1 => {
+                // GPSAltitude
+                altitude = read_rational(data, value_offset as usize, byte_order);
+            }
+            29 if format == 2 && count >= 10 => {
+                // GPSDateStamp: "YYYY:MM:DD\0", always stored at an offset
+                date_stamp = read_date_stamp(data, value_offset as usize);
             }
             _ => {}
         }
@@ -177,50 +200,68 @@ fn parse_gps_ifd(
         final_lon = -final_lon;
     }
 
-    Some((final_lat, final_lon))
-}
+    // Reject positions outside the globe
+    if final_lat.abs() > 90.0 || final_lon.abs() > 180.0 {
+        return None;
+    }
 
-/// Validate that a float value is safe to use (not NaN or Infinity)
-fn is_valid_float(value: f64) -> bool {
-    !value.is_nan() && !value.is_infinite()
+    Some(GpsFix {
+        lat: final_lat,
+        lng: final_lon,
+        altitude: altitude.map(|alt| if below_sea_level { -alt } else { alt }),
+        date_stamp,
+    })
 }
 
-/// Read GPS coordinate (degrees, minutes, seconds) and convert to decimal
-fn read_gps_coordinate(
-    data: &[u8],
-    tiff_start: usize,
-    offset: usize,
-    byte_order: ByteOrder,
-) -> Option<f64> {
-    let pos = tiff_start + offset;
-    if pos + 24 > data.len() {
+/// Read a single unsigned rational value
+fn read_rational(data: &[u8], pos: usize, byte_order: ByteOrder) -> Option<f64> {
+    if pos + 8 > data.len() {
         return None;
     }
 
-    // Read 3 rational values (degrees, minutes, seconds)
-    let deg_num = read_u32(&data[pos..pos + 4], byte_order) as f64;
-    let deg_den = read_u32(&data[pos + 4..pos + 8], byte_order) as f64;
-
-    let min_num = read_u32(&data[pos + 8..pos + 12], byte_order) as f64;
-    let min_den = read_u32(&data[pos + 12..pos + 16], byte_order) as f64;
-
-    let sec_num = read_u32(&data[pos + 16..pos + 20], byte_order) as f64;
-    let sec_den = read_u32(&data[pos + 20..pos + 24], byte_order) as f64;
-
-    if deg_den == 0.0 || min_den == 0.0 || sec_den == 0.0 {
+    let num = read_u32(&data[pos..pos + 4], byte_order) as f64;
+    let den = read_u32(&data[pos + 4..pos + 8], byte_order) as f64;
+    if den == 0.0 {
         return None;
     }
 
-    let degrees = deg_num / deg_den;
-    let minutes = min_num / min_den;
-    let seconds = sec_num / sec_den;
+    let value = num / den;
+    is_valid_float(value).then_some(value)
+}
 
-    // Validate all components before using them
-    if !is_valid_float(degrees) || !is_valid_float(minutes) || !is_valid_float(seconds) {
+/// Read GPSDateStamp ("YYYY:MM:DD") and convert it to "YYYY-MM-DD"
+fn read_date_stamp(data: &[u8], pos: usize) -> Option<String> {
+    let raw = data.get(pos..pos + 10)?;
+    let well_formed = raw.iter().enumerate().all(|(i, &b)| match i {
+        4 | 7 => b == b':',
+        _ => b.is_ascii_digit(),
+    });
+    if !well_formed {
         return None;
     }
 
-    let decimal = degrees + minutes / 60.0 + seconds / 3600.0;
+    let text = std::str::from_utf8(raw).ok()?;
+    Some(text.replace(':', "-"))
+}
+
+/// Validate that a float value is safe to use (not NaN or Infinity)
+fn is_valid_float(value: f64) -> bool {
+    !value.is_nan() && !value.is_infinite()
+}
+
+/// Read GPS coordinate (degrees, minutes, seconds) and convert to decimal.
+/// Writers may leave out trailing parts: a single rational is decimal degrees,
+/// two are degrees and decimal minutes.
+fn read_gps_coordinate(data: &[u8], pos: usize, count: u32, byte_order: ByteOrder) -> Option<f64> {
+    let mut decimal = 0.0;
+    for (i, unit) in [1.0, 60.0, 3600.0]
+        .into_iter()
+        .take(count as usize)
+        .enumerate()
+    {
+        let part = read_rational(data, pos + i * 8, byte_order)?;
+        decimal += part / unit;
+    }
 
     // Validate final result
     if is_valid_float(decimal) {
@@ -1,67 +1,105 @@
-use super::generic::{get_datetime_string, get_gps_coord};
+use super::generic::{
+    describe_unparseable_gps, get_datetime_string, get_gps_coord, ExtractedMetadata,
+};
+use super::gps_parser;
+use crate::image_processing::{detect_image_format, ImageFormat};
 use anyhow::{bail, Result};
 use exif::Tag;
 use std::path::Path;
 
-pub fn extract_metadata_from_heic(path: &Path) -> Result<(f64, f64, Option<String>)> {
-    // Try to read as HEIC first
-    let heic_result = (|| -> Result<(f64, f64, Option<String>)> {
-        let path_str = path
-            .to_str()
-            .ok_or_else(|| anyhow::anyhow!("Path contains invalid UTF-8 characters: {:?}", path))?;
-        let ctx = libheif_rs::HeifContext::read_from_file(path_str)
-            .map_err(|e| anyhow::anyhow!("Failed to read HEIF context: {}", e))?;
-
-        let primary_image_handle = ctx
-            .primary_image_handle()
-            .map_err(|e| anyhow::anyhow!("Failed to get primary image handle: {}", e))?;
-
-        // Corrected usage for metadata_block_ids based on compiler's implied signature
-        // Pass 0 for type_filter to match all types (0 implements Into<FourCC>)
-        let count = primary_image_handle.number_of_metadata_blocks(0);
-
-        if count == 0 {
-            bail!("No metadata found in HEIF file");
+/// Reads position and date from a TIFF buffer: the EXIF block of a HEIC file or
+/// a whole TIFF file. When the IFD chain is broken, the GPS IFD is walked directly
+/// like for JPEGs.
+pub(super) fn metadata_from_tiff(tiff: &[u8]) -> Result<ExtractedMetadata> {
+    let mut reader = exif::Reader::new();
+    reader.continue_on_error(true);
+    let exif = match reader.read_raw(tiff.to_vec()) {
+        Ok(exif) => Some(exif),
+        Err(exif::Error::PartialResult(partial)) => Some(partial.into_inner().0),
+        Err(_) => None,
+    };
+
+    if let Some(exif) = &exif {
+        let lat = get_gps_coord(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef)?;
+        let lng = get_gps_coord(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef)?;
+        if let (Some(lat), Some(lng)) = (lat, lng) {
+            return Ok(
+                ExtractedMetadata::new(lat, lng, get_datetime_string(exif)).with_gps_details(exif)
+            );
         }
+    }
+
+    if let Some(fix) = gps_parser::extract_gps_from_tiff(tiff) {
+        let datetime = exif.as_ref().and_then(get_datetime_string);
+        return Ok(ExtractedMetadata::new(fix.lat, fix.lng, datetime));
+    }
+
+    match exif.as_ref().and_then(describe_unparseable_gps) {
+        Some(problem) => Err(super::ExifError::GpsUnparseable(problem).into()),
+        None => Err(super::ExifError::GpsNotFound.into()),
+    }
+}
+
+/// Raw TIFF buffers of the EXIF metadata blocks attached to the primary image
+pub(super) fn exif_tiff_blocks(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Path contains invalid UTF-8 characters: {:?}", path))?;
+    let ctx = libheif_rs::HeifContext::read_from_file(path_str)
+        .map_err(|e| anyhow::anyhow!("Failed to read HEIF context: {}", e))?;
 
-        let mut metadata_ids_buffer = vec![0; count as usize];
-        let count = primary_image_handle.metadata_block_ids(&mut metadata_ids_buffer, 0);
-
-        for id in metadata_ids_buffer.iter().take(count) {
-            // Check if it's Exif
-            if let Some(type_str) = primary_image_handle.metadata_type(*id) {
-                if type_str == "Exif" {
-                    let exif_data = primary_image_handle.metadata(*id).map_err(|e| {
-                        anyhow::anyhow!("Failed to get metadata for ID {}: {}", id, e)
-                    })?;
-
-                    // `libheif-rs` provides the raw EXIF data, which usually starts with "Exif\0\0"
-                    // and then the TIFF header. `exif::Reader::read_raw` expects the TIFF header directly.
-                    // The first 4 bytes are the length of the data, so we skip them.
-                    let tiff_header_start =
-                        if exif_data.len() > 4 && exif_data[4..].starts_with(b"Exif\0\0") {
-                            10
-                        } else if exif_data.starts_with(b"Exif\0\0") {
-                            6
-                        } else {
-                            0
-                        };
-
-                    if exif_data.len() > tiff_header_start {
-                        if let Ok(exif) =
-                            exif::Reader::new().read_raw(exif_data[tiff_header_start..].to_vec())
-                        {
-                            let lat = get_gps_coord(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef)?;
-                            let lng =
-                                get_gps_coord(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef)?;
-                            let datetime = get_datetime_string(&exif);
-
-                            if let (Some(lat), Some(lng)) = (lat, lng) {
-                                return Ok((lat, lng, datetime));
-                            }
-                        }
-                    }
-                }
+    let primary_image_handle = ctx
+        .primary_image_handle()
+        .map_err(|e| anyhow::anyhow!("Failed to get primary image handle: {}", e))?;
+
+    // Corrected usage for metadata_block_ids based on compiler's implied signature
+    // Pass 0 for type_filter to match all types (0 implements Into<FourCC>)
+    let count = primary_image_handle.number_of_metadata_blocks(0);
+
+    if count == 0 {
+        bail!("No metadata found in HEIF file");
+    }
+
+    let mut metadata_ids_buffer = vec![0; count as usize];
+    let count = primary_image_handle.metadata_block_ids(&mut metadata_ids_buffer, 0);
+
+    let mut blocks = Vec::new();
+    for id in metadata_ids_buffer.iter().take(count) {
+        // Check if it's Exif
+        if primary_image_handle.metadata_type(*id) != Some("Exif") {
+            continue;
+        }
+        let exif_data = primary_image_handle
+            .metadata(*id)
+            .map_err(|e| anyhow::anyhow!("Failed to get metadata for ID {}: {}", id, e))?;
+
+        // `libheif-rs` provides the raw EXIF data, which usually starts with "Exif\0\0"
+        // and then the TIFF header. `exif::Reader::read_raw` expects the TIFF header directly.
+        // The first 4 bytes are the length of the data, so we skip them.
+        let tiff_header_start = if exif_data.len() > 4 && exif_data[4..].starts_with(b"Exif\0\0") {
+            10
+        } else if exif_data.starts_with(b"Exif\0\0") {
+            6
+        } else {
+            0
+        };
+
+        if exif_data.len() > tiff_header_start {
+            blocks.push(exif_data[tiff_header_start..].to_vec());
+        }
+    }
+    Ok(blocks)
+}
+
+pub fn extract_metadata_from_heic(path: &Path) -> Result<ExtractedMetadata> {
+    // Try to read as HEIC first
+    let heic_result = (|| -> Result<ExtractedMetadata> {
+        for tiff in exif_tiff_blocks(path)? {
+            match metadata_from_tiff(&tiff) {
+                Err(e)
+                    if e.downcast_ref::<super::ExifError>()
+                        .is_some_and(|e| matches!(e, super::ExifError::GpsNotFound)) => {}
+                result => return result,
             }
         }
         Err(super::ExifError::GpsNotFound.into())
@@ -72,15 +110,9 @@ pub fn extract_metadata_from_heic(path: &Path) -> Result<(f64, f64, Option<Strin
     }
 
     // Fallback: Check if it's actually a JPEG disguised as HEIC (Xiaomi bug)
-    use std::fs::File;
-    use std::io::Read;
-
-    if let Ok(mut file) = File::open(path) {
-        let mut buffer = [0u8; 2];
-        if file.read_exact(&mut buffer).is_ok() && buffer == [0xFF, 0xD8] {
-            // It's a JPEG! Delegate to JPEG parser
-            return super::jpeg::extract_metadata_from_jpeg(path);
-        }
+    if detect_image_format(path) == Some(ImageFormat::Jpeg) {
+        // It's a JPEG! Delegate to JPEG parser
+        return super::jpeg::extract_metadata_from_jpeg(path);
     }
 
     heic_result
@@ -0,0 +1,239 @@
+//! Camera and exposure details of a photo for the details drawer, read from
+//! the original file on request
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::Result;
+use exif::{In, Tag};
+use serde_json::json;
+
+use super::generic::{ascii_field, get_gps_direction, get_rational, read_tolerant};
+use super::heic;
+use crate::image_processing::image_format_of;
+
+/// Field name to value; fields missing from the file are left out
+pub type PhotoInfo = BTreeMap<&'static str, serde_json::Value>;
+
+/// Reads the interesting EXIF fields of a JPEG or HEIC file. Fields that are
+/// absent or malformed are skipped, and a file without EXIF has an empty map.
+pub fn full_info(path: &Path) -> Result<PhotoInfo> {
+    let exif = if image_format_of(path).is_some_and(|format| format.is_heif()) {
+        // Report a missing file, but treat a HEIC libheif cannot parse as one without EXIF
+        std::fs::metadata(path)?;
+        heic::exif_tiff_blocks(path)
+            .unwrap_or_default()
+            .into_iter()
+            .find_map(|tiff| read_tolerant(|reader| reader.read_raw(tiff)))
+    } else {
+        let data = std::fs::read(path)?;
+        read_tolerant(|reader| reader.read_from_container(&mut Cursor::new(&data)))
+    };
+    Ok(exif.map(|exif| info_from_exif(&exif)).unwrap_or_default())
+}
+
+fn info_from_exif(exif: &exif::Exif) -> PhotoInfo {
+    let mut info = PhotoInfo::new();
+
+    let make = text(exif, Tag::Make);
+    let model = text(exif, Tag::Model);
+    let camera = match (make, model) {
+        // Most cameras repeat the make in the model ("Canon" + "Canon EOS R6")
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            Some(model)
+        }
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model),
+    };
+    if let Some(camera) = camera {
+        info.insert("camera", json!(camera));
+    }
+    if let Some(lens) = text(exif, Tag::LensModel).or_else(|| text(exif, Tag::LensMake)) {
+        info.insert("lens", json!(lens));
+    }
+
+    if let Some(focal_length) = get_rational(exif, Tag::FocalLength).filter(|&f| f > 0.0) {
+        info.insert("focal_length_mm", json!(round_to(focal_length, 1)));
+    }
+    if let Some(focal_35mm) = uint(exif, Tag::FocalLengthIn35mmFilm).filter(|&f| f > 0) {
+        info.insert("focal_length_35mm", json!(focal_35mm));
+    }
+    let aperture = get_rational(exif, Tag::FNumber)
+        // APEX aperture value: f-number = 2^(Av / 2)
+        .or_else(|| get_rational(exif, Tag::ApertureValue).map(|av| 2f64.powf(av / 2.0)))
+        .filter(|&f| f > 0.0);
+    if let Some(aperture) = aperture {
+        info.insert("aperture", json!(round_to(aperture, 1)));
+    }
+    if let Some(shutter) = get_rational(exif, Tag::ExposureTime).filter(|&t| t > 0.0) {
+        info.insert("shutter", json!(format_shutter(shutter)));
+    }
+    if let Some(iso) = uint(exif, Tag::PhotographicSensitivity).filter(|&iso| iso > 0) {
+        info.insert("iso", json!(iso));
+    }
+    if let Some(flash) = uint(exif, Tag::Flash) {
+        // Bit 0 tells whether the flash fired
+        info.insert("flash", json!(flash & 1 == 1));
+    }
+
+    let width = uint(exif, Tag::PixelXDimension).or_else(|| uint(exif, Tag::ImageWidth));
+    let height = uint(exif, Tag::PixelYDimension).or_else(|| uint(exif, Tag::ImageLength));
+    if let (Some(width), Some(height)) = (width, height) {
+        info.insert("width", json!(width));
+        info.insert("height", json!(height));
+    }
+
+    if let Some(altitude) = get_rational(exif, Tag::GPSAltitude) {
+        // Reference 1 means below sea level
+        let sign = if uint(exif, Tag::GPSAltitudeRef) == Some(1) {
+            -1.0
+        } else {
+            1.0
+        };
+        info.insert("gps_altitude_m", json!(round_to(sign * altitude, 1)));
+    }
+    if let Some(direction) = get_gps_direction(exif) {
+        info.insert("gps_direction", json!(round_to(direction, 1)));
+        // T is true north, M magnetic north
+        if let Some(reference) = text(exif, Tag::GPSImgDirectionRef) {
+            info.insert("gps_direction_ref", json!(reference));
+        }
+    }
+
+    if let Some(software) = text(exif, Tag::Software) {
+        info.insert("software", json!(software));
+    }
+    info
+}
+
+/// Trimmed text of an ASCII field, skipping empty values and invalid UTF-8
+fn text(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    let bytes = ascii_field(exif, tag)?;
+    let text = std::str::from_utf8(bytes).ok()?;
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// First integer value of a tag in the primary image
+fn uint(exif: &exif::Exif, tag: Tag) -> Option<u32> {
+    exif.get_field(tag, In::PRIMARY)?.value.get_uint(0)
+}
+
+/// "1/250" below a second, "2.5" seconds above
+fn format_shutter(seconds: f64) -> String {
+    if seconds < 1.0 {
+        format!("1/{}", (1.0 / seconds).round())
+    } else {
+        format!("{}", round_to(seconds, 1))
+    }
+}
+
+fn round_to(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::full_info;
+    use exif::experimental::Writer;
+    use exif::{Field, In, Rational, Tag, Value};
+
+    fn rational(num: u32, denom: u32) -> Value {
+        Value::Rational(vec![Rational { num, denom }])
+    }
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        }
+    }
+
+    /// JPEG holding a single APP1 EXIF segment with `fields`
+    fn jpeg_with_fields(fields: &[Field]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn info_of(name: &str, jpeg: Vec<u8>) -> super::PhotoInfo {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, jpeg).unwrap();
+        let info = full_info(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        info
+    }
+
+    #[test]
+    fn reads_exposure_of_a_known_jpeg() {
+        let info = info_of(
+            "photomap_test_info.jpg",
+            jpeg_with_fields(&[
+                field(Tag::Make, Value::Ascii(vec![b"Canon".to_vec()])),
+                field(Tag::Model, Value::Ascii(vec![b"Canon EOS R6".to_vec()])),
+                field(Tag::FNumber, rational(28, 10)),
+                field(Tag::ExposureTime, rational(1, 250)),
+                field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+                field(Tag::FocalLength, rational(50, 1)),
+                field(Tag::Flash, Value::Short(vec![0x10])),
+                field(Tag::PixelXDimension, Value::Long(vec![6000])),
+                field(Tag::PixelYDimension, Value::Long(vec![4000])),
+                field(Tag::GPSAltitude, rational(1234, 10)),
+                field(Tag::GPSAltitudeRef, Value::Byte(vec![1])),
+                field(
+                    Tag::Software,
+                    Value::Ascii(vec![b"Firmware 1.8\0".to_vec()]),
+                ),
+            ]),
+        );
+
+        assert_eq!(info["camera"], "Canon EOS R6");
+        assert_eq!(info["aperture"], 2.8);
+        assert_eq!(info["iso"], 400);
+        assert_eq!(info["shutter"], "1/250");
+        assert_eq!(info["focal_length_mm"], 50.0);
+        assert_eq!(info["flash"], false);
+        assert_eq!(info["width"], 6000);
+        assert_eq!(info["height"], 4000);
+        assert_eq!(info["gps_altitude_m"], -123.4);
+        assert_eq!(info["software"], "Firmware 1.8");
+        assert!(!info.contains_key("lens"));
+    }
+
+    #[test]
+    fn malformed_fields_are_skipped() {
+        let info = info_of(
+            "photomap_test_info_malformed.jpg",
+            jpeg_with_fields(&[
+                // Wrong types and impossible values
+                field(Tag::FNumber, Value::Ascii(vec![b"f/2.8".to_vec()])),
+                field(Tag::ExposureTime, rational(1, 0)),
+                field(Tag::PhotographicSensitivity, Value::Short(vec![200])),
+                field(Tag::Model, Value::Ascii(vec![vec![0xFF, 0xFE]])),
+            ]),
+        );
+
+        assert_eq!(info.len(), 1);
+        assert_eq!(info["iso"], 200);
+    }
+
+    #[test]
+    fn file_without_exif_has_no_info() {
+        let info = info_of("photomap_test_info_plain.jpg", vec![0xFF, 0xD8, 0xFF, 0xD9]);
+        assert!(info.is_empty());
+    }
+}
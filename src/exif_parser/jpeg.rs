@@ -1,68 +1,331 @@
-use super::generic::{get_datetime_string, get_gps_coord};
+use super::generic::{
+    describe_unparseable_gps, get_datetime_string, get_gps_coord, get_gps_direction, get_gps_dop,
+    get_gps_speed, ExtractedMetadata,
+};
 use super::gps_parser;
+use super::mp4;
 use anyhow::Result;
 use exif::Tag;
-use std::fs::File;
-use std::io::BufReader;
+use std::io::Cursor;
 use std::path::Path;
 
-pub fn extract_metadata_from_jpeg(path: &Path) -> Result<(f64, f64, Option<String>)> {
-    let file = File::open(path)?;
-    let mut buf_reader = BufReader::new(file);
+/// Reads the file once and extracts its metadata, see `extract_metadata_from_jpeg_bytes`
+pub fn extract_metadata_from_jpeg(path: &Path) -> Result<ExtractedMetadata> {
+    let data = std::fs::read(path)?;
+    extract_metadata_from_jpeg_bytes(&data)
+}
+
+/// Extracts position and capture time from JPEG data, trying the EXIF reader first
+/// and then the fallbacks for split, malformed or Photoshop-copied EXIF and motion photos.
+/// The motion photo flag is taken from the same buffer, so the file is read only once.
+pub fn extract_metadata_from_jpeg_bytes(data: &[u8]) -> Result<ExtractedMetadata> {
+    read_position_and_time(data).map(|metadata| ExtractedMetadata {
+        is_motion_photo: detect_motion_photo_bytes(data),
+        ..metadata
+    })
+}
+
+fn read_position_and_time(data: &[u8]) -> Result<ExtractedMetadata> {
     let mut exif_reader = exif::Reader::new();
     exif_reader.continue_on_error(true); // Tolerate non-standard EXIF structures
 
-    // Try to extract datetime from the first EXIF read attempt
-    // This avoids re-opening the file later when using custom GPS parser
+    // Datetime from the first EXIF read attempt, used with the fallback parsers
     let mut cached_datetime: Option<String> = None;
+    // Speed, DOP and heading can still be read when only the position needs the fallback parser
+    let mut cached_details: (Option<f64>, Option<f64>, Option<f64>) = (None, None, None);
+    // Why GPS tags found by the reader could not be used, reported if no fallback helps
+    let mut gps_problem: Option<String> = None;
 
-    match exif_reader.read_from_container(&mut buf_reader) {
+    match exif_reader.read_from_container(&mut Cursor::new(data)) {
         Ok(exif) => {
             // Cache datetime from this successful read
             cached_datetime = get_datetime_string(&exif);
+            cached_details = (
+                get_gps_speed(&exif),
+                get_gps_dop(&exif),
+                get_gps_direction(&exif),
+            );
 
             // Try to extract GPS using standard method
             if let (Some(lat), Some(lng)) = (
                 get_gps_coord(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef)?,
                 get_gps_coord(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef)?,
             ) {
-                return Ok((lat, lng, cached_datetime));
+                return Ok(
+                    ExtractedMetadata::new(lat, lng, cached_datetime).with_gps_details(&exif)
+                );
             }
+            gps_problem = describe_unparseable_gps(&exif);
         }
         Err(exif::Error::PartialResult(partial)) => {
             let (exif, _errors) = partial.into_inner();
             // Cache datetime from partial result
             cached_datetime = get_datetime_string(&exif);
+            cached_details = (
+                get_gps_speed(&exif),
+                get_gps_dop(&exif),
+                get_gps_direction(&exif),
+            );
 
             // Try to extract GPS from partial result
             if let (Some(lat), Some(lng)) = (
                 get_gps_coord(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef)?,
                 get_gps_coord(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef)?,
             ) {
-                return Ok((lat, lng, cached_datetime));
+                return Ok(
+                    ExtractedMetadata::new(lat, lng, cached_datetime).with_gps_details(&exif)
+                );
             }
+            gps_problem = describe_unparseable_gps(&exif);
         }
         Err(_) => {}
     }
 
+    // Some camera firmware splits EXIF over two APP1 segments with GPS only in the second,
+    // while the EXIF reader stops at the first one
+    let segments: Vec<exif::Exif> = exif_segments(data)
+        .into_iter()
+        .filter_map(read_exif_segment)
+        .collect();
+    for exif in &segments {
+        if let (Ok(Some(lat)), Ok(Some(lng))) = (
+            get_gps_coord(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef),
+            get_gps_coord(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef),
+        ) {
+            let datetime =
+                cached_datetime.or_else(|| segments.iter().find_map(get_datetime_string));
+            return Ok(ExtractedMetadata::new(lat, lng, datetime).with_gps_details(exif));
+        }
+    }
+
     // Fallback to custom GPS parser for malformed EXIF files (e.g., Lightroom-processed)
-    if let Some((lat, lng)) = gps_parser::extract_gps_from_malformed_exif(path) {
-        // Use cached datetime if available, otherwise try to read it
-        let datetime = if cached_datetime.is_some() {
-            cached_datetime
-        } else {
-            // Only re-open the file if we don't have cached datetime
-            File::open(path)
-                .ok()
-                .and_then(|f| {
-                    let mut buf = BufReader::new(f);
-                    exif::Reader::new().read_from_container(&mut buf).ok()
-                })
-                .and_then(|exif| get_datetime_string(&exif))
+    if let Some(fix) = gps_parser::extract_gps_from_jpeg_bytes(data) {
+        let datetime = cached_datetime.or_else(|| segments.iter().find_map(get_datetime_string));
+
+        let (speed_kmh, gps_dop, direction) = cached_details;
+        return Ok(ExtractedMetadata {
+            speed_kmh,
+            gps_dop,
+            direction,
+            ..ExtractedMetadata::new(fix.lat, fix.lng, datetime)
+        });
+    }
+
+    // Photoshop keeps a copy of the EXIF block in its APP13 resources, which may be
+    // the only one left after the APP1 segment was stripped or damaged
+    for tiff in photoshop_exif_blocks(data) {
+        let exif = read_exif_segment(tiff);
+        let position = exif
+            .as_ref()
+            .and_then(|exif| {
+                Some((
+                    get_gps_coord(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef).ok()??,
+                    get_gps_coord(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef).ok()??,
+                ))
+            })
+            .or_else(|| gps_parser::extract_gps_from_tiff(tiff).map(|fix| (fix.lat, fix.lng)));
+        if let Some((lat, lng)) = position {
+            let datetime = cached_datetime.or_else(|| exif.as_ref().and_then(get_datetime_string));
+            return Ok(ExtractedMetadata::new(lat, lng, datetime));
+        }
+    }
+
+    // Motion photos: fall back to the location recorded by the embedded video
+    if let Some(video) = find_embedded_video(data).map(mp4::extract_video_metadata) {
+        if let Some((lat, lng)) = video.location {
+            return Ok(ExtractedMetadata::new(
+                lat,
+                lng,
+                cached_datetime.or(video.datetime),
+            ));
+        }
+    }
+
+    match gps_problem {
+        Some(problem) => Err(super::ExifError::GpsUnparseable(problem).into()),
+        None => Err(super::ExifError::GpsNotFound.into()),
+    }
+}
+
+/// XMP markers written by Samsung and Google cameras for motion photos
+const MOTION_PHOTO_XMP_MARKERS: &[&[u8]] = &[
+    b"Camera:MotionPhoto=\"1\"",
+    b"Camera:MotionPhoto>1<",
+    b"Camera:MicroVideo=\"1\"",
+];
+
+/// Checks whether a JPEG is a motion photo (Samsung/Google JPEG with an appended MP4)
+pub fn detect_motion_photo(path: &Path) -> bool {
+    std::fs::read(path).is_ok_and(|data| detect_motion_photo_bytes(&data))
+}
+
+/// Checks JPEG data already in memory for the motion photo XMP flag or an appended MP4
+pub fn detect_motion_photo_bytes(data: &[u8]) -> bool {
+    has_motion_photo_xmp(data) || find_embedded_video(data).is_some()
+}
+
+/// Looks for the motion photo flag in the XMP (APP1) segments before the image data
+fn has_motion_photo_xmp(data: &[u8]) -> bool {
+    let header_end = find_start_of_scan(data).unwrap_or(data.len());
+    let header = &data[..header_end];
+    MOTION_PHOTO_XMP_MARKERS
+        .iter()
+        .any(|marker| header.windows(marker.len()).any(|w| w == *marker))
+}
+
+/// Returns the offset of the SOS marker, walking the JPEG segment chain
+fn find_start_of_scan(data: &[u8]) -> Option<usize> {
+    if data.len() < 4 || data[0..2] != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xDA {
+            return Some(pos);
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        pos += 2 + length;
+    }
+    None
+}
+
+/// Returns the payloads of all segments with the given marker before the image data
+fn app_segments(data: &[u8], marker: u8) -> Vec<&[u8]> {
+    let mut segments = Vec::new();
+    if data.len() < 4 || data[0..2] != [0xFF, 0xD8] {
+        return segments;
+    }
+
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF || data[pos + 1] == 0xDA {
+            break;
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if data[pos + 1] == marker {
+            let end = (pos + 2 + length).min(data.len());
+            if let Some(payload) = data.get(pos + 4..end) {
+                segments.push(payload);
+            }
+        }
+        pos += 2 + length;
+    }
+    segments
+}
+
+/// Returns the TIFF payloads of all EXIF APP1 segments before the image data
+fn exif_segments(data: &[u8]) -> Vec<&[u8]> {
+    app_segments(data, 0xE1)
+        .into_iter()
+        .filter_map(|payload| payload.strip_prefix(b"Exif\0\0"))
+        .collect()
+}
+
+/// Photoshop image resource holding a copy of the EXIF block
+const PHOTOSHOP_EXIF_RESOURCE: u16 = 0x0422;
+
+/// Returns the EXIF copies stored as Photoshop image resources (8BIM blocks) in APP13.
+/// IPTC-IIM datasets in the same segment only name places and carry no coordinates.
+fn photoshop_exif_blocks(data: &[u8]) -> Vec<&[u8]> {
+    let mut blocks = Vec::new();
+    for segment in app_segments(data, 0xED) {
+        let Some(mut resources) = segment.strip_prefix(b"Photoshop 3.0\0") else {
+            continue;
         };
+        // 8BIM, resource id, even-padded Pascal name, size, even-padded data
+        while resources.len() >= 8 && resources.starts_with(b"8BIM") {
+            let id = u16::from_be_bytes([resources[4], resources[5]]);
+            let name_len = (resources[6] as usize + 2) & !1;
+            let Some(size) = resources.get(6 + name_len..10 + name_len) else {
+                break;
+            };
+            let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+            let start = 10 + name_len;
+            let Some(block) = resources.get(start..start.saturating_add(size)) else {
+                break;
+            };
+            if id == PHOTOSHOP_EXIF_RESOURCE {
+                blocks.push(block);
+            }
+            resources = resources
+                .get(start + ((size + 1) & !1)..)
+                .unwrap_or_default();
+        }
+    }
+    blocks
+}
 
-        return Ok((lat, lng, datetime));
+/// Parses one APP1 payload, keeping whatever could be read from a damaged one
+fn read_exif_segment(tiff: &[u8]) -> Option<exif::Exif> {
+    let mut reader = exif::Reader::new();
+    reader.continue_on_error(true);
+    match reader.read_raw(tiff.to_vec()) {
+        Ok(exif) => Some(exif),
+        Err(exif::Error::PartialResult(partial)) => Some(partial.into_inner().0),
+        Err(_) => None,
     }
+}
+
+/// Returns the embedded MP4 that follows the JPEG EOI marker (0xFF 0xD9), if any
+fn find_embedded_video(data: &[u8]) -> Option<&[u8]> {
+    let sos = find_start_of_scan(data)?;
+
+    // Entropy-coded data stuffs 0xFF bytes, so the first FF D9 after SOS is the EOI
+    let eoi = sos + data[sos..].windows(2).position(|w| w == [0xFF, 0xD9])? + 2;
+    let trailer = &data[eoi..];
+
+    // The video starts at the box header preceding "ftyp" (some writers add a marker first)
+    let ftyp = trailer.windows(4).position(|w| w == b"ftyp")?;
+    if ftyp < 4 {
+        return None;
+    }
+    Some(&trailer[ftyp - 4..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_motion_photo_bytes, find_embedded_video, has_motion_photo_xmp};
+
+    fn minimal_jpeg(app1_payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend_from_slice(&((app1_payload.len() + 2) as u16).to_be_bytes());
+        data.extend_from_slice(app1_payload);
+        // SOS header followed by a few bytes of entropy-coded data and EOI
+        data.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn detects_motion_photo_xmp_flag() {
+        let xmp = b"http://ns.adobe.com/xap/1.0/\0<rdf:Description GCamera:MotionPhoto=\"1\"/>";
+        assert!(has_motion_photo_xmp(&minimal_jpeg(xmp)));
+        assert!(!has_motion_photo_xmp(&minimal_jpeg(b"Exif\0\0")));
+    }
+
+    #[test]
+    fn finds_video_appended_after_eoi() {
+        let mut data = minimal_jpeg(b"Exif\0\0");
+        assert!(find_embedded_video(&data).is_none());
 
-    Err(super::ExifError::GpsNotFound.into())
+        let video = b"\0\0\0\x18ftypmp42\0\0\0\0isommp42";
+        data.extend_from_slice(b"MotionPhoto_Data");
+        data.extend_from_slice(video);
+        assert_eq!(find_embedded_video(&data), Some(&video[..]));
+    }
+
+    #[test]
+    fn detects_motion_photos_from_bytes() {
+        let xmp = b"http://ns.adobe.com/xap/1.0/\0<rdf:Description GCamera:MicroVideo=\"1\"/>";
+        assert!(detect_motion_photo_bytes(&minimal_jpeg(xmp)));
+
+        let mut data = minimal_jpeg(b"Exif\0\0");
+        assert!(!detect_motion_photo_bytes(&data));
+        data.extend_from_slice(b"\0\0\0\x18ftypmp42\0\0\0\0isommp42");
+        assert!(detect_motion_photo_bytes(&data));
+    }
 }
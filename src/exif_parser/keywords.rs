@@ -0,0 +1,147 @@
+//! Keywords embedded in a photo, imported as its initial tags when
+//! `import_keywords` is enabled
+
+use std::io::Cursor;
+use std::path::Path;
+
+use exif::{Context, In, Tag, Value};
+
+/// Windows "Tags" field: UCS-2 LE text with keywords separated by ';'
+const XP_KEYWORDS: Tag = Tag(Context::Tiff, 0x9C9E);
+
+/// Keywords from the EXIF XPKeywords field and the XMP `dc:subject` bag,
+/// without duplicates. Unreadable files have none.
+pub fn read_keywords(path: &Path) -> Vec<String> {
+    match std::fs::read(path) {
+        Ok(data) => keywords_from_bytes(&data),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn keywords_from_bytes(data: &[u8]) -> Vec<String> {
+    let mut keywords = exif_keywords(data);
+    for keyword in xmp_subjects(data) {
+        if !keywords.contains(&keyword) {
+            keywords.push(keyword);
+        }
+    }
+    keywords
+}
+
+fn exif_keywords(data: &[u8]) -> Vec<String> {
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) else {
+        return Vec::new();
+    };
+    let Some(Value::Byte(bytes)) = exif.get_field(XP_KEYWORDS, In::PRIMARY).map(|f| &f.value)
+    else {
+        return Vec::new();
+    };
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .split(';')
+        .map(str::trim)
+        .filter(|keyword| !keyword.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Items of the first `<dc:subject>` bag found in the file
+fn xmp_subjects(data: &[u8]) -> Vec<String> {
+    let Some(start) = find(data, b"<dc:subject>") else {
+        return Vec::new();
+    };
+    let Some(len) = find(&data[start..], b"</dc:subject>") else {
+        return Vec::new();
+    };
+    let bag = String::from_utf8_lossy(&data[start..start + len]);
+
+    let mut subjects = Vec::new();
+    let mut rest = bag.as_ref();
+    while let Some(item) = rest.find("<rdf:li") {
+        rest = &rest[item..];
+        let Some(open_end) = rest.find('>') else {
+            break;
+        };
+        rest = &rest[open_end + 1..];
+        let Some(close) = rest.find("</rdf:li>") else {
+            break;
+        };
+        let subject = unescape_xml(rest[..close].trim());
+        if !subject.is_empty() {
+            subjects.push(subject);
+        }
+        rest = &rest[close..];
+    }
+    subjects
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keywords_from_bytes, read_keywords, XP_KEYWORDS};
+    use exif::experimental::Writer;
+    use exif::{Field, In, Value};
+
+    /// JPEG with an EXIF block holding XPKeywords and an XMP packet with `dc:subject`
+    fn tagged_jpeg() -> Vec<u8> {
+        let keywords: Vec<u8> = "Wedding; Family\0"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let mut writer = Writer::new();
+        let field = Field {
+            tag: XP_KEYWORDS,
+            ifd_num: In::PRIMARY,
+            value: Value::Byte(keywords),
+        };
+        writer.push_field(&field);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let xmp = b"http://ns.adobe.com/xap/1.0/\0<dc:subject><rdf:Bag>\
+            <rdf:li>family</rdf:li><rdf:li xml:lang=\"x-default\">Dogs &amp; cats</rdf:li>\
+            </rdf:Bag></dc:subject>";
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        for (header, payload) in [(&b"Exif\0\0"[..], &tiff[..]), (&b""[..], &xmp[..])] {
+            jpeg.extend_from_slice(&[0xFF, 0xE1]);
+            jpeg.extend_from_slice(&((header.len() + payload.len() + 2) as u16).to_be_bytes());
+            jpeg.extend_from_slice(header);
+            jpeg.extend_from_slice(payload);
+        }
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn reads_xp_keywords_and_xmp_subjects() {
+        let path = std::env::temp_dir().join("photomap_test_keywords.jpg");
+        std::fs::write(&path, tagged_jpeg()).unwrap();
+        let keywords = read_keywords(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(keywords, ["Wedding", "Family", "family", "Dogs & cats"]);
+    }
+
+    #[test]
+    fn files_without_keywords_have_none() {
+        assert!(keywords_from_bytes(b"").is_empty());
+        assert!(keywords_from_bytes(b"<dc:subject><rdf:Bag></rdf:Bag>").is_empty());
+    }
+}
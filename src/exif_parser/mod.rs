@@ -1,18 +1,107 @@
 pub mod generic;
 pub mod gps_parser;
 pub mod heic;
+pub mod info;
 pub mod jpeg;
+pub mod keywords;
+pub mod mp4;
+pub mod sidecar;
+pub mod validation;
+pub mod writer;
 
-pub use generic::{apply_exif_orientation, get_datetime_string, get_gps_coord};
+#[cfg(test)]
+mod tests;
+
+pub use generic::{
+    apply_exif_orientation, describe_unparseable_gps, get_datetime_string, get_gps_coord,
+    get_gps_direction, get_gps_dop, get_gps_speed, ExtractedMetadata,
+};
 pub use heic::extract_metadata_from_heic;
-pub use jpeg::extract_metadata_from_jpeg;
+pub use info::{full_info, PhotoInfo};
+pub use jpeg::{
+    detect_motion_photo, detect_motion_photo_bytes, extract_metadata_from_jpeg,
+    extract_metadata_from_jpeg_bytes,
+};
+pub use keywords::read_keywords;
+pub use sidecar::extract_metadata_from_sidecar;
+pub use validation::{validate_gps_coordinate, GpsQuality, GpsValidationResult};
+pub use writer::write_gps_to_jpeg;
+
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::Result;
+
+use self::generic::read_tolerant;
+use crate::image_processing::{image_format_of, ImageFormat};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ExifError {
     #[error("GPS data not found")]
     GpsNotFound,
+    #[error("GPS data present but unparseable: {0}")]
+    GpsUnparseable(String),
+    #[error("Suspicious GPS data: {0}")]
+    GpsSuspicious(String),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("EXIF error: {0}")]
     Exif(#[from] exif::Error),
 }
+
+/// Reads GPS position and capture date of an image file
+pub trait ExifExtractor: Send + Sync {
+    fn extract(&self, path: &Path) -> Result<ExtractedMetadata>;
+}
+
+/// Reads metadata embedded in the file, choosing the parser by the file content
+/// and by the extension when the content is not recognized
+pub struct DefaultExifExtractor;
+
+impl ExifExtractor for DefaultExifExtractor {
+    fn extract(&self, path: &Path) -> Result<ExtractedMetadata> {
+        match image_format_of(path) {
+            Some(format) if format.is_heif() => return extract_metadata_from_heic(path),
+            Some(ImageFormat::Jpeg) => return extract_metadata_from_jpeg(path),
+            _ => {}
+        }
+
+        extract_metadata_from_container(path)
+    }
+}
+
+/// Reads position and date of a PNG, TIFF or WebP file, or of a file of unknown
+/// format. A TIFF is an EXIF block itself, so its GPS IFD gets the same fallback
+/// as HEIC files; the others keep their EXIF in a chunk the reader looks up.
+fn extract_metadata_from_container(path: &Path) -> Result<ExtractedMetadata> {
+    let data = std::fs::read(path)?;
+    if image_format_of(path) == Some(ImageFormat::Tiff) {
+        return heic::metadata_from_tiff(&data);
+    }
+
+    // Damaged blocks yield what could be parsed, and no EXIF at all means no GPS
+    let exif = read_tolerant(|reader| reader.read_from_container(&mut Cursor::new(&data)))
+        .ok_or(ExifError::GpsNotFound)?;
+    let lat = get_gps_coord(&exif, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef)?;
+    let lng = get_gps_coord(&exif, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef)?;
+    match (lat, lng) {
+        (Some(lat), Some(lng)) => Ok(
+            ExtractedMetadata::new(lat, lng, get_datetime_string(&exif)).with_gps_details(&exif)
+        ),
+        _ => Err(match describe_unparseable_gps(&exif) {
+            Some(problem) => ExifError::GpsUnparseable(problem),
+            None => ExifError::GpsNotFound,
+        }
+        .into()),
+    }
+}
+
+impl ExifError {
+    /// Whether the file was read fine but yields no usable location
+    pub fn is_missing_gps(&self) -> bool {
+        matches!(
+            self,
+            ExifError::GpsNotFound | ExifError::GpsUnparseable(_) | ExifError::GpsSuspicious(_)
+        )
+    }
+}
@@ -0,0 +1,191 @@
+//! Minimal ISO-BMFF (MP4/QuickTime) reader for videos embedded in motion photos
+//! Only walks the boxes needed for location (`moov/udta/©xyz`) and creation time (`moov/mvhd`)
+
+use super::generic::format_unix_timestamp;
+
+/// Seconds between 1904-01-01 (MP4 epoch) and 1970-01-01 (Unix epoch)
+const MP4_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+/// Metadata recovered from an embedded video
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VideoMetadata {
+    pub location: Option<(f64, f64)>,
+    pub datetime: Option<String>,
+}
+
+/// Extracts location and creation time from an in-memory MP4 blob
+pub fn extract_video_metadata(data: &[u8]) -> VideoMetadata {
+    let mut metadata = VideoMetadata::default();
+
+    let Some(moov) = find_box(data, b"moov") else {
+        return metadata;
+    };
+
+    if let Some(mvhd) = find_box(moov, b"mvhd") {
+        metadata.datetime = parse_mvhd_creation_time(mvhd);
+    }
+
+    if let Some(udta) = find_box(moov, b"udta") {
+        if let Some(xyz) = find_box(udta, b"\xA9xyz") {
+            metadata.location = parse_xyz_box(xyz);
+        }
+    }
+
+    metadata
+}
+
+/// Iterates over sibling boxes and returns the payload of the first box with the given type
+fn find_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        let current_type = &data[pos + 4..pos + 8];
+
+        let (header_len, box_len) = match size {
+            // Box extends to the end of the enclosing data
+            0 => (8, data.len() - pos),
+            // 64-bit extended size follows the type
+            1 => {
+                if pos + 16 > data.len() {
+                    return None;
+                }
+                let mut large = [0u8; 8];
+                large.copy_from_slice(&data[pos + 8..pos + 16]);
+                (16, usize::try_from(u64::from_be_bytes(large)).ok()?)
+            }
+            n => (8, n as usize),
+        };
+
+        if box_len < header_len || pos.checked_add(box_len)? > data.len() {
+            return None;
+        }
+
+        if current_type == box_type {
+            return Some(&data[pos + header_len..pos + box_len]);
+        }
+
+        pos += box_len;
+    }
+    None
+}
+
+/// Reads the creation time from a `mvhd` payload (version 0 or 1)
+fn parse_mvhd_creation_time(mvhd: &[u8]) -> Option<String> {
+    let version = *mvhd.first()?;
+    let seconds = if version == 1 {
+        let bytes: [u8; 8] = mvhd.get(4..12)?.try_into().ok()?;
+        u64::from_be_bytes(bytes)
+    } else {
+        let bytes: [u8; 4] = mvhd.get(4..8)?.try_into().ok()?;
+        u32::from_be_bytes(bytes) as u64
+    };
+
+    // Writers without a clock store zero here
+    if seconds <= MP4_EPOCH_OFFSET {
+        return None;
+    }
+
+    Some(format_unix_timestamp(seconds - MP4_EPOCH_OFFSET))
+}
+
+/// Parses the `©xyz` payload: [u16 length][u16 language][ISO 6709 string]
+fn parse_xyz_box(xyz: &[u8]) -> Option<(f64, f64)> {
+    let text = if xyz.len() >= 4 {
+        let len = u16::from_be_bytes([xyz[0], xyz[1]]) as usize;
+        xyz.get(4..4 + len).unwrap_or(&xyz[4..])
+    } else {
+        xyz
+    };
+    parse_iso6709(std::str::from_utf8(text).ok()?)
+}
+
+/// Parses an ISO 6709 location string such as "+37.7749-122.4194+010.000/"
+fn parse_iso6709(value: &str) -> Option<(f64, f64)> {
+    let value = value.trim().trim_end_matches('/');
+
+    // Split into signed components: latitude, longitude, optional altitude
+    let mut components = Vec::new();
+    let mut start = 0;
+    for (i, c) in value.char_indices().skip(1) {
+        if c == '+' || c == '-' {
+            components.push(&value[start..i]);
+            start = i;
+        }
+    }
+    components.push(&value[start..]);
+
+    if components.len() < 2 {
+        return None;
+    }
+
+    let lat: f64 = components[0].parse().ok()?;
+    let lng: f64 = components[1].parse().ok()?;
+
+    if !lat.is_finite() || !lng.is_finite() || lat.abs() > 90.0 || lng.abs() > 180.0 {
+        return None;
+    }
+
+    // Devices without a fix record the null island
+    if lat == 0.0 && lng == 0.0 {
+        return None;
+    }
+
+    Some((lat, lng))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(box_type);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn sample_video(location: &str) -> Vec<u8> {
+        let mut xyz_payload = (location.len() as u16).to_be_bytes().to_vec();
+        xyz_payload.extend_from_slice(&[0x15, 0xC7]);
+        xyz_payload.extend_from_slice(location.as_bytes());
+
+        // 2024-06-01 12:30:00 UTC in MP4 epoch seconds
+        let created = 1_717_245_000u64 + MP4_EPOCH_OFFSET;
+        let mut mvhd_payload = vec![0u8; 4];
+        mvhd_payload.extend_from_slice(&(created as u32).to_be_bytes());
+        mvhd_payload.extend_from_slice(&[0u8; 92]);
+
+        let udta = mp4_box(b"udta", &mp4_box(b"\xA9xyz", &xyz_payload));
+        let mut moov_payload = mp4_box(b"mvhd", &mvhd_payload);
+        moov_payload.extend_from_slice(&udta);
+
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0isommp42");
+        data.extend_from_slice(&mp4_box(b"moov", &moov_payload));
+        data
+    }
+
+    #[test]
+    fn reads_location_and_creation_time() {
+        let metadata = extract_video_metadata(&sample_video("+37.7749-122.4194+010.000/"));
+        assert_eq!(metadata.location, Some((37.7749, -122.4194)));
+        assert_eq!(metadata.datetime.as_deref(), Some("2024-06-01 12:30:00"));
+    }
+
+    #[test]
+    fn rejects_invalid_iso6709_values() {
+        assert_eq!(parse_iso6709("+00.0000+000.0000/"), None);
+        assert_eq!(parse_iso6709("+95.0000+010.0000/"), None);
+        assert_eq!(parse_iso6709("garbage"), None);
+        assert_eq!(
+            parse_iso6709("-33.8688+151.2093/"),
+            Some((-33.8688, 151.2093))
+        );
+    }
+
+    #[test]
+    fn truncated_boxes_are_ignored() {
+        let mut data = sample_video("+37.7749-122.4194/");
+        data.truncate(data.len() - 10);
+        assert_eq!(extract_video_metadata(&data), VideoMetadata::default());
+    }
+}
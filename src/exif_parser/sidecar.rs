@@ -0,0 +1,378 @@
+//! Metadata from files stored next to a photo: XMP sidecars written by photo
+//! editors and JSON files shipped with Google Photos Takeout exports
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use super::generic::format_unix_timestamp;
+use super::ExifError;
+use crate::database::ExifSource;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TakeoutJson {
+    photo_taken_time: Option<TakeoutTime>,
+    geo_data_exif: Option<TakeoutGeoData>,
+    geo_data: Option<TakeoutGeoData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TakeoutTime {
+    timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TakeoutGeoData {
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Reads GPS and timestamp from a sidecar of the given photo.
+/// XMP sidecars are preferred over Takeout JSON.
+pub fn extract_metadata_from_sidecar(
+    path: &Path,
+) -> Result<(f64, f64, Option<String>, ExifSource)> {
+    for sidecar in sidecar_candidates(path, &["xmp", "XMP"]) {
+        if let Ok(text) = std::fs::read_to_string(&sidecar) {
+            if let Some((lat, lng, datetime)) = parse_xmp_sidecar(&text) {
+                return Ok((lat, lng, datetime, ExifSource::XmpSidecar));
+            }
+        }
+    }
+
+    for sidecar in takeout_json_candidates(path) {
+        if let Ok(text) = std::fs::read_to_string(&sidecar) {
+            if let Some((lat, lng, datetime)) = parse_takeout_json(&text) {
+                return Ok((lat, lng, datetime, ExifSource::GoogleTakeoutJson));
+            }
+        }
+    }
+
+    Err(ExifError::GpsNotFound.into())
+}
+
+/// Lists `{filename}.{suffix}` and `{stem}.{suffix}` paths that exist
+fn sidecar_candidates(path: &Path, suffixes: &[&str]) -> Vec<PathBuf> {
+    let (Some(filename), Some(stem)) = (
+        path.file_name().and_then(|n| n.to_str()),
+        path.file_stem().and_then(|n| n.to_str()),
+    ) else {
+        return Vec::new();
+    };
+
+    suffixes
+        .iter()
+        .flat_map(|suffix| {
+            [
+                path.with_file_name(format!("{filename}.{suffix}")),
+                path.with_file_name(format!("{stem}.{suffix}")),
+            ]
+        })
+        .filter(|candidate| candidate.is_file())
+        .collect()
+}
+
+/// Lists the Takeout JSON files that may describe a photo. Takeout writes no JSON for
+/// `IMG-edited.jpg`, which shares the one of `IMG.jpg`, and names the JSON of the
+/// duplicate `IMG(1).jpg` as `IMG.jpg(1).json`.
+fn takeout_json_candidates(path: &Path) -> Vec<PathBuf> {
+    const SUFFIXES: [&str; 2] = ["supplemental-metadata.json", "json"];
+
+    let mut photos = vec![path.to_path_buf()];
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{e}"))
+        .unwrap_or_default();
+    if let Some(original) = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|stem| stem.strip_suffix("-edited"))
+    {
+        photos.push(path.with_file_name(format!("{original}{extension}")));
+    }
+
+    let mut candidates = Vec::new();
+    for photo in &photos {
+        candidates.extend(sidecar_candidates(photo, &SUFFIXES));
+
+        let Some((base, counter)) = photo
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(split_duplicate_counter)
+        else {
+            continue;
+        };
+        candidates.extend(
+            [
+                format!("{base}{extension}.supplemental-metadata{counter}.json"),
+                format!("{base}{extension}{counter}.json"),
+            ]
+            .into_iter()
+            .map(|name| photo.with_file_name(name))
+            .filter(|candidate| candidate.is_file()),
+        );
+    }
+    candidates
+}
+
+/// Splits "IMG(1)" into ("IMG", "(1)")
+fn split_duplicate_counter(stem: &str) -> Option<(&str, &str)> {
+    let open = stem.strip_suffix(')')?.rfind('(')?;
+    let digits = &stem[open + 1..stem.len() - 1];
+    (!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .then(|| (&stem[..open], &stem[open..]))
+}
+
+/// Parses a Takeout JSON sidecar; `geoDataExif` (camera position) wins over
+/// `geoData` (position edited in Google Photos). Takeout timestamps are UTC.
+fn parse_takeout_json(text: &str) -> Option<(f64, f64, Option<String>)> {
+    let json: TakeoutJson = serde_json::from_str(text).ok()?;
+
+    let (lat, lng) = [json.geo_data_exif, json.geo_data]
+        .into_iter()
+        .flatten()
+        .map(|geo| (geo.latitude, geo.longitude))
+        .find(|&(lat, lng)| is_valid_position(lat, lng))?;
+
+    let datetime = json
+        .photo_taken_time
+        .and_then(|t| t.timestamp.trim().parse::<u64>().ok())
+        .filter(|&seconds| seconds > 0)
+        .map(format_unix_timestamp);
+
+    Some((lat, lng, datetime))
+}
+
+/// Parses GPS and capture date from an XMP sidecar (darktable, Lightroom, digiKam)
+fn parse_xmp_sidecar(text: &str) -> Option<(f64, f64, Option<String>)> {
+    let lat = parse_xmp_coordinate(xmp_value(text, "exif:GPSLatitude")?)?;
+    let lng = parse_xmp_coordinate(xmp_value(text, "exif:GPSLongitude")?)?;
+    if !is_valid_position(lat, lng) {
+        return None;
+    }
+
+    let datetime = [
+        "exif:DateTimeOriginal",
+        "photoshop:DateCreated",
+        "xmp:CreateDate",
+    ]
+    .iter()
+    .filter_map(|name| xmp_value(text, name))
+    .find_map(parse_xmp_datetime);
+
+    Some((lat, lng, datetime))
+}
+
+/// Finds a property written either as an attribute or as an element
+fn xmp_value<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let attribute = format!("{name}=\"");
+    if let Some(start) = text.find(&attribute).map(|i| i + attribute.len()) {
+        let end = text[start..].find('"')?;
+        return Some(&text[start..start + end]);
+    }
+
+    let element = format!("<{name}>");
+    let start = text.find(&element)? + element.len();
+    let end = text[start..].find('<')?;
+    Some(text[start..start + end].trim())
+}
+
+/// Parses XMP GPS coordinates: "DDD,MM.mmk" or "DDD,MM,SSk" with k in N/S/E/W
+fn parse_xmp_coordinate(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let reference = value.chars().last()?;
+    let sign = match reference.to_ascii_uppercase() {
+        'N' | 'E' => 1.0,
+        'S' | 'W' => -1.0,
+        _ => return None,
+    };
+
+    let parts: Vec<f64> = value[..value.len() - 1]
+        .split(',')
+        .map(|p| p.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    let decimal = match parts.as_slice() {
+        [degrees, minutes] => degrees + minutes / 60.0,
+        [degrees, minutes, seconds] => degrees + minutes / 60.0 + seconds / 3600.0,
+        _ => return None,
+    };
+
+    Some(sign * decimal)
+}
+
+/// Converts an XMP date ("YYYY-MM-DDTHH:MM:SS[.sss][zone]") to "YYYY-MM-DD HH:MM:SS"
+fn parse_xmp_datetime(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.len() < 19 || value.as_bytes()[10] != b'T' {
+        return None;
+    }
+    Some(format!("{} {}", &value[..10], value.get(11..19)?))
+}
+
+fn is_valid_position(lat: f64, lng: f64) -> bool {
+    lat.is_finite()
+        && lng.is_finite()
+        && lat.abs() <= 90.0
+        && lng.abs() <= 180.0
+        // Takeout writes zeros when a photo has no location
+        && !(lat == 0.0 && lng == 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Layout of a Google Photos Takeout export (2024+ naming)
+    const TAKEOUT_JSON: &str = r#"{
+  "title": "IMG_20210331_153703.jpg",
+  "description": "",
+  "imageViews": "12",
+  "creationTime": {
+    "timestamp": "1617282931",
+    "formatted": "1 Apr 2021, 13:15:31 UTC"
+  },
+  "photoTakenTime": {
+    "timestamp": "1617197823",
+    "formatted": "31 Mar 2021, 13:37:03 UTC"
+  },
+  "geoData": {
+    "latitude": 41.3851,
+    "longitude": 2.1734,
+    "altitude": 0.0,
+    "latitudeSpan": 0.0,
+    "longitudeSpan": 0.0
+  },
+  "geoDataExif": {
+    "latitude": 41.4036299,
+    "longitude": 2.1743558,
+    "altitude": 48.3,
+    "latitudeSpan": 0.0,
+    "longitudeSpan": 0.0
+  },
+  "url": "https://photos.google.com/photo/AF1QipExample",
+  "googlePhotosOrigin": {
+    "mobileUpload": {
+      "deviceType": "ANDROID_PHONE"
+    }
+  }
+}"#;
+
+    // Screenshots and messenger images carry no camera position
+    const TAKEOUT_JSON_NO_EXIF_GPS: &str = r#"{
+  "title": "IMG-20220514-WA0007.jpg",
+  "photoTakenTime": {
+    "timestamp": "1652529600",
+    "formatted": "14 May 2022, 12:00:00 UTC"
+  },
+  "geoData": {
+    "latitude": 52.3676,
+    "longitude": 4.9041,
+    "altitude": 0.0,
+    "latitudeSpan": 0.0,
+    "longitudeSpan": 0.0
+  },
+  "geoDataExif": {
+    "latitude": 0.0,
+    "longitude": 0.0,
+    "altitude": 0.0,
+    "latitudeSpan": 0.0,
+    "longitudeSpan": 0.0
+  }
+}"#;
+
+    const XMP_SIDECAR: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:exif="http://ns.adobe.com/exif/1.0/"
+    exif:DateTimeOriginal="2023-08-12T18:04:55.120+02:00"
+    exif:GPSLatitude="48,51.3960N"
+    exif:GPSLongitude="2,21.1320E"/>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+    #[test]
+    fn takeout_prefers_exif_position() {
+        let (lat, lng, datetime) = parse_takeout_json(TAKEOUT_JSON).unwrap();
+        assert_eq!((lat, lng), (41.4036299, 2.1743558));
+        assert_eq!(datetime.as_deref(), Some("2021-03-31 13:37:03"));
+    }
+
+    #[test]
+    fn takeout_falls_back_to_edited_position() {
+        let (lat, lng, _) = parse_takeout_json(TAKEOUT_JSON_NO_EXIF_GPS).unwrap();
+        assert_eq!((lat, lng), (52.3676, 4.9041));
+    }
+
+    #[test]
+    fn takeout_without_position_is_ignored() {
+        let json = r#"{"photoTakenTime": {"timestamp": "1652529600"},
+            "geoData": {"latitude": 0.0, "longitude": 0.0}}"#;
+        assert!(parse_takeout_json(json).is_none());
+        assert!(parse_takeout_json("not json").is_none());
+    }
+
+    #[test]
+    fn parses_xmp_sidecar() {
+        let (lat, lng, datetime) = parse_xmp_sidecar(XMP_SIDECAR).unwrap();
+        assert!((lat - 48.8566).abs() < 1e-6);
+        assert!((lng - 2.3522).abs() < 1e-6);
+        assert_eq!(datetime.as_deref(), Some("2023-08-12 18:04:55"));
+
+        let south = parse_xmp_coordinate("33,52,7.68S").unwrap();
+        assert!((south + 33.8688).abs() < 1e-6);
+        assert_eq!(parse_xmp_coordinate("33.8688"), None);
+    }
+
+    #[test]
+    fn finds_supplemental_metadata_next_to_photo() {
+        let dir = std::env::temp_dir().join("photomap_test_takeout_sidecar");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let photo = dir.join("IMG_20210331_153703.jpg");
+        std::fs::write(&photo, b"").unwrap();
+        assert!(extract_metadata_from_sidecar(&photo).is_err());
+
+        std::fs::write(
+            dir.join("IMG_20210331_153703.jpg.supplemental-metadata.json"),
+            TAKEOUT_JSON,
+        )
+        .unwrap();
+        let (lat, _, _, source) = extract_metadata_from_sidecar(&photo).unwrap();
+        assert_eq!(lat, 41.4036299);
+        assert_eq!(source, ExifSource::GoogleTakeoutJson);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn edited_and_duplicate_photos_use_takeout_names() {
+        let dir = std::env::temp_dir().join("photomap_test_takeout_variants");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // The edited copy shares the JSON of the original
+        std::fs::write(dir.join("PXL_0001.jpg.json"), TAKEOUT_JSON).unwrap();
+        let edited = dir.join("PXL_0001-edited.jpg");
+        std::fs::write(&edited, b"").unwrap();
+        let (lat, _, _, _) = extract_metadata_from_sidecar(&edited).unwrap();
+        assert_eq!(lat, 41.4036299);
+
+        // The counter of a duplicate moves behind the extension
+        std::fs::write(dir.join("IMG_0002.jpg(1).json"), TAKEOUT_JSON_NO_EXIF_GPS).unwrap();
+        let duplicate = dir.join("IMG_0002(1).jpg");
+        std::fs::write(&duplicate, b"").unwrap();
+        let (lat, _, _, _) = extract_metadata_from_sidecar(&duplicate).unwrap();
+        assert_eq!(lat, 52.3676);
+        assert!(extract_metadata_from_sidecar(&dir.join("IMG_0002.jpg")).is_err());
+
+        assert_eq!(split_duplicate_counter("IMG(12)"), Some(("IMG", "(12)")));
+        assert_eq!(split_duplicate_counter("Trip (Rome)"), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
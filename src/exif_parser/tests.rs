@@ -0,0 +1,707 @@
+//! Property tests for GPS parsing of untrusted EXIF data
+
+use exif::experimental::Writer;
+use exif::{Field, In, Rational, Reader, Tag, Value};
+use proptest::prelude::*;
+
+use super::generic::{
+    describe_unparseable_gps, get_datetime_string, get_gps_coord, get_gps_direction, get_gps_dop,
+    get_gps_speed, parse_timestamp, try_get_gps_from_ifd, validate_gps_coordinate, GpsQuality,
+};
+use super::gps_parser::{extract_gps_from_jpeg_bytes, extract_gps_from_tiff};
+use super::heic::metadata_from_tiff;
+use super::jpeg::{extract_metadata_from_jpeg, extract_metadata_from_jpeg_bytes};
+use super::{DefaultExifExtractor, ExifError, ExifExtractor};
+
+/// Builds an in-memory EXIF block holding one GPS coordinate and its reference
+fn synthetic_exif(
+    coord_tag: Tag,
+    ref_tag: Tag,
+    parts: &[(u32, u32); 3],
+    reference: u8,
+) -> Option<exif::Exif> {
+    let coord = Field {
+        tag: coord_tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Rational(
+            parts
+                .iter()
+                .map(|&(num, denom)| Rational { num, denom })
+                .collect(),
+        ),
+    };
+    let coord_ref = Field {
+        tag: ref_tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![vec![reference]]),
+    };
+
+    let mut writer = Writer::new();
+    writer.push_field(&coord);
+    writer.push_field(&coord_ref);
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buffer, false).ok()?;
+
+    Reader::new().read_raw(buffer.into_inner()).ok()
+}
+
+fn rational_parts() -> impl Strategy<Value = [(u32, u32); 3]> {
+    // Mix fully arbitrary values with realistic small ones so both paths are hit
+    let part = prop_oneof![
+        (any::<u32>(), any::<u32>()),
+        (0u32..200, 0u32..4),
+        (0u32..6000, 1u32..100),
+    ];
+    [part.clone(), part.clone(), part]
+}
+
+fn reference_byte() -> impl Strategy<Value = u8> {
+    prop_oneof![Just(b'N'), Just(b'S'), Just(b'E'), Just(b'W'), any::<u8>()]
+}
+
+fn assert_coordinate_in_range(value: Option<f64>, limit: f64) -> Result<(), TestCaseError> {
+    if let Some(value) = value {
+        prop_assert!(value.is_finite(), "non-finite coordinate {}", value);
+        prop_assert!(
+            value.abs() <= limit,
+            "coordinate {} exceeds {}",
+            value,
+            limit
+        );
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn latitude_rationals_never_produce_invalid_values(
+        parts in rational_parts(),
+        reference in reference_byte(),
+    ) {
+        let Some(exif) = synthetic_exif(Tag::GPSLatitude, Tag::GPSLatitudeRef, &parts, reference)
+        else {
+            return Ok(());
+        };
+
+        let direct =
+            try_get_gps_from_ifd(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, In::PRIMARY)
+                .unwrap();
+        assert_coordinate_in_range(direct, 90.0)?;
+
+        let any_ifd = get_gps_coord(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef).unwrap();
+        assert_coordinate_in_range(any_ifd, 90.0)?;
+    }
+
+    #[test]
+    fn longitude_rationals_never_produce_invalid_values(
+        parts in rational_parts(),
+        reference in reference_byte(),
+    ) {
+        let Some(exif) =
+            synthetic_exif(Tag::GPSLongitude, Tag::GPSLongitudeRef, &parts, reference)
+        else {
+            return Ok(());
+        };
+
+        let direct =
+            try_get_gps_from_ifd(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, In::PRIMARY)
+                .unwrap();
+        assert_coordinate_in_range(direct, 180.0)?;
+
+        let any_ifd = get_gps_coord(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef).unwrap();
+        assert_coordinate_in_range(any_ifd, 180.0)?;
+    }
+
+    #[test]
+    fn malformed_exif_parser_never_panics(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let _ = extract_gps_from_jpeg_bytes(&data);
+    }
+
+    #[test]
+    fn malformed_exif_parser_never_panics_past_tiff_header(
+        little_endian in any::<bool>(),
+        tail in proptest::collection::vec(any::<u8>(), 0..512),
+    ) {
+        // A valid SOI/APP1/TIFF prefix sends random bytes into the IFD walkers
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1, 0xFF, 0xFF];
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(if little_endian { b"II\x2A\0" } else { b"MM\0\x2A" });
+        data.extend_from_slice(&tail);
+
+        if let Some(fix) = extract_gps_from_jpeg_bytes(&data) {
+            prop_assert!(fix.lat.is_finite() && fix.lat.abs() <= 90.0);
+            prop_assert!(fix.lng.is_finite() && fix.lng.abs() <= 180.0);
+            prop_assert!(fix.altitude.is_none_or(f64::is_finite));
+        }
+    }
+}
+
+#[test]
+fn synthetic_exif_round_trips_a_known_position() {
+    // 48°51'24" S
+    let exif = synthetic_exif(
+        Tag::GPSLatitude,
+        Tag::GPSLatitudeRef,
+        &[(48, 1), (51, 1), (24, 1)],
+        b'S',
+    )
+    .unwrap();
+    let lat = try_get_gps_from_ifd(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, In::PRIMARY)
+        .unwrap()
+        .unwrap();
+    assert!((lat + 48.856_666).abs() < 1e-5);
+}
+
+#[test]
+fn unparseable_gps_is_described() {
+    // 200° is out of range for a latitude, and the longitude is absent
+    let exif = synthetic_exif(
+        Tag::GPSLatitude,
+        Tag::GPSLatitudeRef,
+        &[(200, 1), (0, 1), (0, 1)],
+        b'N',
+    )
+    .unwrap();
+    let problem = describe_unparseable_gps(&exif).unwrap();
+    assert!(
+        problem.starts_with("invalid GPSLatitude value"),
+        "{problem}"
+    );
+    assert!(problem.ends_with("GPSLongitude missing"), "{problem}");
+
+    let valid = synthetic_exif(
+        Tag::GPSLatitude,
+        Tag::GPSLatitudeRef,
+        &[(48, 1), (51, 1), (24, 1)],
+        b'N',
+    )
+    .unwrap();
+    assert_eq!(
+        describe_unparseable_gps(&valid).as_deref(),
+        Some("GPSLongitude missing")
+    );
+}
+
+/// EXIF block with the given GPS speed and DOP fields
+fn speed_exif(speed: (u32, u32), unit: Option<u8>, dop: Option<(u32, u32)>) -> exif::Exif {
+    let rational = |(num, denom): (u32, u32)| Value::Rational(vec![Rational { num, denom }]);
+    let mut fields = vec![Field {
+        tag: Tag::GPSSpeed,
+        ifd_num: In::PRIMARY,
+        value: rational(speed),
+    }];
+    if let Some(unit) = unit {
+        fields.push(Field {
+            tag: Tag::GPSSpeedRef,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![vec![unit]]),
+        });
+    }
+    if let Some(dop) = dop {
+        fields.push(Field {
+            tag: Tag::GPSDOP,
+            ifd_num: In::PRIMARY,
+            value: rational(dop),
+        });
+    }
+
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buffer, false).unwrap();
+    Reader::new().read_raw(buffer.into_inner()).unwrap()
+}
+
+#[test]
+fn gps_speed_is_converted_to_kmh() {
+    let kmh = speed_exif((123, 10), Some(b'K'), Some((32, 10)));
+    assert_eq!(get_gps_speed(&kmh), Some(12.3));
+    assert_eq!(get_gps_dop(&kmh), Some(3.2));
+
+    let mph = get_gps_speed(&speed_exif((10, 1), Some(b'M'), None)).unwrap();
+    assert!((mph - 16.09344).abs() < 1e-9, "{mph}");
+    let knots = get_gps_speed(&speed_exif((10, 1), Some(b'N'), None)).unwrap();
+    assert!((knots - 18.52).abs() < 1e-9, "{knots}");
+    assert_eq!(get_gps_speed(&speed_exif((5, 1), None, None)), Some(5.0));
+
+    // Unknown unit and a zero denominator give no speed
+    assert_eq!(get_gps_speed(&speed_exif((5, 1), Some(b'X'), None)), None);
+    let broken = speed_exif((5, 0), Some(b'K'), Some((1, 0)));
+    assert_eq!((get_gps_speed(&broken), get_gps_dop(&broken)), (None, None));
+}
+
+#[test]
+fn gps_positions_are_graded() {
+    let quality = |lat: f64, lng: f64, dop: Option<f64>| {
+        validate_gps_coordinate(lat, lng).with_dop(dop).quality
+    };
+    assert_eq!(quality(48.8584, 2.2945, None), GpsQuality::High);
+    assert_eq!(quality(48.8584, 2.2945, Some(3.0)), GpsQuality::High);
+    assert_eq!(quality(48.8584, 2.2945, Some(8.0)), GpsQuality::Medium);
+    assert_eq!(quality(48.8584, 2.2945, Some(25.0)), GpsQuality::Low);
+    // 52/1 0/1 0/1 and 13/1 0/1 0/1: degrees without minutes or seconds
+    assert_eq!(quality(52.0, 13.0, None), GpsQuality::Low);
+    assert_eq!(quality(0.0, 0.0, None), GpsQuality::Suspicious);
+    assert_eq!(quality(0.0, 0.0, Some(25.0)), GpsQuality::Suspicious);
+    assert_eq!(quality(4294967.295, 10.0, None), GpsQuality::Suspicious);
+    assert_eq!(quality(10.0, f64::NAN, None), GpsQuality::Suspicious);
+
+    let result = validate_gps_coordinate(0.0, 0.0).with_dop(Some(25.0));
+    assert_eq!(result.issues.len(), 2, "{:?}", result.issues);
+    assert!(validate_gps_coordinate(48.8584, 2.2945).issues.is_empty());
+}
+
+/// Little-endian TIFF whose GPS IFD pointer has type UNDEFINED instead of LONG,
+/// so a regular EXIF reader does not follow it while the GPS IFD itself is valid.
+/// The GPS IFD also carries 12.5 m below sea level and a 2024:06:01 date stamp
+fn tiff_with_mistyped_gps_pointer() -> Vec<u8> {
+    let mut tiff = Vec::new();
+    tiff.extend_from_slice(b"II");
+    tiff.extend_from_slice(&42u16.to_le_bytes());
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+
+    // IFD0 at 8: a single GPS IFD pointer
+    let rationals_at = 8 + 2 + 12 + 4;
+    let altitude_at = rationals_at + 48;
+    let date_at = altitude_at + 8;
+    let gps_at = date_at + 12;
+    tiff.extend_from_slice(&1u16.to_le_bytes());
+    tiff.extend_from_slice(&0x8825u16.to_le_bytes());
+    tiff.extend_from_slice(&7u16.to_le_bytes());
+    tiff.extend_from_slice(&1u32.to_le_bytes());
+    tiff.extend_from_slice(&(gps_at as u32).to_le_bytes());
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+
+    // 55°45'0" and 37°37'12"
+    for value in [55u32, 45, 0, 37, 37, 12] {
+        tiff.extend_from_slice(&value.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+    }
+    tiff.extend_from_slice(&25u32.to_le_bytes());
+    tiff.extend_from_slice(&2u32.to_le_bytes());
+    tiff.extend_from_slice(b"2024:06:01\0\0");
+
+    tiff.extend_from_slice(&7u16.to_le_bytes());
+    let entry = |tiff: &mut Vec<u8>, tag: u16, format: u16, count: u32, value: [u8; 4]| {
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&format.to_le_bytes());
+        tiff.extend_from_slice(&count.to_le_bytes());
+        tiff.extend_from_slice(&value);
+    };
+    entry(&mut tiff, 1, 2, 2, *b"N\0\0\0");
+    entry(&mut tiff, 2, 5, 3, (rationals_at as u32).to_le_bytes());
+    entry(&mut tiff, 3, 2, 2, *b"E\0\0\0");
+    entry(&mut tiff, 4, 5, 3, (rationals_at as u32 + 24).to_le_bytes());
+    entry(&mut tiff, 5, 1, 1, [1, 0, 0, 0]);
+    entry(&mut tiff, 6, 5, 1, (altitude_at as u32).to_le_bytes());
+    entry(&mut tiff, 29, 2, 11, (date_at as u32).to_le_bytes());
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    tiff
+}
+
+#[test]
+fn heic_exif_with_mistyped_gps_pointer_falls_back_to_raw_gps_walk() {
+    let tiff = tiff_with_mistyped_gps_pointer();
+    let fix = extract_gps_from_tiff(&tiff).unwrap();
+    let (lat, lng) = (fix.lat, fix.lng);
+    assert!((lat - 55.75).abs() < 1e-9 && (lng - 37.62).abs() < 1e-9);
+    assert_eq!(fix.altitude, Some(-12.5));
+    assert_eq!(fix.date_stamp.as_deref(), Some("2024-06-01"));
+
+    let mut reader = Reader::new();
+    reader.continue_on_error(true);
+    let standard = match reader.read_raw(tiff.clone()) {
+        Ok(exif) => Some(exif),
+        Err(exif::Error::PartialResult(partial)) => Some(partial.into_inner().0),
+        Err(_) => None,
+    };
+    assert!(standard
+        .and_then(|exif| get_gps_coord(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef).unwrap())
+        .is_none());
+
+    let metadata = metadata_from_tiff(&tiff).unwrap();
+    assert_eq!((metadata.lat, metadata.lng), (lat, lng));
+
+    // The JPEG path is a wrapper around the same walk
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+    jpeg.extend_from_slice(b"Exif\0\0");
+    jpeg.extend_from_slice(&tiff);
+    jpeg.extend_from_slice(&[0xFF, 0xD9]);
+    assert_eq!(extract_gps_from_jpeg_bytes(&jpeg), Some(fix));
+}
+
+/// Serializes the given fields as a TIFF buffer for an APP1 segment
+fn tiff_with_fields(fields: &[Field]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buffer, false).unwrap();
+    buffer.into_inner()
+}
+
+#[test]
+fn gps_in_second_app1_segment_is_found() {
+    let rationals = |parts: [u32; 3]| {
+        Value::Rational(
+            parts
+                .iter()
+                .map(|&num| Rational { num, denom: 1 })
+                .collect(),
+        )
+    };
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let first = tiff_with_fields(&[field(
+        Tag::DateTimeOriginal,
+        Value::Ascii(vec![b"2023:07:14 10:20:30".to_vec()]),
+    )]);
+    let second = tiff_with_fields(&[
+        field(Tag::GPSLatitude, rationals([48, 51, 24])),
+        field(Tag::GPSLatitudeRef, Value::Ascii(vec![b"N".to_vec()])),
+        field(Tag::GPSLongitude, rationals([2, 21, 0])),
+        field(Tag::GPSLongitudeRef, Value::Ascii(vec![b"E".to_vec()])),
+    ]);
+
+    let mut jpeg = vec![0xFF, 0xD8];
+    for tiff in [&first, &second] {
+        jpeg.extend_from_slice(&[0xFF, 0xE1]);
+        jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(tiff);
+    }
+    jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF, 0xD9]);
+
+    let path = std::env::temp_dir().join("photomap_test_second_app1.jpg");
+    std::fs::write(&path, &jpeg).unwrap();
+    let metadata = extract_metadata_from_jpeg(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let metadata = metadata.unwrap();
+    assert!((metadata.lat - 48.856_666).abs() < 1e-5, "{}", metadata.lat);
+    assert!((metadata.lng - 2.35).abs() < 1e-9, "{}", metadata.lng);
+    assert!(metadata.datetime.is_some());
+}
+
+/// Photoshop APP13 payload with an IPTC resource followed by an EXIF copy
+fn photoshop_app13(exif: &[u8]) -> Vec<u8> {
+    let mut payload = b"Photoshop 3.0\0".to_vec();
+    let mut resource = |id: u16, name: &[u8], data: &[u8]| {
+        payload.extend_from_slice(b"8BIM");
+        payload.extend_from_slice(&id.to_be_bytes());
+        payload.push(name.len() as u8);
+        payload.extend_from_slice(name);
+        if name.len().is_multiple_of(2) {
+            payload.push(0);
+        }
+        payload.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        payload.extend_from_slice(data);
+        if !data.len().is_multiple_of(2) {
+            payload.push(0);
+        }
+    };
+    // IPTC-IIM City (2:90), odd length to exercise padding
+    resource(0x0404, b"iptc", b"\x1C\x02\x5A\x00\x05Paris");
+    resource(0x0422, b"", exif);
+    payload
+}
+
+#[test]
+fn gps_is_read_from_photoshop_exif_copy() {
+    let tiff = tiff_with_fields(&[
+        Field {
+            tag: Tag::GPSLatitude,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![
+                Rational { num: 55, denom: 1 },
+                Rational { num: 45, denom: 1 },
+                Rational { num: 0, denom: 1 },
+            ]),
+        },
+        Field {
+            tag: Tag::GPSLatitudeRef,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"N".to_vec()]),
+        },
+        Field {
+            tag: Tag::GPSLongitude,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![
+                Rational { num: 37, denom: 1 },
+                Rational { num: 36, denom: 1 },
+                Rational { num: 0, denom: 1 },
+            ]),
+        },
+        Field {
+            tag: Tag::GPSLongitudeRef,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"E".to_vec()]),
+        },
+    ]);
+    let app13 = photoshop_app13(&tiff);
+
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xED];
+    jpeg.extend_from_slice(&((app13.len() + 2) as u16).to_be_bytes());
+    jpeg.extend_from_slice(&app13);
+    jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF, 0xD9]);
+
+    let metadata = extract_metadata_from_jpeg_bytes(&jpeg).unwrap();
+    assert!((metadata.lat - 55.75).abs() < 1e-9, "{}", metadata.lat);
+    assert!((metadata.lng - 37.6).abs() < 1e-9, "{}", metadata.lng);
+}
+
+/// Rational parts of a GPS coordinate: degrees, then optionally minutes and seconds
+type CoordParts<'a> = &'a [(u32, u32)];
+
+/// TIFF holding a position whose coordinates have the given rational parts
+fn tiff_with_position(lat: CoordParts, lng: CoordParts) -> Vec<u8> {
+    let rationals = |parts: CoordParts| {
+        Value::Rational(
+            parts
+                .iter()
+                .map(|&(num, denom)| Rational { num, denom })
+                .collect(),
+        )
+    };
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    tiff_with_fields(&[
+        field(Tag::GPSLatitude, rationals(lat)),
+        field(Tag::GPSLatitudeRef, Value::Ascii(vec![b"N".to_vec()])),
+        field(Tag::GPSLongitude, rationals(lng)),
+        field(Tag::GPSLongitudeRef, Value::Ascii(vec![b"W".to_vec()])),
+    ])
+}
+
+#[test]
+fn partial_dms_encodings_match_exiftool() {
+    // (latitude parts, longitude parts, exiftool's values to 6 decimals)
+    let cases: [(CoordParts, CoordParts, &str, &str); 4] = [
+        // Minutes only, as written by Google exports
+        (
+            &[(37, 1), (4650, 100), (0, 1)],
+            &[(122, 1), (2514, 100), (0, 1)],
+            "37.775000",
+            "-122.419000",
+        ),
+        // Degrees and decimal minutes without seconds
+        (
+            &[(37, 1), (4650, 100)],
+            &[(122, 1), (2514, 100)],
+            "37.775000",
+            "-122.419000",
+        ),
+        // Decimal degrees only
+        (
+            &[(37_774_929, 1_000_000)],
+            &[(122_419_416, 1_000_000)],
+            "37.774929",
+            "-122.419416",
+        ),
+        // Fractional seconds
+        (
+            &[(55, 1), (45, 1), (2134, 100)],
+            &[(37, 1), (37, 1), (1799, 100)],
+            "55.755928",
+            "-37.621664",
+        ),
+    ];
+
+    for (lat_parts, lng_parts, lat, lng) in cases {
+        let tiff = tiff_with_position(lat_parts, lng_parts);
+        let exif = Reader::new().read_raw(tiff.clone()).unwrap();
+        let read = (
+            get_gps_coord(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef)
+                .unwrap()
+                .unwrap(),
+            get_gps_coord(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef)
+                .unwrap()
+                .unwrap(),
+        );
+        assert_eq!(
+            (format!("{:.6}", read.0), format!("{:.6}", read.1)),
+            (lat.to_string(), lng.to_string())
+        );
+
+        // The raw GPS IFD walk gives the same values
+        let fix = extract_gps_from_tiff(&tiff).unwrap();
+        assert_eq!(
+            (format!("{:.6}", fix.lat), format!("{:.6}", fix.lng)),
+            (lat.to_string(), lng.to_string())
+        );
+    }
+}
+
+fn ascii(tag: Tag, value: &str) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+    }
+}
+
+#[test]
+fn offset_and_subsec_give_utc_milliseconds() {
+    let tiff = tiff_with_fields(&[
+        ascii(Tag::DateTimeOriginal, "2024:06:01 15:30:00"),
+        ascii(Tag::OffsetTimeOriginal, "+05:30"),
+        ascii(Tag::SubSecTimeOriginal, "123"),
+    ]);
+    let exif = Reader::new().read_raw(tiff).unwrap();
+    let datetime = get_datetime_string(&exif).unwrap();
+    assert_eq!(datetime, "2024-06-01 10:00:00.123");
+    assert_eq!(
+        parse_timestamp(&datetime),
+        parse_timestamp("2024-06-01 10:00:00")
+    );
+}
+
+#[test]
+fn datetime_without_offset_stays_as_written() {
+    let naive = tiff_with_fields(&[ascii(Tag::DateTimeOriginal, "2024:06:01 15:30:00")]);
+    let exif = Reader::new().read_raw(naive).unwrap();
+    assert_eq!(
+        get_datetime_string(&exif).as_deref(),
+        Some("2024-06-01 15:30:00")
+    );
+
+    // The fallback tag uses its own offset and sub-second tags
+    let fallback = tiff_with_fields(&[
+        ascii(Tag::DateTime, "2023:12:31 20:00:00"),
+        ascii(Tag::OffsetTime, "-08:00"),
+        ascii(Tag::SubSecTime, "4"),
+        ascii(Tag::OffsetTimeOriginal, "garbage"),
+    ]);
+    let exif = Reader::new().read_raw(fallback).unwrap();
+    assert_eq!(
+        get_datetime_string(&exif).as_deref(),
+        Some("2024-01-01 04:00:00.400")
+    );
+}
+
+/// TIFF with a position and GPSImgDirection, plus its reference when given
+fn tiff_with_direction(direction: (u32, u32), reference: Option<&str>) -> Vec<u8> {
+    let coord = |num| Value::Rational(vec![Rational { num, denom: 1 }]);
+    let mut fields = vec![
+        field_of(Tag::GPSLatitude, coord(48)),
+        ascii(Tag::GPSLatitudeRef, "N"),
+        field_of(Tag::GPSLongitude, coord(2)),
+        ascii(Tag::GPSLongitudeRef, "E"),
+        field_of(
+            Tag::GPSImgDirection,
+            Value::Rational(vec![Rational {
+                num: direction.0,
+                denom: direction.1,
+            }]),
+        ),
+    ];
+    if let Some(reference) = reference {
+        fields.push(ascii(Tag::GPSImgDirectionRef, reference));
+    }
+    tiff_with_fields(&fields)
+}
+
+fn field_of(tag: Tag, value: Value) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    }
+}
+
+#[test]
+fn gps_image_direction_is_read_with_the_position() {
+    let tiff = tiff_with_direction((2755, 10), Some("M"));
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+    jpeg.extend_from_slice(b"Exif\0\0");
+    jpeg.extend_from_slice(&tiff);
+    jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF, 0xD9]);
+
+    let metadata = extract_metadata_from_jpeg_bytes(&jpeg).unwrap();
+    assert_eq!((metadata.lat, metadata.lng), (48.0, 2.0));
+    assert_eq!(metadata.direction, Some(275.5));
+
+    let direction = |direction, reference| {
+        get_gps_direction(
+            &Reader::new()
+                .read_raw(tiff_with_direction(direction, reference))
+                .unwrap(),
+        )
+    };
+    assert_eq!(direction((90, 1), None), Some(90.0));
+    // 360° is north again, and out-of-range values wrap around
+    assert_eq!(direction((360, 1), Some("T")), Some(0.0));
+    assert_eq!(direction((450, 1), Some("T")), Some(90.0));
+    // A zero denominator or an unknown reference gives no heading
+    assert_eq!(direction((90, 0), Some("T")), None);
+    assert_eq!(direction((90, 1), Some("X")), None);
+}
+
+/// CRC-32 of a PNG chunk, over its type and data
+fn png_crc(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// A small PNG with the TIFF as its eXIf chunk, right after IHDR
+fn png_with_exif(tiff: &[u8]) -> Vec<u8> {
+    let mut png = Vec::new();
+    image::RgbImage::from_pixel(8, 8, image::Rgb([30, 60, 90]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    // Signature (8 bytes), then IHDR: length, type, 13 bytes of data, CRC
+    let ihdr_end = 8 + 4 + 4 + 13 + 4;
+    let mut chunk = (tiff.len() as u32).to_be_bytes().to_vec();
+    let mut body = b"eXIf".to_vec();
+    body.extend_from_slice(tiff);
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&png_crc(&body).to_be_bytes());
+    png.splice(ihdr_end..ihdr_end, chunk);
+    png
+}
+
+#[test]
+fn gps_is_read_from_png_and_tiff_files() {
+    let tiff = tiff_with_direction((90, 1), None);
+    let dir = std::env::temp_dir();
+    let png = dir.join("photomap_test_gps.png");
+    std::fs::write(&png, png_with_exif(&tiff)).unwrap();
+    let tif = dir.join("photomap_test_gps.tif");
+    std::fs::write(&tif, &tiff).unwrap();
+
+    for path in [&png, &tif] {
+        let metadata = DefaultExifExtractor.extract(path).unwrap();
+        assert_eq!((metadata.lat, metadata.lng), (48.0, 2.0), "{path:?}");
+        assert_eq!(metadata.direction, Some(90.0));
+    }
+    // The PNG still decodes, so it gets thumbnails like any photo
+    assert!(image::open(&png).is_ok());
+
+    // A PNG without EXIF has no GPS rather than being unreadable
+    image::RgbImage::from_pixel(8, 8, image::Rgb([0, 0, 0]))
+        .save(&png)
+        .unwrap();
+    let err = DefaultExifExtractor.extract(&png).unwrap_err();
+    assert!(err
+        .downcast_ref::<ExifError>()
+        .is_some_and(ExifError::is_missing_gps));
+    let _ = std::fs::remove_file(&png);
+    let _ = std::fs::remove_file(&tif);
+}
@@ -0,0 +1,83 @@
+//! Plausibility grading of GPS positions. Kept out of `generic.rs`, which the
+//! fuzz crate compiles on its own without serde, utoipa or the constants.
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{GPS_DOP_POOR, GPS_DOP_ROUGH};
+
+/// How far a photo's GPS position can be trusted, from best to worst
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum GpsQuality {
+    #[default]
+    High,
+    /// Rough fix: GPSDOP above `GPS_DOP_ROUGH`
+    Medium,
+    /// Poor fix or whole degrees only; the photo may be kilometres off
+    Low,
+    /// Null island or out of range: most likely a firmware bug, not a place
+    Suspicious,
+}
+
+/// Quality of a GPS position and what lowered it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GpsValidationResult {
+    pub quality: GpsQuality,
+    /// Reasons for a quality below `High`
+    pub issues: Vec<String>,
+}
+
+impl GpsValidationResult {
+    fn flag(&mut self, quality: GpsQuality, issue: String) {
+        self.quality = self.quality.max(quality);
+        self.issues.push(issue);
+    }
+
+    /// Lowers the quality for the dilution of precision recorded with the fix
+    pub fn with_dop(mut self, dop: Option<f64>) -> Self {
+        match dop {
+            Some(dop) if dop > GPS_DOP_POOR => {
+                self.flag(GpsQuality::Low, format!("poor GPS fix (DOP {:.1})", dop))
+            }
+            Some(dop) if dop > GPS_DOP_ROUGH => self.flag(
+                GpsQuality::Medium,
+                format!("rough GPS fix (DOP {:.1})", dop),
+            ),
+            _ => {}
+        }
+        self
+    }
+}
+
+/// Checks decimal degrees for values that come from broken receivers rather
+/// than from a place: exactly (0, 0), which cameras without a fix write, values
+/// out of range, and whole degrees with zero minutes and seconds.
+pub fn validate_gps_coordinate(lat: f64, lng: f64) -> GpsValidationResult {
+    let mut result = GpsValidationResult::default();
+    if !lat.is_finite() || !lng.is_finite() || lat.abs() > 90.0 || lng.abs() > 180.0 {
+        result.flag(
+            GpsQuality::Suspicious,
+            format!("coordinates out of range ({}, {})", lat, lng),
+        );
+    } else if lat == 0.0 && lng == 0.0 {
+        result.flag(GpsQuality::Suspicious, "null island (0, 0)".to_string());
+    } else if lat.fract() == 0.0 && lng.fract() == 0.0 {
+        result.flag(
+            GpsQuality::Low,
+            format!("whole degrees only ({}, {})", lat, lng),
+        );
+    }
+    result
+}
@@ -0,0 +1,510 @@
+//! Writes GPS coordinates into the EXIF APP1 segment of a JPEG
+
+use super::generic::get_gps_coord;
+use crate::types::GpsCoordinate;
+use anyhow::{bail, Context, Result};
+use exif::experimental::Writer;
+use exif::{Field, In, Rational, Tag, Value};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+/// Largest APP1 payload: the 16-bit segment length includes its own two bytes
+const MAX_SEGMENT_PAYLOAD: usize = u16::MAX as usize - 2;
+/// Seconds are stored with this denominator (1/10000" is about 3 mm)
+const SECONDS_DENOMINATOR: u32 = 10_000;
+
+/// Sets the GPS position of a JPEG file in place.
+/// The new file is written next to the original and renamed over it, so a
+/// failure at any step leaves the original untouched.
+pub fn write_gps_to_jpeg(path: &Path, coords: GpsCoordinate) -> Result<()> {
+    let original = fs::read(path).with_context(|| format!("Reading {}", path.display()))?;
+    let updated = set_gps_in_jpeg(&original, coords)?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid file name: {}", path.display()))?;
+    let temp_path = path.with_file_name(format!(".{}.photomap-tmp", file_name.to_string_lossy()));
+
+    let result = (|| -> Result<()> {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(&updated)?;
+        file.sync_all()?;
+        fs::set_permissions(&temp_path, fs::metadata(path)?.permissions())?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result.with_context(|| format!("Writing GPS to {}", path.display()))
+}
+
+/// Returns a copy of `jpeg` whose EXIF GPS IFD holds `coords`.
+/// An existing EXIF block is only appended to, so other fields, the thumbnail and
+/// maker notes with offsets into the block stay where they were; image data is
+/// copied unchanged.
+pub fn set_gps_in_jpeg(jpeg: &[u8], coords: GpsCoordinate) -> Result<Vec<u8>> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        bail!("Not a JPEG file");
+    }
+    let segments = metadata_segments(jpeg)?;
+    let existing = segments
+        .iter()
+        .find(|s| s.marker == 0xE1 && jpeg[s.payload.clone()].starts_with(EXIF_HEADER));
+
+    let tiff = match existing {
+        Some(segment) => {
+            append_gps_ifd(&jpeg[segment.payload.clone()][EXIF_HEADER.len()..], coords)?
+        }
+        None => encode_gps_exif(coords)?,
+    };
+    if EXIF_HEADER.len() + tiff.len() > MAX_SEGMENT_PAYLOAD {
+        bail!("EXIF data does not fit into one APP1 segment");
+    }
+    let mut app1 = Vec::with_capacity(4 + EXIF_HEADER.len() + tiff.len());
+    app1.extend_from_slice(&[0xFF, 0xE1]);
+    app1.extend_from_slice(&((2 + EXIF_HEADER.len() + tiff.len()) as u16).to_be_bytes());
+    app1.extend_from_slice(EXIF_HEADER);
+    app1.extend_from_slice(&tiff);
+
+    // Replace the EXIF segment, or insert one after SOI and a leading JFIF APP0
+    let (start, end) = match existing {
+        Some(segment) => (segment.start, segment.payload.end),
+        None => {
+            let at = segments
+                .first()
+                .filter(|s| s.marker == 0xE0)
+                .map_or(2, |s| s.payload.end);
+            (at, at)
+        }
+    };
+    let mut output = Vec::with_capacity(jpeg.len() + app1.len());
+    output.extend_from_slice(&jpeg[..start]);
+    output.extend_from_slice(&app1);
+    output.extend_from_slice(&jpeg[end..]);
+
+    verify_gps(&output, coords)?;
+    Ok(output)
+}
+
+struct Segment {
+    marker: u8,
+    /// Offset of the 0xFF marker byte
+    start: usize,
+    payload: std::ops::Range<usize>,
+}
+
+/// Lists the marker segments between SOI and the start of the scan data
+fn metadata_segments(jpeg: &[u8]) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        if pos + 4 > jpeg.len() || jpeg[pos] != 0xFF {
+            bail!("Truncated or malformed JPEG marker at offset {}", pos);
+        }
+        let marker = jpeg[pos + 1];
+        if marker == 0xFF {
+            // Fill byte before a marker
+            pos += 1;
+            continue;
+        }
+        if marker == 0xDA || marker == 0xD9 {
+            return Ok(segments);
+        }
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        if length < 2 || pos + 2 + length > jpeg.len() {
+            bail!("Invalid JPEG segment length at offset {}", pos);
+        }
+        segments.push(Segment {
+            marker,
+            start: pos,
+            payload: pos + 4..pos + 2 + length,
+        });
+        pos += 2 + length;
+    }
+}
+
+/// Encodes a new EXIF block holding only the GPS IFD
+fn encode_gps_exif(coords: GpsCoordinate) -> Result<Vec<u8>> {
+    let mut writer = Writer::new();
+    let gps_fields = gps_fields(coords);
+    for field in &gps_fields {
+        writer.push_field(field);
+    }
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    writer
+        .write(&mut buffer, true)
+        .context("Failed to encode EXIF data")?;
+    Ok(buffer.into_inner())
+}
+
+/// Appends a new GPS IFD and a copy of IFD0 pointing to it to an existing TIFF block.
+/// Nothing already in the block moves, because maker notes often hold offsets that
+/// no EXIF library knows how to update when the block is re-encoded.
+fn append_gps_ifd(tiff: &[u8], coords: GpsCoordinate) -> Result<Vec<u8>> {
+    let little_endian = match tiff.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => bail!("Existing EXIF data is malformed"),
+    };
+    let u16_at = |pos: usize| {
+        tiff.get(pos..pos + 2)
+            .map(|b| {
+                if little_endian {
+                    u16::from_le_bytes([b[0], b[1]])
+                } else {
+                    u16::from_be_bytes([b[0], b[1]])
+                }
+            })
+            .context("Existing EXIF data is truncated")
+    };
+    let u32_at = |pos: usize| {
+        tiff.get(pos..pos + 4)
+            .map(|b| {
+                if little_endian {
+                    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+                } else {
+                    u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+                }
+            })
+            .context("Existing EXIF data is truncated")
+    };
+    if u16_at(2)? != 42 {
+        bail!("Existing EXIF data is malformed");
+    }
+
+    // IFD0 entries except the old GPS pointer, whose IFD is left unreferenced
+    let ifd0 = u32_at(4)? as usize;
+    let entries_start = ifd0 + 2;
+    let entries_end = entries_start + 12 * u16_at(ifd0)? as usize;
+    let next_ifd = u32_at(entries_end)?;
+    let mut entries: Vec<(u16, &[u8])> = Vec::new();
+    for pos in (entries_start..entries_end).step_by(12) {
+        let tag = u16_at(pos)?;
+        if tag != GPS_INFO_TAG {
+            entries.push((tag, &tiff[pos..pos + 12]));
+        }
+    }
+
+    let mut out = TiffBuffer {
+        data: tiff.to_vec(),
+        little_endian,
+    };
+    // IFDs start on a word boundary
+    out.data.resize(out.data.len().next_multiple_of(2), 0);
+
+    // GPS IFD: version, latitude and longitude, with the rationals right after it
+    let gps_ifd = out.data.len();
+    let rationals = gps_ifd + 2 + 5 * 12 + 4;
+    let reference = |positive: bool, pos: u8, neg: u8| [if positive { pos } else { neg }, 0, 0, 0];
+    out.u16(5);
+    out.entry(0, TYPE_BYTE, 4, [2, 3, 0, 0]);
+    out.entry(1, TYPE_ASCII, 2, reference(coords.lat() >= 0.0, b'N', b'S'));
+    out.entry_at(2, TYPE_RATIONAL, 3, rationals);
+    out.entry(3, TYPE_ASCII, 2, reference(coords.lng() >= 0.0, b'E', b'W'));
+    out.entry_at(4, TYPE_RATIONAL, 3, rationals + 24);
+    out.u32(0);
+    for (num, denom) in dms_rationals(coords.lat())
+        .into_iter()
+        .chain(dms_rationals(coords.lng()))
+    {
+        out.u32(num);
+        out.u32(denom);
+    }
+
+    // New IFD0 with the same entries and a pointer to the new GPS IFD
+    let new_ifd0 = out.data.len();
+    let gps_pointer = out.encode_entry_at(GPS_INFO_TAG, TYPE_LONG, 1, gps_ifd);
+    entries.push((GPS_INFO_TAG, &gps_pointer[..]));
+    entries.sort_by_key(|(tag, _)| *tag);
+    out.u16(entries.len() as u16);
+    for (_, entry) in &entries {
+        out.data.extend_from_slice(entry);
+    }
+    out.u32(next_ifd);
+
+    let header = out.encode_u32(new_ifd0 as u32);
+    out.data[4..8].copy_from_slice(&header);
+    Ok(out.data)
+}
+
+const GPS_INFO_TAG: u16 = 0x8825;
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+/// TIFF data being extended in the byte order of the existing block
+struct TiffBuffer {
+    data: Vec<u8>,
+    little_endian: bool,
+}
+
+impl TiffBuffer {
+    fn encode_u16(&self, value: u16) -> [u8; 2] {
+        if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    fn encode_u32(&self, value: u32) -> [u8; 4] {
+        if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    fn u16(&mut self, value: u16) {
+        let bytes = self.encode_u16(value);
+        self.data.extend_from_slice(&bytes);
+    }
+
+    fn u32(&mut self, value: u32) {
+        let bytes = self.encode_u32(value);
+        self.data.extend_from_slice(&bytes);
+    }
+
+    /// IFD entry whose value fits into the 4-byte value field
+    fn entry(&mut self, tag: u16, kind: u16, count: u32, value: [u8; 4]) {
+        self.u16(tag);
+        self.u16(kind);
+        self.u32(count);
+        self.data.extend_from_slice(&value);
+    }
+
+    /// IFD entry whose value field holds an offset (or a single LONG)
+    fn entry_at(&mut self, tag: u16, kind: u16, count: u32, offset: usize) {
+        let entry = self.encode_entry_at(tag, kind, count, offset);
+        self.data.extend_from_slice(&entry);
+    }
+
+    fn encode_entry_at(&self, tag: u16, kind: u16, count: u32, offset: usize) -> [u8; 12] {
+        let mut entry = [0; 12];
+        entry[0..2].copy_from_slice(&self.encode_u16(tag));
+        entry[2..4].copy_from_slice(&self.encode_u16(kind));
+        entry[4..8].copy_from_slice(&self.encode_u32(count));
+        entry[8..12].copy_from_slice(&self.encode_u32(offset as u32));
+        entry
+    }
+}
+
+fn gps_fields(coords: GpsCoordinate) -> Vec<Field> {
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let reference = |positive: bool, pos: &[u8], neg: &[u8]| {
+        Value::Ascii(vec![if positive { pos } else { neg }.to_vec()])
+    };
+    vec![
+        field(Tag::GPSVersionID, Value::Byte(vec![2, 3, 0, 0])),
+        field(
+            Tag::GPSLatitudeRef,
+            reference(coords.lat() >= 0.0, b"N", b"S"),
+        ),
+        field(Tag::GPSLatitude, degrees_to_rationals(coords.lat())),
+        field(
+            Tag::GPSLongitudeRef,
+            reference(coords.lng() >= 0.0, b"E", b"W"),
+        ),
+        field(Tag::GPSLongitude, degrees_to_rationals(coords.lng())),
+    ]
+}
+
+/// Splits decimal degrees into degrees, minutes and seconds rationals
+fn degrees_to_rationals(degrees: f64) -> Value {
+    Value::Rational(dms_rationals(degrees).map(Rational::from).to_vec())
+}
+
+/// Degrees, minutes and seconds as numerator and denominator pairs
+fn dms_rationals(degrees: f64) -> [(u32, u32); 3] {
+    let units_per_minute = 60 * SECONDS_DENOMINATOR as u64;
+    let units_per_degree = 60 * units_per_minute;
+    let total = (degrees.abs() * units_per_degree as f64).round() as u64;
+    [
+        ((total / units_per_degree) as u32, 1),
+        (((total % units_per_degree) / units_per_minute) as u32, 1),
+        ((total % units_per_minute) as u32, SECONDS_DENOMINATOR),
+    ]
+}
+
+/// Reads the written file back and checks it yields the requested position
+fn verify_gps(jpeg: &[u8], coords: GpsCoordinate) -> Result<()> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(jpeg))
+        .context("Written EXIF data cannot be read back")?;
+    let lat = get_gps_coord(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef)?;
+    let lng = get_gps_coord(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef)?;
+    match (lat, lng) {
+        (Some(lat), Some(lng))
+            if (lat - coords.lat()).abs() < 1e-6 && (lng - coords.lng()).abs() < 1e-6 =>
+        {
+            Ok(())
+        }
+        _ => bail!("Written GPS data does not match the requested position"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set_gps_in_jpeg, write_gps_to_jpeg};
+    use crate::exif_parser::{extract_metadata_from_jpeg, ExtractedMetadata};
+    use crate::types::GpsCoordinate;
+    use exif::experimental::Writer;
+    use exif::{Field, In, Tag, Value};
+
+    fn plain_jpeg() -> Vec<u8> {
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(16, 16, image::Rgb([200, 10, 10]))
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        jpeg.into_inner()
+    }
+
+    /// JPEG with an EXIF block holding a camera model and a wrong GPS position
+    fn jpeg_with_exif() -> Vec<u8> {
+        jpeg_with_exif_fields(&[])
+    }
+
+    fn jpeg_with_exif_fields(extra: &[Field]) -> Vec<u8> {
+        let fields = [
+            Field {
+                tag: Tag::Model,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"Test Camera".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLatitudeRef,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"N".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![(1, 1).into(), (0, 1).into(), (0, 1).into()]),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in fields.iter().chain(extra) {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let jpeg = plain_jpeg();
+        let mut output = jpeg[..2].to_vec();
+        output.extend_from_slice(&[0xFF, 0xE1]);
+        output.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        output.extend_from_slice(b"Exif\0\0");
+        output.extend_from_slice(&tiff);
+        output.extend_from_slice(&jpeg[2..]);
+        output
+    }
+
+    /// TIFF block of the EXIF segment that directly follows SOI
+    fn exif_block(jpeg: &[u8]) -> &[u8] {
+        let length = u16::from_be_bytes([jpeg[4], jpeg[5]]) as usize;
+        &jpeg[10..4 + length]
+    }
+
+    fn read_back(jpeg: &[u8]) -> (f64, f64) {
+        let path = std::env::temp_dir().join(format!(
+            "photomap_test_gps_writer_{}.jpg",
+            std::process::id()
+        ));
+        std::fs::write(&path, jpeg).unwrap();
+        let ExtractedMetadata { lat, lng, .. } = extract_metadata_from_jpeg(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        (lat, lng)
+    }
+
+    #[test]
+    fn adds_gps_to_jpeg_without_exif() {
+        let jpeg = plain_jpeg();
+        let coords = GpsCoordinate::new(-33.8688, 151.2093).unwrap();
+        let updated = set_gps_in_jpeg(&jpeg, coords).unwrap();
+
+        let (lat, lng) = read_back(&updated);
+        assert!((lat + 33.8688).abs() < 1e-6 && (lng - 151.2093).abs() < 1e-6);
+        // The image itself still decodes to the same pixels
+        assert_eq!(
+            image::load_from_memory(&updated).unwrap().to_rgb8(),
+            image::load_from_memory(&jpeg).unwrap().to_rgb8()
+        );
+    }
+
+    #[test]
+    fn replaces_gps_and_keeps_other_fields() {
+        let coords = GpsCoordinate::new(48.8566, -2.3522).unwrap();
+        let updated = set_gps_in_jpeg(&jpeg_with_exif(), coords).unwrap();
+
+        let (lat, lng) = read_back(&updated);
+        assert!((lat - 48.8566).abs() < 1e-6 && (lng + 2.3522).abs() < 1e-6);
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(&updated))
+            .unwrap();
+        assert_eq!(
+            exif.get_field(Tag::Model, In::PRIMARY)
+                .unwrap()
+                .display_value()
+                .to_string(),
+            "\"Test Camera\""
+        );
+    }
+
+    #[test]
+    fn keeps_maker_note_bytes_in_place() {
+        // Maker notes like Nikon's carry offsets into the EXIF block, so they
+        // only survive when nothing before them moves
+        let maker_note = b"Nikon\0\x02\x10\0\0MM\0\x2a\0\0\0\x08\0\x01\0\x02".to_vec();
+        let original = jpeg_with_exif_fields(&[Field {
+            tag: Tag::MakerNote,
+            ifd_num: In::PRIMARY,
+            value: Value::Undefined(maker_note.clone(), 0),
+        }]);
+        let coords = GpsCoordinate::new(35.6762, 139.6503).unwrap();
+        let updated = set_gps_in_jpeg(&original, coords).unwrap();
+
+        let (lat, lng) = read_back(&updated);
+        assert!((lat - 35.6762).abs() < 1e-6 && (lng - 139.6503).abs() < 1e-6);
+
+        // Only the IFD0 offset in the header changes; the rest is appended
+        let (before, after) = (exif_block(&original), exif_block(&updated));
+        assert!(after.len() > before.len());
+        assert_eq!(after[..4], before[..4]);
+        assert_eq!(after[8..before.len()], before[8..]);
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(&updated))
+            .unwrap();
+        match &exif.get_field(Tag::MakerNote, In::PRIMARY).unwrap().value {
+            Value::Undefined(bytes, _) => assert_eq!(bytes, &maker_note),
+            other => panic!("Unexpected MakerNote value {:?}", other),
+        }
+        assert!(exif.get_field(Tag::Model, In::PRIMARY).is_some());
+    }
+
+    #[test]
+    fn leaves_file_untouched_when_rewrite_fails() {
+        let path = std::env::temp_dir().join("photomap_test_gps_writer_invalid.jpg");
+        std::fs::write(&path, b"not a jpeg").unwrap();
+
+        let coords = GpsCoordinate::new(1.0, 2.0).unwrap();
+        assert!(write_gps_to_jpeg(&path, coords).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a jpeg");
+
+        std::fs::write(&path, plain_jpeg()).unwrap();
+        write_gps_to_jpeg(&path, coords).unwrap();
+        let ExtractedMetadata { lat, lng, .. } = extract_metadata_from_jpeg(&path).unwrap();
+        assert!((lat - 1.0).abs() < 1e-6 && (lng - 2.0).abs() < 1e-6);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
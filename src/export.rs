@@ -0,0 +1,127 @@
+//! GeoJSON export of the library, shared by `/api/export/geojson` and the
+//! `--export` command line option
+
+use anyhow::{Context, Result};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::database::{Database, PhotoFilter, PhotoMetadata, SortOrder};
+
+/// Features serialized per chunk of the GeoJSON export
+pub const GEOJSON_CHUNK_FEATURES: usize = 1000;
+
+/// A photo as a GeoJSON point feature; coordinates are longitude first
+fn geojson_feature(photo: &PhotoMetadata) -> serde_json::Value {
+    serde_json::json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [photo.coords.lng(), photo.coords.lat()]
+        },
+        "properties": {
+            "id": photo.id,
+            "filename": photo.filename,
+            "relative_path": photo.relative_path,
+            "datetime": photo.datetime,
+            "location": photo.location,
+            "direction": photo.direction,
+            "gps_quality": photo.gps_quality,
+            "is_heic": photo.is_heic
+        }
+    })
+}
+
+/// Hands the FeatureCollection of all photos, oldest first, to `sink` one
+/// chunk of `GEOJSON_CHUNK_FEATURES` photos at a time. Stops early when `sink`
+/// returns false. Returns the number of features written.
+pub fn write_geojson_chunks(db: &Database, mut sink: impl FnMut(Vec<u8>) -> bool) -> Result<usize> {
+    if !sink(br#"{"type":"FeatureCollection","features":["#.to_vec()) {
+        return Ok(0);
+    }
+    let mut written = 0;
+    let mut failed = None;
+    let mut stopped = false;
+    db.for_each_photo_chunk(
+        &PhotoFilter::default(),
+        SortOrder::DateAsc,
+        GEOJSON_CHUNK_FEATURES,
+        |photos| {
+            let mut chunk = Vec::new();
+            for photo in &photos {
+                if written > 0 {
+                    chunk.push(b',');
+                }
+                if let Err(e) = serde_json::to_writer(&mut chunk, &geojson_feature(photo)) {
+                    failed = Some(e);
+                    return false;
+                }
+                written += 1;
+            }
+            stopped = !sink(chunk);
+            !stopped
+        },
+    )?;
+    if let Some(e) = failed {
+        return Err(e.into());
+    }
+    if !stopped {
+        sink(b"]}".to_vec());
+    }
+    Ok(written)
+}
+
+/// Writes all photos to a GeoJSON file; returns how many features it holds
+pub fn export_geojson_file(db: &Database, path: &Path) -> Result<usize> {
+    let file =
+        std::fs::File::create(path).with_context(|| format!("Creating {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    let mut write_error = None;
+    let written = write_geojson_chunks(db, |chunk| match out.write_all(&chunk) {
+        Ok(()) => true,
+        Err(e) => {
+            write_error = Some(e);
+            false
+        }
+    })?;
+    if let Some(e) = write_error {
+        return Err(e).with_context(|| format!("Writing {}", path.display()));
+    }
+    out.flush()
+        .with_context(|| format!("Writing {}", path.display()))?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::export_geojson_file;
+    use crate::database::{Database, PhotoMetadata};
+
+    #[test]
+    fn geojson_file_holds_every_photo() {
+        let db = Database::new().unwrap();
+        db.insert_photos_batch(&[
+            PhotoMetadata::sample("2024/rome.jpg", "2024-08-01 12:00:00", 41.9028, 12.4964),
+            PhotoMetadata::sample("2024/paris.jpg", "2024-06-01 12:00:00", 48.8566, 2.3522),
+        ])
+        .unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "photomap_test_export_{}.geojson",
+            std::process::id()
+        ));
+
+        assert_eq!(export_geojson_file(&db, &path).unwrap(), 2);
+        let geojson: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+        // Oldest first
+        assert_eq!(features[0]["properties"]["relative_path"], "2024/paris.jpg");
+        assert_eq!(
+            features[1]["geometry"]["coordinates"],
+            serde_json::json!([12.4964, 41.9028])
+        );
+    }
+}
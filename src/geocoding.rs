@@ -1,21 +1,155 @@
 use anyhow::{Context, Result};
 use flate2::read::GzDecoder;
 use serde::{Deserialize, Serialize};
-use std::sync::OnceLock;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use crate::constants::{DEFAULT_LOCATION_FORMAT, DEFAULT_MAX_GEOCODE_DISTANCE_KM};
 
 // Embed the compressed geodata binary
 const GEODATA_BYTES: &[u8] = include_bytes!("geodata.bin.gz");
 
+const GEODATA_SIZE_LIMIT: u64 = 20 * 1024 * 1024;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Cities compared by great-circle distance after the fast scan
+const NEAREST_CANDIDATES: usize = 3;
+
+/// Index of the nearest city and its distance in km
+type Nearest = (usize, f64);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeoLocation {
     pub name: String,
     pub lat: f64,
     pub lng: f64,
     pub country: String,
+    /// State or region; empty in geodata built before it was added
+    pub admin1: String,
+}
+
+/// Layout of `GeoLocation` in geodata built without admin1
+#[derive(Deserialize)]
+struct LegacyGeoLocation {
+    name: String,
+    lat: f64,
+    lng: f64,
+    country: String,
+}
+
+/// Names of the place nearest to a photo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Place {
+    pub city: String,
+    pub admin1: Option<String>,
+    pub country: String,
+}
+
+impl Place {
+    /// Fills `{name}`, `{admin1}` and `{country}` in a template like "{name}, {admin1}, {country}"
+    pub fn format(&self, template: &str) -> String {
+        let formatted = template
+            .replace("{name}", &self.city)
+            .replace("{admin1}", self.admin1.as_deref().unwrap_or(""))
+            .replace("{country}", &self.country);
+        // Drop the separators left around an unknown region
+        formatted
+            .split(", ")
+            .filter(|part| !part.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl From<&GeoLocation> for Place {
+    fn from(location: &GeoLocation) -> Self {
+        Place {
+            city: location.name.clone(),
+            admin1: (!location.admin1.is_empty()).then(|| location.admin1.clone()),
+            country: location.country.clone(),
+        }
+    }
 }
 
+/// Template of location names, empty until set from the settings
+static LOCATION_FORMAT: RwLock<String> = RwLock::new(String::new());
+
+/// Sets the template used by `location_name`
+pub fn set_location_format(format: &str) {
+    *LOCATION_FORMAT.write().unwrap() = format.to_string();
+}
+
+static MAX_DISTANCE_KM: RwLock<f64> = RwLock::new(DEFAULT_MAX_GEOCODE_DISTANCE_KM);
+
+/// Sets how far a photo may be from the nearest city and still be named after it
+pub fn set_max_distance_km(km: f64) {
+    *MAX_DISTANCE_KM.write().unwrap() = km;
+}
+
+/// Great-circle distance between two positions in kilometres
+pub fn haversine_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lng = (lng2 - lng1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+/// Degrees of latitude covered by a distance along a meridian
+pub fn latitude_degrees(distance_km: f64) -> f64 {
+    (distance_km / EARTH_RADIUS_KM).to_degrees()
+}
+
+/// Formats a place with the configured template
+pub fn location_name(place: &Place) -> String {
+    let format = LOCATION_FORMAT.read().unwrap();
+    if format.trim().is_empty() {
+        place.format(DEFAULT_LOCATION_FORMAT)
+    } else {
+        place.format(&format)
+    }
+}
+
+/// Decodes gzipped geodata, with or without admin1
+fn decode_geodata(bytes: &[u8]) -> Result<Vec<GeoLocation>> {
+    let mut data = Vec::new();
+    GzDecoder::new(bytes)
+        .take(GEODATA_SIZE_LIMIT)
+        .read_to_end(&mut data)
+        .context("Failed to decompress geodata")?;
+
+    use bincode::Options;
+    let options = bincode::options()
+        .with_limit(GEODATA_SIZE_LIMIT)
+        .with_fixint_encoding();
+    if let Ok(locations) = options.deserialize::<Vec<GeoLocation>>(&data) {
+        return Ok(locations);
+    }
+    let legacy: Vec<LegacyGeoLocation> = options
+        .deserialize(&data)
+        .context("Failed to deserialize geodata")?;
+    Ok(legacy
+        .into_iter()
+        .map(|loc| GeoLocation {
+            name: loc.name,
+            lat: loc.lat,
+            lng: loc.lng,
+            country: loc.country,
+            admin1: String::new(),
+        })
+        .collect())
+}
+
+/// Lookups are memoized on a grid of 0.001° (~100 m)
+const CACHE_PRECISION: f64 = 1000.0;
+
 pub struct ReverseGeocoder {
     locations: Vec<GeoLocation>,
+    /// Lowercase names in the order of `locations`, for search
+    names_lower: Vec<String>,
+    cache: Mutex<HashMap<(i32, i32), Option<Nearest>>>,
 }
 
 // Global singleton instance — wrapped in Option so failures are stored as None
@@ -26,27 +160,30 @@ impl ReverseGeocoder {
         println!("🌍 Initializing Reverse Geocoder...");
         let start = std::time::Instant::now();
 
-        // Decompress and deserialize
-        let decoder = GzDecoder::new(GEODATA_BYTES);
-        use bincode::Options;
-        let locations: Vec<GeoLocation> = bincode::options()
-            .with_limit(20 * 1024 * 1024)
-            .with_fixint_encoding()
-            .deserialize_from(decoder)
-            .context("Failed to deserialize geodata")?;
+        let locations = decode_geodata(GEODATA_BYTES)?;
+        let names_lower = locations.iter().map(|l| l.name.to_lowercase()).collect();
 
         println!(
             "✅ Geocoder initialized in {:?} with {} cities",
             start.elapsed(),
             locations.len()
         );
-        Ok(ReverseGeocoder { locations })
+        Ok(ReverseGeocoder {
+            locations,
+            names_lower,
+            cache: Mutex::new(HashMap::new()),
+        })
     }
 
     pub fn get() -> Option<&'static ReverseGeocoder> {
         GEOCODER.get().and_then(|opt| opt.as_ref())
     }
 
+    /// False while `init` is still loading the geodata
+    pub fn is_initialized() -> bool {
+        GEOCODER.get().is_some()
+    }
+
     pub fn init() {
         // Initialize in background or on first access — skip on corrupt/missing geodata
         let _ = GEOCODER.get_or_init(|| match ReverseGeocoder::new() {
@@ -58,50 +195,243 @@ impl ReverseGeocoder {
         });
     }
 
+    /// Nearest city of the grid cell containing the position, as "City, CC" by default
     pub fn lookup(&self, lat: f64, lng: f64) -> Option<String> {
-        // Simple linear search with squared euclidean distance
-        // For the embedded city set this is fast enough (~1-2ms)
-        let mut nearest: Option<&GeoLocation> = None;
-        let mut nearest_dist_sq = f64::MAX;
+        self.lookup_detailed(lat, lng)
+            .map(|location| location_name(&location.into()))
+    }
+
+    /// Nearest city of the grid cell containing the position, None when it is farther
+    /// than the configured maximum distance
+    pub fn lookup_detailed(&self, lat: f64, lng: f64) -> Option<&GeoLocation> {
+        let key = (
+            (lat * CACHE_PRECISION).round() as i32,
+            (lng * CACHE_PRECISION).round() as i32,
+        );
+        let cached = self.cache.lock().unwrap().get(&key).copied();
+        let nearest = cached.unwrap_or_else(|| {
+            // Search from the cell center so the result doesn't depend on which photo came first
+            let nearest = self.nearest(
+                key.0 as f64 / CACHE_PRECISION,
+                key.1 as f64 / CACHE_PRECISION,
+            );
+            self.cache.lock().unwrap().insert(key, nearest);
+            nearest
+        });
+        let max_distance = *MAX_DISTANCE_KM.read().unwrap();
+        nearest
+            .filter(|&(_, distance)| distance <= max_distance)
+            .map(|(i, _)| &self.locations[i])
+    }
 
-        for loc in &self.locations {
-            // Squared euclidean distance (faster than sqrt, sufficient for comparison)
+    /// Cities whose name contains the query, ignoring case. Exact matches come first,
+    /// then names starting with the query; ties are sorted by name and country.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<&GeoLocation> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let mut matches: Vec<(u8, usize)> = self
+            .names_lower
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| {
+                let rank = if *name == query {
+                    0
+                } else if name.starts_with(&query) {
+                    1
+                } else if name.contains(&query) {
+                    2
+                } else {
+                    return None;
+                };
+                Some((rank, i))
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| self.names_lower[a.1].cmp(&self.names_lower[b.1]))
+                .then_with(|| {
+                    self.locations[a.1]
+                        .country
+                        .cmp(&self.locations[b.1].country)
+                })
+        });
+        matches.truncate(limit);
+        matches
+            .into_iter()
+            .map(|(_, i)| &self.locations[i])
+            .collect()
+    }
+
+    /// Forgets memoized lookups, e.g. before re-geocoding the library
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Nearest city and its great-circle distance in km
+    fn nearest(&self, lat: f64, lng: f64) -> Option<Nearest> {
+        // Linear scan on degrees with longitude shrunk by cos(latitude), which keeps the
+        // right cities among the best few except very close to the poles
+        let lng_scale = lat.to_radians().cos();
+        let mut candidates: Vec<(f64, usize)> = Vec::with_capacity(NEAREST_CANDIDATES + 1);
+
+        for (i, loc) in self.locations.iter().enumerate() {
             let d_lat = loc.lat - lat;
-            let d_lng = loc.lng - lng;
+            let d_lng = ((loc.lng - lng + 540.0) % 360.0 - 180.0) * lng_scale;
             let dist_sq = d_lat * d_lat + d_lng * d_lng;
 
-            if dist_sq < nearest_dist_sq {
-                nearest_dist_sq = dist_sq;
-                nearest = Some(loc);
+            if candidates.len() < NEAREST_CANDIDATES
+                || dist_sq < candidates[NEAREST_CANDIDATES - 1].0
+            {
+                let at = candidates.partition_point(|&(d, _)| d <= dist_sq);
+                candidates.insert(at, (dist_sq, i));
+                candidates.truncate(NEAREST_CANDIDATES);
             }
         }
 
-        nearest.map(|loc| format!("{}, {}", loc.name, loc.country))
+        candidates
+            .into_iter()
+            .map(|(_, i)| {
+                let loc = &self.locations[i];
+                (i, haversine_km(lat, lng, loc.lat, loc.lng))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
     }
 }
 
-// Public helper for easy access
-pub fn get_location_name(lat: f64, lng: f64) -> Option<String> {
-    if let Some(geocoder) = ReverseGeocoder::get() {
-        geocoder.lookup(lat, lng)
-    } else {
+fn geocoder() -> Option<&'static ReverseGeocoder> {
+    ReverseGeocoder::get().or_else(|| {
         // Try to init if not initialized (lazy)
         ReverseGeocoder::init();
-        if let Some(geocoder) = ReverseGeocoder::get() {
-            geocoder.lookup(lat, lng)
-        } else {
-            None
-        }
-    }
+        ReverseGeocoder::get()
+    })
+}
+
+// Public helpers for easy access
+pub fn get_location_name(lat: f64, lng: f64) -> Option<String> {
+    geocoder()?.lookup(lat, lng)
+}
+
+pub fn get_place(lat: f64, lng: f64) -> Option<Place> {
+    geocoder()?.lookup_detailed(lat, lng).map(Place::from)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ReverseGeocoder;
+    use super::{decode_geodata, haversine_km, latitude_degrees, Place, ReverseGeocoder};
+    use flate2::write::GzEncoder;
+    use std::io::Write;
 
     #[test]
     fn embedded_geodata_deserializes() {
         let geocoder = ReverseGeocoder::new().expect("embedded geodata should deserialize");
         assert!(!geocoder.locations.is_empty());
     }
+
+    #[test]
+    fn lookups_are_memoized_per_grid_cell() {
+        let geocoder = ReverseGeocoder::new().unwrap();
+        let paris = geocoder.lookup(48.85661, 2.35222);
+        assert!(paris.is_some());
+        assert_eq!(geocoder.lookup(48.85658, 2.35219), paris);
+        assert_eq!(geocoder.cache.lock().unwrap().len(), 1);
+
+        geocoder.lookup(40.7128, -74.006);
+        assert_eq!(geocoder.cache.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn haversine_matches_known_distances() {
+        // Published great-circle distances, matched within 0.5%
+        let pairs = [
+            ((48.8566, 2.3522), (51.5074, -0.1278), 344.0),
+            ((40.7128, -74.006), (34.0522, -118.2437), 3944.0),
+            ((-33.8688, 151.2093), (-37.8136, 144.9631), 713.0),
+        ];
+        for ((lat1, lng1), (lat2, lng2), expected) in pairs {
+            let km = haversine_km(lat1, lng1, lat2, lng2);
+            assert!(
+                (km - expected).abs() / expected < 0.005,
+                "{km} vs {expected}"
+            );
+            assert_eq!(km, haversine_km(lat2, lng2, lat1, lng1));
+        }
+        assert!((latitude_degrees(111.195) - 1.0).abs() < 1e-3);
+        // Across the antimeridian
+        assert!((haversine_km(0.0, 179.5, 0.0, -179.5) - 111.2).abs() < 0.5);
+    }
+
+    #[test]
+    fn remote_positions_have_no_location() {
+        let geocoder = ReverseGeocoder::new().unwrap();
+        // Near the North Pole the closest town is hundreds of kilometres away
+        assert!(geocoder.lookup(89.9, 0.0).is_none());
+        // Mid-Atlantic
+        assert!(geocoder.lookup(30.0, -40.0).is_none());
+        assert!(geocoder.lookup(48.8566, 2.3522).is_some());
+        // The distance is still known, so only the threshold decides
+        assert!(geocoder.nearest(30.0, -40.0).unwrap().1 > 1000.0);
+    }
+
+    #[test]
+    fn search_ranks_exact_then_prefix_then_substring() {
+        let geocoder = ReverseGeocoder::new().unwrap();
+        let results = geocoder.search("LISBON", 10);
+        assert_eq!(results[0].name, "Lisbon");
+        assert_eq!(results[0].country, "PT");
+
+        let results = geocoder.search("lis", 50);
+        assert!(!results.is_empty());
+        let first_substring = results
+            .iter()
+            .position(|l| !l.name.to_lowercase().starts_with("lis"))
+            .unwrap_or(results.len());
+        assert!(results[first_substring..]
+            .iter()
+            .all(|l| l.name.to_lowercase().contains("lis")));
+        let prefixed: Vec<_> = results[..first_substring]
+            .iter()
+            .map(|l| l.name.to_lowercase())
+            .collect();
+        assert!(prefixed.windows(2).all(|w| w[0] <= w[1]));
+
+        assert_eq!(geocoder.search("lis", 3).len(), 3);
+        assert!(geocoder.search("  ", 10).is_empty());
+    }
+
+    #[test]
+    fn place_format_skips_unknown_region() {
+        let mut place = Place {
+            city: "Springfield".to_string(),
+            admin1: Some("Illinois".to_string()),
+            country: "US".to_string(),
+        };
+        assert_eq!(
+            place.format("{name}, {admin1}, {country}"),
+            "Springfield, Illinois, US"
+        );
+        assert_eq!(place.format("{name}, {country}"), "Springfield, US");
+
+        place.admin1 = None;
+        assert_eq!(
+            place.format("{name}, {admin1}, {country}"),
+            "Springfield, US"
+        );
+    }
+
+    #[test]
+    fn geodata_without_admin1_is_decoded() {
+        let legacy = vec![("Berlin".to_string(), 52.52, 13.41, "DE".to_string())];
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder
+            .write_all(&bincode::serialize(&legacy).unwrap())
+            .unwrap();
+
+        let locations = decode_geodata(&encoder.finish().unwrap()).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].name, "Berlin");
+        assert_eq!(locations[0].admin1, "");
+    }
 }
@@ -0,0 +1,191 @@
+use lru::LruCache;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+use crate::image_processing::OutputFormat;
+
+/// Encoded image kept in memory between requests
+#[derive(Debug)]
+pub struct CachedImage {
+    pub data: Vec<u8>,
+    pub format: OutputFormat,
+}
+
+/// Snapshot of cache counters for the stats endpoint
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+}
+
+/// In-memory LRU cache of generated images bounded by total encoded size.
+/// Keys embed the image ETag, so entries for changed files are never hit again
+/// and simply age out.
+#[derive(Clone)]
+pub struct ImageCache {
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+struct CacheInner {
+    entries: LruCache<String, Arc<CachedImage>>,
+    total_bytes: usize,
+    max_bytes: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl CacheInner {
+    fn evict_to_budget(&mut self) {
+        while self.total_bytes > self.max_bytes {
+            let Some((_, evicted)) = self.entries.pop_lru() else {
+                break;
+            };
+            self.total_bytes -= evicted.data.len();
+            self.evictions += 1;
+        }
+    }
+}
+
+/// Builds the cache key for a source file and its image validator
+pub fn cache_key(file_path: &str, etag: &str) -> String {
+    format!("{file_path}|{etag}")
+}
+
+impl ImageCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(CacheInner {
+                entries: LruCache::unbounded(),
+                total_bytes: 0,
+                max_bytes,
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+            })),
+        }
+    }
+
+    /// Looks up an image and marks it as recently used
+    pub fn get(&self, key: &str) -> Option<Arc<CachedImage>> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.entries.get(key).cloned() {
+            Some(image) => {
+                inner.hits += 1;
+                Some(image)
+            }
+            None => {
+                inner.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Checks for an image without touching recency or counters
+    pub fn contains(&self, key: &str) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.entries.contains(key)
+    }
+
+    /// Stores an image, evicting least recently used entries when over budget.
+    /// Images larger than the whole budget are not cached.
+    pub fn insert(&self, key: String, image: CachedImage) {
+        let size = image.data.len();
+        let mut inner = self.inner.lock().unwrap();
+        if size > inner.max_bytes {
+            return;
+        }
+
+        if let Some(previous) = inner.entries.put(key, Arc::new(image)) {
+            inner.total_bytes -= previous.data.len();
+        }
+        inner.total_bytes += size;
+        inner.evict_to_budget();
+    }
+
+    /// Changes the memory budget, evicting immediately if it shrank
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.max_bytes = max_bytes;
+        inner.evict_to_budget();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            evictions: inner.evictions,
+            entries: inner.entries.len(),
+            bytes: inner.total_bytes,
+            max_bytes: inner.max_bytes,
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.total_bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(size: usize) -> CachedImage {
+        CachedImage {
+            data: vec![0; size],
+            format: OutputFormat::Jpeg,
+        }
+    }
+
+    #[test]
+    fn evicts_oldest_entries_over_budget() {
+        let cache = ImageCache::new(100);
+        cache.insert("a".to_string(), image(40));
+        cache.insert("b".to_string(), image(40));
+        cache.insert("c".to_string(), image(40));
+
+        assert!(!cache.contains("a"));
+        assert!(cache.contains("b"));
+        assert!(cache.contains("c"));
+    }
+
+    #[test]
+    fn recently_used_entries_survive_eviction() {
+        let cache = ImageCache::new(100);
+        cache.insert("a".to_string(), image(40));
+        cache.insert("b".to_string(), image(40));
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), image(40));
+
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(cache.contains("c"));
+    }
+
+    #[test]
+    fn counts_hits_misses_and_evictions() {
+        let cache = ImageCache::new(100);
+        cache.insert("a".to_string(), image(60));
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("missing").is_none());
+        cache.set_max_bytes(50);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 1, 1));
+        assert_eq!((stats.entries, stats.bytes, stats.max_bytes), (0, 0, 50));
+    }
+
+    #[test]
+    fn skips_images_larger_than_budget() {
+        let cache = ImageCache::new(10);
+        cache.insert("big".to_string(), image(11));
+        assert!(cache.get("big").is_none());
+    }
+}
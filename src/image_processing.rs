@@ -1,13 +1,143 @@
 use anyhow::{Context, Result};
 
+use crate::config::ProcessingConfig;
 use crate::constants::*;
 use crate::database::PhotoMetadata;
+use crate::settings::Settings;
 use image::{DynamicImage, GenericImageView, ImageReader};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+use xxhash_rust::xxh3::Xxh3;
 
-/// Creates a scaled JPG image from a DynamicImage.
+mod font;
+mod format;
+
+pub use format::{detect_image_format, image_format_of, sniff_image_format, ImageFormat};
+
+/// Output format for generated images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    /// Parses a format name as stored in settings (case-insensitive)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "webp" => Some(OutputFormat::Webp),
+            _ => None,
+        }
+    }
+
+    /// Returns the name used in settings
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Webp => "webp",
+        }
+    }
+
+    /// Returns the Content-Type for encoded images
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// Encoding parameters for generated images.
+/// Quality applies to JPEG and WebP alike; both encoders are lossy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Quality of popup images; markers and thumbnails use `processing`
+    pub quality: u8,
+    pub format: OutputFormat,
+    pub processing: ProcessingConfig,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            quality: DEFAULT_IMAGE_QUALITY,
+            format: OutputFormat::Jpeg,
+            processing: ProcessingConfig::default(),
+        }
+    }
+}
+
+impl EncodeOptions {
+    pub fn new(quality: u8, format: OutputFormat) -> Self {
+        Self {
+            quality: clamp_quality(quality as i64),
+            format,
+            processing: ProcessingConfig::default(),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            processing: settings.processing,
+            ..Self::new(settings.image_quality, settings.thumbnail_format)
+        }
+    }
+
+    /// JPEG quality used for the given image type
+    pub fn quality_for(&self, image_type: ImageType) -> u8 {
+        let quality = match image_type {
+            ImageType::Marker => self.processing.marker_jpeg_quality,
+            ImageType::Thumbnail | ImageType::Gallery | ImageType::Tiny => {
+                self.processing.thumbnail_jpeg_quality
+            }
+            ImageType::Popup => self.quality,
+        };
+        clamp_quality(quality as i64)
+    }
+}
+
+/// Clamps an encoder quality value to the valid 1-100 range
+pub fn clamp_quality(quality: i64) -> u8 {
+    quality.clamp(1, 100) as u8
+}
+
+/// Encodes an RGB image with the requested format and quality
+fn encode_rgb_image(image: &image::RgbImage, options: EncodeOptions) -> Result<Vec<u8>> {
+    match options.format {
+        OutputFormat::Jpeg => {
+            // Encode to JPEG using turbojpeg (faster than image crate's encoder)
+            let jpeg_data =
+                turbojpeg::compress_image(image, options.quality as i32, turbojpeg::Subsamp::None)
+                    .with_context(|| "Failed to compress image with turbojpeg")?;
+            Ok(jpeg_data.to_vec())
+        }
+        OutputFormat::Webp => {
+            // libwebp, as the image crate only encodes lossless WebP, which
+            // is larger than JPEG for photos
+            let webp_data = webp::Encoder::from_rgb(image.as_raw(), image.width(), image.height())
+                .encode_simple(false, options.quality as f32)
+                .map_err(|e| anyhow::anyhow!("Failed to encode image as WebP: {:?}", e))?;
+            Ok(webp_data.to_vec())
+        }
+    }
+}
+
+/// Creates a scaled image from a DynamicImage in the requested output format.
 /// Can optionally pad the image to a square.
-fn create_scaled_image(img: DynamicImage, size: u32, pad_to_square: bool) -> Result<Vec<u8>> {
+fn create_scaled_image(
+    img: DynamicImage,
+    size: u32,
+    pad_to_square: bool,
+    options: EncodeOptions,
+) -> Result<Vec<u8>> {
     if pad_to_square {
         // Create a square canvas with a white background
         let mut canvas = image::RgbImage::from_fn(size, size, |_, _| {
@@ -31,22 +161,43 @@ fn create_scaled_image(img: DynamicImage, size: u32, pad_to_square: bool) -> Res
             y_offset as i64,
         );
 
-        // Encode to JPEG using turbojpeg
-        let jpeg_data = turbojpeg::compress_image(&canvas, 85, turbojpeg::Subsamp::None)
-            .with_context(|| "Failed to compress image with turbojpeg")?;
-
-        Ok(jpeg_data.to_vec())
+        encode_rgb_image(&canvas, options)
     } else {
         // Just resize the image to the given size (max dimension) while maintaining the aspect ratio
         let scaled = img.resize(size, size, image::imageops::FilterType::Triangle);
 
-        // Convert to RGB8 and encode with turbojpeg (faster than image crate's encoder)
-        let rgb_image = scaled.to_rgb8();
-        let jpeg_data = turbojpeg::compress_image(&rgb_image, 85, turbojpeg::Subsamp::None)
-            .with_context(|| "Failed to compress image with turbojpeg")?;
+        encode_rgb_image(&scaled.to_rgb8(), options)
+    }
+}
 
-        Ok(jpeg_data.to_vec())
+/// Packs square tiles row by row into one JPEG sheet.
+/// Returns the encoded sheet and its number of columns.
+pub fn pack_sprite(tiles: &[DynamicImage], tile_size: u32, quality: u8) -> Result<(Vec<u8>, u32)> {
+    let count = tiles.len().max(1) as u32;
+    let columns = (count as f64).sqrt().ceil() as u32;
+    let rows = count.div_ceil(columns);
+    let mut sheet = image::RgbImage::from_pixel(
+        columns * tile_size,
+        rows * tile_size,
+        image::Rgb([255, 255, 255]),
+    );
+    for (i, tile) in (0u32..).zip(tiles) {
+        // The macOS HEIC fallback returns full-size images
+        let tile = if tile.dimensions() == (tile_size, tile_size) {
+            tile.to_rgb8()
+        } else {
+            tile.resize_exact(tile_size, tile_size, image::imageops::FilterType::Triangle)
+                .to_rgb8()
+        };
+        image::imageops::replace(
+            &mut sheet,
+            &tile,
+            ((i % columns) * tile_size) as i64,
+            ((i / columns) * tile_size) as i64,
+        );
     }
+    let options = EncodeOptions::new(quality, OutputFormat::Jpeg);
+    Ok((encode_rgb_image(&sheet, options)?, columns))
 }
 
 fn try_load_jpeg(path: &Path, target_size: u32) -> Result<Option<DynamicImage>> {
@@ -135,24 +286,202 @@ fn native_path(path: &Path) -> PathBuf {
     }
 }
 
-pub fn create_scaled_image_in_memory(source_path: &Path, image_type: ImageType) -> Result<Vec<u8>> {
+/// Decodes the largest page of a TIFF. Multi-page files and raw wrappers often put
+/// a preview or thumbnail next to the full image, and not always after it.
+fn load_largest_tiff_page(path: &Path) -> Result<DynamicImage> {
+    use tiff::decoder::{Decoder, DecodingResult};
+    use tiff::ColorType;
+
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut decoder = Decoder::new(file)?;
+    let (mut largest, mut largest_pixels) = (0, 0u64);
+    let mut page = 0;
+    loop {
+        let (width, height) = decoder.dimensions()?;
+        let pixels = u64::from(width) * u64::from(height);
+        if pixels > largest_pixels {
+            (largest, largest_pixels) = (page, pixels);
+        }
+        // A broken later page leaves the ones read so far
+        if !decoder.more_images() || decoder.next_image().is_err() {
+            break;
+        }
+        page += 1;
+    }
+
+    decoder.seek_to_image(largest)?;
+    let (width, height) = decoder.dimensions()?;
+    let color = decoder.colortype()?;
+    let img = match (color, decoder.read_image()?) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            image::GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            image::GrayAlphaImage::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            image::RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            image::RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            image::ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+        }
+        (ColorType::RGB(16), DecodingResult::U16(data)) => {
+            image::ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+        }
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+            image::ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+        }
+        (color, _) => anyhow::bail!("Unsupported TIFF colour type {:?}", color),
+    };
+    img.context("TIFF page holds fewer pixels than its dimensions")
+}
+
+/// Decodes the full-size image of a photo. TIFF and BigTIFF files use their largest
+/// page; everything else goes through the image crate, which is told the format by
+/// the file content rather than its extension.
+pub fn load_primary_image(path: &Path) -> Result<DynamicImage> {
+    if image_format_of(path) == Some(ImageFormat::Tiff) {
+        load_largest_tiff_page(path)
+            .with_context(|| format!("Failed to open TIFF: {}", path.display()))
+    } else {
+        ImageReader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(image::ImageError::IoError)
+            .and_then(|reader| reader.decode())
+            .with_context(|| format!("Failed to open image: {}", path.display()))
+    }
+}
+
+/// Decodes a photo, at least `size` pixels on its short side when turbojpeg can scale
+/// it, and applies the EXIF orientation
+fn load_oriented_image(
+    source_path: &Path,
+    size: u32,
+    options: &EncodeOptions,
+) -> Result<DynamicImage> {
     let source_path = native_path(source_path);
-    let size = image_type.size();
-    let pad_to_square = image_type.pad_to_square();
 
     // Try to load with turbojpeg first (fast path for JPEGs)
-    // We pass target_size to allow for future optimization with scaling
-    let mut img = if let Ok(Some(img)) = try_load_jpeg(&source_path, size) {
+    let fast_path = options
+        .processing
+        .use_turbojpeg_fast_path
+        .then(|| try_load_jpeg(&source_path, size).ok().flatten())
+        .flatten();
+    let img = if let Some(img) = fast_path {
         img
     } else {
-        image::open(&source_path)
-            .with_context(|| format!("Failed to open image: {}", source_path.display()))?
+        load_primary_image(&source_path)?
     };
 
-    // Apply EXIF orientation
-    img = crate::exif_parser::apply_exif_orientation(&source_path, img)?;
+    crate::exif_parser::apply_exif_orientation(&source_path, img)
+}
+
+pub fn create_scaled_image_in_memory(
+    source_path: &Path,
+    image_type: ImageType,
+    options: EncodeOptions,
+) -> Result<Vec<u8>> {
+    let img = load_oriented_image(source_path, image_type.size(), &options)?;
+    let options = EncodeOptions {
+        quality: options.quality_for(image_type),
+        ..options
+    };
+    create_scaled_image(img, image_type.size(), image_type.pad_to_square(), options)
+}
+
+/// Decodes a photo into an `ImageType::Tiny` copy, the source of the previews
+/// stored with each photo
+pub fn load_tiny_image(source_path: &Path) -> Result<DynamicImage> {
+    let size = ImageType::Tiny.size();
+    let img = load_oriented_image(source_path, size, &EncodeOptions::default())?;
+    Ok(img.thumbnail(size, size))
+}
+
+/// BlurHash components across and down, enough for a soft colour placeholder
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
 
-    create_scaled_image(img, size, pad_to_square)
+/// Side of the grid the dominant colour is computed on
+const DOMINANT_COLOR_GRID: u32 = 16;
+
+/// Computes the BlurHash placeholder of a photo from its tiny copy
+pub fn create_blurhash(img: &DynamicImage) -> Result<String> {
+    let pixels = img.to_rgba8();
+    let (x, y) = BLURHASH_COMPONENTS;
+    blurhash::encode(x, y, pixels.width(), pixels.height(), pixels.as_raw())
+        .map_err(|e| anyhow::anyhow!("Failed to encode blurhash: {}", e))
+}
+
+/// Dominant colour as k-means with k = 1 over a 16×16 copy, which is the mean colour
+pub fn extract_dominant_color(img: &DynamicImage) -> [u8; 3] {
+    let grid = img
+        .resize_exact(
+            DOMINANT_COLOR_GRID,
+            DOMINANT_COLOR_GRID,
+            image::imageops::FilterType::Triangle,
+        )
+        .to_rgb8();
+    let mut sums = [0u64; 3];
+    for pixel in grid.pixels() {
+        for (sum, &channel) in sums.iter_mut().zip(pixel.0.iter()) {
+            *sum += channel as u64;
+        }
+    }
+    let count = (DOMINANT_COLOR_GRID * DOMINANT_COLOR_GRID) as u64;
+    sums.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+/// Most characters of a placeholder label, e.g. "HEIC" or "TIFF"
+const PLACEHOLDER_LABEL_CHARS: usize = 5;
+
+/// Square JPEG standing in for a photo that cannot be decoded: a grey-blue
+/// gradient with `label` (usually the file extension) in the middle
+pub fn generate_placeholder(size: u32, label: &str) -> Vec<u8> {
+    let size = size.max(font::GLYPH_HEIGHT);
+    let label: Vec<char> = label.chars().take(PLACEHOLDER_LABEL_CHARS).collect();
+
+    // Glyphs are one column apart; the label spans at most 3/5 of the width
+    let columns = (label.len() as u32 * (font::GLYPH_WIDTH + 1)).saturating_sub(1);
+    let scale = (size * 3 / 5)
+        .checked_div(columns)
+        .unwrap_or(1)
+        .min(size / 3 / font::GLYPH_HEIGHT)
+        .max(1);
+    let left = size.saturating_sub(columns * scale) / 2;
+    let top = size.saturating_sub(font::GLYPH_HEIGHT * scale) / 2;
+    let glyphs: Vec<[u8; 7]> = label.iter().map(|&c| font::glyph(c)).collect();
+
+    let image = image::RgbImage::from_fn(size, size, |x, y| {
+        let in_label = x >= left && y >= top && {
+            let (column, row) = ((x - left) / scale, (y - top) / scale);
+            let (index, column) = (
+                (column / (font::GLYPH_WIDTH + 1)) as usize,
+                column % (font::GLYPH_WIDTH + 1),
+            );
+            row < font::GLYPH_HEIGHT
+                && column < font::GLYPH_WIDTH
+                && glyphs
+                    .get(index)
+                    .is_some_and(|glyph| font::is_set(glyph, column, row))
+        };
+        if in_label {
+            return image::Rgb([236, 239, 244]);
+        }
+        // Diagonal gradient from the top left to the bottom right
+        let t = (x + y) as f32 / (2 * size) as f32;
+        let mix = |from: f32, to: f32| (from + (to - from) * t) as u8;
+        image::Rgb([mix(104.0, 52.0), mix(116.0, 60.0), mix(138.0, 76.0)])
+    });
+
+    let mut data = Vec::new();
+    // Encoding an in-memory RGB buffer only fails on allocation
+    let _ = image.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+        &mut data,
+        DEFAULT_IMAGE_QUALITY,
+    ));
+    data
 }
 
 /// Image types for processing
@@ -162,9 +491,22 @@ pub enum ImageType {
     Thumbnail,
     Gallery,
     Popup,
+    Tiny,
 }
 
 impl ImageType {
+    /// Parses a name returned by `name`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "marker" => Some(ImageType::Marker),
+            "thumbnail" => Some(ImageType::Thumbnail),
+            "gallery" => Some(ImageType::Gallery),
+            "popup" => Some(ImageType::Popup),
+            "tiny" => Some(ImageType::Tiny),
+            _ => None,
+        }
+    }
+
     /// Returns the size of the image in pixels
     pub fn size(&self) -> u32 {
         match self {
@@ -172,6 +514,7 @@ impl ImageType {
             ImageType::Thumbnail => THUMBNAIL_SIZE,
             ImageType::Gallery => GALLERY_SIZE,
             ImageType::Popup => POPUP_SIZE,
+            ImageType::Tiny => TINY_SIZE,
         }
     }
 
@@ -182,6 +525,7 @@ impl ImageType {
             ImageType::Thumbnail => "thumbnail",
             ImageType::Gallery => "gallery",
             ImageType::Popup => "popup",
+            ImageType::Tiny => "tiny",
         }
     }
 
@@ -189,89 +533,69 @@ impl ImageType {
     pub fn pad_to_square(&self) -> bool {
         match self {
             ImageType::Marker | ImageType::Thumbnail | ImageType::Gallery => true,
-            ImageType::Popup => false,
+            ImageType::Popup | ImageType::Tiny => false,
         }
     }
 }
 
-struct TempFileGuard {
-    path: Option<PathBuf>,
-}
+/// Links (or on non-Unix hard-links, falling back to copying) the file under a
+/// unique name with a lowercase extension in the OS temp dir. The link is
+/// deleted when the returned value is dropped, also on panic.
+fn lowercase_extension_link(original: &Path, ext_lower: &str) -> Result<NamedTempFile<()>> {
+    tempfile::Builder::new()
+        .prefix("photomap_heic_")
+        .suffix(&format!(".{ext_lower}"))
+        .make(|link| {
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink(original, link)
+            }
 
-impl Drop for TempFileGuard {
-    fn drop(&mut self) {
-        if let Some(ref path) = self.path {
-            let _ = std::fs::remove_file(path);
-        }
-    }
+            // Symlinks require special privileges on Windows; a hard link only
+            // works on the same volume, so copy as the last resort
+            #[cfg(not(unix))]
+            {
+                std::fs::hard_link(original, link)
+                    .or_else(|_| std::fs::copy(original, link).map(|_| ()))
+            }
+        })
+        .with_context(|| format!("Failed to link HEIC file for decoding: {:?}", original))
 }
 
-/// Converts a HEIC file to JPEG with specified dimensions using native code
-fn convert_heic_to_jpeg_native(photo: &PhotoMetadata, size_param: &str) -> Result<Vec<u8>> {
-    let max_dimension = match size_param {
-        "marker" => MARKER_SIZE,
-        "thumbnail" => THUMBNAIL_SIZE,
-        "gallery" => GALLERY_SIZE,
-        "popup" => POPUP_SIZE,
-        _ => 4096, // A reasonable default for 'full size'
+/// Converts a HEIC file to the requested format with specified dimensions using native code
+fn convert_heic_to_jpeg_native(
+    photo: &PhotoMetadata,
+    size_param: &str,
+    options: EncodeOptions,
+) -> Result<Vec<u8>> {
+    // Size, padding and quality follow `ImageType` like for other photos
+    let image_type = ImageType::from_name(size_param);
+    let max_dimension = image_type.map_or(4096, |t| t.size()); // 4096 for 'full size'
+    let pad_to_square = image_type.is_some_and(|t| t.pad_to_square());
+    let options = match image_type {
+        Some(image_type) => EncodeOptions {
+            quality: options.quality_for(image_type),
+            ..options
+        },
+        None => options,
     };
 
-    let pad_to_square = matches!(size_param, "marker" | "thumbnail" | "gallery");
-
     let original_path = native_path(Path::new(&photo.file_path));
     let mut path_to_decode = original_path.clone();
-    let mut temp_guard = TempFileGuard { path: None };
-
-    // Check the file extension
-    let ext_lower = original_path
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.to_lowercase())
-        .unwrap_or_default();
-
-    // If it's HEIC/HEIF and the extension is not lowercase, create a temporary symlink
-    if (ext_lower == "heic" || ext_lower == "heif")
-        && original_path
-            .extension()
-            .is_some_and(|ext| ext.to_ascii_lowercase() != ext)
-    {
-        let parent = original_path.parent().unwrap_or_else(|| Path::new("."));
-        let filename_stem = original_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("temp_heic");
-
-        // Create a unique name for the symlink to avoid collisions
-        let mut counter = 0;
-        let final_symlink_path = loop {
-            let current_symlink_name = format!("{}_{}.{}", filename_stem, counter, ext_lower);
-            let current_symlink_path = parent.join(current_symlink_name);
-            if !current_symlink_path.exists() {
-                break current_symlink_path;
-            }
-            counter += 1;
-        };
+    let mut _temp_link = None;
 
-        #[cfg(unix)]
-        {
-            std::os::unix::fs::symlink(&original_path, &final_symlink_path).with_context(|| {
-                format!(
-                    "Failed to create symlink for HEIC file: {:?}",
-                    original_path
-                )
-            })?;
-        }
-
-        #[cfg(not(unix))]
-        {
-            // On Windows and other non-Unix systems, we copy the file instead of symlinking
-            // because symlinks require special privileges on Windows
-            std::fs::copy(&original_path, &final_symlink_path).with_context(|| {
-                format!("Failed to copy HEIC file for decoding: {:?}", original_path)
-            })?;
-        }
-        path_to_decode = final_symlink_path.clone();
-        temp_guard.path = Some(final_symlink_path);
+    // libheif is chosen by the extension, so a HEIF file without a lowercase HEIF
+    // extension (IMG_1.HEIC, or a HEIC saved as .jpg) is decoded through a link
+    let heif = image_format_of(&original_path).filter(|format| format.is_heif());
+    let extension = original_path.extension().and_then(|s| s.to_str());
+    if let Some(format) = heif.filter(|&format| {
+        !extension.is_some_and(|ext| {
+            ext == ext.to_lowercase() && ImageFormat::from_extension(ext) == Some(format)
+        })
+    }) {
+        let link = lowercase_extension_link(&original_path, format.extension())?;
+        path_to_decode = link.path().to_path_buf();
+        _temp_link = Some(link);
     }
 
     let img = ImageReader::open(&path_to_decode)?
@@ -279,14 +603,20 @@ fn convert_heic_to_jpeg_native(photo: &PhotoMetadata, size_param: &str) -> Resul
         .decode()
         .with_context(|| format!("Failed to decode image: {}", path_to_decode.display()))?;
 
-    create_scaled_image(img, max_dimension, pad_to_square)
+    create_scaled_image(img, max_dimension, pad_to_square, options)
 }
 
-/// Converts a HEIC file to JPEG with the specified dimensions
-pub fn convert_heic_to_jpeg(photo: &PhotoMetadata, size_param: &str) -> Result<Vec<u8>> {
+/// Converts a HEIC file with the specified dimensions.
+/// Returns the encoded bytes together with their actual format, since the
+/// macOS `sips` fallback always produces JPEG.
+pub fn convert_heic_to_jpeg(
+    photo: &PhotoMetadata,
+    size_param: &str,
+    options: EncodeOptions,
+) -> Result<(Vec<u8>, OutputFormat)> {
     // First, try the native method
-    if let Ok(data) = convert_heic_to_jpeg_native(photo, size_param) {
-        return Ok(data);
+    if let Ok(data) = convert_heic_to_jpeg_native(photo, size_param, options) {
+        return Ok((data, options.format));
     }
 
     // As a fallback on macOS, use sips
@@ -301,7 +631,7 @@ pub fn convert_heic_to_jpeg(photo: &PhotoMetadata, size_param: &str) -> Result<V
             .output()
         {
             if output.status.success() {
-                return Ok(output.stdout);
+                return Ok((output.stdout, OutputFormat::Jpeg));
             }
         }
     }
@@ -312,11 +642,174 @@ pub fn convert_heic_to_jpeg(photo: &PhotoMetadata, size_param: &str) -> Result<V
     )
 }
 
+/// Generated thumbnails kept on disk, so markers and thumbnails of a large
+/// library are not re-decoded after every restart. Files are sharded by the
+/// first two hex digits of their key: `{dir}/ab/ab12..._marker.jpg`.
+/// Keys change when a photo is edited, so the oldest files are deleted once
+/// the cache grows past `max_bytes`.
+#[derive(Debug, Clone)]
+pub struct DiskThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Bytes stored since the size was last checked, shared between clones
+    written: Arc<AtomicU64>,
+}
+
+/// Number and total size of the files in the disk thumbnail cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, utoipa::ToSchema)]
+pub struct DiskCacheStats {
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+impl DiskThumbnailCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_max_bytes(dir, DISK_CACHE_MAX_MB * 1024 * 1024)
+    }
+
+    /// Cache that keeps at most `max_bytes` of files. The size is checked on the
+    /// first store, which prunes files left from a larger limit, and then every
+    /// sixteenth of the limit written.
+    pub fn with_max_bytes(dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes,
+            written: Arc::new(AtomicU64::new(Self::check_interval(max_bytes))),
+        }
+    }
+
+    fn check_interval(max_bytes: u64) -> u64 {
+        (max_bytes / 16).max(1)
+    }
+
+    /// Key of a generated image: the path of its source together with the
+    /// file's size and mtime and the encoding settings, so an edited photo or
+    /// a changed quality never hits a stale file. None if the source is gone.
+    pub fn key(
+        source_path: &Path,
+        image_type: ImageType,
+        options: EncodeOptions,
+    ) -> Option<String> {
+        let metadata = std::fs::metadata(source_path).ok()?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+
+        let mut hasher = Xxh3::new();
+        hasher.update(source_path.as_os_str().as_encoded_bytes());
+        mtime.hash(&mut hasher);
+        metadata.len().hash(&mut hasher);
+        options.quality_for(image_type).hash(&mut hasher);
+        options.format.name().hash(&mut hasher);
+        options.processing.use_turbojpeg_fast_path.hash(&mut hasher);
+        Some(format!("{:016x}_{}", hasher.digest(), image_type.name()))
+    }
+
+    fn path_of(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(key.get(0..2).unwrap_or(key))
+            .join(format!("{key}.jpg"))
+    }
+
+    /// Stored image, or None on a miss. WebP thumbnails share the .jpg name
+    /// and are told apart by their content.
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.path_of(key)).ok()
+    }
+
+    /// Stores an image through a temporary file renamed into place, so a
+    /// concurrent reader never sees a partial file
+    pub fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.path_of(key);
+        let parent = path.parent().unwrap_or(&self.dir);
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+        let mut temp = NamedTempFile::new_in(parent)?;
+        temp.write_all(data)?;
+        temp.persist(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        let written = self.written.fetch_add(data.len() as u64, Ordering::Relaxed);
+        if written + data.len() as u64 >= Self::check_interval(self.max_bytes) {
+            self.written.store(0, Ordering::Relaxed);
+            self.prune();
+        }
+        Ok(())
+    }
+
+    /// Deletes the least recently written files until the cache fits `max_bytes`
+    fn prune(&self) {
+        let mut files = self.files();
+        let mut total: u64 = files.iter().map(|(_, metadata)| metadata.len()).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+        files.sort_by_key(|(_, metadata)| metadata.modified().ok());
+        for (path, metadata) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            // A concurrent prune may have removed it already
+            if std::fs::remove_file(&path).is_ok() {
+                total -= metadata.len();
+            }
+        }
+    }
+
+    /// Cached files with their metadata; a missing cache directory is empty
+    fn files(&self) -> Vec<(PathBuf, std::fs::Metadata)> {
+        let mut result = Vec::new();
+        let Ok(shards) = std::fs::read_dir(&self.dir) else {
+            return result;
+        };
+        for shard in shards.flatten() {
+            let Ok(files) = std::fs::read_dir(shard.path()) else {
+                continue;
+            };
+            for file in files.flatten() {
+                if file.path().extension().is_none_or(|ext| ext != "jpg") {
+                    continue;
+                }
+                if let Ok(metadata) = file.metadata() {
+                    result.push((file.path(), metadata));
+                }
+            }
+        }
+        result
+    }
+
+    /// Walks the shard directories; a missing cache directory is empty
+    pub fn stats(&self) -> DiskCacheStats {
+        let files = self.files();
+        DiskCacheStats {
+            entries: files.len() as u64,
+            bytes: files.iter().map(|(_, metadata)| metadata.len()).sum(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::native_path;
+    use super::{
+        clamp_quality, create_blurhash, create_scaled_image, extract_dominant_color,
+        generate_placeholder, load_primary_image, load_tiny_image, lowercase_extension_link,
+        native_path, pack_sprite, DiskCacheStats, DiskThumbnailCache, EncodeOptions, ImageType,
+        OutputFormat,
+    };
+    use crate::config::ProcessingConfig;
+    use crate::constants::POPUP_SIZE;
+    use image::DynamicImage;
     use std::path::Path;
 
+    fn gradient_fixture() -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(320, 240, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        }))
+    }
+
     #[test]
     fn native_path_repairs_windows_separators() {
         let repaired = native_path(Path::new("D:/Photo\\Nested/image.jpg"));
@@ -327,4 +820,340 @@ mod tests {
         #[cfg(not(windows))]
         assert_eq!(repaired.to_string_lossy(), "D:/Photo\\Nested/image.jpg");
     }
+
+    #[test]
+    fn heic_links_are_unique_and_removed_outside_the_photo_folder() {
+        let dir = std::env::temp_dir().join("photomap_heic_link_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let original = dir.join("IMG_0001.HEIC");
+        std::fs::write(&original, b"heic bytes").unwrap();
+
+        let links: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| lowercase_extension_link(&original, "heic").unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut paths: Vec<_> = links.iter().map(|l| l.path().to_path_buf()).collect();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 8);
+        for path in &paths {
+            assert!(!path.starts_with(&dir));
+            assert_eq!(path.extension().unwrap(), "heic");
+            assert_eq!(std::fs::read(path).unwrap(), b"heic bytes");
+        }
+        // Nothing is written next to the original
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        drop(links);
+        assert!(paths.iter().all(|path| !path.exists()));
+        assert!(original.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn quality_is_clamped_to_valid_range() {
+        assert_eq!(clamp_quality(0), 1);
+        assert_eq!(clamp_quality(-5), 1);
+        assert_eq!(clamp_quality(85), 85);
+        assert_eq!(clamp_quality(250), 100);
+        assert_eq!(EncodeOptions::new(0, OutputFormat::Jpeg).quality, 1);
+    }
+
+    #[test]
+    fn quality_depends_on_image_type() {
+        let options = EncodeOptions {
+            processing: ProcessingConfig {
+                thumbnail_jpeg_quality: 70,
+                marker_jpeg_quality: 60,
+                ..Default::default()
+            },
+            ..EncodeOptions::new(90, OutputFormat::Jpeg)
+        };
+        assert_eq!(options.quality_for(ImageType::Marker), 60);
+        assert_eq!(options.quality_for(ImageType::Thumbnail), 70);
+        assert_eq!(options.quality_for(ImageType::Gallery), 70);
+        assert_eq!(options.quality_for(ImageType::Popup), 90);
+    }
+
+    #[test]
+    fn lower_jpeg_quality_produces_smaller_output() {
+        let high = create_scaled_image(
+            gradient_fixture(),
+            240,
+            false,
+            EncodeOptions::new(95, OutputFormat::Jpeg),
+        )
+        .unwrap();
+        let low = create_scaled_image(
+            gradient_fixture(),
+            240,
+            false,
+            EncodeOptions::new(40, OutputFormat::Jpeg),
+        )
+        .unwrap();
+        assert!(low.len() < high.len());
+    }
+
+    /// Photo-like content: sky, hills and light falloff under sensor noise,
+    /// which gradients lack and lossless codecs cannot shrink
+    fn photo_fixture() -> DynamicImage {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(800, 600, |x, y| {
+            // xorshift64: cheap, deterministic grain
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let grain = (state % 13) as f32 - 6.0;
+            let (fx, fy) = (x as f32 / 800.0, y as f32 / 600.0);
+            let horizon = 0.55 + 0.08 * (fx * 11.0).sin() + 0.03 * (fx * 37.0).sin();
+            let [r, g, b] = if fy < horizon {
+                [120.0 + 60.0 * fy, 160.0 + 50.0 * fy, 230.0 - 20.0 * fy]
+            } else {
+                let texture = 25.0 * (fx * 90.0 + fy * 40.0).sin() * (fy * 70.0).cos();
+                [70.0 + texture, 110.0 + texture, 45.0 + texture / 2.0]
+            };
+            let vignette = 1.0 - 0.35 * ((fx - 0.5).powi(2) + (fy - 0.5).powi(2));
+            let pixel = |v: f32| (v * vignette + grain).clamp(0.0, 255.0) as u8;
+            image::Rgb([pixel(r), pixel(g), pixel(b)])
+        }))
+    }
+
+    #[test]
+    fn webp_output_is_smaller_than_jpeg_for_photos() {
+        let encode = |format, quality| {
+            create_scaled_image(
+                photo_fixture(),
+                POPUP_SIZE,
+                false,
+                EncodeOptions::new(quality, format),
+            )
+            .unwrap()
+        };
+        let jpeg = encode(OutputFormat::Jpeg, 85);
+        let webp = encode(OutputFormat::Webp, 85);
+
+        assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+        assert_eq!(&webp[..4], b"RIFF");
+        assert_eq!(&webp[8..12], b"WEBP");
+        assert!(webp.len() < jpeg.len(), "{} vs {}", webp.len(), jpeg.len());
+        // The quality setting applies to WebP too
+        assert!(encode(OutputFormat::Webp, 40).len() < webp.len());
+    }
+
+    #[test]
+    fn output_format_parses_settings_values() {
+        assert_eq!(OutputFormat::parse("WebP"), Some(OutputFormat::Webp));
+        assert_eq!(OutputFormat::parse("jpg"), Some(OutputFormat::Jpeg));
+        assert_eq!(OutputFormat::parse("gif"), None);
+        assert_eq!(OutputFormat::Webp.mime_type(), "image/webp");
+    }
+
+    #[test]
+    fn blurhash_is_generated_from_photo() {
+        let dir = std::env::temp_dir().join("photomap_test_blurhash");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("gradient.jpg");
+        gradient_fixture().save(&path).unwrap();
+
+        let tiny = load_tiny_image(&path).unwrap();
+        assert_eq!(tiny.width(), 32);
+        let hash = create_blurhash(&tiny).unwrap();
+        // One size digit, one maximum digit, four DC digits and two per AC component
+        assert_eq!(hash.len(), 28);
+        assert!(hash.is_ascii());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dominant_color_is_the_mean_color() {
+        let half_red = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, _| {
+            if x < 32 {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([0, 0, 255])
+            }
+        }));
+        let color = extract_dominant_color(&half_red);
+        assert!(color[0].abs_diff(128) <= 2, "{color:?}");
+        assert_eq!(color[1], 0);
+        assert!(color[2].abs_diff(128) <= 2, "{color:?}");
+    }
+
+    #[test]
+    fn sprite_places_tiles_row_by_row() {
+        let tiles: Vec<DynamicImage> = [0u8, 120, 250]
+            .iter()
+            .map(|&shade| {
+                DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+                    40,
+                    40,
+                    image::Rgb([shade, shade, shade]),
+                ))
+            })
+            .collect();
+        let (data, columns) = pack_sprite(&tiles, 40, 95).unwrap();
+        assert_eq!(columns, 2);
+
+        let sheet = image::load_from_memory(&data).unwrap().to_luma8();
+        assert_eq!(sheet.dimensions(), (80, 80));
+        for (x, y, shade) in [(20, 20, 0u8), (60, 20, 120), (20, 60, 250), (60, 60, 255)] {
+            let actual = sheet.get_pixel(x, y)[0];
+            assert!(
+                actual.abs_diff(shade) < 8,
+                "({x}, {y}): {actual} vs {shade}"
+            );
+        }
+    }
+
+    #[test]
+    fn largest_tiff_page_is_loaded() {
+        use tiff::encoder::{colortype, TiffEncoder, TiffKind};
+
+        // Thumbnail, full image, then a preview; page 2 is the largest
+        fn write_pages<K: TiffKind>(mut tiff: TiffEncoder<std::fs::File, K>) {
+            for (width, height, value) in [(4, 3, 10u8), (32, 24, 200), (16, 12, 90)] {
+                let data = vec![value; (width * height * 3) as usize];
+                tiff.write_image::<colortype::RGB8>(width, height, &data)
+                    .unwrap();
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        for (name, big) in [("pages.tif", false), ("pages_big.TIFF", true)] {
+            let path = dir.path().join(name);
+            let file = std::fs::File::create(&path).unwrap();
+            if big {
+                write_pages(TiffEncoder::new_big(file).unwrap());
+            } else {
+                write_pages(TiffEncoder::new(file).unwrap());
+            }
+            let img = load_primary_image(&path).unwrap();
+            assert_eq!((img.width(), img.height()), (32, 24), "{name}");
+            assert_eq!(img.to_rgb8().get_pixel(5, 5).0, [200, 200, 200]);
+        }
+        assert!(load_primary_image(&dir.path().join("missing.tif")).is_err());
+    }
+
+    #[test]
+    fn images_with_a_wrong_extension_are_decoded_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let png_as_jpg = dir.path().join("IMG_0001.jpg");
+        image::RgbImage::from_pixel(12, 8, image::Rgb([10, 200, 30]))
+            .save_with_format(&png_as_jpg, image::ImageFormat::Png)
+            .unwrap();
+
+        let img = load_primary_image(&png_as_jpg).unwrap();
+        assert_eq!((img.width(), img.height()), (12, 8));
+        assert_eq!(img.to_rgb8().get_pixel(3, 3).0, [10, 200, 30]);
+    }
+
+    #[test]
+    fn placeholder_shows_the_label_on_a_gradient() {
+        for size in [ImageType::Marker.size(), ImageType::Thumbnail.size()] {
+            let img = image::load_from_memory(&generate_placeholder(size, "jpg"))
+                .unwrap()
+                .to_rgb8();
+            assert_eq!(img.dimensions(), (size, size));
+            let brightness =
+                |x: u32, y: u32| img.get_pixel(x, y).0.iter().map(|&c| c as u32).sum::<u32>();
+            assert!(brightness(1, 1) < 450, "corner is not the gradient");
+            let label_pixels = (0..size)
+                .flat_map(|y| (0..size).map(move |x| (x, y)))
+                .filter(|&(x, y)| brightness(x, y) > 500)
+                .count();
+            assert!(label_pixels > 0, "no label drawn at {size} px");
+        }
+        // Labels without a glyph and empty labels still give an image
+        for label in ["", "ÿ", "verylonglabel"] {
+            assert!(image::load_from_memory(&generate_placeholder(40, label)).is_ok());
+        }
+    }
+
+    #[test]
+    fn disk_cache_round_trips_and_tracks_the_source() {
+        let dir = std::env::temp_dir().join("photomap_test_disk_cache");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("photo.jpg");
+        std::fs::write(&source, b"original").unwrap();
+        let cache = DiskThumbnailCache::new(dir.join("thumbnails"));
+        assert_eq!(cache.stats(), DiskCacheStats::default());
+
+        let options = EncodeOptions::default();
+        let key = DiskThumbnailCache::key(&source, ImageType::Marker, options).unwrap();
+        assert!(key.ends_with("_marker"));
+        assert_eq!(cache.get(&key), None);
+        cache.put(&key, b"marker bytes").unwrap();
+        assert_eq!(cache.get(&key).as_deref(), Some(&b"marker bytes"[..]));
+        assert!(dir
+            .join("thumbnails")
+            .join(&key[0..2])
+            .join(format!("{key}.jpg"))
+            .is_file());
+        assert_eq!(
+            cache.stats(),
+            DiskCacheStats {
+                entries: 1,
+                bytes: 12
+            }
+        );
+
+        // Another variant, other settings or an edited source miss
+        let webp = EncodeOptions::new(90, OutputFormat::Webp);
+        assert_ne!(
+            DiskThumbnailCache::key(&source, ImageType::Thumbnail, options).unwrap(),
+            key
+        );
+        assert_ne!(
+            DiskThumbnailCache::key(&source, ImageType::Marker, webp).unwrap(),
+            key
+        );
+        std::fs::write(&source, b"edited photo").unwrap();
+        assert_ne!(
+            DiskThumbnailCache::key(&source, ImageType::Marker, options).unwrap(),
+            key
+        );
+        assert_eq!(
+            DiskThumbnailCache::key(&dir.join("gone.jpg"), ImageType::Marker, options),
+            None
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn disk_cache_deletes_oldest_files_past_its_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskThumbnailCache::with_max_bytes(dir.path(), 30);
+        let age = |key: &str, secs: u64| {
+            let path = dir.path().join(&key[0..2]).join(format!("{key}.jpg"));
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+                .unwrap();
+        };
+
+        cache.put("aa01_marker", b"first marker").unwrap();
+        cache.put("bb02_marker", b"other marker").unwrap();
+        age("aa01_marker", 1_000);
+        age("bb02_marker", 2_000);
+        assert_eq!(cache.stats().entries, 2);
+
+        cache.put("cc03_marker", b"third marker").unwrap();
+        assert_eq!(cache.get("aa01_marker"), None);
+        assert!(cache.get("bb02_marker").is_some());
+        assert!(cache.get("cc03_marker").is_some());
+        assert_eq!(
+            cache.stats(),
+            DiskCacheStats {
+                entries: 2,
+                bytes: 24
+            }
+        );
+    }
 }
@@ -0,0 +1,194 @@
+//! Image formats told apart by their magic bytes, so a file whose extension
+//! lies (a PNG saved as .jpg, a JPEG saved as .heic by some phones) is still
+//! parsed, decoded and served as what it is
+
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from the start of a file, enough for the ISOBMFF brand list
+const SNIFF_LEN: usize = 64;
+
+/// ISOBMFF brands of HEIF images, besides the AVIF ones
+const HEIF_BRANDS: [&[u8; 4]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Heic,
+    Avif,
+    Tiff,
+    Webp,
+    Gif,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// Format a file extension stands for (case-insensitive)
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            "heic" | "heif" => Some(ImageFormat::Heic),
+            "avif" => Some(ImageFormat::Avif),
+            "tif" | "tiff" => Some(ImageFormat::Tiff),
+            "webp" => Some(ImageFormat::Webp),
+            "gif" => Some(ImageFormat::Gif),
+            "bmp" => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
+
+    /// Lowercase extension the decoders recognize the format by
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Heic => "heic",
+            ImageFormat::Avif => "avif",
+            ImageFormat::Tiff => "tiff",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Bmp => "bmp",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Heic => "image/heic",
+            ImageFormat::Avif => "image/avif",
+            ImageFormat::Tiff => "image/tiff",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Bmp => "image/bmp",
+        }
+    }
+
+    /// Whether the format is decoded by libheif
+    pub fn is_heif(self) -> bool {
+        matches!(self, ImageFormat::Heic | ImageFormat::Avif)
+    }
+}
+
+/// Format of a file from its first bytes; None if unreadable or not a known image
+pub fn detect_image_format(path: &Path) -> Option<ImageFormat> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    std::fs::File::open(path)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    sniff_image_format(&header)
+}
+
+/// Format of a file by content, falling back to its extension when the
+/// content is not recognized
+pub fn image_format_of(path: &Path) -> Option<ImageFormat> {
+    detect_image_format(path).or_else(|| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(ImageFormat::from_extension)
+    })
+}
+
+/// Format from the magic bytes at the start of a file
+pub fn sniff_image_format(header: &[u8]) -> Option<ImageFormat> {
+    match header {
+        [0xFF, 0xD8, 0xFF, ..] => Some(ImageFormat::Jpeg),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageFormat::Png),
+        // Classic TIFF (42) and BigTIFF (43) in either byte order
+        [b'I', b'I', 42 | 43, 0, ..] | [b'M', b'M', 0, 42 | 43, ..] => Some(ImageFormat::Tiff),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::Webp),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageFormat::Gif),
+        [b'B', b'M', ..] => Some(ImageFormat::Bmp),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => heif_format(header),
+        _ => None,
+    }
+}
+
+/// HEIC or AVIF from the brands of an ISOBMFF `ftyp` box. A generic major
+/// brand such as `mif1` is followed by `avif` among the compatible brands.
+fn heif_format(header: &[u8]) -> Option<ImageFormat> {
+    let box_len = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+    let ftyp = header.get(8..box_len.min(header.len()))?;
+    // Major brand, minor version, then the compatible brands
+    let brands =
+        std::iter::once(ftyp.get(0..4)?).chain(ftyp.get(8..).unwrap_or_default().chunks_exact(4));
+    let mut heif = false;
+    for brand in brands {
+        if brand == b"avif" || brand == b"avis" {
+            return Some(ImageFormat::Avif);
+        }
+        heif |= HEIF_BRANDS.iter().any(|known| brand == &known[..]);
+    }
+    heif.then_some(ImageFormat::Heic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{image_format_of, sniff_image_format, ImageFormat};
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let mut header = ((16 + 4 * compatible.len()) as u32).to_be_bytes().to_vec();
+        header.extend_from_slice(b"ftyp");
+        header.extend_from_slice(major);
+        header.extend_from_slice(&[0, 0, 0, 0]);
+        for brand in compatible {
+            header.extend_from_slice(&brand[..]);
+        }
+        header
+    }
+
+    #[test]
+    fn formats_are_told_apart_by_magic_bytes() {
+        assert_eq!(
+            sniff_image_format(&[0xFF, 0xD8, 0xFF, 0xE1]),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            sniff_image_format(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            sniff_image_format(b"II*\0\x08\0\0\0"),
+            Some(ImageFormat::Tiff)
+        );
+        assert_eq!(sniff_image_format(b"MM\0+\0\x08"), Some(ImageFormat::Tiff));
+        assert_eq!(
+            sniff_image_format(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some(ImageFormat::Webp)
+        );
+        assert_eq!(sniff_image_format(b"GIF89a"), Some(ImageFormat::Gif));
+        assert_eq!(
+            sniff_image_format(&ftyp(b"heic", &[b"mif1", b"heic"])),
+            Some(ImageFormat::Heic)
+        );
+        assert_eq!(
+            sniff_image_format(&ftyp(b"mif1", &[b"mif1", b"avif"])),
+            Some(ImageFormat::Avif)
+        );
+
+        // Videos are ISOBMFF too, and text is no image at all
+        assert_eq!(sniff_image_format(&ftyp(b"isom", &[b"mp41"])), None);
+        assert_eq!(sniff_image_format(b"hello"), None);
+        assert_eq!(sniff_image_format(&[]), None);
+    }
+
+    #[test]
+    fn content_wins_over_the_extension() {
+        let dir = std::env::temp_dir();
+        let png_as_jpg = dir.join("photomap_test_png_as.jpg");
+        std::fs::write(&png_as_jpg, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let unknown = dir.join("photomap_test_unknown.jpg");
+        std::fs::write(&unknown, b"not an image").unwrap();
+
+        assert_eq!(image_format_of(&png_as_jpg), Some(ImageFormat::Png));
+        assert_eq!(image_format_of(&unknown), Some(ImageFormat::Jpeg));
+        let _ = std::fs::remove_file(&png_as_jpg);
+        let _ = std::fs::remove_file(&unknown);
+    }
+}
@@ -0,0 +1,20 @@
+//! PhotoMap processing library: folder scanning, EXIF metadata extraction,
+//! image generation and the map server. The `photomap_processor` binary is a
+//! thin wrapper around it; benchmarks use it directly.
+
+pub mod cli;
+pub mod config;
+pub mod constants;
+pub mod database;
+pub mod exif_parser;
+pub mod export;
+pub mod geocoding;
+pub mod image_cache;
+pub mod image_processing;
+pub mod process_manager;
+pub mod processing;
+pub mod server;
+pub mod settings;
+pub mod trips;
+pub mod types;
+pub mod utils;
@@ -1,23 +1,18 @@
 use anyhow::{Context, Result};
 use std::path::Path;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-mod constants;
-mod database;
-mod exif_parser;
-mod geocoding;
-mod image_processing;
-mod process_manager;
-mod processing;
-pub mod server;
-mod settings;
-mod utils;
-
-use database::Database;
+use clap::Parser;
 use libheif_rs::integration::image::register_all_decoding_hooks;
-use server::state::AppState;
-use settings::Settings;
+use photomap_processor::cli::{self, CliArgs, ExportTarget};
+use photomap_processor::database::{Database, StorageKind};
+use photomap_processor::image_cache::ImageCache;
+use photomap_processor::image_processing::DiskThumbnailCache;
+use photomap_processor::server::state::{unix_now, AppState, FolderStats, LastRunStats};
+use photomap_processor::settings::{self, Settings};
+use photomap_processor::{export, geocoding, process_manager, processing, server, utils};
 
 fn display_path(path: &str) -> String {
     #[cfg(windows)]
@@ -34,62 +29,34 @@ fn display_path(path: &str) -> String {
 #[tokio::main]
 async fn main() -> Result<()> {
     const VERSION: &str = env!("CARGO_PKG_VERSION");
+    // Parse before anything logs so --log-level, --cache-dir and --config apply from the start
+    let args = CliArgs::parse();
+    cli::init_logging(args.log_level);
+    if let Some(cache_dir) = &args.cache_dir {
+        utils::set_app_data_dir(cache_dir.clone());
+    }
+    if let Some(config) = &args.config {
+        utils::set_config_path(config.clone());
+    }
     println!("---");
     println!("🚀 Session start: PhotoMap Processor v{}", VERSION);
     println!("---");
+    tracing::debug!(?args, "command line arguments");
 
     register_all_decoding_hooks();
 
-    let mut port = 3001;
-    let args: Vec<String> = std::env::args().collect();
-    let mut i = 1;
-    while i < args.len() {
-        match args[i].as_str() {
-            "--port" | "-p" => {
-                if i + 1 < args.len() {
-                    if let Ok(p) = args[i + 1].parse::<u16>() {
-                        port = p;
-                    } else {
-                        eprintln!("⚠️ Invalid port value: {}", args[i + 1]);
-                        std::process::exit(1);
-                    }
-                    i += 2;
-                } else {
-                    eprintln!("⚠️ Missing value for --port");
-                    std::process::exit(1);
-                }
-            }
-            "--help" | "-h" => {
-                println!("PhotoMap Processor v{}", VERSION);
-                println!("Parallel photo processing, EXIF metadata extraction and interactive map server.");
-                println!();
-                println!("Usage:");
-                println!("  photomap_processor [options]");
-                println!();
-                println!("Options:");
-                println!("  -p, --port <port>  Specify port number (default: 3001)");
-                println!("  -h, --help         Show this help message");
-                return Ok(());
-            }
-            _ => {
-                eprintln!("⚠️ Unknown argument: {}", args[i]);
-                eprintln!("Run with --help to see available options.");
-                std::process::exit(1);
-            }
-        }
+    // An export runs next to the app instead of replacing it
+    if args.export.is_none() {
+        process_manager::ensure_single_instance()?;
     }
 
-    process_manager::ensure_single_instance()?;
-
-    println!("🗄️ Initializing database (In-Memory)...");
-    let db = Database::new().with_context(|| "Failed to initialize database")?;
-    println!("✅ Database initialized successfully");
-
     std::thread::spawn(|| {
         geocoding::ReverseGeocoder::init();
     });
 
-    println!(" 🚀 Starting HTTP server for on-demand marker generation");
+    if args.export.is_none() {
+        println!(" 🚀 Starting HTTP server for on-demand marker generation");
+    }
 
     let (event_sender, event_sender_receiver) = tokio::sync::mpsc::channel(100);
     let event_broadcast = tokio::sync::broadcast::channel(100).0;
@@ -108,49 +75,161 @@ async fn main() -> Result<()> {
         Settings::config_path().display()
     );
 
-    let folder_paths: Vec<String> = {
+    let port = args.port.unwrap_or(settings.lock().await.port);
+
+    {
+        let settings = settings.lock().await;
+        geocoding::set_location_format(&settings.location_format);
+        geocoding::set_max_distance_km(settings.max_geocode_distance_km);
+    }
+
+    // An export builds its own in-memory database, so the store of a running app
+    // is never pruned or cleared from here
+    let storage = match args.export {
+        Some(_) => StorageKind::Memory,
+        None => settings.lock().await.storage,
+    };
+    println!("🗄️ Initializing database ({})...", storage.name());
+    let db = match args.export {
+        Some(_) => Database::new(),
+        None => Database::open(storage),
+    }
+    .with_context(|| "Failed to initialize database")?;
+    println!("✅ Database initialized successfully");
+
+    // Folders from the command line replace the saved ones for this run only,
+    // unless --save asks to keep them
+    if !args.folder.is_empty() {
+        let mut folders: Vec<settings::FolderConfig> = Vec::new();
+        for folder in &args.folder {
+            let folder = std::fs::canonicalize(folder).unwrap_or_else(|_| folder.clone());
+            let folder = settings::FolderConfig::new(&folder.to_string_lossy());
+            println!(
+                "📂 Using folder from command line: {}",
+                display_path(&folder.path)
+            );
+            if !folders.iter().any(|f| f.path == folder.path) {
+                folders.push(folder);
+            }
+        }
+        let mut guard = settings.lock().await;
+        guard.override_folders(folders);
+        if args.save {
+            guard.keep_folders();
+            match guard.save() {
+                Ok(()) => println!("💾 Folders saved to the settings"),
+                Err(e) => eprintln!("⚠️ Failed to save settings: {}", e),
+            }
+        }
+    }
+
+    let cancel_processing = Arc::new(AtomicBool::new(false));
+
+    let (folder_paths, walk_options, processing_config, folder_override) = {
         let guard = settings.lock().await;
-        guard
-            .folders
-            .iter()
-            .filter_map(|f| f.as_ref().cloned())
-            .collect()
+        // A folder disabled by editing the config file still has its rows in SQLite
+        for folder in guard.disabled_folders() {
+            if let Err(e) = db.unload_folder(&folder) {
+                eprintln!("⚠️ Failed to unload disabled folder {}: {}", folder, e);
+            }
+        }
+        (
+            guard.enabled_folders(),
+            processing::WalkOptions::from_settings(&guard),
+            guard.processing,
+            guard.has_folder_override(),
+        )
+    };
+    // During a command line override the saved folders keep their SQLite rows,
+    // so only the photos of this run's folders are dropped
+    let clear_photos = || {
+        if folder_override {
+            for folder in &folder_paths {
+                let _ = db.remove_photos_under(folder);
+            }
+        } else {
+            let _ = db.clear_all_photos();
+        }
     };
+    // Startup processing is reported at /api/stats like runs started from the UI
+    let mut startup_run = None;
 
     if !folder_paths.is_empty() {
-        match db.load_from_disk(&folder_paths) {
-            Ok(true) => {
-                let count = db.get_photos_count().unwrap_or(0);
-                println!("✅ Loaded {} photos from cache (paths match)", count);
+        let to_process = if args.rescan {
+            println!("🔄 Rescan requested, ignoring caches");
+            clear_photos();
+            folder_paths.clone()
+        } else {
+            let loaded = if folder_override {
+                db.load_from_disk_keeping_others(&folder_paths, &walk_options)
+            } else {
+                db.load_from_disk(&folder_paths, &walk_options)
+            };
+            match loaded {
+                Ok(missing) => missing,
+                Err(e) => {
+                    eprintln!("⚠️ Failed to load cache: {}", e);
+                    clear_photos();
+                    folder_paths.clone()
+                }
             }
-            _ => {
-                println!(
-                    "🚀 Cache miss or mismatch. Processing {} folder(s)...",
-                    folder_paths.len()
-                );
-                let _ = db.clear_all_photos();
-
-                for folder_path in &folder_paths {
-                    let photos_path = Path::new(folder_path);
-                    if !photos_path.exists() {
-                        eprintln!("⚠️ Saved folder not found: {}", display_path(folder_path));
-                        continue;
+        };
+        let cached = folder_paths.len() - to_process.len();
+        if cached > 0 {
+            let count = db.get_photos_count().unwrap_or(0);
+            println!(
+                "✅ Loaded {} photos of {} folder(s) from cache",
+                count, cached
+            );
+        }
+        if !to_process.is_empty() {
+            println!(
+                "🚀 No valid cache for {} folder(s). Processing...",
+                to_process.len()
+            );
+            let started_at = unix_now();
+            let mut folder_stats = Vec::new();
+            let mut processed = Vec::new();
+
+            for folder_path in &to_process {
+                let photos_path = Path::new(folder_path);
+                if !photos_path.exists() {
+                    eprintln!("⚠️ Saved folder not found: {}", display_path(folder_path));
+                    continue;
+                }
+                println!("📂 Processing saved folder: {}", display_path(folder_path));
+                match processing::process_photos_with_stats(
+                    &db,
+                    photos_path,
+                    false,
+                    false,
+                    &walk_options,
+                    &processing_config,
+                    &cancel_processing,
+                    None,
+                ) {
+                    Ok(stats) => {
+                        processed.push(folder_path.clone());
+                        folder_stats.push(FolderStats {
+                            path: display_path(folder_path),
+                            stats,
+                        })
                     }
-                    println!("📂 Processing saved folder: {}", display_path(folder_path));
-                    if let Err(e) =
-                        processing::process_photos_with_stats(&db, photos_path, false, false)
-                    {
-                        eprintln!("⚠️ Error processing {}: {}", display_path(folder_path), e);
+                    Err(e) => {
+                        eprintln!("⚠️ Error processing {}: {}", display_path(folder_path), e)
                     }
                 }
+            }
+            startup_run = Some(LastRunStats::new(started_at, folder_stats, false));
 
-                let count = db.get_photos_count().unwrap_or(0);
-                println!("✅ Total photos in database: {}", count);
+            let count = db.get_photos_count().unwrap_or(0);
+            println!("✅ Total photos in database: {}", count);
 
-                if let Err(e) = db.save_to_disk(&folder_paths) {
-                    eprintln!("⚠️ Failed to save cache: {}", e);
-                } else {
-                    println!("💾 Cache saved successfully");
+            // The caches belong to the app; an export leaves them as they are
+            if args.export.is_none() {
+                match db.save_to_disk(&processed, &walk_options) {
+                    Ok(()) => println!("💾 Cache saved successfully"),
+                    Err(e) => eprintln!("⚠️ Failed to save cache: {}", e),
                 }
             }
         }
@@ -158,18 +237,36 @@ async fn main() -> Result<()> {
         println!("ℹ️ No saved folders found. Please select folders using the web interface");
     }
 
+    if let Some(ExportTarget::Geojson(path)) = &args.export {
+        let count = export::export_geojson_file(&db, path)?;
+        println!("📤 Exported {} photos to {}", count, path.display());
+        return Ok(());
+    }
+
     let app_state = AppState {
         db,
         settings: settings.clone(),
+        image_cache: ImageCache::new(settings.lock().await.image_cache_bytes()),
+        disk_cache: DiskThumbnailCache::new(utils::get_app_data_dir().join("thumbnails")),
+        cancel_processing,
+        processing: Default::default(),
+        folder_dialog: Default::default(),
+        last_run: Arc::new(std::sync::Mutex::new(startup_run)),
         event_sender,
         event_broadcast,
         shutdown_sender,
     };
 
+    server::warmup::spawn_thumbnail_warmup(app_state.clone());
+
     {
         let guard = settings.lock().await;
-        if guard.start_browser {
-            let url = format!("http://127.0.0.1:{}", port);
+        if guard.start_browser && !args.no_browser {
+            let mut url = format!("http://127.0.0.1:{}", port);
+            // With LAN access on, the API wants the token from this computer too
+            if guard.allow_lan {
+                url.push_str(&format!("/?token={}", guard.access_token));
+            }
             println!(" 🌐 Opening browser at {}", url);
             tokio::spawn(async move {
                 tokio::time::sleep(std::time::Duration::from_millis(500)).await;
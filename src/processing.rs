@@ -1,56 +1,227 @@
-use crate::constants::{is_heic_format, is_supported_image};
-use crate::database::{Database, PhotoMetadata};
+use crate::config::ProcessingConfig;
+use crate::constants::{is_supported_image, IGNORE_FILE_NAME, INSERT_BATCH_SIZE};
+use crate::database::{
+    Database, ExifSource, FileStamps, GpsQuality, NoGpsEntry, PhotoId, PhotoMetadata, UNKNOWN_DATE,
+};
 use crate::exif_parser::{
-    extract_metadata_from_heic, extract_metadata_from_jpeg, get_datetime_string, get_gps_coord,
+    detect_motion_photo, extract_metadata_from_sidecar, read_keywords, validate_gps_coordinate,
+    DefaultExifExtractor, ExifError, ExifExtractor, ExtractedMetadata,
+};
+use crate::geocoding::{get_place, location_name};
+use crate::image_processing::{
+    create_blurhash, extract_dominant_color, image_format_of, load_tiny_image, ImageFormat,
 };
+use crate::server::events::{ProcessingData, ProcessingEvent};
+use crate::settings::Settings;
+use crate::types::GpsCoordinate;
 use anyhow::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
 use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Builds the exclude matcher for a folder: global globs from settings first,
+/// then the folder's `.photomapignore`, so the file can re-include with `!`
+fn build_excludes(dir: &Path, exclude_globs: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    for glob in exclude_globs {
+        if let Err(e) = builder.add_line(None, glob) {
+            eprintln!("⚠️ Warning: Invalid exclude pattern '{}': {}", glob, e);
+        }
+    }
+
+    let ignore_file = dir.join(IGNORE_FILE_NAME);
+    if ignore_file.is_file() {
+        if let Some(e) = builder.add(&ignore_file) {
+            eprintln!(
+                "⚠️ Warning: Failed to read {}: {}",
+                ignore_file.display(),
+                e
+            );
+        }
+    }
+
+    builder.build().unwrap_or_else(|e| {
+        eprintln!("⚠️ Warning: Exclude patterns ignored: {}", e);
+        Gitignore::empty()
+    })
+}
+
+/// Matches directory names against the excluded directory globs at any level
+fn build_dir_excludes(dir: &Path, exclude_dirs: &[String]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(dir);
+    for glob in exclude_dirs {
+        // A trailing slash restricts gitignore patterns to directories
+        let pattern = format!("{}/", glob.trim_end_matches('/'));
+        if let Err(e) = builder.add_line(None, &pattern) {
+            eprintln!("⚠️ Warning: Invalid excluded directory '{}': {}", glob, e);
+        }
+    }
+    builder.build().unwrap_or_else(|e| {
+        eprintln!("⚠️ Warning: Excluded directories ignored: {}", e);
+        Gitignore::empty()
+    })
+}
+
+/// Outcome of processing one folder
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProcessingStats {
+    pub total_files: usize,
+    /// Photos with GPS inserted into the database
+    pub processed: usize,
+    pub no_gps: usize,
+    pub heic_files: usize,
+    /// Supported images found inside symlinked folders or through file symlinks
+    pub symlinked_files: usize,
+    pub duration_secs: f64,
+    /// Files that could not be read or parsed, as opposed to files without GPS
+    pub failed_files: Vec<FailedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailedFile {
+    pub path: String,
+    pub error: String,
+}
+
+/// How folders are scanned for photos
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+    /// Gitignore-style patterns applied in every folder
+    pub exclude_globs: Vec<String>,
+    /// Directory name globs skipped at any level
+    pub exclude_dirs: Vec<String>,
+    /// Descend into directories whose name starts with '.'
+    pub include_hidden: bool,
+    /// Subfolder levels to descend into; 0 scans only the folder itself
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self::from_settings(&Settings::default())
+    }
+}
+
+impl WalkOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            exclude_globs: settings.exclude_globs.clone(),
+            exclude_dirs: settings.exclude_dirs.clone(),
+            include_hidden: settings.include_hidden,
+            max_depth: settings.max_depth,
+            follow_symlinks: settings.follow_symlinks,
+        }
+    }
+}
+
+/// A file found by `walk_dir`
+#[derive(Debug, Clone)]
+struct WalkedFile {
+    path: PathBuf,
+    /// The file or one of its parent folders is a symlink
+    via_symlink: bool,
+}
+
+/// Modification time of every supported file in a folder, keyed by relative path.
+/// Its `FolderSignature` is compared with the cached one on startup.
+pub fn folder_file_stamps(dir: &Path, walk: &WalkOptions) -> FileStamps {
+    if !dir.is_dir() {
+        return FileStamps::new();
+    }
+    walk_dir(dir, walk)
+        .into_iter()
+        .filter(|file| is_supported_path(&file.path))
+        .map(|file| {
+            let mtime_ms = fs::metadata(&file.path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_millis() as u64);
+            (relative_path_of(&file.path, dir), mtime_ms)
+        })
+        .collect()
+}
+
+/// Recursively walks a directory collecting image files.
+/// When following symlinks, every folder is scanned once by its canonical path,
+/// so symlink cycles and several links to the same folder cannot repeat the walk.
+fn walk_dir(dir: &Path, options: &WalkOptions) -> Vec<WalkedFile> {
+    let excludes = build_excludes(dir, &options.exclude_globs);
+    let dir_excludes = build_dir_excludes(dir, &options.exclude_dirs);
+    let include_hidden = options.include_hidden;
+    let follow_symlinks = options.follow_symlinks;
+    let visited = Arc::new(Mutex::new(HashSet::new()));
+    if let Ok(root) = dir.canonicalize() {
+        visited.lock().unwrap().insert(root);
+    }
+    let mut builder = WalkBuilder::new(dir);
+    builder
+        // Only our own filters apply: no .gitignore, and hidden files are kept
+        .standard_filters(false)
+        .follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth.map(|depth| depth + 1))
+        .filter_entry(move |entry| {
+            if entry.depth() == 0 {
+                return true;
+            }
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            if is_dir {
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                if (hidden && !include_hidden)
+                    || dir_excludes.matched(entry.path(), true).is_ignore()
+                {
+                    return false;
+                }
+            }
+            if excludes.matched(entry.path(), is_dir).is_ignore() {
+                return false;
+            }
+            if is_dir && follow_symlinks {
+                if let Ok(canonical) = entry.path().canonicalize() {
+                    if !visited.lock().unwrap().insert(canonical) {
+                        println!(
+                            "🔁 Skipping already scanned folder: {}",
+                            native_path_string(entry.path())
+                        );
+                        return false;
+                    }
+                }
+            }
+            true
+        });
 
-/// Recursively walks a directory collecting image files
-fn walk_dir(dir: &Path) -> Vec<PathBuf> {
     let mut files = Vec::new();
-    let mut dirs_to_visit = vec![dir.to_path_buf()];
-
-    while let Some(current_dir) = dirs_to_visit.pop() {
-        match fs::read_dir(&current_dir) {
-            Ok(entries) => {
-                for entry_res in entries {
-                    match entry_res {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            if path.is_dir() {
-                                // Skip hidden directories and common ignore patterns
-                                if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                                    if !name.starts_with('.')
-                                        && name != "node_modules"
-                                        && name != "target"
-                                        && name != ".git"
-                                    {
-                                        dirs_to_visit.push(path);
-                                    }
-                                }
-                            } else if path.is_file() {
-                                files.push(path);
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!(
-                                "⚠️ Warning: Failed to read directory entry in {}: {}",
-                                current_dir.display(),
-                                e
-                            );
-                        }
+    // Walked paths of symlinked folders; parents are yielded before their contents
+    let mut symlinked_dirs: Vec<PathBuf> = Vec::new();
+    for entry in builder.build() {
+        match entry {
+            Ok(entry) => {
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                if is_dir {
+                    if entry.path_is_symlink() {
+                        symlinked_dirs.push(entry.into_path());
                     }
+                } else if entry.path().is_file() {
+                    let via_symlink = entry.path_is_symlink()
+                        || symlinked_dirs.iter().any(|d| entry.path().starts_with(d));
+                    files.push(WalkedFile {
+                        path: entry.into_path(),
+                        via_symlink,
+                    });
                 }
             }
             Err(e) => {
-                eprintln!(
-                    "⚠️ Warning: Failed to read directory {}: {}",
-                    current_dir.display(),
-                    e
-                );
+                tracing::warn!("Failed to read directory entry: {}", e);
             }
         }
     }
@@ -70,13 +241,26 @@ fn native_path_string(path: &Path) -> String {
 }
 
 /// Processes photos and saves metadata to the database
-/// Returns processing statistics: (total_files, processed_count, no_gps_count, heic_count)
+/// Setting `cancel` stops the run early; photos parsed so far are still inserted.
+/// Files matching the exclude globs or the folder's `.photomapignore` are skipped.
+/// Photos are inserted in batches while processing runs; a `photos_available`
+/// event is sent to `events` after each batch.
+/// Runs on a dedicated pool when `config` asks for a different thread count.
+#[allow(clippy::too_many_arguments)]
 pub fn process_photos_with_stats(
     db: &Database,
     photos_dir: &Path,
     silent_mode: bool,
     clear_database: bool,
-) -> Result<(usize, usize, usize, usize)> {
+    walk: &WalkOptions,
+    config: &ProcessingConfig,
+    cancel: &AtomicBool,
+    events: Option<&mpsc::Sender<ProcessingEvent>>,
+) -> Result<ProcessingStats> {
+    // Everything logged for this folder, from the worker threads too, carries its path
+    let folder_span = tracing::info_span!("folder", path = %native_path_string(photos_dir));
+    let _folder = folder_span.enter();
+
     if !silent_mode {
         println!(
             "🔍 Scanning photos directory: {}",
@@ -93,7 +277,7 @@ pub fn process_photos_with_stats(
             return Err(anyhow::Error::msg(error_msg));
         } else {
             eprintln!("{}", error_msg);
-            return Ok((0, 0, 0, 0));
+            return Ok(ProcessingStats::default());
         }
     }
 
@@ -116,7 +300,7 @@ pub fn process_photos_with_stats(
             return Err(anyhow::Error::msg(error_msg));
         } else {
             eprintln!("{}", error_msg);
-            return Ok((0, 0, 0, 0));
+            return Ok(ProcessingStats::default());
         }
     }
 
@@ -132,7 +316,21 @@ pub fn process_photos_with_stats(
     }
 
     // Collect all image files using custom walk function
-    let all_files = walk_dir(photos_dir);
+    let all_files = walk_dir(photos_dir, walk);
+    let symlinked_files = all_files
+        .iter()
+        .filter(|f| f.via_symlink && is_supported_path(&f.path))
+        .count();
+    let mut all_files: Vec<PathBuf> = all_files.into_iter().map(|f| f.path).collect();
+
+    // Files kept from the cache of a changed folder are not parsed again
+    let known = db.folder_relative_paths(&photos_dir.to_string_lossy())?;
+    if !known.is_empty() {
+        all_files.retain(|path| !known.contains(&relative_path_of(path, photos_dir)));
+        if !silent_mode {
+            println!("♻️ Keeping {} unchanged files from the cache", known.len());
+        }
+    }
 
     // Process files in parallel using Rayon with timing
     let start_time = std::time::Instant::now();
@@ -141,76 +339,130 @@ pub fn process_photos_with_stats(
         println!("📊 Starting parallel processing of files...");
     }
 
-    let reduction_result = all_files
-        .into_par_iter() // Rayon parallel iterator
-        .filter(|path| {
-            // Filter by extension - only process supported image formats
-            path.extension()
-                .and_then(|s| s.to_str())
-                .map(is_supported_image)
-                .unwrap_or(false)
-        })
-        .fold(
-            || (vec![], 0usize, 0usize), // Initial state for each thread: (photo_metadata_vec, total_files, heic_count)
-            |mut acc, path: PathBuf| {
-                acc.1 += 1; // Increment total_files
-
-                if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                    if matches!(ext.to_lowercase().as_str(), "heic" | "heif") {
-                        acc.2 += 1; // Increment heic_count
-                    }
-                }
+    // Workers hand parsed photos to one inserter thread, so the database fills
+    // up while processing runs instead of after the whole walk
+    let (photo_sender, photo_receiver) = sync_channel::<PhotoMetadata>(INSERT_BATCH_SIZE);
+    let worker_span = folder_span.clone();
+    let run = move || {
+        std::thread::scope(|scope| {
+            let inserter = scope.spawn(|| {
+                let _folder = worker_span.enter();
+                insert_in_batches(db, photo_receiver, events)
+            });
 
-                // Process file to metadata (don't insert yet)
-                match process_file_to_metadata(&path, photos_dir) {
-                    Ok(photo_metadata) => {
-                        acc.0.push(photo_metadata); // Collect successful metadata
-                    }
-                    Err(e) => {
-                        if let Some(crate::exif_parser::ExifError::GpsNotFound) =
-                            e.downcast_ref::<crate::exif_parser::ExifError>()
-                        {
-                            println!("ℹ️  Skipped {}: No GPS data", native_path_string(&path));
-                        } else {
-                            eprintln!(
-                                "Failed to process file {}: {}",
-                                native_path_string(&path),
-                                e
-                            );
+            let counts = all_files
+                .into_par_iter() // Rayon parallel iterator
+                // Filter by extension - only process supported image formats
+                .filter(|path| is_supported_path(path))
+                .fold(
+                    // Initial state for each thread:
+                    // (total_files, heic_count, failed_files, no_gps_files, keywords)
+                    || (0usize, 0usize, Vec::new(), Vec::new(), Vec::new()),
+                    |mut acc, path: PathBuf| {
+                        // Skip remaining files once cancelled; fold and reduce still complete normally
+                        if cancel.load(Ordering::Relaxed) {
+                            return acc;
                         }
-                    }
-                }
-                acc
-            },
-        )
-        .reduce(
-            || (vec![], 0usize, 0usize), // Initial state for reduction
-            |mut a, mut b| {
-                a.0.append(&mut b.0); // Combine photo_metadata vectors
-                a.1 += b.1; // Sum total_files
-                a.2 += b.2; // Sum heic_count
-                a
-            },
-        );
 
-    let (all_photos, total_files, heic_count) = reduction_result;
-    let mut successful_count = 0;
+                        acc.0 += 1; // Increment total_files
 
-    // Insert all photos into database at once
-    if !silent_mode {
-        println!("💾 Inserting {} photos into database...", all_photos.len());
+                        let _folder = worker_span.enter();
+                        let _file = tracing::debug_span!("file", path = %native_path_string(&path))
+                            .entered();
+
+                        if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
+                            if matches!(ext.to_lowercase().as_str(), "heic" | "heif") {
+                                acc.1 += 1; // Increment heic_count
+                            }
+                        }
+
+                        match process_file_to_metadata(
+                            &path,
+                            photos_dir,
+                            &DefaultExifExtractor,
+                            config,
+                        ) {
+                            Ok(photo_metadata) => {
+                                if config.import_keywords {
+                                    let keywords = read_keywords(&path);
+                                    if !keywords.is_empty() {
+                                        acc.4.push((photo_metadata.id.clone(), keywords));
+                                    }
+                                }
+                                // Only fails if the inserter is gone; its error is reported on join
+                                let _ = photo_sender.send(photo_metadata);
+                            }
+                            Err(e) => {
+                                let missing_gps = e
+                                    .downcast_ref::<ExifError>()
+                                    .is_some_and(|e| e.is_missing_gps());
+                                if missing_gps {
+                                    tracing::info!("Skipped: {}", e);
+                                    acc.3.push(NoGpsEntry {
+                                        path: native_path_string(&path),
+                                        relative_path: relative_path_of(&path, photos_dir),
+                                        reason: e.to_string(),
+                                    });
+                                } else {
+                                    tracing::warn!("Failed to process file: {}", e);
+                                    acc.2.push(FailedFile {
+                                        path: native_path_string(&path),
+                                        error: e.to_string(),
+                                    });
+                                }
+                            }
+                        }
+                        acc
+                    },
+                )
+                .reduce(
+                    || (0usize, 0usize, Vec::new(), Vec::new(), Vec::new()), // Initial state for reduction
+                    |mut a, mut b| {
+                        a.2.append(&mut b.2);
+                        a.3.append(&mut b.3);
+                        a.4.append(&mut b.4);
+                        (a.0 + b.0, a.1 + b.1, a.2, a.3, a.4)
+                    },
+                );
+
+            // Closing the channel lets the inserter flush its last batch and finish
+            drop(photo_sender);
+            let inserted = inserter.join().unwrap_or_else(|_| {
+                tracing::error!("Failed to insert photos: inserter thread panicked");
+                0
+            });
+            (inserted, counts)
+        })
+    };
+    let (successful_count, (total_files, heic_count, failed_files, no_gps_files, keywords)) =
+        match config.thread_pool() {
+            Some(pool) => pool.install(run),
+            None => run(),
+        };
+
+    if let Err(e) = db.add_no_gps_entries(no_gps_files) {
+        tracing::error!("Failed to record files without GPS: {}", e);
     }
 
-    match db.insert_photos_batch(&all_photos) {
-        Ok(inserted) => {
-            successful_count = inserted;
-            if !silent_mode {
-                println!("✅ Successfully inserted {} photos", inserted);
+    if !keywords.is_empty() {
+        match db.import_photo_tags(keywords) {
+            Ok(imported) if imported > 0 && !silent_mode => {
+                println!("🏷️ Tagged {} photos with their keywords", imported);
             }
+            Ok(_) => {}
+            Err(e) => tracing::error!("Failed to import keywords as tags: {}", e),
         }
-        Err(e) => {
-            eprintln!("Failed to insert photos: {}", e);
-        }
+    }
+
+    if cancel.load(Ordering::Relaxed) && !silent_mode {
+        println!(
+            "⏹️ Processing cancelled after {} files, keeping {} photos",
+            total_files, successful_count
+        );
+    }
+
+    if !silent_mode {
+        println!("✅ Successfully inserted {} photos", successful_count);
     }
 
     let processing_time = start_time.elapsed();
@@ -230,6 +482,9 @@ pub fn process_photos_with_stats(
         println!("   📸 Photos with GPS: {}", successful_count);
         println!("   ❌ Without GPS: {}", no_gps_count);
         println!("   📱 HEIC files: {}", heic_count);
+        if symlinked_files > 0 {
+            println!("   🔗 Reached via symlinks: {}", symlinked_files);
+        }
         println!(
             "   📷 JPEG/other: {}",
             successful_count.saturating_sub(heic_count)
@@ -249,25 +504,144 @@ pub fn process_photos_with_stats(
 
     // Note: Cache is saved manually by caller (main.rs) with all folder paths
 
-    Ok((total_files, successful_count, no_gps_count, heic_count))
+    Ok(ProcessingStats {
+        total_files,
+        processed: successful_count,
+        no_gps: no_gps_count,
+        heic_files: heic_count,
+        symlinked_files,
+        duration_secs: processing_secs,
+        failed_files,
+    })
+}
+
+fn is_supported_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map(is_supported_image)
+        .unwrap_or(false)
+}
+
+/// Inserts photos as workers produce them, `INSERT_BATCH_SIZE` at a time.
+/// Returns the number of photos inserted.
+fn insert_in_batches(
+    db: &Database,
+    receiver: Receiver<PhotoMetadata>,
+    events: Option<&mpsc::Sender<ProcessingEvent>>,
+) -> usize {
+    let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+    let mut inserted = 0;
+
+    for photo in receiver {
+        batch.push(photo);
+        if batch.len() >= INSERT_BATCH_SIZE {
+            inserted += flush_batch(db, &mut batch, inserted, events);
+        }
+    }
+    if !batch.is_empty() {
+        inserted += flush_batch(db, &mut batch, inserted, events);
+    }
+    inserted
+}
+
+fn flush_batch(
+    db: &Database,
+    batch: &mut Vec<PhotoMetadata>,
+    inserted_before: usize,
+    events: Option<&mpsc::Sender<ProcessingEvent>>,
+) -> usize {
+    let inserted = match db.insert_photos_batch(batch) {
+        Ok(inserted) => inserted,
+        Err(e) => {
+            tracing::error!("Failed to insert photos: {}", e);
+            0
+        }
+    };
+    batch.clear();
+
+    if let Some(events) = events.filter(|_| inserted > 0) {
+        let total = inserted_before + inserted;
+        // Progress is best effort; never stall processing on a full channel
+        let _ = events.try_send(ProcessingEvent {
+            event_type: "photos_available".to_string(),
+            data: ProcessingData {
+                processed: Some(total),
+                gps_found: Some(total),
+                message: Some(format!("{} photos on the map", total)),
+                phase: Some("processing".to_string()),
+                ..Default::default()
+            },
+        });
+    }
+    inserted
 }
 
 /// Processes photos from the specified folder and sends progress events
 pub fn process_photos_from_directory(
     db: &Database,
     photos_dir: &Path,
-) -> Result<(usize, usize, usize, usize)> {
+    walk: &WalkOptions,
+    config: &ProcessingConfig,
+    cancel: &AtomicBool,
+    events: Option<&mpsc::Sender<ProcessingEvent>>,
+) -> Result<ProcessingStats> {
     println!(
         "🔍 Processing photos from directory: {}",
         native_path_string(photos_dir)
     );
 
-    // Use the new combined function, but without silent_mode
-    process_photos_with_stats(db, photos_dir, false, true)
+    // Photos of the other folders and of unchanged files stay in the database
+    process_photos_with_stats(db, photos_dir, false, false, walk, config, cancel, events)
 }
 
 /// Processes a single file and returns PhotoMetadata (without inserting to DB)
-fn process_file_to_metadata(path: &Path, photos_dir: &Path) -> Result<PhotoMetadata> {
+/// Retries after a transient I/O error, as network mounts time out now and then
+const IO_RETRIES: u32 = 3;
+/// Pause before the first retry, doubled before each next one
+const IO_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Runs `op` again after a short pause while it fails with a transient I/O
+/// error; any other error, like a file that is no image, is returned at once
+fn retry_io<T>(mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut backoff = IO_RETRY_BACKOFF;
+    for _ in 0..IO_RETRIES {
+        match op() {
+            Err(e) if is_transient_io(&e) => {
+                tracing::debug!("Retrying in {:?} after {}", backoff, e);
+                std::thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    op()
+}
+
+/// Whether an error comes from an I/O failure that may pass on its own
+fn is_transient_io(error: &anyhow::Error) -> bool {
+    use std::io::ErrorKind;
+    error.chain().any(|cause| {
+        cause.downcast_ref::<std::io::Error>().is_some_and(|e| {
+            matches!(
+                e.kind(),
+                ErrorKind::Interrupted
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::TimedOut
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::NotConnected
+                    | ErrorKind::ResourceBusy
+            )
+        })
+    })
+}
+
+fn process_file_to_metadata(
+    path: &Path,
+    photos_dir: &Path,
+    extractor: &dyn ExifExtractor,
+    config: &ProcessingConfig,
+) -> Result<PhotoMetadata> {
     // Check the file extension, saving it in lowercase for checks
     let ext_lower = path
         .extension()
@@ -280,38 +654,41 @@ fn process_file_to_metadata(path: &Path, photos_dir: &Path) -> Result<PhotoMetad
         anyhow::bail!("File is not a supported image");
     }
 
-    // Check if it's HEIC/HEIF format
-    let is_heif = is_heic_format(&ext_lower);
+    // The content decides the format, as some cameras save JPEGs with a .heic name
+    let format = image_format_of(path);
+    let is_heif = format.is_some_and(ImageFormat::is_heif);
 
     // --- GPS and date extraction ---
-    let (lat, lng, datetime_opt) = if is_heif {
-        // Try to extract metadata from HEIC
-        extract_metadata_from_heic(path)?
-    } else {
-        // For standard formats, use our parsers
-        if ext_lower == "jpg" || ext_lower == "jpeg" {
-            // Use our own JPEG parser
-            extract_metadata_from_jpeg(path)?
-        } else {
-            // Fallback for other formats with EXIF
-            let file = fs::File::open(path)?;
-            let mut bufreader = std::io::BufReader::new(&file);
-            let exifreader = exif::Reader::new();
-            let exif = exifreader.read_from_container(&mut bufreader)?;
-
-            let lat = get_gps_coord(&exif, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef)?;
-            let lng = get_gps_coord(&exif, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef)?;
-            let datetime = get_datetime_string(&exif);
-
-            if lat.is_none() || lng.is_none() {
-                return Err(crate::exif_parser::ExifError::GpsNotFound.into());
-            }
-
-            (lat.unwrap(), lng.unwrap(), datetime)
+    // Fall back to sidecar files when the image itself has no usable metadata
+    let (metadata, source) = match retry_io(|| extractor.extract(path)) {
+        Ok(metadata) => (metadata, ExifSource::Exif),
+        Err(e) => {
+            let (lat, lng, datetime, source) =
+                extract_metadata_from_sidecar(path).map_err(|_| e)?;
+            (ExtractedMetadata::new(lat, lng, datetime), source)
         }
     };
 
-    let datetime_str = datetime_opt.unwrap_or_else(|| "Unknown Date".to_string());
+    // Positions written by receivers without a fix are listed with the files without GPS
+    let validation = validate_gps_coordinate(metadata.lat, metadata.lng).with_dop(metadata.gps_dop);
+    if validation.quality == GpsQuality::Suspicious && !config.include_suspicious_gps {
+        return Err(ExifError::GpsSuspicious(validation.issues.join(", ")).into());
+    }
+
+    // Out-of-range values from a parser are reported like any other unusable GPS data
+    let coords = GpsCoordinate::new(metadata.lat, metadata.lng)
+        .map_err(|e| ExifError::GpsUnparseable(e.to_string()))?;
+
+    let datetime_str = metadata
+        .datetime
+        .unwrap_or_else(|| UNKNOWN_DATE.to_string());
+
+    // Samsung/Google motion photos carry an MP4 after the JPEG data. The EXIF reader
+    // checks the bytes it already read; only sidecar positions need another look.
+    let is_motion_photo = match source {
+        ExifSource::Exif => metadata.is_motion_photo,
+        _ => format == Some(ImageFormat::Jpeg) && detect_motion_photo(path),
+    };
 
     // --- Create a database record ---
     let filename = path
@@ -319,27 +696,238 @@ fn process_file_to_metadata(path: &Path, photos_dir: &Path) -> Result<PhotoMetad
         .and_then(|n| n.to_str())
         .ok_or_else(|| anyhow::Error::msg("Invalid file name"))?;
 
-    // Generate relative path from photos directory
-    let relative_path = path
-        .strip_prefix(photos_dir)
-        .map(|p| p.to_string_lossy().replace('\\', "/"))
-        .unwrap_or_else(|_| filename.to_string());
+    let relative_path = relative_path_of(path, photos_dir);
+    let (blurhash, dominant_color) = previews_of(path);
+    let place = get_place(coords.lat(), coords.lng());
+    let file_size = fs::metadata(path).map_or(0, |m| m.len());
 
     Ok(PhotoMetadata {
+        id: PhotoId::from_relative_path(&relative_path),
         filename: filename.to_string(),
         relative_path,
         datetime: datetime_str,
-        lat,
-        lng,
+        coords,
         file_path: native_path_string(path),
         is_heic: is_heif,
+        is_motion_photo,
+        source,
+        speed_kmh: metadata.speed_kmh,
+        gps_dop: metadata.gps_dop,
+        direction: metadata.direction,
+        blurhash,
+        location: place.as_ref().map(location_name),
+        place,
+        dominant_color,
+        file_size,
+        gps_quality: validation.quality,
     })
 }
 
+/// Path of a photo relative to its folder, with '/' separators
+fn relative_path_of(path: &Path, photos_dir: &Path) -> String {
+    path.strip_prefix(photos_dir)
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_else(|_| {
+            path.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+}
+
+/// BlurHash and dominant colour of a photo, computed from one tiny decode
+fn previews_of(path: &Path) -> (Option<String>, Option<[u8; 3]>) {
+    match load_tiny_image(path) {
+        Ok(tiny) => (
+            create_blurhash(&tiny).ok(),
+            Some(extract_dominant_color(&tiny)),
+        ),
+        Err(_) => (None, None),
+    }
+}
+
+/// Builds the record of a file from the no-GPS list once it is geotagged by hand
+pub fn manually_geotagged_photo(entry: &NoGpsEntry, coords: GpsCoordinate) -> PhotoMetadata {
+    let path = Path::new(&entry.path);
+    let format = image_format_of(path);
+
+    // The capture time may still be there even though the GPS tags were not
+    let (blurhash, dominant_color) = previews_of(path);
+    let place = get_place(coords.lat(), coords.lng());
+    let datetime = fs::File::open(path)
+        .ok()
+        .and_then(|file| {
+            exif::Reader::new()
+                .read_from_container(&mut std::io::BufReader::new(file))
+                .ok()
+        })
+        .and_then(|exif| crate::exif_parser::get_datetime_string(&exif))
+        .unwrap_or_else(|| UNKNOWN_DATE.to_string());
+
+    PhotoMetadata {
+        id: PhotoId::from_relative_path(&entry.relative_path),
+        filename: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        relative_path: entry.relative_path.clone(),
+        datetime,
+        coords,
+        file_path: entry.path.clone(),
+        is_heic: format.is_some_and(ImageFormat::is_heif),
+        is_motion_photo: format == Some(ImageFormat::Jpeg) && detect_motion_photo(path),
+        source: ExifSource::Manual,
+        speed_kmh: None,
+        gps_dop: None,
+        direction: None,
+        blurhash,
+        location: place.as_ref().map(location_name),
+        place,
+        dominant_color,
+        file_size: fs::metadata(path).map_or(0, |m| m.len()),
+        // Set by hand, so as good as the user's aim
+        gps_quality: GpsQuality::High,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::native_path_string;
+    use super::{
+        native_path_string, process_file_to_metadata, process_photos_with_stats, retry_io,
+        walk_dir, WalkOptions,
+    };
+    use crate::config::ProcessingConfig;
+    use crate::constants::INSERT_BATCH_SIZE;
+    use crate::database::{Database, ExifSource, GpsQuality};
+    use crate::exif_parser::{DefaultExifExtractor, ExifError, ExifExtractor, ExtractedMetadata};
+    use anyhow::Result;
     use std::path::Path;
+    use std::sync::atomic::AtomicBool;
+
+    /// Returns fixed metadata without reading the file
+    struct MockExifExtractor(f64, f64, Option<&'static str>);
+
+    impl ExifExtractor for MockExifExtractor {
+        fn extract(&self, _path: &Path) -> Result<ExtractedMetadata> {
+            Ok(ExtractedMetadata::new(
+                self.0,
+                self.1,
+                self.2.map(String::from),
+            ))
+        }
+    }
+
+    #[test]
+    fn builds_records_from_extracted_metadata() {
+        let dir = Path::new("/library");
+        let extractor = MockExifExtractor(59.93, 30.31, Some("2023-07-01 10:00:00"));
+        let config = ProcessingConfig::default();
+        let photo = process_file_to_metadata(&dir.join("trip/IMG_1.JPG"), dir, &extractor, &config)
+            .unwrap();
+        assert_eq!(photo.relative_path, "trip/IMG_1.JPG");
+        assert_eq!(photo.filename, "IMG_1.JPG");
+        assert_eq!((photo.coords.lat(), photo.coords.lng()), (59.93, 30.31));
+        assert_eq!(photo.datetime, "2023-07-01 10:00:00");
+        assert_eq!(photo.source, ExifSource::Exif);
+        assert!(!photo.is_heic);
+
+        let undated = MockExifExtractor(59.93, 30.31, None);
+        let heic =
+            process_file_to_metadata(&dir.join("IMG_2.heic"), dir, &undated, &config).unwrap();
+        assert_eq!(heic.datetime, "Unknown Date");
+        assert!(heic.is_heic);
+
+        let db = Database::new().unwrap();
+        db.insert_photos_batch(&[photo.clone(), photo, heic])
+            .unwrap();
+        assert_eq!(db.get_photos_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn transient_io_errors_are_retried() {
+        let mut calls = 0;
+        let result = retry_io(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(ExifError::Io(std::io::ErrorKind::TimedOut.into()).into())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // A file that is no image fails on the first try
+        let mut calls = 0;
+        let result: Result<()> = retry_io(|| {
+            calls += 1;
+            Err(ExifError::GpsNotFound.into())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        // Errors that do not pass are returned after the last retry
+        let mut calls = 0;
+        let result: Result<()> = retry_io(|| {
+            calls += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::Interrupted).into())
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 4);
+    }
+
+    #[test]
+    fn out_of_range_coordinates_are_unparseable_gps() {
+        let dir = Path::new("/library");
+        let err = process_file_to_metadata(
+            &dir.join("IMG_3.avif"),
+            dir,
+            &MockExifExtractor(91.0, 0.0, None),
+            &ProcessingConfig::default(),
+        )
+        .unwrap_err();
+        assert!(err
+            .downcast_ref::<ExifError>()
+            .is_some_and(|e| e.is_missing_gps()));
+        assert!(process_file_to_metadata(
+            &dir.join("notes.txt"),
+            dir,
+            &MockExifExtractor(0.0, 0.0, None),
+            &ProcessingConfig::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn suspicious_gps_is_skipped_unless_included() {
+        let dir = Path::new("/library");
+        let null_island = MockExifExtractor(0.0, 0.0, None);
+        let err = process_file_to_metadata(
+            &dir.join("IMG_4.jpg"),
+            dir,
+            &null_island,
+            &ProcessingConfig::default(),
+        )
+        .unwrap_err();
+        let err = err.downcast_ref::<ExifError>().unwrap();
+        assert!(matches!(err, ExifError::GpsSuspicious(_)) && err.is_missing_gps());
+
+        let include = ProcessingConfig {
+            include_suspicious_gps: true,
+            ..ProcessingConfig::default()
+        };
+        let photo =
+            process_file_to_metadata(&dir.join("IMG_4.jpg"), dir, &null_island, &include).unwrap();
+        assert_eq!(photo.gps_quality, GpsQuality::Suspicious);
+
+        let whole_degrees = MockExifExtractor(52.0, 13.0, None);
+        let photo = process_file_to_metadata(
+            &dir.join("IMG_5.jpg"),
+            dir,
+            &whole_degrees,
+            &ProcessingConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(photo.gps_quality, GpsQuality::Low);
+    }
 
     #[test]
     fn native_path_string_repairs_mixed_windows_paths() {
@@ -351,4 +939,457 @@ mod tests {
         #[cfg(not(windows))]
         assert_eq!(path, "D:/Photo\\Nested/image.jpg");
     }
+
+    #[test]
+    fn falls_back_to_takeout_sidecar_without_exif_gps() {
+        let dir = std::env::temp_dir().join("photomap_test_takeout_processing");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let config = ProcessingConfig::default();
+        let photo = dir.join("Screenshot_20220514.jpg");
+        image::RgbImage::from_pixel(16, 16, image::Rgb([0, 0, 0]))
+            .save(&photo)
+            .unwrap();
+        assert!(process_file_to_metadata(&photo, &dir, &DefaultExifExtractor, &config).is_err());
+
+        std::fs::write(
+            dir.join("Screenshot_20220514.jpg.json"),
+            r#"{"title": "Screenshot_20220514.jpg",
+                "photoTakenTime": {"timestamp": "1652529600", "formatted": "14 May 2022, 12:00:00 UTC"},
+                "geoDataExif": {"latitude": 52.3676, "longitude": 4.9041, "altitude": 0.0}}"#,
+        )
+        .unwrap();
+
+        let metadata =
+            process_file_to_metadata(&photo, &dir, &DefaultExifExtractor, &config).unwrap();
+        assert_eq!(
+            (metadata.coords.lat(), metadata.coords.lng()),
+            (52.3676, 4.9041)
+        );
+        assert_eq!(metadata.datetime, "2022-05-14 12:00:00");
+        assert_eq!(metadata.source, ExifSource::GoogleTakeoutJson);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cancelled_run_skips_remaining_files() {
+        let dir = std::env::temp_dir().join("photomap_test_cancelled_run");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..4 {
+            let photo = dir.join(format!("photo{i}.jpg"));
+            image::RgbImage::from_pixel(16, 16, image::Rgb([0, 0, 0]))
+                .save(&photo)
+                .unwrap();
+            std::fs::write(
+                dir.join(format!("photo{i}.jpg.json")),
+                r#"{"geoDataExif": {"latitude": 52.3676, "longitude": 4.9041}}"#,
+            )
+            .unwrap();
+        }
+
+        let db = Database::new().unwrap();
+        let cancelled = AtomicBool::new(true);
+        let stats = process_photos_with_stats(
+            &db,
+            &dir,
+            true,
+            false,
+            &WalkOptions::default(),
+            &ProcessingConfig::default(),
+            &cancelled,
+            None,
+        )
+        .unwrap();
+        assert_eq!((stats.total_files, stats.processed), (0, 0));
+        assert_eq!(db.get_photos_count().unwrap(), 0);
+
+        let running = AtomicBool::new(false);
+        let stats = process_photos_with_stats(
+            &db,
+            &dir,
+            true,
+            false,
+            &WalkOptions::default(),
+            &ProcessingConfig::default(),
+            &running,
+            None,
+        )
+        .unwrap();
+        assert_eq!(stats.processed, 4);
+        assert_eq!(db.get_photos_count().unwrap(), 4);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cancelling_mid_run_keeps_only_processed_photos() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(16, 16, image::Rgb([0, 0, 0]))
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        let total = 3000;
+        for i in 0..total {
+            std::fs::write(dir.join(format!("photo{i}.jpg")), jpeg.get_ref()).unwrap();
+            std::fs::write(
+                dir.join(format!("photo{i}.jpg.json")),
+                r#"{"geoDataExif": {"latitude": 52.3676, "longitude": 4.9041}}"#,
+            )
+            .unwrap();
+        }
+
+        // One worker keeps the run slow enough to be interrupted
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let db = Database::new().unwrap();
+        let cancel = AtomicBool::new(false);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let stats = std::thread::scope(|scope| {
+            // Cancel on the progress event of the first inserted batch
+            scope.spawn(|| {
+                if rx.blocking_recv().is_some() {
+                    cancel.store(true, std::sync::atomic::Ordering::SeqCst);
+                }
+            });
+            let stats = pool.install(|| {
+                process_photos_with_stats(
+                    &db,
+                    dir,
+                    true,
+                    false,
+                    &WalkOptions::default(),
+                    &ProcessingConfig::default(),
+                    &cancel,
+                    Some(&tx),
+                )
+                .unwrap()
+            });
+            // Lets the listener finish if no event was sent
+            drop(tx);
+            stats
+        });
+
+        assert!(stats.processed >= INSERT_BATCH_SIZE);
+        assert!(stats.total_files < total, "run was not interrupted");
+        assert_eq!(db.get_photos_count().unwrap(), stats.processed);
+    }
+
+    #[test]
+    fn walk_respects_photomapignore_and_global_globs() {
+        let dir = std::env::temp_dir().join("photomap_test_photomapignore");
+        let _ = std::fs::remove_dir_all(&dir);
+        for sub in ["Screenshots", "archive/keep", "archive/old", "trips"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in [
+            "a.jpg",
+            "a.edited.jpg",
+            "Screenshots/s.jpg",
+            "archive/keep/k.jpg",
+            "archive/old/o.jpg",
+            "trips/t.jpg",
+            "trips/t.heic",
+        ] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        std::fs::write(
+            dir.join(".photomapignore"),
+            "Screenshots/\n*.edited.jpg\narchive/*\n!keep/\n",
+        )
+        .unwrap();
+
+        let options = WalkOptions {
+            exclude_globs: vec!["*.heic".to_string()],
+            ..Default::default()
+        };
+        let mut found: Vec<String> = walk_dir(&dir, &options)
+            .iter()
+            .map(|f| {
+                f.path
+                    .strip_prefix(&dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        found.sort();
+
+        assert_eq!(
+            found,
+            vec![
+                ".photomapignore",
+                "a.jpg",
+                "archive/keep/k.jpg",
+                "trips/t.jpg"
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn hidden_and_excluded_dirs_follow_options() {
+        let dir = std::env::temp_dir().join("photomap_test_hidden_dirs");
+        let _ = std::fs::remove_dir_all(&dir);
+        for sub in [
+            ".sync/2024",
+            "Screenshots",
+            "trips/Screenshots",
+            "node_modules",
+        ] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in [
+            "a.jpg",
+            ".sync/2024/synced.jpg",
+            "Screenshots/s1.jpg",
+            "trips/Screenshots/s2.jpg",
+            "trips/t.jpg",
+            "node_modules/n.jpg",
+        ] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+
+        // Defaults skip dot-directories and node_modules like before
+        assert_eq!(
+            walked_names(&dir, &WalkOptions::default()),
+            vec!["a.jpg", "s1.jpg", "s2.jpg", "t.jpg"]
+        );
+
+        let options = WalkOptions {
+            exclude_dirs: vec!["Screen*".to_string()],
+            include_hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            walked_names(&dir, &options),
+            vec!["a.jpg", "n.jpg", "synced.jpg", "t.jpg"]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn walked_names(dir: &std::path::Path, options: &WalkOptions) -> Vec<String> {
+        let mut names: Vec<String> = walk_dir(dir, options)
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn max_depth_limits_subfolder_levels() {
+        let dir = std::env::temp_dir().join("photomap_test_max_depth");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("2024/june")).unwrap();
+        for file in ["top.jpg", "2024/year.jpg", "2024/june/day.jpg"] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+
+        let depth = |max_depth| WalkOptions {
+            max_depth,
+            ..Default::default()
+        };
+        assert_eq!(walked_names(&dir, &depth(Some(0))), vec!["top.jpg"]);
+        assert_eq!(
+            walked_names(&dir, &depth(Some(1))),
+            vec!["top.jpg", "year.jpg"]
+        );
+        assert_eq!(
+            walked_names(&dir, &depth(None)),
+            vec!["day.jpg", "top.jpg", "year.jpg"]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinked_folders_without_looping() {
+        let base = std::env::temp_dir().join("photomap_test_symlinks");
+        let _ = std::fs::remove_dir_all(&base);
+        let library = base.join("library");
+        let external = base.join("external/2019");
+        std::fs::create_dir_all(&library).unwrap();
+        std::fs::create_dir_all(&external).unwrap();
+        std::fs::write(library.join("local.jpg"), b"").unwrap();
+        std::fs::write(external.join("linked.jpg"), b"").unwrap();
+        std::os::unix::fs::symlink(&external, library.join("2019")).unwrap();
+        // A cycle back to the library root must not hang the walk
+        std::os::unix::fs::symlink(&library, external.join("back")).unwrap();
+        // A second link to the same folder must not scan it twice
+        std::os::unix::fs::symlink(&external, library.join("2019 again")).unwrap();
+
+        let follow = |follow_symlinks| WalkOptions {
+            follow_symlinks,
+            ..Default::default()
+        };
+        assert_eq!(
+            walked_names(&library, &follow(true)),
+            vec!["linked.jpg", "local.jpg"]
+        );
+        assert_eq!(walked_names(&library, &follow(false)), vec!["local.jpg"]);
+
+        let stats = process_photos_with_stats(
+            &Database::new().unwrap(),
+            &library,
+            true,
+            false,
+            &follow(true),
+            &ProcessingConfig::default(),
+            &AtomicBool::new(false),
+            None,
+        )
+        .unwrap();
+        assert_eq!((stats.total_files, stats.symlinked_files), (2, 1));
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn records_files_without_gps_with_reason() {
+        let dir = std::env::temp_dir().join("photomap_test_no_gps_list");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(8, 8, image::Rgb([0, 0, 0]))
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        std::fs::write(dir.join("plain.jpg"), jpeg.get_ref()).unwrap();
+
+        let db = Database::new().unwrap();
+        let stats = process_photos_with_stats(
+            &db,
+            &dir,
+            true,
+            true,
+            &WalkOptions::default(),
+            &ProcessingConfig::default(),
+            &AtomicBool::new(false),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!((stats.total_files, stats.no_gps), (1, 1));
+        let entries = db.get_no_gps_entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].path.ends_with("plain.jpg"));
+        assert_eq!(entries[0].reason, "GPS data not found");
+
+        db.clear_all_photos().unwrap();
+        assert!(db.get_no_gps_entries().unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn inserts_photos_in_batches_while_processing() {
+        let dir = std::env::temp_dir().join("photomap_test_batched_inserts");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(16, 16, image::Rgb([0, 0, 0]))
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        let total = 2000;
+        for i in 0..total {
+            std::fs::write(dir.join(format!("photo{i}.jpg")), jpeg.get_ref()).unwrap();
+            std::fs::write(
+                dir.join(format!("photo{i}.jpg.json")),
+                r#"{"geoDataExif": {"latitude": 52.3676, "longitude": 4.9041}}"#,
+            )
+            .unwrap();
+        }
+
+        let db = Database::new().unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+        let stats = process_photos_with_stats(
+            &db,
+            &dir,
+            true,
+            false,
+            &WalkOptions::default(),
+            &ProcessingConfig::default(),
+            &AtomicBool::new(false),
+            Some(&tx),
+        )
+        .unwrap();
+
+        assert_eq!(
+            (
+                stats.total_files,
+                stats.processed,
+                stats.no_gps,
+                stats.heic_files
+            ),
+            (total, total, 0, 0)
+        );
+        assert!(stats.failed_files.is_empty());
+        assert_eq!(db.get_photos_count().unwrap(), total);
+
+        let mut flushed = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            assert_eq!(event.event_type, "photos_available");
+            flushed.push(event.data.processed.unwrap());
+        }
+        assert!(flushed.len() >= 2, "expected intermediate flushes");
+        assert!(flushed.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(flushed.last(), Some(&total));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn one_worker_thread_inserts_the_same_photos() {
+        let dir = std::env::temp_dir().join("photomap_test_worker_threads");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(8, 8, image::Rgb([0, 0, 0]))
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        let total = 200;
+        for i in 0..total {
+            std::fs::write(dir.join(format!("photo{i}.jpg")), jpeg.get_ref()).unwrap();
+            std::fs::write(
+                dir.join(format!("photo{i}.jpg.json")),
+                r#"{"geoDataExif": {"latitude": 52.3676, "longitude": 4.9041}}"#,
+            )
+            .unwrap();
+        }
+
+        for rayon_threads in [None, Some(1)] {
+            let db = Database::new().unwrap();
+            let config = ProcessingConfig {
+                rayon_threads,
+                ..Default::default()
+            };
+            let stats = process_photos_with_stats(
+                &db,
+                &dir,
+                true,
+                false,
+                &WalkOptions::default(),
+                &config,
+                &AtomicBool::new(false),
+                None,
+            )
+            .unwrap();
+            assert_eq!((stats.total_files, stats.processed), (total, total));
+            assert_eq!(db.get_photos_count().unwrap(), total, "{rayon_threads:?}");
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,93 @@
+//! Correlation ids and the access log. Every request gets an `x-request-id`
+//! (a UUID unless the client sent one), which is echoed in the response and
+//! recorded on the request's span, so errors logged while handling it carry
+//! the id. With `access_log` set, each response is logged with its status,
+//! latency and size.
+
+use std::time::Duration;
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{Request, Response},
+    Router,
+};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+
+/// Adds request ids and the request span to every route of `router`
+pub fn with_request_ids<S>(router: Router<S>, access_log: bool) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let trace = TraceLayer::new_for_http()
+        .make_span_with(|request: &Request<Body>| {
+            let request_id = request
+                .headers()
+                .get("x-request-id")
+                .and_then(|id| id.to_str().ok())
+                .unwrap_or("-");
+            tracing::info_span!(
+                "request",
+                request_id = %request_id,
+                method = %request.method(),
+                uri = %request.uri(),
+            )
+        })
+        .on_response(
+            move |response: &Response<Body>, latency: Duration, _span: &Span| {
+                if !access_log {
+                    return;
+                }
+                // Streamed bodies have no known size
+                let bytes = response
+                    .body()
+                    .size_hint()
+                    .exact()
+                    .map_or_else(|| "-".to_string(), |len| len.to_string());
+                tracing::info!(
+                    status = response.status().as_u16(),
+                    latency_ms = latency.as_millis() as u64,
+                    bytes = %bytes,
+                    "response"
+                );
+            },
+        );
+
+    // The last layer runs first: the id is set before the span is made from it
+    router
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(trace)
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::with_request_ids;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn request_id(sent: Option<&str>) -> String {
+        let app = with_request_ids(Router::new().route("/health", get(|| async { "ok" })), true);
+        let mut request = Request::builder().uri("/health");
+        if let Some(id) = sent {
+            request = request.header("x-request-id", id);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn responses_carry_a_request_id() {
+        let generated = request_id(None).await;
+        assert_eq!(generated.len(), 36, "not a UUID: {generated}");
+        assert_ne!(generated, request_id(None).await);
+        assert_eq!(request_id(Some("client-42")).await, "client-42");
+    }
+}
@@ -0,0 +1,192 @@
+//! Token check for LAN access. With `allow_lan` the server listens on all
+//! interfaces, so every request to the API, the originals and the HEIC
+//! converter must carry `access_token` as a bearer token, a `?token=` query
+//! parameter or the cookie the page sets from it. The page and its static
+//! assets stay public, so a phone can load the app from the printed URL.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+
+/// Cookie the frontend stores the `?token=` of its URL in
+pub const TOKEN_COOKIE: &str = "photomap_token";
+
+/// Requires `token` on the protected routes of `router`; None leaves it open
+pub fn require_token<S>(router: Router<S>, token: Option<String>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    match token {
+        Some(token) => router.layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            check_token,
+        )),
+        None => router,
+    }
+}
+
+fn is_protected(path: &str) -> bool {
+    path == "/api"
+        || path.starts_with("/api/")
+        || path.starts_with("/photos/")
+        || path == "/convert-heic"
+}
+
+async fn check_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    if !is_protected(request.uri().path()) {
+        return next.run(request).await;
+    }
+    match request_token(&request) {
+        Some(sent) if constant_time_eq(sent.as_bytes(), token.as_bytes()) => {
+            next.run(request).await
+        }
+        sent => {
+            let message = if sent.is_some() {
+                "Invalid access token"
+            } else {
+                "Access token required"
+            };
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(serde_json::json!({ "status": "error", "message": message })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Token of a request from the Authorization header, the query or the cookie
+fn request_token(request: &Request) -> Option<String> {
+    let headers = request.headers();
+    if let Some(bearer) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(bearer.trim().to_string());
+    }
+
+    if let Ok(Query(mut query)) = Query::<HashMap<String, String>>::try_from_uri(request.uri()) {
+        if let Some(token) = query.remove("token") {
+            return Some(token);
+        }
+    }
+
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == TOKEN_COOKIE).then(|| value.to_string())
+        })
+}
+
+/// Compares without stopping at the first difference, so response times do
+/// not tell how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{require_token, TOKEN_COOKIE};
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    fn test_app(token: Option<&str>) -> Router {
+        let router = Router::new()
+            .route("/", get(|| async { "page" }))
+            .route("/script.js", get(|| async { "script" }))
+            .route("/api/photos", get(|| async { "[]" }))
+            .route("/photos/*filepath", get(|| async { "original" }));
+        require_token(router, token.map(str::to_string))
+    }
+
+    async fn status(app: Router, request: Request<Body>) -> StatusCode {
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    fn get_request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn missing_and_wrong_tokens_are_rejected() {
+        for uri in ["/api/photos", "/photos/2024/IMG_1.jpg"] {
+            assert_eq!(
+                status(test_app(Some("secret")), get_request(uri)).await,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        let wrong_bearer = Request::builder()
+            .uri("/api/photos")
+            .header(header::AUTHORIZATION, "Bearer secreT")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            status(test_app(Some("secret")), wrong_bearer).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(
+                test_app(Some("secret")),
+                get_request("/api/photos?token=secrets")
+            )
+            .await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn correct_token_is_accepted_in_any_form() {
+        let bearer = Request::builder()
+            .uri("/api/photos")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        let cookie = Request::builder()
+            .uri("/photos/2024/IMG_1.jpg")
+            .header(header::COOKIE, format!("theme=dark; {TOKEN_COOKIE}=secret"))
+            .body(Body::empty())
+            .unwrap();
+        for request in [
+            bearer,
+            cookie,
+            get_request("/api/photos?year=2024&token=secret"),
+        ] {
+            assert_eq!(
+                status(test_app(Some("secret")), request).await,
+                StatusCode::OK
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn page_is_public_and_no_token_leaves_everything_open() {
+        for uri in ["/", "/script.js"] {
+            assert_eq!(
+                status(test_app(Some("secret")), get_request(uri)).await,
+                StatusCode::OK
+            );
+        }
+        assert_eq!(
+            status(test_app(None), get_request("/api/photos")).await,
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! CORS policy: the app's own pages on localhost, plus origins listed in
+//! `cors_allowed_origins` for LAN setups.
+
+use axum::http::{header, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// One entry of `cors_allowed_origins`
+#[derive(Debug, Clone, PartialEq)]
+enum OriginPattern {
+    Exact(HeaderValue),
+    /// `http://*.local` matches any host ending in ".local"
+    Wildcard {
+        prefix: String,
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim().trim_end_matches('/');
+        match entry.split_once('*') {
+            Some((prefix, suffix)) if !suffix.contains('*') => Some(OriginPattern::Wildcard {
+                prefix: prefix.to_ascii_lowercase(),
+                suffix: suffix.to_ascii_lowercase(),
+            }),
+            Some(_) => None,
+            None => HeaderValue::from_str(entry).ok().map(OriginPattern::Exact),
+        }
+    }
+
+    fn matches(&self, origin: &HeaderValue) -> bool {
+        match self {
+            OriginPattern::Exact(allowed) => {
+                allowed.as_bytes().eq_ignore_ascii_case(origin.as_bytes())
+            }
+            OriginPattern::Wildcard { prefix, suffix } => {
+                let Ok(origin) = origin.to_str() else {
+                    return false;
+                };
+                let origin = origin.to_ascii_lowercase();
+                origin.len() > prefix.len() + suffix.len()
+                    && origin.starts_with(prefix.as_str())
+                    && origin.ends_with(suffix.as_str())
+                    && !origin[prefix.len()..origin.len() - suffix.len()].contains('/')
+            }
+        }
+    }
+}
+
+fn is_localhost(origin: &HeaderValue) -> bool {
+    let bytes = origin.as_bytes();
+    bytes.starts_with(b"http://localhost:")
+        || bytes.starts_with(b"http://127.0.0.1:")
+        || bytes == b"http://localhost"
+        || bytes == b"http://127.0.0.1"
+}
+
+/// Builds the CORS layer; unparsable entries are reported and skipped
+pub fn cors_layer(allowed_origins: &[String]) -> CorsLayer {
+    let patterns: Vec<OriginPattern> = allowed_origins
+        .iter()
+        .filter_map(|entry| {
+            let pattern = OriginPattern::parse(entry);
+            if pattern.is_none() {
+                eprintln!("⚠️ Ignoring invalid CORS origin: {}", entry);
+            }
+            pattern
+        })
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin, _parts| {
+            is_localhost(origin) || patterns.iter().any(|p| p.matches(origin))
+        }))
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        // Clients on other origins send the LAN access token as a Bearer header
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cors_layer, OriginPattern};
+    use axum::{
+        body::Body,
+        http::{header, HeaderValue, Method, Request},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    async fn allowed_origin(allowed: &[&str], origin: &str) -> Option<HeaderValue> {
+        let allowed: Vec<String> = allowed.iter().map(|s| s.to_string()).collect();
+        let app = Router::new()
+            .route("/api/photos", get(|| async { "[]" }))
+            .layer(cors_layer(&allowed));
+        let request = Request::builder()
+            .uri("/api/photos")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .cloned()
+    }
+
+    #[tokio::test]
+    async fn unlisted_origin_gets_no_cors_header() {
+        let allowed = ["http://photos.example.com", "http://*.local"];
+        assert_eq!(
+            allowed_origin(&allowed, "http://photos.example.com")
+                .await
+                .unwrap(),
+            "http://photos.example.com"
+        );
+        assert!(allowed_origin(&allowed, "http://nas.local").await.is_some());
+        assert!(allowed_origin(&allowed, "http://evil.example.com")
+            .await
+            .is_none());
+        assert!(allowed_origin(&allowed, "http://localhost:3001")
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn empty_list_keeps_localhost_only() {
+        assert!(allowed_origin(&[], "http://127.0.0.1:3001").await.is_some());
+        assert!(allowed_origin(&[], "http://nas.local").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn preflight_allows_the_access_token_header() {
+        let app = Router::new()
+            .route("/api/photos", get(|| async { "[]" }))
+            .layer(cors_layer(&["http://nas.local".to_string()]));
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/photos")
+            .header(header::ORIGIN, "http://nas.local")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let allowed = response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_HEADERS)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        assert!(allowed.contains("authorization"), "{allowed}");
+    }
+
+    #[test]
+    fn wildcard_matches_one_host_part() {
+        let pattern = OriginPattern::parse("http://*.local").unwrap();
+        let matches = |origin: &'static str| pattern.matches(&HeaderValue::from_static(origin));
+        assert!(matches("http://nas.local"));
+        assert!(matches("http://NAS.Local"));
+        assert!(!matches("http://.local"));
+        assert!(!matches("https://nas.local"));
+        assert!(!matches("http://evil.com/x.local"));
+        assert!(OriginPattern::parse("http://*.*.local").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::Response;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::image_processing::EncodeOptions;
+
+/// Cache policy for validated responses: the browser may store them but must
+/// revalidate with If-None-Match, which is answered with a cheap 304.
+pub const REVALIDATE: &str = "public, no-cache";
+
+/// Builds a weak ETag for a generated image from the source file state,
+/// the requested variant, encoding settings and the database generation.
+pub fn image_etag(
+    source_path: &Path,
+    variant: &str,
+    options: EncodeOptions,
+    generation: u64,
+) -> Option<String> {
+    let metadata = std::fs::metadata(source_path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    source_path.hash(&mut hasher);
+    mtime.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    variant.hash(&mut hasher);
+    options.quality.hash(&mut hasher);
+    options.format.name().hash(&mut hasher);
+    options.processing.hash(&mut hasher);
+    generation.hash(&mut hasher);
+    Some(format!("W/\"{:016x}\"", hasher.finish()))
+}
+
+/// Builds a weak ETag for a response generated from the database at `revision`
+pub fn revision_etag(revision: u64, variant: &str) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    revision.hash(&mut hasher);
+    variant.hash(&mut hasher);
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+/// Checks If-None-Match against an ETag using weak comparison
+pub fn is_not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let expected = opaque(etag);
+    value
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == expected)
+}
+
+/// Builds an empty 304 response carrying the validator
+pub fn not_modified(etag: &str) -> Result<Response, StatusCode> {
+    Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header(header::ETAG, etag)
+        .header(header::CACHE_CONTROL, REVALIDATE)
+        .body(axum::body::Body::empty())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_not_modified(&headers, "W/\"abc\""));
+
+        headers.insert(header::IF_NONE_MATCH, "\"xyz\", \"abc\"".parse().unwrap());
+        assert!(is_not_modified(&headers, "W/\"abc\""));
+        assert!(!is_not_modified(&headers, "W/\"def\""));
+
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(is_not_modified(&headers, "W/\"def\""));
+    }
+
+    #[test]
+    fn revision_etag_changes_with_revision_and_variant() {
+        assert_eq!(revision_etag(7, "photos:"), revision_etag(7, "photos:"));
+        assert_ne!(revision_etag(7, "photos:"), revision_etag(8, "photos:"));
+        assert_ne!(
+            revision_etag(7, "photos:"),
+            revision_etag(7, "photos:Holidays")
+        );
+    }
+}
@@ -1,13 +1,13 @@
 use serde::{Deserialize, Serialize};
 
 // SSE Event types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct ProcessingEvent {
     pub event_type: String,
     pub data: ProcessingData,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, utoipa::ToSchema)]
 pub struct ProcessingData {
     pub total_files: Option<usize>,
     pub processed: Option<usize>,
@@ -1,36 +1,68 @@
 use anyhow::Result;
 use axum::{
-    extract::{Path as AxumPath, Query, State},
-    http::{header, StatusCode},
-    response::{Html, Json, Response, Sse},
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path as AxumPath, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Json, Response, Sse},
 };
 use futures_core::Stream;
-use std::collections::HashMap;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::convert::Infallible;
+use std::io::{Cursor, Write};
 use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::io::ReaderStream;
+use tower_http::services::ServeFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
 
-use crate::database::ImageMetadata;
+use crate::config::ProcessingConfig;
+use crate::constants::{
+    DEFAULT_TRIP_MAX_GAP_DAYS, DEFAULT_TRIP_MAX_JUMP_KM, MARKER_SIZE, MAX_SPRITE_TILES, POPUP_SIZE,
+};
+use crate::database::{
+    verify_duplicates, ClusterEntry, Database, DuplicateReason, ExifSource, ImageMetadata,
+    NoGpsEntry, PhotoFilter, PhotoId, PhotoMetadata, SortOrder,
+};
+use crate::exif_parser::generic::format_unix_timestamp;
+use crate::exif_parser::{full_info, write_gps_to_jpeg};
+use crate::export;
 use crate::geocoding;
-use crate::image_processing::{convert_heic_to_jpeg, create_scaled_image_in_memory, ImageType};
-use crate::processing::{process_photos_from_directory, process_photos_with_stats};
-use crate::settings::Settings;
+use crate::image_cache::{cache_key, CacheStats, CachedImage};
+use crate::image_processing::{
+    convert_heic_to_jpeg, create_scaled_image_in_memory, detect_image_format, generate_placeholder,
+    image_format_of, pack_sprite, sniff_image_format, DiskCacheStats, DiskThumbnailCache,
+    EncodeOptions, ImageFormat, ImageType, OutputFormat,
+};
+use crate::processing::{
+    manually_geotagged_photo, process_photos_from_directory, process_photos_with_stats, WalkOptions,
+};
+use crate::settings::{FolderConfig, Settings};
+use crate::types::{BoundingBox, GpsCoordinate};
 
+use super::etag;
 use super::events::{ProcessingData, ProcessingEvent};
-use super::state::AppState;
+use super::state::{unix_now, AppState, FolderStats, LastRunStats, RunGuard};
+use super::warmup::spawn_thumbnail_warmup;
 
 const INDEX_HTML: &[u8] = include_bytes!("../../frontend/index.html");
 const STYLE_CSS: &[u8] = include_bytes!("../../frontend/style.css");
 const SCRIPT_JS: &[u8] = include_bytes!("../../frontend/script.js");
 
-struct ReceiverStream {
-    rx: mpsc::Receiver<Result<axum::response::sse::Event, Infallible>>,
+struct ReceiverStream<T> {
+    rx: mpsc::Receiver<T>,
 }
 
-impl Stream for ReceiverStream {
-    type Item = Result<axum::response::sse::Event, Infallible>;
+impl<T> Stream for ReceiverStream<T> {
+    type Item = T;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         self.rx.poll_recv(cx)
@@ -44,12 +76,16 @@ fn get_mime_type(path: &std::path::Path) -> &'static str {
         Some("heic") | Some("heif") => "image/heic",
         Some("gif") => "image/gif",
         Some("webp") => "image/webp",
+        Some("tif") | Some("tiff") => "image/tiff",
         Some("bmp") => "image/bmp",
         Some("svg") => "image/svg+xml",
         _ => "application/octet-stream",
     }
 }
 
+/// Relative path of a photo as a URL path: every segment is percent-encoded,
+/// so `#`, `?`, spaces and non-ASCII names survive routing. Handlers get the
+/// path back decoded from the `*filename` wildcard, `%2F` included.
 fn encode_url_path(path: &str) -> String {
     path.replace('\\', "/")
         .split('/')
@@ -83,62 +119,531 @@ fn display_path(path: &std::path::Path) -> String {
     }
 }
 
+/// Converts a database record and its tags into the photo object served to the frontend
+fn image_metadata(photo: PhotoMetadata, tags: Vec<String>) -> ImageMetadata {
+    let encoded_path = encode_url_path(&photo.relative_path);
+    let album = photo.album().to_string();
+    let (url, fallback_url) = if photo.is_heic {
+        let jpg_url = format!("/convert-heic?filename={encoded_path}");
+        (jpg_url.clone(), jpg_url)
+    } else {
+        let photo_url = format!("/api/popup/{encoded_path}");
+        (photo_url.clone(), photo_url)
+    };
+
+    ImageMetadata {
+        id: photo.id,
+        filename: photo.filename,
+        album,
+        relative_path: photo.relative_path,
+        url,
+        fallback_url,
+        marker_icon: format!("/api/marker/{encoded_path}"),
+        lat: photo.coords.lat(),
+        lng: photo.coords.lng(),
+        datetime: photo.datetime,
+        file_path: photo.file_path,
+        is_heic: photo.is_heic,
+        is_motion_photo: photo.is_motion_photo,
+        source: photo.source,
+        speed_kmh: photo.speed_kmh,
+        gps_dop: photo.gps_dop,
+        gps_quality: photo.gps_quality,
+        direction: photo.direction,
+        blurhash: photo.blurhash,
+        location: photo.location,
+        city: photo.place.as_ref().map(|place| place.city.clone()),
+        admin1: photo.place.as_ref().and_then(|place| place.admin1.clone()),
+        country: photo.place.map(|place| place.country),
+        dominant_color: photo
+            .dominant_color
+            .map(|[r, g, b]| format!("#{r:02x}{g:02x}{b:02x}")),
+        tags,
+    }
+}
+
+/// `image_metadata` with the tags stored for the photo
+fn tagged_image_metadata(db: &Database, photo: PhotoMetadata) -> ImageMetadata {
+    let tags = db.photo_tags(&photo.id);
+    image_metadata(photo, tags)
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PhotosQuery {
+    /// Only photos of this album, see `PhotoMetadata::album`
+    pub album: Option<String>,
+    /// Comma-separated; only photos carrying all of these tags
+    pub tags: Option<String>,
+    /// `date_desc` (default), `date_asc`, `name` or `distance`
+    pub sort: Option<String>,
+    /// `lat,lng` that `sort=distance` measures from
+    pub near: Option<String>,
+    /// Inclusive `YYYY-MM-DD` bounds of the capture date
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+}
+
+impl PhotosQuery {
+    fn tags(&self) -> Vec<String> {
+        self.tags
+            .as_deref()
+            .map(|tags| tags.split(',').map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    fn check_dates(&self) -> Result<(), String> {
+        for date in [&self.start_date, &self.end_date].into_iter().flatten() {
+            if !crate::database::is_valid_date(date) {
+                return Err(format!("Invalid date (expected YYYY-MM-DD): {}", date));
+            }
+        }
+        Ok(())
+    }
+
+    fn sort_order(&self) -> Result<SortOrder, String> {
+        match self.sort.as_deref().unwrap_or("date_desc") {
+            "date_desc" => Ok(SortOrder::DateDesc),
+            "date_asc" => Ok(SortOrder::DateAsc),
+            "name" => Ok(SortOrder::Name),
+            "distance" => {
+                let near = self
+                    .near
+                    .as_deref()
+                    .ok_or("sort=distance needs near=lat,lng")?;
+                let point = near
+                    .split_once(',')
+                    .and_then(|(lat, lng)| {
+                        GpsCoordinate::new(lat.trim().parse().ok()?, lng.trim().parse().ok()?).ok()
+                    })
+                    .ok_or_else(|| format!("Invalid near point: {}", near))?;
+                Ok(SortOrder::Distance {
+                    lat: point.lat(),
+                    lng: point.lng(),
+                })
+            }
+            other => Err(format!("Unknown sort order: {}", other)),
+        }
+    }
+}
+
+/// Photos serialized per streamed chunk of `/api/photos`
+const PHOTOS_CHUNK: usize = 1000;
+
+/// Brackets and commas of a JSON array sent in several chunks. A chunk may
+/// hold no elements, e.g. when its photos were removed by a rescan meanwhile.
+#[derive(Default)]
+struct JsonArrayChunks {
+    opened: bool,
+    has_elements: bool,
+}
+
+impl JsonArrayChunks {
+    /// New chunk, starting with the opening bracket if none was sent yet
+    fn start(&mut self) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        if !self.opened {
+            chunk.push(b'[');
+            self.opened = true;
+        }
+        chunk
+    }
+
+    /// Adds the comma needed before the next element
+    fn separate(&mut self, chunk: &mut Vec<u8>) {
+        if self.has_elements {
+            chunk.push(b',');
+        }
+        self.has_elements = true;
+    }
+
+    /// Closing bytes, a whole empty array if no chunk was started
+    fn end(&self) -> &'static [u8] {
+        if self.opened {
+            b"]"
+        } else {
+            b"[]"
+        }
+    }
+}
+
+/// Writes the photos JSON array into `tx` one chunk of `PHOTOS_CHUNK` photos at a time
+fn send_photos_json(
+    db: &Database,
+    filter: &PhotoFilter,
+    order: SortOrder,
+    tx: &mpsc::Sender<Result<Vec<u8>, std::io::Error>>,
+) -> Result<()> {
+    let mut array = JsonArrayChunks::default();
+    let mut failed = None;
+    db.for_each_photo_chunk(filter, order, PHOTOS_CHUNK, |photos| {
+        let mut chunk = array.start();
+        for photo in photos {
+            array.separate(&mut chunk);
+            if let Err(e) = serde_json::to_writer(&mut chunk, &tagged_image_metadata(db, photo)) {
+                failed = Some(e);
+                return false;
+            }
+        }
+        // Stops when the client went away
+        tx.blocking_send(Ok(chunk)).is_ok()
+    })?;
+    if let Some(e) = failed {
+        return Err(e.into());
+    }
+    let _ = tx.blocking_send(Ok(array.end().to_vec()));
+    Ok(())
+}
+
+/// All photos, or those of one album, with the given tags or taken between two
+/// dates, newest first unless `sort` says otherwise. The JSON is streamed in
+/// chunks so large libraries are never held in memory twice.
+#[utoipa::path(
+    get,
+    path = "/api/photos",
+    tag = "photos",
+    params(PhotosQuery),
+    responses(
+        (status = 200, description = "Matching photos, streamed as one JSON array", body = Vec<ImageMetadata>),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "Invalid date (expected YYYY-MM-DD): 2024"}))
+    )
+)]
 pub async fn get_all_photos(
     State(state): State<AppState>,
-) -> Result<Json<Vec<ImageMetadata>>, StatusCode> {
-    let photos = match tokio::task::spawn_blocking({
+    Query(query): Query<PhotosQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let order = match query.check_dates().and_then(|()| query.sort_order()) {
+        Ok(order) => order,
+        Err(message) => {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "message": message
+                })),
+            )
+                .into_response())
+        }
+    };
+
+    let etag = etag::revision_etag(
+        state.db.revision(),
+        &format!(
+            "photos:{}:{}:{}:{}:{}:{}",
+            query.album.as_deref().unwrap_or(""),
+            query.tags.as_deref().unwrap_or(""),
+            query.sort.as_deref().unwrap_or(""),
+            query.near.as_deref().unwrap_or(""),
+            query.start_date.as_deref().unwrap_or(""),
+            query.end_date.as_deref().unwrap_or("")
+        ),
+    );
+    if etag::is_not_modified(&headers, &etag) {
+        return etag::not_modified(&etag);
+    }
+
+    let (tx, rx) = mpsc::channel(4);
+    let db = state.db.clone();
+    // Errors of the blocking task are logged with the request id too
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _span = span.enter();
+        let tags = query.tags();
+        let filter = PhotoFilter {
+            album: query.album.as_deref(),
+            tags: &tags,
+            start_date: query.start_date.as_deref(),
+            end_date: query.end_date.as_deref(),
+        };
+        if let Err(e) = send_photos_json(&db, &filter, order, &tx) {
+            tracing::error!("Database error: {}", e);
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CACHE_CONTROL, etag::REVALIDATE)
+        .header(header::ETAG, etag)
+        .body(Body::from_stream(ReceiverStream { rx }))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+const SEARCH_PHOTOS_PER_PAGE: usize = 100;
+const MAX_SEARCH_PHOTOS_PER_PAGE: usize = 1000;
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchPhotosQuery {
+    #[serde(default)]
+    pub q: String,
+    /// 1-based
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+/// Photos whose filename or capture date contains `q`, newest first
+#[utoipa::path(
+    get,
+    path = "/api/photos/search",
+    tag = "photos",
+    params(SearchPhotosQuery),
+    responses(
+        (status = 200, description = "One page of matches: `page`, `per_page`, `total` and `photos`", body = Object),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "Search query is empty"})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn search_photos(
+    State(state): State<AppState>,
+    Query(query): Query<SearchPhotosQuery>,
+) -> Response {
+    if query.q.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": "Search query is empty"
+            })),
+        )
+            .into_response();
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(SEARCH_PHOTOS_PER_PAGE)
+        .clamp(1, MAX_SEARCH_PHOTOS_PER_PAGE);
+
+    let result = tokio::task::spawn_blocking({
         let db = state.db.clone();
-        move || db.get_all_photos()
+        move || db.search_photos(&query.q, (page - 1).saturating_mul(per_page), per_page)
     })
-    .await
-    {
-        Ok(Ok(photos)) => photos,
+    .await;
+    match result {
+        Ok(Ok(photos)) => {
+            let photos: Vec<ImageMetadata> = photos
+                .into_iter()
+                .map(|photo| tagged_image_metadata(&state.db, photo))
+                .collect();
+            Json(serde_json::json!({
+                "page": page,
+                "per_page": per_page,
+                "photos": photos,
+            }))
+            .into_response()
+        }
         Ok(Err(e)) => {
-            eprintln!("Database error: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+const NEAR_PHOTOS_RADIUS_M: f64 = 500.0;
+const NEAR_PHOTOS_LIMIT: usize = 50;
+const MAX_NEAR_PHOTOS_LIMIT: usize = 1000;
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NearPhotosQuery {
+    pub lat: f64,
+    pub lng: f64,
+    pub radius_m: Option<f64>,
+    pub limit: Option<usize>,
+    /// Relative path of a photo to leave out, usually the one in the open popup
+    pub exclude: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct NearbyImage {
+    #[serde(flatten)]
+    photo: ImageMetadata,
+    distance_m: f64,
+}
+
+/// Photos within `radius_m` of a position, nearest first with their distance
+#[utoipa::path(
+    get,
+    path = "/api/photos/near",
+    tag = "photos",
+    params(NearPhotosQuery),
+    responses(
+        (status = 200, description = "Photos with their `distance_m`, nearest first", body = Vec<ImageMetadata>),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "Invalid radius_m: -5"})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_photos_near(
+    State(state): State<AppState>,
+    Query(query): Query<NearPhotosQuery>,
+) -> Response {
+    let bad_request = |message: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "status": "error", "message": message })),
+        )
+            .into_response()
     };
+    let center = match GpsCoordinate::new(query.lat, query.lng) {
+        Ok(center) => center,
+        Err(e) => return bad_request(e.to_string()),
+    };
+    let radius_m = query.radius_m.unwrap_or(NEAR_PHOTOS_RADIUS_M);
+    if !radius_m.is_finite() || radius_m <= 0.0 {
+        return bad_request(format!("Invalid radius_m: {}", radius_m));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(NEAR_PHOTOS_LIMIT)
+        .clamp(1, MAX_NEAR_PHOTOS_LIMIT);
+    let exclude = query.exclude.as_deref().map(PhotoId::from_relative_path);
 
-    let api_photos: Vec<ImageMetadata> = photos
-        .into_iter()
-        .map(|photo| {
-            let encoded_path = encode_url_path(&photo.relative_path);
-            let (url, fallback_url) = if photo.is_heic {
-                let jpg_url = format!("/convert-heic?filename={encoded_path}");
-                (jpg_url.clone(), jpg_url)
-            } else {
-                let photo_url = format!("/api/popup/{encoded_path}");
-                (photo_url.clone(), photo_url)
-            };
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        db.photos_near(center, radius_m, limit, exclude.as_ref())
+    })
+    .await;
+    match result {
+        Ok(Ok(found)) => {
+            let photos: Vec<NearbyImage> = found
+                .into_iter()
+                .map(|nearby| NearbyImage {
+                    photo: tagged_image_metadata(&state.db, nearby.photo),
+                    distance_m: nearby.distance_m,
+                })
+                .collect();
+            Json(photos).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
 
-            ImageMetadata {
-                filename: photo.filename.clone(),
-                relative_path: photo.relative_path.clone(),
-                url,
-                fallback_url,
-                marker_icon: format!("/api/marker/{encoded_path}"),
-                lat: photo.lat,
-                lng: photo.lng,
-                datetime: photo.datetime,
-                file_path: photo.file_path.clone(),
-                is_heic: photo.is_heic,
-                location: geocoding::get_location_name(photo.lat, photo.lng),
-            }
-        })
-        .collect();
+const CLUSTER_PHOTOS_PER_PAGE: usize = 60;
+const MAX_CLUSTER_PHOTOS_PER_PAGE: usize = 500;
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClusterPhotosQuery {
+    pub lat: f64,
+    pub lng: f64,
+    pub radius_m: f64,
+    /// 1-based
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+/// One tile of the cluster gallery
+#[derive(Debug, serde::Serialize)]
+struct ClusterPhoto {
+    id: PhotoId,
+    relative_path: String,
+    /// Square `ImageType::Gallery` image, also for HEIC photos
+    gallery_url: String,
+    /// Full-size image for the popup, see `ImageMetadata::url`
+    url: String,
+    datetime: String,
+    location: Option<String>,
+}
+
+/// Photos of a map cluster for a gallery grid: those within `radius_m` of the
+/// cluster position, newest first, one page at a time
+#[utoipa::path(
+    get,
+    path = "/api/cluster-photos",
+    tag = "photos",
+    params(ClusterPhotosQuery),
+    responses(
+        (status = 200, description = "One page of the cluster gallery: `page`, `per_page`, `total` and `photos`", body = Object),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "Latitude 95 is outside [-90, 90]"})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_cluster_photos(
+    State(state): State<AppState>,
+    Query(query): Query<ClusterPhotosQuery>,
+) -> Response {
+    let bad_request = |message: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "status": "error", "message": message })),
+        )
+            .into_response()
+    };
+    let center = match GpsCoordinate::new(query.lat, query.lng) {
+        Ok(center) => center,
+        Err(e) => return bad_request(e.to_string()),
+    };
+    if !query.radius_m.is_finite() || query.radius_m <= 0.0 {
+        return bad_request(format!("Invalid radius_m: {}", query.radius_m));
+    }
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(CLUSTER_PHOTOS_PER_PAGE)
+        .clamp(1, MAX_CLUSTER_PHOTOS_PER_PAGE);
 
-    Ok(Json(api_photos))
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        db.photos_near(center, query.radius_m, usize::MAX, None)
+    })
+    .await;
+    match result {
+        Ok(Ok(found)) => {
+            let mut photos: Vec<PhotoMetadata> =
+                found.into_iter().map(|nearby| nearby.photo).collect();
+            photos.sort_by(|a, b| SortOrder::DateDesc.compare(a, b));
+            let total = photos.len();
+            let photos: Vec<ClusterPhoto> = photos
+                .into_iter()
+                .skip((page - 1).saturating_mul(per_page))
+                .take(per_page)
+                .map(|photo| {
+                    let gallery_url =
+                        format!("/api/gallery/{}", encode_url_path(&photo.relative_path));
+                    let metadata = image_metadata(photo, Vec::new());
+                    ClusterPhoto {
+                        id: metadata.id,
+                        relative_path: metadata.relative_path,
+                        gallery_url,
+                        url: metadata.url,
+                        datetime: metadata.datetime,
+                        location: metadata.location,
+                    }
+                })
+                .collect();
+            Json(serde_json::json!({
+                "page": page,
+                "per_page": per_page,
+                "total": total,
+                "photos": photos,
+            }))
+            .into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
 }
 
 pub async fn serve_processed_image(
     State(state): State<AppState>,
     AxumPath(filename): AxumPath<String>,
+    headers: HeaderMap,
     image_type: ImageType,
 ) -> Result<Response, StatusCode> {
     let photo = state
         .db
-        .get_photo_by_relative_path(&filename)
+        .get_photo_by_id(&PhotoId::from_relative_path(&filename))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
@@ -157,58 +662,435 @@ pub async fn serve_processed_image(
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
     }
 
-    let jpeg_data = match tokio::task::spawn_blocking(move || {
-        create_scaled_image_in_memory(std::path::Path::new(&photo.file_path), image_type)
+    let options = EncodeOptions::from_settings(&*state.settings.lock().await);
+
+    let etag = etag::image_etag(
+        std::path::Path::new(&photo.file_path),
+        image_type.name(),
+        options,
+        state.db.generation(),
+    );
+    if let Some(ref etag) = etag {
+        if etag::is_not_modified(&headers, etag) {
+            return etag::not_modified(etag);
+        }
+    }
+
+    let key = etag.as_ref().map(|etag| cache_key(&photo.file_path, etag));
+    if let Some(cached) = key.as_ref().and_then(|key| state.image_cache.get(key)) {
+        return image_response(cached.data.clone(), cached.format, etag);
+    }
+
+    let label = placeholder_label(&photo.file_path);
+    let disk_cache = state.disk_cache.clone();
+    let (image_data, format) = match tokio::task::spawn_blocking(move || {
+        render_on_disk_miss(&disk_cache, &photo, image_type, options, || {
+            render_image(&photo, image_type, options)
+        })
+        .map_err(|e| (e, generate_placeholder(image_type.size(), &label)))
+    })
+    .await
+    {
+        Ok(Ok(result)) => result,
+        Ok(Err((e, placeholder))) => {
+            tracing::warn!("Image processing error, serving a placeholder: {}", e);
+            return placeholder_response(placeholder);
+        }
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    if let Some(key) = key {
+        state.image_cache.insert(
+            key,
+            CachedImage {
+                data: image_data.clone(),
+                format,
+            },
+        );
+    }
+
+    image_response(image_data, format, etag)
+}
+
+/// Generates an image of a photo; HEIC files go through the HEIC converter
+fn render_image(
+    photo: &PhotoMetadata,
+    image_type: ImageType,
+    options: EncodeOptions,
+) -> Result<(Vec<u8>, OutputFormat)> {
+    if photo.is_heic {
+        convert_heic_to_jpeg(photo, image_type.name(), options)
+    } else {
+        create_scaled_image_in_memory(std::path::Path::new(&photo.file_path), image_type, options)
+            .map(|data| (data, options.format))
+    }
+}
+
+/// Thumbnail-sized image from the disk cache, rendered and stored there on a
+/// miss. Popups are too large to be worth keeping and are always rendered.
+fn render_on_disk_miss(
+    disk_cache: &DiskThumbnailCache,
+    photo: &PhotoMetadata,
+    image_type: ImageType,
+    options: EncodeOptions,
+    render: impl FnOnce() -> Result<(Vec<u8>, OutputFormat)>,
+) -> Result<(Vec<u8>, OutputFormat)> {
+    let key = match image_type {
+        ImageType::Popup => None,
+        _ => DiskThumbnailCache::key(std::path::Path::new(&photo.file_path), image_type, options),
+    };
+    let cached = key.as_deref().and_then(|key| disk_cache.get(key));
+    if let Some(data) = cached {
+        match sniff_image_format(&data) {
+            Some(ImageFormat::Jpeg) => return Ok((data, OutputFormat::Jpeg)),
+            Some(ImageFormat::Webp) => return Ok((data, OutputFormat::Webp)),
+            _ => tracing::warn!("Unreadable disk cache entry for {}", photo.file_path),
+        }
+    }
+
+    let (data, format) = render()?;
+    if let Some(key) = key {
+        if let Err(e) = disk_cache.put(&key, &data) {
+            tracing::warn!("Failed to store a thumbnail on disk: {}", e);
+        }
+    }
+    Ok((data, format))
+}
+
+/// Marker of a photo from the image cache, generated and cached on a miss
+fn cached_marker(
+    state: &AppState,
+    photo: &PhotoMetadata,
+    options: EncodeOptions,
+) -> Result<Arc<CachedImage>> {
+    let etag = etag::image_etag(
+        std::path::Path::new(&photo.file_path),
+        ImageType::Marker.name(),
+        options,
+        state.db.generation(),
+    );
+    let key = etag.map(|etag| cache_key(&photo.file_path, &etag));
+    if let Some(cached) = key.as_ref().and_then(|key| state.image_cache.get(key)) {
+        return Ok(cached);
+    }
+
+    let (data, format) =
+        render_on_disk_miss(&state.disk_cache, photo, ImageType::Marker, options, || {
+            render_image(photo, ImageType::Marker, options)
+        })?;
+    if let Some(key) = key {
+        state.image_cache.insert(
+            key,
+            CachedImage {
+                data: data.clone(),
+                format,
+            },
+        );
+    }
+    Ok(Arc::new(CachedImage { data, format }))
+}
+
+/// Position of a marker in a sprite sheet, in pixels
+#[derive(Debug, serde::Serialize)]
+pub struct SpriteTile {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SpriteManifest {
+    pub width: u32,
+    pub height: u32,
+    pub tiles: HashMap<String, SpriteTile>,
+    /// Requested paths that are unknown or could not be rendered
+    pub missing: Vec<String>,
+}
+
+/// Renders the markers of many photos into one sprite sheet
+fn build_marker_sprite(
+    state: &AppState,
+    mut paths: Vec<String>,
+    options: EncodeOptions,
+) -> Result<(SpriteManifest, Vec<u8>)> {
+    paths.sort_unstable();
+    paths.dedup();
+    // Rayon threads do not inherit the request span
+    let span = tracing::Span::current();
+    let rendered: Vec<(String, Option<image::DynamicImage>)> = paths
+        .into_par_iter()
+        .map(|path| {
+            let _span = span.enter();
+            let tile = state
+                .db
+                .get_photo_by_id(&PhotoId::from_relative_path(&path))
+                .ok()
+                .flatten()
+                .and_then(|photo| {
+                    cached_marker(state, &photo, options)
+                        .map_err(|e| tracing::error!("Image processing error for {}: {}", path, e))
+                        .ok()
+                })
+                .and_then(|marker| image::load_from_memory(&marker.data).ok());
+            (path, tile)
+        })
+        .collect();
+
+    let mut placed = Vec::new();
+    let mut images = Vec::new();
+    let mut missing = Vec::new();
+    for (path, tile) in rendered {
+        match tile {
+            Some(image) => {
+                placed.push(path);
+                images.push(image);
+            }
+            None => missing.push(path),
+        }
+    }
+
+    let (sprite, columns) =
+        pack_sprite(&images, MARKER_SIZE, options.quality_for(ImageType::Marker))?;
+    let rows = (images.len().max(1) as u32).div_ceil(columns);
+    let tiles = (0u32..)
+        .zip(placed)
+        .map(|(i, path)| {
+            let tile = SpriteTile {
+                x: (i % columns) * MARKER_SIZE,
+                y: (i / columns) * MARKER_SIZE,
+                size: MARKER_SIZE,
+            };
+            (path, tile)
+        })
+        .collect();
+    let manifest = SpriteManifest {
+        width: columns * MARKER_SIZE,
+        height: rows * MARKER_SIZE,
+        tiles,
+        missing,
+    };
+    Ok((manifest, sprite))
+}
+
+/// Returns the markers of the given relative paths in one response: a
+/// `multipart/form-data` body with the JSON `manifest` and the `sprite` JPEG,
+/// readable in the browser with `Response.formData()`
+#[utoipa::path(
+    post,
+    path = "/api/markers/batch",
+    tag = "images",
+    request_body(content = Vec<String>, description = "Relative paths of the photos"),
+    responses(
+        (status = 200, description = "`multipart/form-data` with the JSON `manifest` and the `sprite` JPEG", content_type = "multipart/form-data"),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "At most 1000 markers per request"})),
+        (status = 429, description = "Rate limit exceeded")
+    )
+)]
+pub async fn get_marker_batch(
+    State(state): State<AppState>,
+    Json(paths): Json<Vec<String>>,
+) -> Response {
+    if paths.len() > MAX_SPRITE_TILES {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": format!("At most {} markers per request", MAX_SPRITE_TILES)
+            })),
+        )
+            .into_response();
+    }
+    let options = EncodeOptions::from_settings(&*state.settings.lock().await);
+
+    let span = tracing::Span::current();
+    let (manifest, sprite) = match tokio::task::spawn_blocking(move || {
+        span.in_scope(|| build_marker_sprite(&state, paths, options))
     })
     .await
     {
-        Ok(Ok(data)) => data,
+        Ok(Ok(result)) => result,
         Ok(Err(e)) => {
-            eprintln!("Image processing error: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            tracing::error!("Sprite error: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
         }
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let manifest = match serde_json::to_vec(&manifest) {
+        Ok(json) => json,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     };
+    // The boundary must not occur in the parts; derive it from their content
+    let boundary = format!(
+        "photomap-{:016x}",
+        xxhash_rust::xxh3::xxh3_64(&[manifest.as_slice(), &sprite].concat())
+    );
+    let mut body = Vec::with_capacity(manifest.len() + sprite.len() + 512);
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"manifest\"\r\n\
+             Content-Type: application/json\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&manifest);
+    body.extend_from_slice(
+        format!(
+            "\r\n--{boundary}\r\nContent-Disposition: form-data; name=\"sprite\"; \
+             filename=\"markers.jpg\"\r\nContent-Type: image/jpeg\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&sprite);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .body(body.into())
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// Placeholders are cached briefly, so a repaired or replaced file shows up soon
+const PLACEHOLDER_CACHE: &str = "public, max-age=60";
 
+/// Text on the placeholder of a photo: its extension, e.g. "HEIC"
+fn placeholder_label(file_path: &str) -> String {
+    std::path::Path::new(file_path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_uppercase())
+        .unwrap_or_else(|| "?".to_string())
+}
+
+/// A JPEG from `generate_placeholder`, served with 200 so the map shows a tile
+fn placeholder_response(data: Vec<u8>) -> Result<Response, StatusCode> {
     Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "image/jpeg")
-        .header(header::CACHE_CONTROL, "public, max-age=3600")
-        .body(jpeg_data.into())
+        .header(header::CONTENT_TYPE, OutputFormat::Jpeg.mime_type())
+        .header(header::CACHE_CONTROL, PLACEHOLDER_CACHE)
+        .body(data.into())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn image_response(
+    data: Vec<u8>,
+    format: OutputFormat,
+    etag: Option<String>,
+) -> Result<Response, StatusCode> {
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.mime_type())
+        .header(header::CACHE_CONTROL, etag::REVALIDATE);
+    if let Some(etag) = etag {
+        response = response.header(header::ETAG, etag);
+    }
+    response
+        .body(data.into())
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/marker/{filename}",
+    tag = "images",
+    params(("filename" = String, Path, description = "Relative path of the photo, percent-encoded per segment")),
+    responses(
+        (status = 200, description = "Round map marker, or a placeholder tile when the photo cannot be decoded", content_type = "image/jpeg"),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Unknown photo"),
+        (status = 429, description = "Rate limit exceeded")
+    )
+)]
 pub async fn get_marker_image(
     state: State<AppState>,
     filename: AxumPath<String>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    serve_processed_image(state, filename, ImageType::Marker).await
+    serve_processed_image(state, filename, headers, ImageType::Marker).await
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/thumbnail/{filename}",
+    tag = "images",
+    params(("filename" = String, Path, description = "Relative path of the photo, percent-encoded per segment")),
+    responses(
+        (status = 200, description = "Thumbnail, or a placeholder tile when the photo cannot be decoded", content_type = "image/jpeg"),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Unknown photo"),
+        (status = 429, description = "Rate limit exceeded")
+    )
+)]
 pub async fn get_thumbnail_image(
     state: State<AppState>,
     filename: AxumPath<String>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    serve_processed_image(state, filename, ImageType::Thumbnail).await
+    serve_processed_image(state, filename, headers, ImageType::Thumbnail).await
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/gallery/{filename}",
+    tag = "images",
+    params(("filename" = String, Path, description = "Relative path of the photo, percent-encoded per segment")),
+    responses(
+        (status = 200, description = "Square gallery tile, or a placeholder tile when the photo cannot be decoded", content_type = "image/jpeg"),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Unknown photo"),
+        (status = 429, description = "Rate limit exceeded")
+    )
+)]
 pub async fn get_gallery_image(
     state: State<AppState>,
     filename: AxumPath<String>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    serve_processed_image(state, filename, ImageType::Gallery).await
+    serve_processed_image(state, filename, headers, ImageType::Gallery).await
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/popup/{filename}",
+    tag = "images",
+    params(("filename" = String, Path, description = "Relative path of the photo, percent-encoded per segment")),
+    responses(
+        (status = 200, description = "Popup-sized image, or a placeholder tile when the photo cannot be decoded", content_type = "image/jpeg"),
+        (status = 304, description = "Unchanged since the ETag in If-None-Match"),
+        (status = 404, description = "Unknown photo"),
+        (status = 429, description = "Rate limit exceeded")
+    )
+)]
 pub async fn get_popup_image(
     state: State<AppState>,
     filename: AxumPath<String>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    serve_processed_image(state, filename, ImageType::Popup).await
+    serve_processed_image(state, filename, headers, ImageType::Popup).await
 }
 
+#[utoipa::path(
+    get,
+    path = "/convert-heic",
+    tag = "images",
+    params(
+        ("filename" = String, Query, description = "Relative path of the photo"),
+        ("size" = Option<String>, Query, description = "`marker`, `thumbnail`, `gallery` or `popup` (default)")
+    ),
+    responses(
+        (status = 200, description = "The photo as a JPEG", content_type = "image/jpeg"),
+        (status = 400, description = "No filename"),
+        (status = 404, description = "Unknown photo"),
+        (status = 429, description = "Rate limit exceeded")
+    )
+)]
 pub async fn convert_heic(
     State(state): State<AppState>,
     Query(query_params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
     let filename = query_params
         .get("filename")
@@ -220,69 +1102,235 @@ pub async fn convert_heic(
 
     let photo = state
         .db
-        .get_photo_by_relative_path(filename)
+        .get_photo_by_id(&PhotoId::from_relative_path(filename))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let jpeg_data = match tokio::task::spawn_blocking(move || {
-        convert_heic_to_jpeg(&photo, &size_param)
+    let options = EncodeOptions::from_settings(&*state.settings.lock().await);
+
+    let etag = etag::image_etag(
+        std::path::Path::new(&photo.file_path),
+        &size_param,
+        options,
+        state.db.generation(),
+    );
+    if let Some(ref etag) = etag {
+        if etag::is_not_modified(&headers, etag) {
+            return etag::not_modified(etag);
+        }
+    }
+
+    let key = etag.as_ref().map(|etag| cache_key(&photo.file_path, etag));
+    if let Some(cached) = key.as_ref().and_then(|key| state.image_cache.get(key)) {
+        return image_response(cached.data.clone(), cached.format, etag);
+    }
+
+    let label = placeholder_label(&photo.file_path);
+    let image_type = ImageType::from_name(&size_param);
+    let size = image_type.map_or(POPUP_SIZE, |t| t.size());
+    let disk_cache = state.disk_cache.clone();
+    let (image_data, format) = match tokio::task::spawn_blocking(move || {
+        let convert = || convert_heic_to_jpeg(&photo, &size_param, options);
+        let converted = match image_type {
+            Some(image_type) => {
+                render_on_disk_miss(&disk_cache, &photo, image_type, options, convert)
+            }
+            None => convert(),
+        };
+        converted.map_err(|e| (e, generate_placeholder(size, &label)))
     })
     .await
     {
-        Ok(Ok(data)) => data,
-        Ok(Err(e)) => {
-            eprintln!("HEIC conversion error: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        Ok(Ok(result)) => result,
+        Ok(Err((e, placeholder))) => {
+            tracing::warn!("HEIC conversion error, serving a placeholder: {}", e);
+            return placeholder_response(placeholder);
         }
         Err(e) => {
-            eprintln!("HEIC conversion task failed: {}", e);
+            tracing::error!("HEIC conversion task failed: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "image/jpeg")
-        .header(header::CACHE_CONTROL, "public, max-age=3600")
-        .body(jpeg_data.into())
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    if let Some(key) = key {
+        state.image_cache.insert(
+            key,
+            CachedImage {
+                data: image_data.clone(),
+                format,
+            },
+        );
+    }
+
+    image_response(image_data, format, etag)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/cache-stats",
+    tag = "app",
+    responses((status = 200, description = "Hits, misses and size of the image cache", body = Object))
+)]
+pub async fn get_cache_stats(State(state): State<AppState>) -> Json<CacheStats> {
+    Json(state.image_cache.stats())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/cache/stats",
+    tag = "app",
+    responses((status = 200, description = "Number and total size of the thumbnails cached on disk", body = DiskCacheStats))
+)]
+pub async fn get_disk_cache_stats(State(state): State<AppState>) -> Response {
+    let disk_cache = state.disk_cache.clone();
+    match tokio::task::spawn_blocking(move || disk_cache.stats()).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
 }
 
+/// Streams an original photo. Range requests (206/416), Last-Modified with
+/// conditional requests, and HEAD are handled by tower-http's file service.
+#[utoipa::path(
+    get,
+    path = "/photos/{filepath}",
+    tag = "images",
+    params(("filepath" = String, Path, description = "Relative path of the photo")),
+    responses(
+        (status = 200, description = "The original file"),
+        (status = 206, description = "The requested byte range"),
+        (status = 304, description = "Not modified since If-Modified-Since"),
+        (status = 404, description = "Unknown photo"),
+        (status = 416, description = "Range outside the file")
+    )
+)]
 pub async fn serve_photo(
     State(state): State<AppState>,
     AxumPath(filepath): AxumPath<String>,
+    request: Request,
 ) -> Result<Response, StatusCode> {
     let photo = state
         .db
-        .get_photo_by_relative_path(&filepath)
+        .get_photo_by_id(&PhotoId::from_relative_path(&filepath))
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
     let path = std::path::Path::new(&photo.file_path);
-    if !path.exists() {
+    if !path.is_file() {
         return Err(StatusCode::NOT_FOUND);
     }
-    let data = tokio::fs::read(path)
+
+    let mut response = ServeFile::new(path)
+        .try_call(request)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let content_type = get_mime_type(path);
-    Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, content_type)
-        .body(data.into())
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Body::new);
+
+    if response.status().is_success() {
+        // The content decides, e.g. a PNG saved with a .jpg name is served as image/png
+        let mime_type =
+            detect_image_format(path).map_or_else(|| get_mime_type(path), ImageFormat::mime_type);
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(mime_type));
+    }
+    Ok(response)
 }
 
-pub async fn get_settings(State(state): State<AppState>) -> Result<Json<Settings>, StatusCode> {
-    let settings = state.settings.lock().await;
-    Ok(Json((*settings).clone()))
+/// Metadata of one photo, looked up by its id
+#[utoipa::path(
+    get,
+    path = "/api/photos/{photo}",
+    tag = "photos",
+    params(("photo" = String, Path, description = "Photo id")),
+    responses(
+        (status = 200, description = "The photo", body = ImageMetadata),
+        (status = 404, description = "Unknown photo")
+    )
+)]
+pub async fn get_photo(State(state): State<AppState>, AxumPath(id): AxumPath<PhotoId>) -> Response {
+    match state.db.get_photo_by_id(&id) {
+        Ok(Some(photo)) => Json(tagged_image_metadata(&state.db, photo)).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": format!("Photo not found: {}", id)
+            })),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
 }
 
-pub async fn set_folder(
+/// EXIF changes only when the photo is replaced, so details may be reused for a while
+const PHOTO_INFO_CACHE: &str = "private, max-age=300";
+
+/// Camera and exposure details read from the original file
+#[utoipa::path(
+    get,
+    path = "/api/photo-info/{relative_path}",
+    tag = "photos",
+    params(("relative_path" = String, Path, description = "Relative path of the photo")),
+    responses(
+        (status = 200, description = "Camera and exposure details read from the file", body = Object),
+        (status = 404, description = "Unknown photo")
+    )
+)]
+pub async fn get_photo_info(
     State(state): State<AppState>,
-    Json(payload): Json<serde_json::Value>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let folder_paths =
+    AxumPath(relative_path): AxumPath<String>,
+) -> Result<Response, StatusCode> {
+    let photo = state
+        .db
+        .get_photo_by_id(&PhotoId::from_relative_path(&relative_path))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let path = std::path::PathBuf::from(&photo.file_path);
+    match tokio::task::spawn_blocking(move || full_info(&path)).await {
+        Ok(Ok(info)) => Ok((
+            [(header::CACHE_CONTROL, PHOTO_INFO_CACHE)],
+            Json(serde_json::json!({
+                "relative_path": photo.relative_path,
+                "info": info,
+            })),
+        )
+            .into_response()),
+        Ok(Err(e)) => {
+            tracing::error!("Failed to read details of {}: {}", photo.file_path, e);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/settings",
+    tag = "settings",
+    responses((status = 200, description = "Current settings", body = Settings))
+)]
+pub async fn get_settings(State(state): State<AppState>) -> Result<Json<Settings>, StatusCode> {
+    let settings = state.settings.lock().await;
+    Ok(Json((*settings).clone()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/set-folder",
+    tag = "folders",
+    request_body(content = Object, description = "`folder_paths` or a single `folder_path`", example = json!({"folder_paths": ["/home/me/Pictures"]})),
+    responses((status = 200, description = "`status` is `success`, or `error` with a `message`", body = Object))
+)]
+pub async fn set_folder(
+    State(state): State<AppState>,
+    Json(payload): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let folder_paths =
         if let Some(paths_array) = payload.get("folder_paths").and_then(|v| v.as_array()) {
             paths_array
                 .iter()
@@ -307,7 +1355,6 @@ pub async fn set_folder(
     let folders_to_store: Vec<String> = folder_paths
         .into_iter()
         .map(|path| crate::settings::normalize_folder_path(&path))
-        .take(5)
         .collect();
 
     for folder_path in &folders_to_store {
@@ -319,17 +1366,26 @@ pub async fn set_folder(
         }
     }
 
+    // Folders that stay keep their enabled flag
     let mut settings = state.settings.lock().await;
-    for i in 0..5 {
-        settings.folders[i] = None;
-    }
-    for (i, folder_path) in folders_to_store.iter().enumerate() {
-        settings.folders[i] = Some(folder_path.clone());
-    }
+    settings.keep_folders();
+    settings.folders = folders_to_store
+        .iter()
+        .map(|path| {
+            settings
+                .folders
+                .iter()
+                .find(|f| &f.path == path)
+                .cloned()
+                .unwrap_or_else(|| FolderConfig::new(path))
+        })
+        .collect();
 
     if let Err(e) = settings.save() {
-        eprintln!("Failed to save settings: {}", e);
+        tracing::error!("Failed to save settings: {}", e);
     }
+    drop(settings);
+    notify_folders_changed(&state, "Folder list replaced".to_string());
 
     Ok(Json(serde_json::json!({
         "status": "success",
@@ -343,50 +1399,192 @@ pub async fn set_folder(
     })))
 }
 
+/// Applies the given fields over the current settings; fields left out keep
+/// their value, unknown fields are ignored
+#[utoipa::path(
+    post,
+    path = "/api/update_settings",
+    tag = "settings",
+    request_body(content = Object, description = "Any subset of the settings", example = json!({"start_browser": false})),
+    responses(
+        (status = 200, description = "Settings updated", body = Object),
+        (status = 400, description = "Rejected fields; nothing was changed", body = Object, example = json!({"status": "error", "message": "Invalid settings: port", "errors": [{"field": "port", "message": "must be between 1 and 65535"}]}))
+    )
+)]
 pub async fn update_settings(
     State(state): State<AppState>,
-    Json(new_settings): Json<Settings>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Json(patch): Json<serde_json::Value>,
+) -> Response {
     let mut settings = state.settings.lock().await;
-    *settings = new_settings.clone();
+    let new_settings = match settings.merged(&patch) {
+        Ok(new_settings) => new_settings,
+        Err(errors) => {
+            let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "status": "error",
+                    "message": format!("Invalid settings: {}", fields.join(", ")),
+                    "errors": errors
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    *settings = new_settings;
+    geocoding::set_location_format(&settings.location_format);
+    geocoding::set_max_distance_km(settings.max_geocode_distance_km);
+    state
+        .image_cache
+        .set_max_bytes(settings.image_cache_bytes());
 
     if let Err(e) = settings.save() {
-        eprintln!("Failed to save settings: {}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        tracing::error!("Failed to save settings: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
     }
+    drop(settings);
 
-    Ok(Json(serde_json::json!({
+    let changed: Vec<&str> = patch
+        .as_object()
+        .map(|fields| fields.keys().map(String::as_str).collect())
+        .unwrap_or_default();
+    let _ = state.event_sender.try_send(ProcessingEvent {
+        event_type: "settings_changed".to_string(),
+        data: ProcessingData {
+            message: Some(format!("Changed {}", changed.join(", "))),
+            ..Default::default()
+        },
+    });
+    if changed.contains(&"folders") {
+        notify_folders_changed(&state, "Folder list replaced".to_string());
+    }
+
+    Json(serde_json::json!({
         "status": "success",
         "message": "Settings updated successfully"
-    })))
+    }))
+    .into_response()
 }
 
-pub async fn reprocess_photos(
-    State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let folders_to_process = {
+/// Marks a processing run as started and resets cancellation.
+/// Returns None if another run is still active.
+fn try_start_processing(state: &AppState, folders: &[std::path::PathBuf]) -> Option<RunGuard> {
+    let guard = state.processing.try_start(folders)?;
+    state.cancel_processing.store(false, Ordering::SeqCst);
+    Some(guard)
+}
+
+fn already_running() -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(serde_json::json!({
+            "status": "busy",
+            "message": "Processing is already running. Cancel it or wait for it to finish"
+        })),
+    )
+        .into_response()
+}
+
+/// Builds the final event of a processing run from accumulated statistics
+fn finished_event(run: &LastRunStats, folder_count: usize) -> ProcessingEvent {
+    let (event_type, message, phase) = if run.cancelled {
+        (
+            "processing_cancelled",
+            format!(
+                "Processing cancelled. Kept {} photos processed so far",
+                run.processed
+            ),
+            "cancelled",
+        )
+    } else {
+        (
+            "processing_complete",
+            format!(
+                "Processing finished! Processed {} photos from {} folder(s)",
+                run.processed, folder_count
+            ),
+            "completed",
+        )
+    };
+
+    ProcessingEvent {
+        event_type: event_type.to_string(),
+        data: ProcessingData {
+            total_files: Some(run.total_files),
+            processed: Some(run.processed),
+            gps_found: Some(run.processed),
+            no_gps: Some(run.no_gps),
+            heic_files: Some(run.heic_files),
+            skipped: Some(run.total_files - run.processed),
+            message: Some(message),
+            phase: Some(phase.to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+/// Stores the statistics of a finished run and returns its final event
+fn finish_run(
+    last_run: &std::sync::Mutex<Option<LastRunStats>>,
+    started_at: u64,
+    folders: Vec<FolderStats>,
+    folder_count: usize,
+    cancelled: bool,
+) -> ProcessingEvent {
+    let run = LastRunStats::new(started_at, folders, cancelled);
+    let event = finished_event(&run, folder_count);
+    *last_run.lock().unwrap_or_else(|e| e.into_inner()) = Some(run);
+    event
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/reprocess",
+    tag = "processing",
+    responses(
+        (status = 200, description = "`started`, or `error` without folders", body = Object),
+        (status = 409, description = "Processing is already running", body = Object, example = json!({"status": "busy", "message": "Processing is already running. Cancel it or wait for it to finish"}))
+    )
+)]
+pub async fn reprocess_photos(State(state): State<AppState>) -> Response {
+    let (folders_to_process, walk_options, processing_config) = {
         let settings = state.settings.lock().await;
-        settings
-            .folders
-            .iter()
-            .filter_map(|f| f.as_ref().map(|s| std::path::Path::new(s).to_path_buf()))
-            .collect::<Vec<_>>()
+        let folders = settings
+            .enabled_folders()
+            .into_iter()
+            .map(std::path::PathBuf::from)
+            .collect::<Vec<_>>();
+        (
+            folders,
+            WalkOptions::from_settings(&settings),
+            settings.processing,
+        )
     };
 
     if folders_to_process.is_empty() {
-        return Ok(Json(serde_json::json!({
+        return Json(serde_json::json!({
             "status": "error",
             "message": "No folders configured"
-        })));
+        }))
+        .into_response();
     }
 
     let event_sender = state.event_sender.clone();
     let db = state.db.clone();
     let folders_clone = folders_to_process.clone();
+    let Some(run_guard) = try_start_processing(&state, &folders_to_process) else {
+        return already_running();
+    };
+    let cancel = state.cancel_processing.clone();
+    let last_run = state.last_run.clone();
+    let warmup_state = state.clone();
 
     std::thread::spawn(move || {
+        // Generated images of the previous run are no longer reachable
+        warmup_state.image_cache.clear();
         if let Err(e) = db.clear_all_photos() {
-            eprintln!("Failed to clear database: {}", e);
+            tracing::error!("Failed to clear database: {}", e);
             let _ = event_sender.blocking_send(ProcessingEvent {
                 event_type: "processing_error".to_string(),
                 data: ProcessingData {
@@ -398,11 +1596,17 @@ pub async fn reprocess_photos(
             return;
         }
 
-        let mut total_stats = (0usize, 0usize, 0usize, 0usize);
+        let started_at = unix_now();
+        let mut folder_stats = Vec::new();
+        let mut processed = Vec::new();
 
         for photos_dir in &folders_clone {
+            if cancel.load(Ordering::SeqCst) {
+                break;
+            }
+
             if !photos_dir.exists() {
-                eprintln!("⚠️ Folder not found: {}", display_path(photos_dir));
+                tracing::warn!("Folder not found: {}", display_path(photos_dir));
                 let _ = event_sender.blocking_send(ProcessingEvent {
                     event_type: "processing_error".to_string(),
                     data: ProcessingData {
@@ -414,15 +1618,25 @@ pub async fn reprocess_photos(
                 continue;
             }
 
-            match process_photos_with_stats(&db, photos_dir, false, false) {
-                Ok((total_files, processed_count, no_gps_count, heic_count)) => {
-                    total_stats.0 += total_files;
-                    total_stats.1 += processed_count;
-                    total_stats.2 += no_gps_count;
-                    total_stats.3 += heic_count;
+            match process_photos_with_stats(
+                &db,
+                photos_dir,
+                false,
+                false,
+                &walk_options,
+                &processing_config,
+                &cancel,
+                Some(&event_sender),
+            ) {
+                Ok(stats) => {
+                    processed.push(photos_dir.to_string_lossy().to_string());
+                    folder_stats.push(FolderStats {
+                        path: display_path(photos_dir),
+                        stats,
+                    })
                 }
                 Err(e) => {
-                    eprintln!("Processing error for {}: {}", display_path(photos_dir), e);
+                    tracing::error!("Processing error for {}: {}", display_path(photos_dir), e);
                     let _ = event_sender.blocking_send(ProcessingEvent {
                         event_type: "processing_error".to_string(),
                         data: ProcessingData {
@@ -439,234 +1653,1735 @@ pub async fn reprocess_photos(
             }
         }
 
-        let _ = event_sender.blocking_send(ProcessingEvent {
-            event_type: "processing_complete".to_string(),
-            data: ProcessingData {
-                total_files: Some(total_stats.0),
-                processed: Some(total_stats.1),
-                gps_found: Some(total_stats.1),
-                no_gps: Some(total_stats.2),
-                heic_files: Some(total_stats.3),
-                skipped: Some(total_stats.0 - total_stats.1),
-                message: Some(format!(
-                    "Processing finished! Processed {} photos from {} folder(s)",
-                    total_stats.1,
-                    folders_clone.len()
-                )),
-                phase: Some("completed".to_string()),
-                ..Default::default()
-            },
-        });
+        let cancelled = cancel.load(Ordering::SeqCst);
+        if !cancelled {
+            if let Err(e) = db.save_to_disk(&processed, &walk_options) {
+                tracing::warn!("Failed to save cache: {}", e);
+            }
+        }
+        let event = finish_run(
+            &last_run,
+            started_at,
+            folder_stats,
+            folders_clone.len(),
+            cancelled,
+        );
+        drop(run_guard);
+        let _ = event_sender.blocking_send(event);
+
+        if !cancelled {
+            spawn_thumbnail_warmup(warmup_state);
+        }
     });
 
-    Ok(Json(serde_json::json!({
+    Json(serde_json::json!({
         "status": "started",
         "message": format!("Processing {} folder(s)", folders_to_process.len()),
         "count": folders_to_process.len()
-    })))
+    }))
+    .into_response()
 }
 
-pub async fn initiate_processing(
-    State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let event_sender = state.event_sender.clone();
-    let db = state.db.clone();
-
-    let folders_to_process = {
+#[utoipa::path(
+    post,
+    path = "/api/initiate-processing",
+    tag = "processing",
+    responses(
+        (status = 200, description = "`started`, or `error` without folders", body = Object),
+        (status = 409, description = "Processing is already running", body = Object, example = json!({"status": "busy", "message": "Processing is already running. Cancel it or wait for it to finish"}))
+    )
+)]
+pub async fn initiate_processing(State(state): State<AppState>) -> Response {
+    let (folders_to_process, walk_options, processing_config) = {
         let settings = state.settings.lock().await;
-        settings
-            .folders
-            .iter()
-            .filter_map(|f| f.as_ref().map(|s| std::path::Path::new(s).to_path_buf()))
-            .collect::<Vec<_>>()
+        let folders = settings
+            .enabled_folders()
+            .into_iter()
+            .map(std::path::PathBuf::from)
+            .collect::<Vec<_>>();
+        (
+            folders,
+            WalkOptions::from_settings(&settings),
+            settings.processing,
+        )
     };
 
     if folders_to_process.is_empty() {
-        return Ok(Json(serde_json::json!({
+        return Json(serde_json::json!({
             "status": "error",
             "message": "No folders configured"
-        })));
+        }))
+        .into_response();
     }
 
+    start_incremental_run(&state, folders_to_process, walk_options, processing_config)
+}
+
+/// Processes the new photos of the folders in the background, keeping the stored ones
+fn start_incremental_run(
+    state: &AppState,
+    folders_to_process: Vec<std::path::PathBuf>,
+    walk_options: WalkOptions,
+    processing_config: ProcessingConfig,
+) -> Response {
+    let event_sender = state.event_sender.clone();
+    let db = state.db.clone();
     let folders_clone = folders_to_process.clone();
+    let Some(run_guard) = try_start_processing(state, &folders_to_process) else {
+        return already_running();
+    };
+    let cancel = state.cancel_processing.clone();
+    let last_run = state.last_run.clone();
+    let warmup_state = state.clone();
 
     std::thread::spawn(move || {
-        let mut total_stats = (0usize, 0usize, 0usize, 0usize);
+        let started_at = unix_now();
+        let mut folder_stats = Vec::new();
+        let mut processed = Vec::new();
 
         for photos_dir in &folders_clone {
+            if cancel.load(Ordering::SeqCst) {
+                break;
+            }
+
             if !photos_dir.exists() {
-                eprintln!("⚠️ Folder not found: {}", display_path(photos_dir));
+                tracing::warn!("Folder not found: {}", display_path(photos_dir));
                 continue;
             }
 
-            match process_photos_from_directory(&db, photos_dir) {
-                Ok((total_files, processed_count, no_gps_count, heic_count)) => {
-                    total_stats.0 += total_files;
-                    total_stats.1 += processed_count;
-                    total_stats.2 += no_gps_count;
-                    total_stats.3 += heic_count;
+            match process_photos_from_directory(
+                &db,
+                photos_dir,
+                &walk_options,
+                &processing_config,
+                &cancel,
+                Some(&event_sender),
+            ) {
+                Ok(stats) => {
+                    processed.push(photos_dir.to_string_lossy().to_string());
+                    folder_stats.push(FolderStats {
+                        path: display_path(photos_dir),
+                        stats,
+                    })
                 }
                 Err(e) => {
-                    eprintln!("Processing error for {}: {}", display_path(photos_dir), e);
+                    tracing::error!("Processing error for {}: {}", display_path(photos_dir), e);
                 }
             }
         }
 
-        let _ = event_sender.blocking_send(ProcessingEvent {
-            event_type: "processing_complete".to_string(),
-            data: ProcessingData {
-                total_files: Some(total_stats.0),
-                processed: Some(total_stats.1),
-                gps_found: Some(total_stats.1),
-                no_gps: Some(total_stats.2),
-                heic_files: Some(total_stats.3),
-                skipped: Some(total_stats.0 - total_stats.1),
-                message: Some(format!(
-                    "Processing finished! Processed {} photos from {} folder(s)",
-                    total_stats.1,
-                    folders_clone.len()
-                )),
-                phase: Some("completed".to_string()),
-                ..Default::default()
-            },
-        });
+        let cancelled = cancel.load(Ordering::SeqCst);
+        if !cancelled {
+            if let Err(e) = db.save_to_disk(&processed, &walk_options) {
+                tracing::warn!("Failed to save cache: {}", e);
+            }
+        }
+        let event = finish_run(
+            &last_run,
+            started_at,
+            folder_stats,
+            folders_clone.len(),
+            cancelled,
+        );
+        drop(run_guard);
+        let _ = event_sender.blocking_send(event);
+
+        if !cancelled {
+            spawn_thumbnail_warmup(warmup_state);
+        }
     });
 
-    Ok(Json(serde_json::json!({
+    Json(serde_json::json!({
         "status": "started",
         "message": format!("Processing {} folder(s)", folders_to_process.len()),
         "count": folders_to_process.len()
-    })))
+    }))
+    .into_response()
 }
 
-pub async fn processing_events_stream(
-    State(state): State<AppState>,
-) -> Sse<impl Stream<Item = Result<axum::response::sse::Event, Infallible>>> {
-    let (tx, rx) = mpsc::channel(100);
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct FolderInfo {
+    /// Position in `Settings.folders`, used by `DELETE /api/folders/{index}`
+    pub index: usize,
+    pub path: String,
+    /// Disabled folders have no photos on the map until enabled again
+    pub enabled: bool,
+    pub photo_count: usize,
+    /// "YYYY-MM-DD HH:MM:SS" (UTC) of the last time the folder's photos were stored
+    pub last_processed: Option<String>,
+    pub exists: bool,
+}
 
-    let mut event_receiver = state.event_broadcast.subscribe();
+/// Photo counts and status of the configured folders
+fn folder_infos(db: &Database, folders: Vec<FolderConfig>) -> Result<Vec<FolderInfo>> {
+    let paths: Vec<String> = folders.iter().map(|f| f.path.clone()).collect();
+    let counts = db.folder_photo_counts(&paths)?;
+    folders
+        .into_iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(index, (folder, photo_count))| {
+            Ok(FolderInfo {
+                index,
+                enabled: folder.enabled,
+                photo_count,
+                last_processed: db
+                    .folder_processed_at(&folder.path)?
+                    .map(format_unix_timestamp),
+                exists: std::path::Path::new(&folder.path).is_dir(),
+                path: folder.path,
+            })
+        })
+        .collect()
+}
 
-    tokio::spawn(async move {
-        loop {
-            tokio::select! {
-                event = event_receiver.recv() => {
-                    match event {
-                        Ok(processing_event) => {
-                            let sse_event = axum::response::sse::Event::default()
-                                .json_data(&processing_event)
-                                .unwrap_or_else(|_| axum::response::sse::Event::default().data("Error serializing event"));
-                            if tx.send(Ok(sse_event)).await.is_err() { break; }
-                        }
-                        Err(_) => break,
-                    }
-                }
-            }
+/// Configured folders with their photo counts
+#[utoipa::path(
+    get,
+    path = "/api/folders",
+    tag = "folders",
+    responses(
+        (status = 200, description = "Configured folders", body = Vec<FolderInfo>),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_folders(State(state): State<AppState>) -> Response {
+    let folders = state.settings.lock().await.folders.clone();
+
+    let db = state.db.clone();
+    match tokio::task::spawn_blocking(move || folder_infos(&db, folders)).await {
+        Ok(Ok(folders)) => Json(folders).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
-    });
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
 
-    let stream = ReceiverStream { rx };
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct AddFolderRequest {
+    pub path: String,
+}
 
-    Sse::new(stream).keep_alive(
-        axum::response::sse::KeepAlive::new()
-            .interval(Duration::from_secs(15))
-            .text("keepalive-message"),
+/// Adds a folder to the end of the folder list and processes its photos
+#[utoipa::path(
+    post,
+    path = "/api/folders",
+    tag = "folders",
+    request_body = AddFolderRequest,
+    responses(
+        (status = 200, description = "Processing of the folder started", body = Object),
+        (status = 400, description = "The folder does not exist", body = Object),
+        (status = 409, description = "Already added, or processing is running", body = Object)
     )
-}
+)]
+pub async fn add_folder(
+    State(state): State<AppState>,
+    Json(request): Json<AddFolderRequest>,
+) -> Response {
+    let error = |status: StatusCode, message: String| {
+        (
+            status,
+            Json(serde_json::json!({ "status": "error", "message": message })),
+        )
+            .into_response()
+    };
 
-pub async fn index_html() -> Html<&'static [u8]> {
-    Html(INDEX_HTML)
+    let folder = crate::settings::normalize_folder_path(&request.path);
+    if !std::path::Path::new(&folder).is_dir() {
+        return error(
+            StatusCode::BAD_REQUEST,
+            format!("Folder does not exist: {}", folder),
+        );
+    }
+    if state.processing.is_running() {
+        return already_running();
+    }
+
+    let (walk_options, processing_config) = {
+        let mut settings = state.settings.lock().await;
+        if settings.has_folder(&folder) {
+            return error(
+                StatusCode::CONFLICT,
+                format!("Folder is already added: {}", folder),
+            );
+        }
+        settings.keep_folders();
+        settings.folders.push(FolderConfig::new(&folder));
+        if let Err(e) = settings.save() {
+            tracing::error!("Failed to save settings: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        (WalkOptions::from_settings(&settings), settings.processing)
+    };
+    notify_folders_changed(&state, format!("Added {}", folder));
+
+    start_incremental_run(
+        &state,
+        vec![std::path::PathBuf::from(folder)],
+        walk_options,
+        processing_config,
+    )
 }
 
-pub async fn style_css() -> Response {
-    Response::builder()
-        .header(header::CONTENT_TYPE, "text/css")
-        .body(STYLE_CSS.to_vec().into())
-        .expect("Failed to build CSS response")
+/// Same as `POST /api/folders`, next to `/api/folders/remove`
+#[utoipa::path(
+    post,
+    path = "/api/folders/add",
+    tag = "folders",
+    request_body = AddFolderRequest,
+    responses(
+        (status = 200, description = "Processing of the folder started", body = Object),
+        (status = 400, description = "The folder does not exist", body = Object),
+        (status = 409, description = "Already added, or processing is running", body = Object)
+    )
+)]
+pub async fn add_folder_by_path(
+    state: State<AppState>,
+    request: Json<AddFolderRequest>,
+) -> Response {
+    add_folder(state, request).await
 }
 
-pub async fn script_js() -> Response {
-    Response::builder()
-        .header(header::CONTENT_TYPE, "application/javascript")
-        .body(SCRIPT_JS.to_vec().into())
-        .expect("Failed to build JS response")
+/// Removes a folder from the settings along with its photos
+#[utoipa::path(
+    delete,
+    path = "/api/folders/{index}",
+    tag = "folders",
+    params(("index" = usize, Path, description = "Position of the folder, see `FolderInfo::index`")),
+    responses(
+        (status = 200, description = "Folder and its photos removed", body = Object),
+        (status = 404, description = "No folder at this position"),
+        (status = 409, description = "Processing is already running", body = Object, example = json!({"status": "busy", "message": "Processing is already running. Cancel it or wait for it to finish"}))
+    )
+)]
+pub async fn remove_folder(
+    State(state): State<AppState>,
+    AxumPath(index): AxumPath<usize>,
+) -> Response {
+    remove_configured_folder(&state, |_| Some(index)).await
 }
 
-pub async fn shutdown_app(
+/// Removes a folder given by its path, see `remove_folder`
+#[utoipa::path(
+    post,
+    path = "/api/folders/remove",
+    tag = "folders",
+    request_body = AddFolderRequest,
+    responses(
+        (status = 200, description = "Folder and its photos removed", body = Object),
+        (status = 404, description = "The folder is not configured"),
+        (status = 409, description = "Processing is already running", body = Object, example = json!({"status": "busy", "message": "Processing is already running. Cancel it or wait for it to finish"}))
+    )
+)]
+pub async fn remove_folder_by_path(
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let _ = state.shutdown_sender.send(());
-    Ok(Json(serde_json::json!({
-        "status": "success",
-        "message": "Server shutting down"
-    })))
+    Json(request): Json<AddFolderRequest>,
+) -> Response {
+    let folder = crate::settings::normalize_folder_path(&request.path);
+    remove_configured_folder(&state, |folders| {
+        folders.iter().position(|f| f.path == folder)
+    })
+    .await
 }
 
-#[cfg(test)]
-mod tests {
-    use super::encode_url_path;
+/// Drops the folder `select` picks from the settings, then its photos and its
+/// cache; the caches of the other folders are left alone
+async fn remove_configured_folder(
+    state: &AppState,
+    select: impl FnOnce(&[FolderConfig]) -> Option<usize>,
+) -> Response {
+    if state.processing.is_running() {
+        return already_running();
+    }
 
-    #[test]
-    fn encodes_photo_paths_for_urls() {
-        assert_eq!(
-            encode_url_path("C телефона и чужие работы\\Маша OLD\\2024 10.jpg"),
-            "C%20%D1%82%D0%B5%D0%BB%D0%B5%D1%84%D0%BE%D0%BD%D0%B0%20%D0%B8%20%D1%87%D1%83%D0%B6%D0%B8%D0%B5%20%D1%80%D0%B0%D0%B1%D0%BE%D1%82%D1%8B/%D0%9C%D0%B0%D1%88%D0%B0%20OLD/2024%2010.jpg"
-        );
+    let folder = {
+        let mut settings = state.settings.lock().await;
+        let Some(index) = select(&settings.folders).filter(|&i| i < settings.folders.len()) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        settings.keep_folders();
+        let folder = settings.folders.remove(index).path;
+        if let Err(e) = settings.save() {
+            tracing::error!("Failed to save settings: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        folder
+    };
+
+    let db = state.db.clone();
+    let removed = folder.clone();
+    match tokio::task::spawn_blocking(move || db.remove_folder(&removed)).await {
+        Ok(Ok(())) => {
+            tracing::info!("Removed folder {}", folder);
+            notify_folders_changed(state, format!("Removed {}", folder));
+            Json(serde_json::json!({
+                "status": "success",
+                "path": folder
+            }))
+            .into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Failed to remove photos of {}: {}", folder, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     }
 }
 
-pub async fn select_folder_dialog(
-    State(_state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let folder_paths = tokio::task::spawn_blocking(crate::utils::select_folders_native)
-        .await
-        .map_err(|e| {
-            eprintln!("Task join error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+/// Tells open tabs to reload the folder list and the photos; best effort
+fn notify_folders_changed(state: &AppState, message: String) {
+    let _ = state.event_sender.try_send(ProcessingEvent {
+        event_type: "folders_changed".to_string(),
+        data: ProcessingData {
+            message: Some(message),
+            ..Default::default()
+        },
+    });
+}
 
-    if !folder_paths.is_empty() {
-        Ok(Json(serde_json::json!({
-            "status": "success",
-            "folder_paths": folder_paths,
-            "count": folder_paths.len(),
-            "message": if folder_paths.len() > 1 {
-                format!("{} folders selected", folder_paths.len())
-            } else {
-                "Folder selected".to_string()
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct FolderEnabledRequest {
+    pub enabled: bool,
+}
+
+/// Shows or hides the photos of a folder. A disabled folder keeps its cache,
+/// so enabling it again only processes the files changed in the meantime.
+#[utoipa::path(
+    post,
+    path = "/api/folders/{index}/enabled",
+    tag = "folders",
+    params(("index" = usize, Path, description = "Position of the folder, see `FolderInfo::index`")),
+    request_body = FolderEnabledRequest,
+    responses(
+        (status = 200, description = "`success`, or `started` when changed files are processed", body = Object),
+        (status = 404, description = "No folder at this position"),
+        (status = 409, description = "Processing is already running", body = Object, example = json!({"status": "busy", "message": "Processing is already running. Cancel it or wait for it to finish"}))
+    )
+)]
+pub async fn set_folder_enabled(
+    State(state): State<AppState>,
+    AxumPath(index): AxumPath<usize>,
+    Json(request): Json<FolderEnabledRequest>,
+) -> Response {
+    if state.processing.is_running() {
+        return already_running();
+    }
+
+    let (folder, changed, walk_options, processing_config) = {
+        let mut settings = state.settings.lock().await;
+        let Some(folder) = settings.folders.get_mut(index) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let changed = folder.enabled != request.enabled;
+        folder.enabled = request.enabled;
+        let folder = folder.path.clone();
+        if changed {
+            settings.keep_folders();
+            if let Err(e) = settings.save() {
+                tracing::error!("Failed to save settings: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
             }
-        })))
-    } else {
-        Ok(Json(serde_json::json!({
-            "status": "cancelled",
-            "message": "Folder selection cancelled"
-        })))
+        }
+        (
+            folder,
+            changed,
+            WalkOptions::from_settings(&settings),
+            settings.processing,
+        )
+    };
+    let success = || {
+        Json(serde_json::json!({
+            "status": "success",
+            "path": folder,
+            "enabled": request.enabled
+        }))
+        .into_response()
+    };
+    if !changed {
+        return success();
+    }
+
+    let db = state.db.clone();
+    let path = folder.clone();
+    let walk = walk_options.clone();
+    let loaded = tokio::task::spawn_blocking(move || {
+        if request.enabled {
+            db.load_folder(&path, &walk)
+        } else {
+            db.unload_folder(&path).map(|()| false)
+        }
+    })
+    .await;
+    let message = format!(
+        "{} {}",
+        if request.enabled {
+            "Enabled"
+        } else {
+            "Disabled"
+        },
+        folder
+    );
+    match loaded {
+        Ok(Ok(false)) => {
+            notify_folders_changed(&state, message);
+            success()
+        }
+        Ok(Ok(true)) => {
+            notify_folders_changed(&state, message);
+            start_incremental_run(
+                &state,
+                vec![std::path::PathBuf::from(&folder)],
+                walk_options,
+                processing_config,
+            )
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Failed to switch photos of {}: {}", folder, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     }
 }
 
-pub async fn reveal_file(
-    Json(file_path): Json<String>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    use std::process::Command;
-    let result = {
-        #[cfg(target_os = "windows")]
-        {
-            let clean_path = file_path.replace("/", "\\");
-            Command::new("explorer")
-                .arg(format!("/select,{}", clean_path))
-                .spawn()
+/// Asks the running processing job to stop; photos processed so far are kept
+#[utoipa::path(
+    post,
+    path = "/api/cancel",
+    tag = "processing",
+    responses((status = 200, description = "`cancelling`, or `idle` when nothing runs", body = Object))
+)]
+pub async fn cancel_processing(State(state): State<AppState>) -> Json<serde_json::Value> {
+    if !state.processing.is_running() {
+        return Json(serde_json::json!({
+            "status": "idle",
+            "message": "No processing is running"
+        }));
+    }
+
+    state.cancel_processing.store(true, Ordering::SeqCst);
+    Json(serde_json::json!({
+        "status": "cancelling",
+        "message": "Cancellation requested"
+    }))
+}
+
+/// Statistics of the last finished processing run, including files that failed to parse
+#[utoipa::path(
+    get,
+    path = "/api/stats/last-run",
+    tag = "processing",
+    responses((status = 200, description = "Results of the last finished run, null before the first one", body = Object))
+)]
+pub async fn get_last_run_stats(State(state): State<AppState>) -> Json<Option<LastRunStats>> {
+    Json(
+        state
+            .last_run
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone(),
+    )
+}
+
+/// Column order of `/api/export.csv`
+const CSV_COLUMNS: [&str; 7] = [
+    "filename",
+    "relative_path",
+    "lat",
+    "lng",
+    "datetime",
+    "location",
+    "is_heic",
+];
+
+/// Rows written per streamed chunk of the CSV export
+const CSV_CHUNK_ROWS: usize = 1000;
+
+/// Bytes written so far, leaving an empty writer in place
+fn take_csv(writer: &mut csv::Writer<Vec<u8>>) -> Result<Vec<u8>> {
+    std::mem::replace(writer, csv::Writer::from_writer(Vec::new()))
+        .into_inner()
+        .map_err(|e| anyhow::anyhow!("Failed to write CSV: {}", e.error()))
+}
+
+/// Writes the CSV export into `tx` in chunks of `CSV_CHUNK_ROWS` photos
+fn send_csv_chunks(
+    db: &Database,
+    tx: &mpsc::Sender<Result<Vec<u8>, std::io::Error>>,
+) -> Result<()> {
+    let photos = db.get_all_photos()?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_COLUMNS)?;
+    for (i, photo) in photos.iter().enumerate() {
+        // f64 Display is the shortest string that parses back to the same value
+        writer.write_record([
+            photo.filename.as_str(),
+            &photo.relative_path,
+            &photo.coords.lat().to_string(),
+            &photo.coords.lng().to_string(),
+            &photo.datetime,
+            photo.location.as_deref().unwrap_or(""),
+            if photo.is_heic { "true" } else { "false" },
+        ])?;
+        if (i + 1) % CSV_CHUNK_ROWS == 0 && tx.blocking_send(Ok(take_csv(&mut writer)?)).is_err() {
+            return Ok(()); // Client went away
         }
-        #[cfg(target_os = "macos")]
-        {
-            Command::new("open").arg("-R").arg(&file_path).spawn()
+    }
+    let _ = tx.blocking_send(Ok(take_csv(&mut writer)?));
+    Ok(())
+}
+
+/// All photo metadata as CSV, streamed in chunks as a download
+#[utoipa::path(
+    get,
+    path = "/api/export.csv",
+    tag = "photos",
+    responses((status = 200, description = "Every photo as one CSV row", content_type = "text/csv", body = String))
+)]
+pub async fn export_csv(State(state): State<AppState>) -> Response {
+    let (tx, rx) = mpsc::channel(4);
+    let db = state.db.clone();
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = send_csv_chunks(&db, &tx) {
+            tracing::error!("CSV export failed: {}", e);
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
         }
-        #[cfg(target_os = "linux")]
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/csv; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"photomap.csv\"",
+        )
+        .body(Body::from_stream(ReceiverStream { rx }))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// All photos as a GeoJSON FeatureCollection of points, streamed in chunks as
+/// a download for GIS tools
+#[utoipa::path(
+    get,
+    path = "/api/export/geojson",
+    tag = "photos",
+    responses((status = 200, description = "Every photo as a point feature", content_type = "application/geo+json", body = Object))
+)]
+pub async fn export_geojson(State(state): State<AppState>) -> Response {
+    let (tx, rx) = mpsc::channel(4);
+    let db = state.db.clone();
+    tokio::task::spawn_blocking(move || {
+        // A failed send means the client went away
+        if let Err(e) =
+            export::write_geojson_chunks(&db, |chunk| tx.blocking_send(Ok(chunk)).is_ok())
         {
-            Command::new("nautilus")
-                .arg("--select")
-                .arg(&file_path)
-                .spawn()
-                .or_else(|_| {
-                    use std::path::Path;
+            tracing::error!("GeoJSON export failed: {}", e);
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/geo+json")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"photomap.geojson\"",
+        )
+        .body(Body::from_stream(ReceiverStream { rx }))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct ExportZipRequest {
+    #[schema(value_type = Vec<String>, example = json!(["3f2a9c0d1b7e4a56"]))]
+    pub photo_ids: Vec<PhotoId>,
+}
+
+/// ZIP of the photos with the given ids, each stored under its relative path.
+/// Photos are compressed already, so entries are stored as they are. Unknown
+/// ids and files that cannot be read are skipped; None if nothing was added.
+fn build_zip(db: &Database, ids: &[PhotoId]) -> Result<Option<Cursor<Vec<u8>>>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut seen = HashSet::new();
+    let mut added = 0;
+    for id in ids {
+        if !seen.insert(id) {
+            continue;
+        }
+        let Some(photo) = db.get_photo_by_id(id)? else {
+            tracing::warn!("Skipping unknown photo {} in ZIP export", id);
+            continue;
+        };
+        let data = match std::fs::read(&photo.file_path) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Skipping {} in ZIP export: {}", photo.file_path, e);
+                continue;
+            }
+        };
+        zip.start_file(photo.relative_path.as_str(), options)?;
+        zip.write_all(&data)?;
+        added += 1;
+    }
+    if added == 0 {
+        return Ok(None);
+    }
+    Ok(Some(zip.finish()?))
+}
+
+/// Selected photos as a ZIP download, at most `export_max_photos` at a time
+#[utoipa::path(
+    post,
+    path = "/api/photos/export/zip",
+    tag = "photos",
+    request_body = ExportZipRequest,
+    responses(
+        (status = 200, description = "The originals under their relative paths", content_type = "application/zip"),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "No photos to export"})),
+        (status = 404, description = "None of the photos could be read")
+    )
+)]
+pub async fn export_zip(
+    State(state): State<AppState>,
+    Json(request): Json<ExportZipRequest>,
+) -> Response {
+    let max_photos = state.settings.lock().await.export_max_photos;
+    let bad_request = |message: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "status": "error", "message": message })),
+        )
+            .into_response()
+    };
+    if request.photo_ids.is_empty() {
+        return bad_request("No photos to export".to_string());
+    }
+    if request.photo_ids.len() > max_photos {
+        return bad_request(format!(
+            "At most {} photos can be exported at once",
+            max_photos
+        ));
+    }
+
+    let db = state.db.clone();
+    let span = tracing::Span::current();
+    let result = tokio::task::spawn_blocking(move || {
+        let _span = span.enter();
+        build_zip(&db, &request.photo_ids)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(archive))) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/zip")
+            .header(
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"photomap_export.zip\"",
+            )
+            .body(Body::from_stream(ReaderStream::new(archive)))
+            .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+        Ok(Ok(None)) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": "None of the photos could be found"
+            })),
+        )
+            .into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("ZIP export failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "status": "error", "message": e.to_string() })),
+            )
+                .into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Photos grouped by location name, for the places list
+#[utoipa::path(
+    get,
+    path = "/api/places",
+    tag = "library",
+    responses(
+        (status = 200, description = "Photos grouped by location name, largest first", body = Object),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_places(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    match tokio::task::spawn_blocking(move || db.places()).await {
+        Ok(Ok(places)) => Json(places).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TripsQuery {
+    pub max_gap_days: Option<f64>,
+    /// Same gap in hours, for sessions within a day; wins over `max_gap_days`
+    pub max_gap_hours: Option<f64>,
+    pub max_jump_km: Option<f64>,
+}
+
+/// Albums by name with their photo count and cover photo
+#[utoipa::path(
+    get,
+    path = "/api/albums",
+    tag = "library",
+    responses(
+        (status = 200, description = "Albums with their count and cover photo", body = Object),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_albums(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    match tokio::task::spawn_blocking(move || db.albums()).await {
+        Ok(Ok(albums)) => Json(albums).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Photos split into trips by time and distance gaps, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/trips",
+    tag = "library",
+    params(TripsQuery),
+    responses(
+        (status = 200, description = "Trips, oldest first", body = Object),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "max_gap_days, max_gap_hours and max_jump_km must be non-negative numbers"})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_trips(State(state): State<AppState>, Query(query): Query<TripsQuery>) -> Response {
+    let max_gap_hours = query
+        .max_gap_hours
+        .unwrap_or(query.max_gap_days.unwrap_or(DEFAULT_TRIP_MAX_GAP_DAYS) * 24.0);
+    let max_jump_km = query.max_jump_km.unwrap_or(DEFAULT_TRIP_MAX_JUMP_KM);
+    let time_gap = Duration::try_from_secs_f64(max_gap_hours * 3600.0);
+    let (Ok(time_gap), true) = (time_gap, max_jump_km.is_finite() && max_jump_km >= 0.0) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": "max_gap_days, max_gap_hours and max_jump_km must be non-negative numbers"
+            })),
+        )
+            .into_response();
+    };
+
+    let db = state.db.clone();
+    let result =
+        tokio::task::spawn_blocking(move || db.group_into_trips(time_gap, max_jump_km)).await;
+    match result {
+        Ok(Ok(trips)) => Json(trips).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Liveness for uptime checkers: photo count and whether place names are available
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "app",
+    responses(
+        (status = 200, description = "Server is up", body = Object, example = json!({"status": "ok", "photos": 1234, "geocoder_ready": true})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn health(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    match tokio::task::spawn_blocking(move || db.get_photos_count()).await {
+        Ok(Ok(photos)) => Json(serde_json::json!({
+            "status": "ok",
+            "photos": photos,
+            "geocoder_ready": geocoding::ReverseGeocoder::get().is_some(),
+        }))
+        .into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Version of the running binary and how it was built
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "app",
+    responses((status = 200, description = "Name, version and build profile", body = Object, example = json!({"name": "photomap_processor", "version": "0.12.1", "profile": "release"})))
+)]
+pub async fn version() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "profile": if cfg!(debug_assertions) { "debug" } else { "release" },
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+    }))
+}
+
+/// Aggregate numbers about the library, cached until the photos change
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    tag = "library",
+    responses(
+        (status = 200, description = "Totals, per-year and per-country counts", body = crate::database::LibraryStats),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_library_stats(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    match tokio::task::spawn_blocking(move || db.get_statistics()).await {
+        Ok(Ok(stats)) => Json(stats).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Dated photos per year, newest first, for the year badges of the sidebar
+#[utoipa::path(
+    get,
+    path = "/api/photos/years",
+    tag = "photos",
+    responses(
+        (status = 200, description = "Dated photos per year, newest first", body = Vec<crate::database::YearSummary>),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_photo_years(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    match tokio::task::spawn_blocking(move || db.get_year_summary()).await {
+        Ok(Ok(years)) => Json(years).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Results of `/api/search-place` when no limit is given
+const SEARCH_PLACE_LIMIT: usize = 10;
+const MAX_SEARCH_PLACE_LIMIT: usize = 100;
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchPlaceQuery {
+    #[serde(default)]
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct PlaceResult {
+    pub name: String,
+    pub admin1: Option<String>,
+    pub country: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Cities matching the query by name, for the map search box. Answers 503 instead of
+/// waiting while the geocoder is still loading.
+#[utoipa::path(
+    get,
+    path = "/api/search-place",
+    tag = "library",
+    params(SearchPlaceQuery),
+    responses(
+        (status = 200, description = "Matching cities, best first", body = Vec<PlaceResult>),
+        (status = 400, description = "`limit` is not a number"),
+        (status = 503, description = "The geocoder is still loading")
+    )
+)]
+pub async fn search_place(Query(query): Query<SearchPlaceQuery>) -> Response {
+    let Some(geocoder) = geocoding::ReverseGeocoder::get() else {
+        let initializing = !geocoding::ReverseGeocoder::is_initialized();
+        let mut response = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": if initializing {
+                    "Place search is still loading"
+                } else {
+                    "Place search is unavailable"
+                }
+            })),
+        )
+            .into_response();
+        if initializing {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        }
+        return response;
+    };
+
+    let limit = query
+        .limit
+        .unwrap_or(SEARCH_PLACE_LIMIT)
+        .min(MAX_SEARCH_PLACE_LIMIT);
+    let results: Vec<PlaceResult> = geocoder
+        .search(&query.q, limit)
+        .into_iter()
+        .map(|location| PlaceResult {
+            name: location.name.clone(),
+            admin1: (!location.admin1.is_empty()).then(|| location.admin1.clone()),
+            country: location.country.clone(),
+            lat: location.lat,
+            lon: location.lng,
+        })
+        .collect();
+    Json(results).into_response()
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClusterQuery {
+    /// "west,south,east,north"; the whole world when omitted
+    pub bbox: Option<String>,
+    pub zoom: u8,
+}
+
+/// Entry of `/api/clusters`: a cluster of photos or a single photo
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClusterItem {
+    Cluster { lat: f64, lng: f64, count: usize },
+    Photo(Box<ImageMetadata>),
+}
+
+/// Grids the photos in the visible map area server-side, so the browser only
+/// renders one marker per cell instead of every photo
+#[utoipa::path(
+    get,
+    path = "/api/clusters",
+    tag = "photos",
+    params(ClusterQuery),
+    responses(
+        (status = 200, description = "Clusters (`type` = `cluster`) and single photos (`type` = `photo`)", body = Object),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "Invalid bbox '10,50,20': expected west,south,east,north"})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_clusters(
+    State(state): State<AppState>,
+    Query(query): Query<ClusterQuery>,
+) -> Response {
+    let bbox = match query.bbox.as_deref().map(BoundingBox::parse) {
+        None => BoundingBox::WORLD,
+        Some(Ok(bbox)) => bbox,
+        Some(Err(e)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "status": "error", "message": e.to_string() })),
+            )
+                .into_response()
+        }
+    };
+
+    let db = state.db.clone();
+    let entries = match tokio::task::spawn_blocking(move || db.cluster(&bbox, query.zoom)).await {
+        Ok(Ok(entries)) => entries,
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let items: Vec<ClusterItem> = entries
+        .into_iter()
+        .map(|entry| match entry {
+            ClusterEntry::Cluster { lat, lng, count } => ClusterItem::Cluster { lat, lng, count },
+            ClusterEntry::Photo(photo) => {
+                ClusterItem::Photo(Box::new(tagged_image_metadata(&state.db, *photo)))
+            }
+        })
+        .collect();
+    Json(items).into_response()
+}
+
+/// A file from the no-GPS list with the id to geotag it by
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct NoGpsFile {
+    #[schema(value_type = String, example = "3f2a9c0d1b7e4a56")]
+    pub id: PhotoId,
+    #[serde(flatten)]
+    pub entry: NoGpsEntry,
+}
+
+/// Lists processed files without a usable location, with the reason for each
+#[utoipa::path(
+    get,
+    path = "/api/no-gps",
+    tag = "library",
+    responses(
+        (status = 200, description = "Processed files without a usable position", body = Vec<NoGpsFile>),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_no_gps_files(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<NoGpsFile>>, StatusCode> {
+    let entries = state.db.get_no_gps_entries().map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(
+        entries
+            .into_iter()
+            .map(|entry| NoGpsFile {
+                id: PhotoId::from_relative_path(&entry.relative_path),
+                entry,
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct LocationRequest {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+/// Sets the location of a photo by hand, including files from the no-GPS list.
+/// With `write_back` enabled the position is also written into the EXIF of a
+/// JPEG; other formats are only updated in the library.
+#[utoipa::path(
+    post,
+    path = "/api/photos/{photo}/location",
+    tag = "photos",
+    params(("photo" = String, Path, description = "Photo id, from the photo or the no-GPS list")),
+    request_body = LocationRequest,
+    responses(
+        (status = 200, description = "The photo with its new position", body = Object),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "Latitude 95 is outside [-90, 90]"})),
+        (status = 404, description = "Unknown photo")
+    )
+)]
+pub async fn set_photo_location(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<PhotoId>,
+    Json(request): Json<LocationRequest>,
+) -> Response {
+    let error = |status: StatusCode, message: String| {
+        (
+            status,
+            Json(serde_json::json!({ "status": "error", "message": message })),
+        )
+            .into_response()
+    };
+
+    let coords = match GpsCoordinate::new(request.lat, request.lng) {
+        Ok(coords) => coords,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    let existing = state.db.get_photo_by_id(&id);
+    let no_gps = state.db.get_no_gps_entry(&id);
+    let mut photo = match (existing, no_gps) {
+        (Ok(Some(photo)), _) => photo,
+        (_, Ok(Some(entry))) => manually_geotagged_photo(&entry, coords),
+        (Err(e), _) | (_, Err(e)) => {
+            return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+        }
+        _ => return error(StatusCode::NOT_FOUND, format!("Photo not found: {}", id)),
+    };
+    photo.coords = coords;
+    photo.source = ExifSource::Manual;
+    photo.place = geocoding::get_place(coords.lat(), coords.lng());
+    photo.location = photo.place.as_ref().map(geocoding::location_name);
+
+    let (write_back, folders, walk_options) = {
+        let settings = state.settings.lock().await;
+        let folders = settings.enabled_folders();
+        (
+            settings.write_back,
+            folders,
+            WalkOptions::from_settings(&settings),
+        )
+    };
+    // The writer only handles JPEG, so the content decides, not the extension
+    let written = write_back
+        && image_format_of(std::path::Path::new(&photo.file_path)) == Some(ImageFormat::Jpeg);
+    if written {
+        let file_path = std::path::PathBuf::from(&photo.file_path);
+        match tokio::task::spawn_blocking(move || write_gps_to_jpeg(&file_path, coords)).await {
+            Ok(Ok(())) => tracing::info!("Wrote location to {}", photo.file_path),
+            Ok(Err(e)) => {
+                tracing::error!("Failed to write location to {}: {:#}", photo.file_path, e);
+                return error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e));
+            }
+            Err(_) => return error(StatusCode::INTERNAL_SERVER_ERROR, "Task failed".to_string()),
+        }
+    }
+
+    if let Err(e) = state.db.upsert_geotagged_photo(&photo) {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    }
+    // Keep the manual location across restarts even when the file is not modified
+    let photo_folders: Vec<String> = folders
+        .into_iter()
+        .filter(|folder| std::path::Path::new(&photo.file_path).starts_with(folder))
+        .collect();
+    if !photo_folders.is_empty() {
+        if let Err(e) = state.db.save_to_disk(&photo_folders, &walk_options) {
+            tracing::warn!("Failed to save cache: {}", e);
+        }
+    }
+
+    Json(serde_json::json!({
+        "status": "success",
+        "id": photo.id,
+        "lat": coords.lat(),
+        "lng": coords.lng(),
+        "written_to_file": written,
+    }))
+    .into_response()
+}
+
+#[derive(Debug, serde::Serialize)]
+struct DuplicateGroup {
+    reason: DuplicateReason,
+    photos: Vec<ImageMetadata>,
+}
+
+/// Groups of photos taken in the same second at the same spot; `reason` is `both`
+/// when the files are identical too
+#[utoipa::path(
+    get,
+    path = "/api/photos/duplicates",
+    tag = "photos",
+    responses(
+        (status = 200, description = "Groups with their `reason` and `photos`", body = Object),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_duplicates(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || -> Result<Vec<DuplicateGroup>> {
+        Ok(db
+            .find_duplicates()?
+            .into_iter()
+            .map(|group| DuplicateGroup {
+                reason: verify_duplicates(&group),
+                photos: group.into_iter().map(image_metadata).collect(),
+            })
+            .collect())
+    })
+    .await;
+    match result {
+        Ok(Ok(groups)) => Json(groups).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct ResolveDuplicatesRequest {
+    /// Photos to drop from the library; the files stay on disk
+    #[schema(value_type = Vec<String>)]
+    pub ids: Vec<PhotoId>,
+}
+
+/// Removes the chosen copies from the library and saves the folder caches
+#[utoipa::path(
+    post,
+    path = "/api/photos/duplicates/resolve",
+    tag = "photos",
+    request_body = ResolveDuplicatesRequest,
+    responses(
+        (status = 200, description = "Number of photos removed", body = Object),
+        (status = 400, description = "Invalid parameters", body = Object, example = json!({"status": "error", "message": "No photos to remove"})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn resolve_duplicates(
+    State(state): State<AppState>,
+    Json(request): Json<ResolveDuplicatesRequest>,
+) -> Response {
+    if request.ids.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": "No photos to remove"
+            })),
+        )
+            .into_response();
+    }
+    let (folders, walk_options) = {
+        let settings = state.settings.lock().await;
+        let folders = settings.enabled_folders();
+        (folders, WalkOptions::from_settings(&settings))
+    };
+
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || -> Result<usize> {
+        let removed = db.remove_photos(&request.ids)?;
+        if removed > 0 && !folders.is_empty() {
+            db.save_to_disk(&folders, &walk_options)?;
+        }
+        Ok(removed)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(removed)) => {
+            tracing::info!("Removed {} duplicate photos", removed);
+            Json(serde_json::json!({ "status": "success", "removed": removed })).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Failed to remove duplicates: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "status": "error", "message": e.to_string() })),
+            )
+                .into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct SetTagsRequest {
+    #[schema(value_type = String)]
+    pub id: PhotoId,
+    /// Replaces the current tags; an empty list clears them
+    pub tags: Vec<String>,
+}
+
+/// Sets the tags of one photo and returns them as stored
+#[utoipa::path(
+    post,
+    path = "/api/photos/tags",
+    tag = "photos",
+    request_body = SetTagsRequest,
+    responses(
+        (status = 200, description = "Tags as stored", body = Object, example = json!({"id": "3f2a9c0d1b7e4a56", "tags": ["beach", "dog"]})),
+        (status = 404, description = "Unknown photo"),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn set_photo_tags(
+    State(state): State<AppState>,
+    Json(request): Json<SetTagsRequest>,
+) -> Response {
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || -> Result<Option<Vec<String>>> {
+        if db.get_photo_by_id(&request.id)?.is_none() {
+            return Ok(None);
+        }
+        db.set_photo_tags(&request.id, &request.tags).map(Some)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(Some(tags))) => {
+            Json(serde_json::json!({ "status": "success", "tags": tags })).into_response()
+        }
+        Ok(Ok(None)) => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "status": "error", "message": "Photo not found" })),
+        )
+            .into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Failed to save tags: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "status": "error", "message": e.to_string() })),
+            )
+                .into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Number of photos per tag, sorted by tag
+#[utoipa::path(
+    get,
+    path = "/api/tags",
+    tag = "photos",
+    responses(
+        (status = 200, description = "Number of photos per tag", body = Object, example = json!({"beach": 12, "dog": 3})),
+        (status = 500, description = "Database or I/O error")
+    )
+)]
+pub async fn get_tags(State(state): State<AppState>) -> Response {
+    let db = state.db.clone();
+    match tokio::task::spawn_blocking(move || db.tag_counts()).await {
+        Ok(Ok(counts)) => Json(counts).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Looks up the location name of every photo again and stores the ones that changed
+#[utoipa::path(
+    post,
+    path = "/api/geocode/refresh",
+    tag = "library",
+    responses(
+        (status = 200, description = "Number of photos whose location name changed", body = Object),
+        (status = 409, description = "Processing is already running", body = Object, example = json!({"status": "busy", "message": "Processing is already running. Cancel it or wait for it to finish"}))
+    )
+)]
+pub async fn refresh_locations(State(state): State<AppState>) -> Response {
+    let (folders, walk_options) = {
+        let settings = state.settings.lock().await;
+        let folders = settings.enabled_folders();
+        (folders, WalkOptions::from_settings(&settings))
+    };
+
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || -> Result<usize> {
+        if let Some(geocoder) = geocoding::ReverseGeocoder::get() {
+            geocoder.clear_cache();
+        }
+        let changed: Vec<PhotoMetadata> = db
+            .get_all_photos()?
+            .into_par_iter()
+            .filter_map(|mut photo| {
+                let place = geocoding::get_place(photo.coords.lat(), photo.coords.lng());
+                let location = place.as_ref().map(geocoding::location_name);
+                (location != photo.location || place != photo.place).then(|| {
+                    photo.location = location;
+                    photo.place = place;
+                    photo
+                })
+            })
+            .collect();
+        db.insert_photos_batch(&changed)?;
+        if !changed.is_empty() && !folders.is_empty() {
+            db.save_to_disk(&folders, &walk_options)?;
+        }
+        Ok(changed.len())
+    })
+    .await;
+
+    match result {
+        Ok(Ok(updated)) => {
+            tracing::info!("Refreshed locations of {} photos", updated);
+            Json(serde_json::json!({ "status": "success", "updated": updated })).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Failed to refresh locations: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "status": "error", "message": e.to_string() })),
+            )
+                .into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Reports whether a processing run is active, so a reloaded page can resume its state
+#[utoipa::path(
+    get,
+    path = "/api/processing-status",
+    tag = "processing",
+    responses((status = 200, description = "`idle`, or `running` with the run details", body = Object))
+)]
+pub async fn get_processing_status(State(state): State<AppState>) -> Json<serde_json::Value> {
+    match state.processing.current() {
+        Some(run) => Json(serde_json::json!({
+            "status": "running",
+            "started_at": run.started_at,
+            "folders": run.folders,
+            "cancelling": state.cancel_processing.load(Ordering::SeqCst),
+        })),
+        None => Json(serde_json::json!({ "status": "idle" })),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "processing",
+    responses((status = 200, description = "Server-sent processing events", content_type = "text/event-stream", body = ProcessingEvent))
+)]
+pub async fn processing_events_stream(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<axum::response::sse::Event, Infallible>>> {
+    let (tx, rx) = mpsc::channel(100);
+
+    let mut event_receiver = state.event_broadcast.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                event = event_receiver.recv() => {
+                    match event {
+                        Ok(processing_event) => {
+                            let sse_event = axum::response::sse::Event::default()
+                                .json_data(&processing_event)
+                                .unwrap_or_else(|_| axum::response::sse::Event::default().data("Error serializing event"));
+                            if tx.send(Ok(sse_event)).await.is_err() { break; }
+                        }
+                        Err(_) => break,
+                    }
+                }
+            }
+        }
+    });
+
+    let stream = ReceiverStream { rx };
+
+    Sse::new(stream).keep_alive(
+        axum::response::sse::KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("keepalive-message"),
+    )
+}
+
+/// How often WebSocket clients are pinged; one missed pong closes the connection
+const WS_PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Relays processing events as JSON text frames, for proxies that buffer SSE
+#[utoipa::path(
+    get,
+    path = "/api/ws",
+    tag = "processing",
+    responses((status = 101, description = "WebSocket relaying the processing events as JSON text frames"))
+)]
+pub async fn processing_events_ws(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    let events = state.event_broadcast.subscribe();
+    ws.on_upgrade(move |socket| relay_events(socket, events))
+}
+
+/// Runs in the connection's own task, so it ends with the socket and drops its subscription
+async fn relay_events(mut socket: WebSocket, mut events: broadcast::Receiver<ProcessingEvent>) {
+    let mut keepalive = tokio::time::interval(WS_PING_INTERVAL);
+    keepalive.tick().await;
+    let mut awaiting_pong = false;
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let Ok(json) = serde_json::to_string(&event) else { continue };
+                    if socket.send(Message::Text(json)).await.is_err() {
+                        break;
+                    }
+                }
+                // A slow client misses some progress updates but keeps the connection
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Pong(_))) => awaiting_pong = false,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by axum; the client has nothing else to say
+                Some(Ok(_)) => {}
+            },
+            _ = keepalive.tick() => {
+                if awaiting_pong || socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                awaiting_pong = true;
+            }
+        }
+    }
+}
+
+pub async fn index_html() -> Html<&'static [u8]> {
+    Html(INDEX_HTML)
+}
+
+pub async fn style_css() -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/css")
+        .body(STYLE_CSS.to_vec().into())
+        .expect("Failed to build CSS response")
+}
+
+pub async fn script_js() -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/javascript")
+        .body(SCRIPT_JS.to_vec().into())
+        .expect("Failed to build JS response")
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/shutdown",
+    tag = "app",
+    responses((status = 200, description = "The server stops after answering", body = Object))
+)]
+pub async fn shutdown_app(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let _ = state.shutdown_sender.send(());
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "message": "Server shutting down"
+    })))
+}
+
+/// Opens the native folder picker and adds the picked folders to the free
+/// slots, keeping the configured ones. Only one picker is open at a time. The
+/// response lists the folders so the UI can start processing right away.
+#[utoipa::path(
+    post,
+    path = "/api/select-folder-dialog",
+    tag = "folders",
+    responses(
+        (status = 200, description = "`success` with the folder list, or `cancelled`", body = Object),
+        (status = 409, description = "A dialog is already open, or processing is running", body = Object)
+    )
+)]
+pub async fn select_folder_dialog(State(state): State<AppState>) -> Response {
+    pick_folders(&state, crate::utils::select_folders_native).await
+}
+
+/// `select_folder_dialog` with the picker passed in
+async fn pick_folders(
+    state: &AppState,
+    picker: impl FnOnce() -> Vec<String> + Send + 'static,
+) -> Response {
+    if state.processing.is_running() {
+        return already_running();
+    }
+    let Some(dialog) = state.folder_dialog.try_open() else {
+        return (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "status": "busy",
+                "message": "A folder dialog is already open"
+            })),
+        )
+            .into_response();
+    };
+
+    // The dialog stays marked open until the picker returns, even if the client left
+    let picked = tokio::task::spawn_blocking(move || {
+        let _dialog = dialog;
+        picker()
+    })
+    .await;
+    let picked = match picked {
+        Ok(picked) => picked,
+        Err(e) => {
+            tracing::error!("Task join error: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    if picked.is_empty() {
+        return Json(serde_json::json!({
+            "status": "cancelled",
+            "message": "Folder selection cancelled"
+        }))
+        .into_response();
+    }
+
+    let (merge, folders) = {
+        let mut settings = state.settings.lock().await;
+        let merge = settings.merge_folders(&picked);
+        if !merge.added.is_empty() {
+            if let Err(e) = settings.save() {
+                tracing::error!("Failed to save settings: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+        (merge, settings.folders.clone())
+    };
+
+    let db = state.db.clone();
+    let folders = match tokio::task::spawn_blocking(move || folder_infos(&db, folders)).await {
+        Ok(Ok(folders)) => folders,
+        Ok(Err(e)) => {
+            tracing::error!("Database error: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let message = match merge.added.len() {
+        0 => "No new folders selected".to_string(),
+        1 => "Folder added".to_string(),
+        added => format!("{} folders added", added),
+    };
+    if !merge.added.is_empty() {
+        notify_folders_changed(state, message.clone());
+    }
+    Json(serde_json::json!({
+        "status": "success",
+        "added": merge.added,
+        "existing": merge.existing,
+        "folders": folders,
+        "message": message
+    }))
+    .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/reveal-file",
+    tag = "app",
+    request_body(content = String, description = "Path of the file", example = json!("/home/me/Pictures/IMG_0042.jpg")),
+    responses(
+        (status = 200, description = "File manager opened", body = Object),
+        (status = 500, description = "No file manager could be started")
+    )
+)]
+pub async fn reveal_file(
+    Json(file_path): Json<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    use std::process::Command;
+    let result = {
+        #[cfg(target_os = "windows")]
+        {
+            let clean_path = file_path.replace("/", "\\");
+            Command::new("explorer")
+                .arg(format!("/select,{}", clean_path))
+                .spawn()
+        }
+        #[cfg(target_os = "macos")]
+        {
+            Command::new("open").arg("-R").arg(&file_path).spawn()
+        }
+        #[cfg(target_os = "linux")]
+        {
+            Command::new("nautilus")
+                .arg("--select")
+                .arg(&file_path)
+                .spawn()
+                .or_else(|_| {
+                    use std::path::Path;
                     let parent = Path::new(&file_path)
                         .parent()
                         .and_then(|p| p.to_str())
@@ -678,8 +3393,859 @@ pub async fn reveal_file(
     match result {
         Ok(_) => Ok(Json(serde_json::json!({"status": "success"}))),
         Err(e) => {
-            eprintln!("❌ Failed to open file manager: {}", e);
+            tracing::error!("Failed to open file manager: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, ExifSource, PhotoMetadata};
+    use crate::export::GEOJSON_CHUNK_FEATURES;
+    use crate::image_cache::ImageCache;
+    use crate::image_processing::DiskThumbnailCache;
+    use crate::types::GpsCoordinate;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn test_state() -> AppState {
+        AppState {
+            db: Database::new().unwrap(),
+            settings: Arc::new(Mutex::new(Settings::default())),
+            image_cache: ImageCache::new(16 * 1024 * 1024),
+            disk_cache: DiskThumbnailCache::new(
+                std::env::temp_dir()
+                    .join(format!("photomap_test_thumbnails_{}", std::process::id())),
+            ),
+            cancel_processing: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            processing: Default::default(),
+            folder_dialog: Default::default(),
+            last_run: Default::default(),
+            event_sender: mpsc::channel(16).0,
+            event_broadcast: broadcast::channel(16).0,
+            shutdown_sender: broadcast::channel(1).0,
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("photomap_test_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_jpeg(path: &Path, shade: u8) {
+        image::RgbImage::from_pixel(64, 48, image::Rgb([shade, 100, 200]))
+            .save(path)
+            .unwrap();
+    }
+
+    fn insert_photo(db: &Database, path: &Path) {
+        db.insert_photo(&PhotoMetadata {
+            file_path: path.to_string_lossy().to_string(),
+            ..PhotoMetadata::sample("photo.jpg", "2024-06-01 12:00:00", 48.8566, 2.3522)
+        })
+        .unwrap();
+    }
+
+    fn if_none_match(etag: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+        headers
+    }
+
+    fn response_etag(response: &Response) -> String {
+        response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    async fn get_marker(state: &AppState, headers: HeaderMap) -> Response {
+        get_marker_image(
+            State(state.clone()),
+            AxumPath("photo.jpg".to_string()),
+            headers,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn image_etag_returns_not_modified_until_file_changes() {
+        let dir = test_dir("etag_image");
+        let path = dir.join("photo.jpg");
+        write_jpeg(&path, 10);
+        let state = test_state();
+        insert_photo(&state.db, &path);
+
+        let first = get_marker(&state, HeaderMap::new()).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = response_etag(&first);
+
+        let cached = get_marker(&state, if_none_match(&etag)).await;
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+
+        write_jpeg(&path, 250);
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(std::time::SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+
+        let updated = get_marker(&state, if_none_match(&etag)).await;
+        assert_eq!(updated.status(), StatusCode::OK);
+        assert_ne!(response_etag(&updated), etag);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn image_etag_changes_after_reprocess() {
+        let dir = test_dir("etag_generation");
+        let path = dir.join("photo.jpg");
+        write_jpeg(&path, 10);
+        let state = test_state();
+        insert_photo(&state.db, &path);
+
+        let etag = response_etag(&get_marker(&state, HeaderMap::new()).await);
+
+        state.db.clear_all_photos().unwrap();
+        insert_photo(&state.db, &path);
+
+        let response = get_marker(&state, if_none_match(&etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn repeated_image_requests_hit_the_cache() {
+        let dir = test_dir("image_cache_hits");
+        let path = dir.join("photo.jpg");
+        write_jpeg(&path, 10);
+        let state = test_state();
+        insert_photo(&state.db, &path);
+
+        assert_eq!(
+            get_marker(&state, HeaderMap::new()).await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_marker(&state, HeaderMap::new()).await.status(),
+            StatusCode::OK
+        );
+
+        let Json(stats) = get_cache_stats(State(state.clone())).await;
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+        assert!(stats.bytes > 0);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn photos_etag_returns_not_modified_for_same_set() {
+        let dir = test_dir("etag_photos");
+        let path = dir.join("photo.jpg");
+        write_jpeg(&path, 10);
+        let state = test_state();
+        insert_photo(&state.db, &path);
+
+        let first = get_all_photos(
+            State(state.clone()),
+            Query(PhotosQuery { album: None }),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = response_etag(&first);
+
+        let cached = get_all_photos(
+            State(state.clone()),
+            Query(PhotosQuery { album: None }),
+            if_none_match(&etag),
+        )
+        .await
+        .unwrap();
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+
+        insert_photo(&state.db, &path);
+        let rewritten = get_all_photos(
+            State(state.clone()),
+            Query(PhotosQuery { album: None }),
+            if_none_match(&etag),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rewritten.status(), StatusCode::OK);
+        let etag = response_etag(&rewritten);
+
+        state.db.clear_all_photos().unwrap();
+        let changed = get_all_photos(
+            State(state.clone()),
+            Query(PhotosQuery { album: None }),
+            if_none_match(&etag),
+        )
+        .await
+        .unwrap();
+        assert_eq!(changed.status(), StatusCode::OK);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn geojson_export_is_streamed_in_chunks() {
+        use futures_util::StreamExt;
+
+        let state = test_state();
+        let total = 50_000;
+        let photos: Vec<PhotoMetadata> = (0..total)
+            .map(|i| {
+                let datetime = format!(
+                    "2024-01-01 {:02}:{:02}:{:02}",
+                    i / 3600 % 24,
+                    i / 60 % 60,
+                    i % 60
+                );
+                PhotoMetadata {
+                    direction: (i % 2 == 0).then_some(90.0),
+                    ..PhotoMetadata::sample(
+                        &format!("{}/IMG_{i:05}.jpg", i / 1000),
+                        &datetime,
+                        40.0 + i as f64 * 1e-4,
+                        -3.7,
+                    )
+                }
+            })
+            .collect();
+        state.db.insert_photos_batch(&photos).unwrap();
+
+        let response = export_geojson(State(state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/geo+json"
+        );
+
+        // Arrives as many small pieces, never as one string
+        let mut stream = response.into_body().into_data_stream();
+        let mut body = Vec::new();
+        let mut chunks = 0;
+        let mut largest = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.unwrap();
+            chunks += 1;
+            largest = largest.max(chunk.len());
+            body.extend_from_slice(&chunk);
+        }
+        assert!(chunks >= total / GEOJSON_CHUNK_FEATURES, "{chunks} chunks");
+        assert!(largest * 20 < body.len(), "largest chunk {largest} bytes");
+
+        let geojson: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), total);
+        let first = &features[0];
+        assert_eq!(first["type"], "Feature");
+        assert_eq!(first["geometry"]["type"], "Point");
+        assert_eq!(
+            first["geometry"]["coordinates"],
+            serde_json::json!([-3.7, 40.0])
+        );
+        assert_eq!(first["properties"]["relative_path"], "0/IMG_00000.jpg");
+        assert_eq!(first["properties"]["direction"], 90.0);
+    }
+
+    #[tokio::test]
+    async fn photos_are_streamed_across_chunks() {
+        let state = test_state();
+        assert!(photos_json(&state).await.is_empty());
+
+        let total = PHOTOS_CHUNK * 2 + 5;
+        let photos: Vec<PhotoMetadata> = (0..total)
+            .map(|i| {
+                let relative_path = format!("{}/IMG_{i:05}.jpg", ["Holidays", "Family"][i % 2]);
+                let datetime = format!("2024-01-01 00:{:02}:{:02}", i / 60 % 60, i % 60);
+                PhotoMetadata::sample(&relative_path, &datetime, 48.8566, 2.3522)
+            })
+            .collect();
+        state.db.insert_photos_batch(&photos).unwrap();
+
+        let all = photos_json(&state).await;
+        assert_eq!(all.len(), total);
+        let datetimes: Vec<&str> = all
+            .iter()
+            .map(|p| p["datetime"].as_str().unwrap())
+            .collect();
+        assert!(datetimes.windows(2).all(|w| w[0] >= w[1]));
+
+        let response = get_all_photos(
+            State(state.clone()),
+            Query(PhotosQuery {
+                album: Some("Family".to_string()),
+            }),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let family: Vec<serde_json::Value> =
+            serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(family.len(), total / 2);
+        assert!(family.iter().all(|p| p["album"] == "Family"));
+    }
+
+    #[tokio::test]
+    async fn duplicate_copies_are_reported_and_resolved() {
+        let dir = test_dir("duplicates");
+        let state = test_state();
+        // The same JPEG in two places, both at the position and time of `insert_photo`
+        insert_photo(&state.db, &dir.join("photo.jpg"));
+        let template = state.db.get_all_photos().unwrap().pop().unwrap();
+        state.db.clear_all_photos().unwrap();
+        for name in ["phone.jpg", "camera.jpg"] {
+            let path = dir.join(name);
+            write_jpeg(&path, 10);
+            state
+                .db
+                .insert_photo(&PhotoMetadata {
+                    id: PhotoId::from_relative_path(name),
+                    filename: name.to_string(),
+                    relative_path: name.to_string(),
+                    file_path: path.to_string_lossy().into_owned(),
+                    ..template.clone()
+                })
+                .unwrap();
+        }
+
+        let response = get_duplicates(State(state.clone())).await;
+        let groups: Vec<serde_json::Value> =
+            serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0]["reason"], "both");
+        assert_eq!(groups[0]["photos"].as_array().unwrap().len(), 2);
+
+        let empty = resolve_duplicates(
+            State(state.clone()),
+            Json(ResolveDuplicatesRequest { ids: Vec::new() }),
+        )
+        .await;
+        assert_eq!(empty.status(), StatusCode::BAD_REQUEST);
+
+        let resolved = resolve_duplicates(
+            State(state.clone()),
+            Json(ResolveDuplicatesRequest {
+                ids: vec![PhotoId::from_relative_path("phone.jpg")],
+            }),
+        )
+        .await;
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(resolved).await).unwrap();
+        assert_eq!(body["removed"], 1);
+        assert_eq!(state.db.get_photos_count().unwrap(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    async fn get_photo(state: &AppState, method: &str, range: Option<&str>) -> Response {
+        let mut request = Request::builder().method(method).uri("/photos/photo.jpg");
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        serve_photo(
+            State(state.clone()),
+            AxumPath("photo.jpg".to_string()),
+            request.body(Body::empty()).unwrap(),
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn body_bytes(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    async fn photos_json(state: &AppState) -> Vec<serde_json::Value> {
+        let response = get_all_photos(
+            State(state.clone()),
+            Query(PhotosQuery { album: None }),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        serde_json::from_slice(&body_bytes(response).await).unwrap()
+    }
+
+    #[tokio::test]
+    async fn csv_export_quotes_fields_and_keeps_full_precision() {
+        let state = test_state();
+        let lat = 48.856_613_123_456_78;
+        state
+            .db
+            .insert_photo(&PhotoMetadata {
+                id: PhotoId::default(),
+                is_heic: true,
+                location: Some("Paris, FR".to_string()),
+                ..PhotoMetadata::sample(
+                    "2024/Paris, \"Louvre\".jpg",
+                    "2024-06-01 12:00:00",
+                    lat,
+                    -0.1,
+                )
+            })
+            .unwrap();
+
+        let response = export_csv(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_DISPOSITION]
+            .to_str()
+            .unwrap()
+            .starts_with("attachment"));
+
+        let body = body_bytes(response).await;
+        let mut reader = csv::Reader::from_reader(body.as_slice());
+        assert_eq!(reader.headers().unwrap(), CSV_COLUMNS.as_slice());
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(&rows[0][0], "Paris, \"Louvre\".jpg");
+        assert_eq!(rows[0][2].parse::<f64>().unwrap(), lat);
+        assert_eq!(&rows[0][5], "Paris, FR");
+        assert_eq!(&rows[0][6], "true");
+    }
+
+    #[tokio::test]
+    async fn photos_use_stored_location_until_refreshed() {
+        let state = test_state();
+        insert_photo(&state.db, Path::new("/photos/photo.jpg"));
+        let mut photo = state.db.get_all_photos().unwrap().pop().unwrap();
+        photo.location = Some("Stored Place".to_string());
+        state.db.insert_photo(&photo).unwrap();
+
+        // The listing echoes the stored name instead of asking the geocoder
+        let photos = photos_json(&state).await;
+        assert_eq!(photos[0]["location"], "Stored Place");
+
+        let response = refresh_locations(State(state.clone())).await;
+        let result: serde_json::Value =
+            serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(result["updated"], 1);
+
+        let expected = geocoding::get_location_name(48.8566, 2.3522);
+        assert!(expected.is_some());
+        let photos = photos_json(&state).await;
+        assert_eq!(photos[0]["location"], serde_json::json!(expected));
+    }
+
+    fn photo_with_content(name: &str) -> (AppState, PathBuf, Vec<u8>) {
+        let dir = test_dir(name);
+        let path = dir.join("photo.jpg");
+        let content: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+        let state = test_state();
+        insert_photo(&state.db, &path);
+        (state, dir, content)
+    }
+
+    #[tokio::test]
+    async fn serves_full_photo_with_last_modified() {
+        let (state, dir, content) = photo_with_content("serve_full");
+
+        let response = get_photo(&state, "GET", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/jpeg");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "1000");
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert!(response.headers().contains_key(header::LAST_MODIFIED));
+        assert_eq!(body_bytes(response).await, content);
+
+        let head = get_photo(&state, "HEAD", None).await;
+        assert_eq!(head.status(), StatusCode::OK);
+        assert_eq!(head.headers()[header::CONTENT_LENGTH], "1000");
+        assert!(body_bytes(head).await.is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn serves_prefix_and_suffix_ranges() {
+        let (state, dir, content) = photo_with_content("serve_ranges");
+
+        let prefix = get_photo(&state, "GET", Some("bytes=0-99")).await;
+        assert_eq!(prefix.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(prefix.headers()[header::CONTENT_RANGE], "bytes 0-99/1000");
+        assert_eq!(prefix.headers()[header::CONTENT_LENGTH], "100");
+        assert_eq!(body_bytes(prefix).await, &content[..100]);
+
+        let suffix = get_photo(&state, "GET", Some("bytes=-100")).await;
+        assert_eq!(suffix.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            suffix.headers()[header::CONTENT_RANGE],
+            "bytes 900-999/1000"
+        );
+        assert_eq!(body_bytes(suffix).await, &content[900..]);
+
+        let open_ended = get_photo(&state, "GET", Some("bytes=990-")).await;
+        assert_eq!(body_bytes(open_ended).await, &content[990..]);
+
+        let unsatisfiable = get_photo(&state, "GET", Some("bytes=1000-1100")).await;
+        assert_eq!(unsatisfiable.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            unsatisfiable.headers()[header::CONTENT_RANGE],
+            "bytes */1000"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn serves_ranges_of_large_sparse_file() {
+        let dir = test_dir("serve_sparse");
+        let path = dir.join("photo.jpg");
+        let size: u64 = 300 * 1024 * 1024;
+        {
+            use std::io::{Seek, SeekFrom, Write};
+            let mut file = std::fs::File::create(&path).unwrap();
+            file.set_len(size).unwrap();
+            file.seek(SeekFrom::End(-4)).unwrap();
+            file.write_all(b"TAIL").unwrap();
+        }
+        let state = test_state();
+        insert_photo(&state.db, &path);
+
+        let range = format!("bytes={}-{}", size - 8, size - 1);
+        let response = get_photo(&state, "GET", Some(&range)).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            format!("bytes {}-{}/{}", size - 8, size - 1, size).as_str()
+        );
+        assert_eq!(body_bytes(response).await, b"\0\0\0\0TAIL");
+
+        let head = get_photo(&state, "HEAD", None).await;
+        assert_eq!(
+            head.headers()[header::CONTENT_LENGTH],
+            size.to_string().as_str()
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn concurrent_runs_are_rejected_with_conflict() {
+        let state = test_state();
+        let dir = test_dir("processing_busy");
+        state.settings.lock().await.folders = vec![FolderConfig::new(&dir.to_string_lossy())];
+
+        let guard = state
+            .processing
+            .try_start(std::slice::from_ref(&dir))
+            .unwrap();
+        let Json(status) = get_processing_status(State(state.clone())).await;
+        assert_eq!(status["status"], "running");
+        assert_eq!(status["folders"][0], dir.to_string_lossy().as_ref());
+
+        for response in [
+            reprocess_photos(State(state.clone())).await,
+            initiate_processing(State(state.clone())).await,
+        ] {
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+            let body: serde_json::Value =
+                serde_json::from_slice(&body_bytes(response).await).unwrap();
+            assert_eq!(body["status"], "busy");
+        }
+
+        drop(guard);
+        let Json(status) = get_processing_status(State(state.clone())).await;
+        assert_eq!(status["status"], "idle");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn folder_picker_is_not_opened_twice() {
+        let state = test_state();
+        let dir = test_dir("folder_picker");
+        let folder = dir.to_string_lossy().to_string();
+        state.settings.lock().await.folders =
+            vec![FolderConfig::new("Photos 2023"), FolderConfig::new(&folder)];
+
+        let json = |response: Response| async move {
+            let status = response.status();
+            let body: serde_json::Value =
+                serde_json::from_slice(&body_bytes(response).await).unwrap();
+            (status, body)
+        };
+
+        // A second request while the dialog is open does not run the picker
+        let open = state.folder_dialog.try_open().unwrap();
+        let (status, body) =
+            json(pick_folders(&state, || panic!("picker opened twice")).await).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["status"], "busy");
+        drop(open);
+
+        let (_, body) = json(pick_folders(&state, Vec::new).await).await;
+        assert_eq!(body["status"], "cancelled");
+
+        // Picking a configured folder keeps its position and lists the folders
+        let picked = folder.clone();
+        let (status, body) = json(pick_folders(&state, move || vec![picked]).await).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "success");
+        assert_eq!(body["existing"], serde_json::json!([folder]));
+        assert!(body["added"].as_array().unwrap().is_empty());
+        assert_eq!(body["folders"].as_array().unwrap().len(), 2);
+        assert_eq!(body["folders"][1]["index"], 1);
+        assert_eq!(body["folders"][1]["enabled"], true);
+        assert_eq!(body["folders"][1]["exists"], true);
+        assert!(state.folder_dialog.try_open().is_some());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn marker_batch_returns_sprite_with_manifest() {
+        let state = test_state();
+        let dir = test_dir("marker_batch");
+        for (name, shade) in [("a.jpg", 0u8), ("b.jpg", 250)] {
+            let path = dir.join(name);
+            write_jpeg(&path, shade);
+            state
+                .db
+                .insert_photo(&PhotoMetadata {
+                    file_path: path.to_string_lossy().to_string(),
+                    ..PhotoMetadata::sample(name, "2024-06-01 12:00:00", 48.8566, 2.3522)
+                })
+                .unwrap();
+        }
+
+        let paths = ["b.jpg", "a.jpg", "missing.jpg", "a.jpg"].map(String::from);
+        let response = get_marker_batch(State(state.clone()), Json(paths.to_vec())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap()
+            .to_string();
+        let body = body_bytes(response).await;
+
+        let delimiter = format!("\r\n--{boundary}");
+        let parts: Vec<&[u8]> = split_bytes(&body[2..], delimiter.as_bytes());
+        assert_eq!(parts.len(), 3);
+        let part_body = |part: &[u8]| {
+            let start = part.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+            part[start..].to_vec()
+        };
+        let manifest: serde_json::Value = serde_json::from_slice(&part_body(parts[0])).unwrap();
+        assert_eq!(manifest["missing"], serde_json::json!(["missing.jpg"]));
+        assert_eq!(manifest["tiles"]["a.jpg"]["x"], 0);
+        assert_eq!(manifest["tiles"]["b.jpg"]["x"], 40);
+        assert_eq!(
+            (manifest["width"].clone(), manifest["height"].clone()),
+            (80.into(), 40.into())
+        );
+
+        let sprite = image::load_from_memory(&part_body(parts[1])).unwrap();
+        assert_eq!((sprite.width(), sprite.height()), (80, 40));
+        assert_eq!(state.image_cache.stats().entries, 2);
+
+        let too_many = vec!["a.jpg".to_string(); MAX_SPRITE_TILES + 1];
+        let response = get_marker_batch(State(state), Json(too_many)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn split_bytes<'a>(data: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+        let mut parts = Vec::new();
+        let mut rest = data;
+        while let Some(pos) = rest.windows(delimiter.len()).position(|w| w == delimiter) {
+            parts.push(&rest[..pos]);
+            rest = &rest[pos + delimiter.len()..];
+        }
+        parts.push(rest);
+        parts
+    }
+
+    #[tokio::test]
+    async fn geotags_photo_from_no_gps_list_and_writes_exif() {
+        let state = test_state();
+        state.settings.lock().await.write_back = true;
+        let dir = test_dir("manual_geotag");
+        std::fs::create_dir_all(dir.join("2024")).unwrap();
+        let path = dir.join("2024/pin.jpg");
+        write_jpeg(&path, 10);
+        state
+            .db
+            .add_no_gps_entries(vec![NoGpsEntry {
+                path: path.to_string_lossy().to_string(),
+                relative_path: "2024/pin.jpg".to_string(),
+                reason: "GPS data not found".to_string(),
+            }])
+            .unwrap();
+
+        let set = |relative_path: &str, lat: f64, lng: f64| {
+            set_photo_location(
+                State(state.clone()),
+                AxumPath(PhotoId::from_relative_path(relative_path)),
+                Json(LocationRequest { lat, lng }),
+            )
+        };
+        assert_eq!(
+            set("2024/pin.jpg", 95.0, 0.0).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            set("2024/other.jpg", 1.0, 2.0).await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let response = set("2024/pin.jpg", 59.3293, 18.0686).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body["written_to_file"], true);
+
+        let photo = state
+            .db
+            .get_photo_by_id(&PhotoId::from_relative_path("2024/pin.jpg"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(photo.source, ExifSource::Manual);
+        assert_eq!(photo.coords, GpsCoordinate::new(59.3293, 18.0686).unwrap());
+        assert!(state.db.get_no_gps_entries().unwrap().is_empty());
+
+        let crate::exif_parser::ExtractedMetadata { lat, lng, .. } =
+            crate::exif_parser::extract_metadata_from_jpeg(&path).unwrap();
+        assert!((lat - 59.3293).abs() < 1e-6 && (lng - 18.0686).abs() < 1e-6);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn geotags_png_without_writing_the_file() {
+        let state = test_state();
+        state.settings.lock().await.write_back = true;
+        let dir = test_dir("manual_geotag_png");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scan.png");
+        image::RgbImage::from_pixel(8, 8, image::Rgb([10, 20, 30]))
+            .save(&path)
+            .unwrap();
+        let original = std::fs::read(&path).unwrap();
+        state
+            .db
+            .add_no_gps_entries(vec![NoGpsEntry {
+                path: path.to_string_lossy().to_string(),
+                relative_path: "scan.png".to_string(),
+                reason: "GPS data not found".to_string(),
+            }])
+            .unwrap();
+
+        let response = set_photo_location(
+            State(state.clone()),
+            AxumPath(PhotoId::from_relative_path("scan.png")),
+            Json(LocationRequest {
+                lat: 41.9028,
+                lng: 12.4964,
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(body["written_to_file"], false);
+
+        let photo = state
+            .db
+            .get_photo_by_id(&PhotoId::from_relative_path("scan.png"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(photo.coords, GpsCoordinate::new(41.9028, 12.4964).unwrap());
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn empty_chunks_keep_the_photos_array_valid() {
+        let mut array = JsonArrayChunks::default();
+        let mut body = Vec::new();
+        for elements in [&["1"][..], &[], &["2", "3"], &[]] {
+            let mut chunk = array.start();
+            for element in elements {
+                array.separate(&mut chunk);
+                chunk.extend_from_slice(element.as_bytes());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        body.extend_from_slice(array.end());
+        assert_eq!(body, b"[1,2,3]");
+
+        let mut array = JsonArrayChunks::default();
+        let mut body = array.start();
+        body.extend_from_slice(array.end());
+        assert_eq!(body, b"[]");
+        assert_eq!(JsonArrayChunks::default().end(), b"[]");
+    }
+
+    #[test]
+    fn encodes_photo_paths_for_urls() {
+        assert_eq!(
+            encode_url_path("C телефона и чужие работы\\Маша OLD\\2024 10.jpg"),
+            "C%20%D1%82%D0%B5%D0%BB%D0%B5%D1%84%D0%BE%D0%BD%D0%B0%20%D0%B8%20%D1%87%D1%83%D0%B6%D0%B8%D0%B5%20%D1%80%D0%B0%D0%B1%D0%BE%D1%82%D1%8B/%D0%9C%D0%B0%D1%88%D0%B0%20OLD/2024%2010.jpg"
+        );
+        assert_eq!(
+            encode_url_path("trips/café #2?.jpg"),
+            "trips/caf%C3%A9%20%232%3F.jpg"
+        );
+    }
+
+    #[tokio::test]
+    async fn websocket_relays_events_and_unsubscribes_on_close() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let state = test_state();
+        let events = state.event_broadcast.clone();
+        let app = axum::Router::new()
+            .route("/api/ws", axum::routing::get(processing_events_ws))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/api/ws"))
+            .await
+            .unwrap();
+        events
+            .send(ProcessingEvent {
+                event_type: "processing_progress".to_string(),
+                data: ProcessingData {
+                    processed: Some(7),
+                    ..Default::default()
+                },
+            })
+            .unwrap();
+
+        let frame = client.next().await.unwrap().unwrap();
+        let WsMessage::Text(text) = frame else {
+            panic!("expected a text frame, got {frame:?}");
+        };
+        let event: ProcessingEvent = serde_json::from_str(&text).unwrap();
+        assert_eq!(event.event_type, "processing_progress");
+        assert_eq!(event.data.processed, Some(7));
+
+        client.send(WsMessage::Close(None)).await.unwrap();
+        drop(client);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while events.receiver_count() > 0 {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "relay kept its subscription"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -1,87 +1,276 @@
 use anyhow::Result;
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
 use tower_http::compression::CompressionLayer;
-use tower_http::cors::CorsLayer;
 
+pub mod access_log;
+pub mod auth;
+pub mod cors;
+pub mod etag;
 pub mod events;
 pub mod handlers;
+pub mod openapi;
+pub mod rate_limit;
 pub mod state;
+pub mod warmup;
 
 use self::handlers::{
-    convert_heic, get_all_photos, get_gallery_image, get_marker_image, get_popup_image,
-    get_settings, get_thumbnail_image, index_html, initiate_processing, processing_events_stream,
-    reprocess_photos, reveal_file, script_js, select_folder_dialog, serve_photo, set_folder,
-    shutdown_app, style_css, update_settings,
+    add_folder, add_folder_by_path, cancel_processing, convert_heic, export_csv, export_geojson,
+    export_zip, get_albums, get_all_photos, get_cache_stats, get_cluster_photos, get_clusters,
+    get_disk_cache_stats, get_duplicates, get_folders, get_gallery_image, get_last_run_stats,
+    get_library_stats, get_marker_batch, get_marker_image, get_no_gps_files, get_photo,
+    get_photo_info, get_photo_years, get_photos_near, get_places, get_popup_image,
+    get_processing_status, get_settings, get_tags, get_thumbnail_image, get_trips, health,
+    index_html, initiate_processing, processing_events_stream, processing_events_ws,
+    refresh_locations, remove_folder, remove_folder_by_path, reprocess_photos, resolve_duplicates,
+    reveal_file, script_js, search_photos, search_place, select_folder_dialog, serve_photo,
+    set_folder, set_folder_enabled, set_photo_location, set_photo_tags, shutdown_app, style_css,
+    update_settings, version,
 };
+use self::openapi::{api_docs, openapi_json};
 use self::state::AppState;
 
-// Create the main application router
-async fn create_app(state: AppState) -> Router {
-    let secure_cors = CorsLayer::new()
-        .allow_origin(tower_http::cors::AllowOrigin::predicate(
-            |origin: &axum::http::HeaderValue, _parts| {
-                let bytes = origin.as_bytes();
-                bytes.starts_with(b"http://localhost:")
-                    || bytes.starts_with(b"http://127.0.0.1:")
-                    || bytes == b"http://localhost"
-                    || bytes == b"http://127.0.0.1"
-            },
-        ))
-        .allow_methods([axum::http::Method::GET, axum::http::Method::POST])
-        .allow_headers([axum::http::header::CONTENT_TYPE]);
-
-    Router::new()
+// Compress JSON, CSS/JS and SVG responses. Generated JPEG/WebP images and original
+// photos are already compressed, and SSE streams must not be buffered by the encoder.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        SizeAbove::new(256)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE)
+            .and(NotForContentType::const_new("application/octet-stream")),
+    )
+}
+
+/// The application router with its middleware, configured from `state.settings`
+pub async fn create_app(state: AppState) -> Router {
+    let settings = state.settings.lock().await.clone();
+    let cors = cors::cors_layer(&settings.cors_allowed_origins);
+
+    // Routes that decode photos and encode images on a cache miss
+    let image_routes = rate_limit::limit(
+        Router::new()
+            .route("/api/marker/*filename", get(get_marker_image))
+            .route("/api/markers/batch", post(get_marker_batch))
+            .route("/api/thumbnail/*filename", get(get_thumbnail_image))
+            .route("/api/gallery/*filename", get(get_gallery_image))
+            .route("/api/popup/*filename", get(get_popup_image))
+            .route("/convert-heic", get(convert_heic)),
+        settings.rate_limit_rps,
+        settings.rate_limit_burst,
+    );
+
+    let router = Router::new()
         .route("/", get(index_html))
         .route("/style.css", get(style_css))
         .route("/script.js", get(script_js))
+        .route("/health", get(health))
+        .route("/version", get(version))
+        .route("/api/openapi.json", get(openapi_json))
+        .route("/api/docs", get(api_docs))
         .route("/api/photos", get(get_all_photos))
-        .route("/api/marker/*filename", get(get_marker_image))
-        .route("/api/thumbnail/*filename", get(get_thumbnail_image))
-        .route("/api/gallery/*filename", get(get_gallery_image))
-        .route("/api/popup/*filename", get(get_popup_image))
-        .route("/convert-heic", get(convert_heic))
+        .route("/api/photos/search", get(search_photos))
+        .route("/api/photos/near", get(get_photos_near))
+        .route("/api/photos/years", get(get_photo_years))
+        .route("/api/photos/duplicates", get(get_duplicates))
+        .route("/api/photos/duplicates/resolve", post(resolve_duplicates))
+        .route("/api/photos/tags", post(set_photo_tags))
+        .route("/api/tags", get(get_tags))
+        .route("/api/photo-info/*relative_path", get(get_photo_info))
+        .route("/api/export.csv", get(export_csv))
+        .route("/api/export/geojson", get(export_geojson))
+        .route("/api/photos/export/zip", post(export_zip))
+        .route("/api/clusters", get(get_clusters))
+        .route("/api/cluster-photos", get(get_cluster_photos))
+        .route("/api/photos/:photo", get(get_photo))
+        .route("/api/photos/:photo/location", post(set_photo_location))
+        .route("/api/cache-stats", get(get_cache_stats))
+        .route("/api/cache/stats", get(get_disk_cache_stats))
+        .route("/api/stats", get(get_library_stats))
+        .route("/api/stats/last-run", get(get_last_run_stats))
+        .route("/api/no-gps", get(get_no_gps_files))
+        .route("/api/geocode/refresh", post(refresh_locations))
+        .route("/api/search-place", get(search_place))
+        .route("/api/places", get(get_places))
+        .route("/api/albums", get(get_albums))
+        .route("/api/trips", get(get_trips))
         .route("/api/settings", get(get_settings))
         .route("/api/update_settings", post(update_settings))
         .route("/api/set-folder", post(set_folder))
-        .route("/api/select-folder", post(select_folder_dialog))
+        .route("/api/folders", get(get_folders).post(add_folder))
+        .route("/api/folders/add", post(add_folder_by_path))
+        .route("/api/folders/remove", post(remove_folder_by_path))
+        .route("/api/folders/:index", delete(remove_folder))
+        .route("/api/folders/:index/enabled", post(set_folder_enabled))
+        .route("/api/select-folder-dialog", post(select_folder_dialog))
         .route("/api/events", get(processing_events_stream))
+        .route("/api/ws", get(processing_events_ws))
         .route("/api/initiate-processing", post(initiate_processing))
         .route("/api/reprocess", axum::routing::post(reprocess_photos))
+        .route("/api/cancel", post(cancel_processing))
+        .route("/api/cancel-processing", post(cancel_processing))
+        .route("/api/processing-status", get(get_processing_status))
         .route("/api/reveal-file", post(reveal_file))
         .route("/api/shutdown", post(shutdown_app))
         .route("/photos/*filepath", get(serve_photo))
-        .layer(
-            ServiceBuilder::new()
-                .layer(secure_cors)
-                .layer(CompressionLayer::new()),
-        )
-        .with_state(state)
+        .merge(image_routes);
+
+    // Inside the CORS layer, which answers preflight requests that carry no token
+    let token = settings.allow_lan.then(|| settings.access_token.clone());
+    let router = auth::require_token(router, token)
+        .layer(ServiceBuilder::new().layer(cors).layer(compression_layer()));
+
+    access_log::with_request_ids(router, settings.access_log).with_state(state)
 }
 
-pub async fn start_server(state: AppState, port: u16) -> Result<()> {
-    // Subscribe to shutdown signal before moving state into app
-    let mut shutdown_receiver = state.shutdown_sender.subscribe();
+/// Binds the HTTP listener on localhost, or on all interfaces with `allow_lan`
+pub async fn bind(port: u16, allow_lan: bool) -> Result<TcpListener> {
+    let ip = if allow_lan {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        Ipv4Addr::LOCALHOST
+    };
+    Ok(TcpListener::bind(SocketAddr::from((ip, port))).await?)
+}
 
-    let app = create_app(state).await;
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    let listener = TcpListener::bind(addr).await?;
+/// Address of this computer on the local network: the one the OS would send
+/// from to a public address. Connecting a UDP socket sends no packets.
+fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(8, 8, 8, 8), 80)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+pub async fn start_server(state: AppState, port: u16) -> Result<()> {
+    let (allow_lan, token) = {
+        let settings = state.settings.lock().await;
+        (settings.allow_lan, settings.access_token.clone())
+    };
+    let listener = bind(port, allow_lan).await?;
 
     println!(
         "   ✅ HTTP server started successfully at http://127.0.0.1:{}",
         port
     );
+    if allow_lan {
+        let host = lan_address().map_or_else(|| "<this-computer>".to_string(), |ip| ip.to_string());
+        println!(
+            "   📱 LAN access is on, open http://{}:{}/?token={} on your devices",
+            host, port, token
+        );
+    }
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(async move {
-            let _ = shutdown_receiver.recv().await;
-            println!("🛑 Server shutting down gracefully...");
-        })
-        .await?;
+    serve(listener, state).await
+}
+
+/// Serves the app on a bound listener until `/api/shutdown` is called
+pub async fn serve(listener: TcpListener, state: AppState) -> Result<()> {
+    // Subscribe to shutdown signal before moving state into app
+    let mut shutdown_receiver = state.shutdown_sender.subscribe();
+    let app = create_app(state).await;
+
+    // The rate limiter keys clients by their address
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        let _ = shutdown_receiver.recv().await;
+        println!("🛑 Server shutting down gracefully...");
+    })
+    .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::compression_layer;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request},
+        response::{IntoResponse, Response},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    fn synthetic_photos_json() -> String {
+        let photos: Vec<serde_json::Value> = (0..10_000)
+            .map(|i| {
+                serde_json::json!({
+                    "filename": format!("IMG_{i:05}.jpg"),
+                    "relative_path": format!("2024/Trip/IMG_{i:05}.jpg"),
+                    "url": format!("/api/popup/2024/Trip/IMG_{i:05}.jpg"),
+                    "marker_icon": format!("/api/marker/2024/Trip/IMG_{i:05}.jpg"),
+                    "lat": 48.8566 + i as f64 * 0.0001,
+                    "lng": 2.3522 - i as f64 * 0.0001,
+                    "datetime": "2024-06-01 12:00:00",
+                    "is_heic": false,
+                })
+            })
+            .collect();
+        serde_json::to_string(&photos).unwrap()
+    }
+
+    fn test_app() -> Router {
+        Router::new()
+            .route(
+                "/api/photos",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "application/json")],
+                        synthetic_photos_json(),
+                    )
+                }),
+            )
+            .route(
+                "/api/marker/image.jpg",
+                get(|| async {
+                    ([(header::CONTENT_TYPE, "image/jpeg")], vec![0u8; 4096]).into_response()
+                }),
+            )
+            .layer(compression_layer())
+    }
+
+    async fn request(uri: &str, accept_encoding: Option<&str>) -> Response {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(encoding) = accept_encoding {
+            builder = builder.header(header::ACCEPT_ENCODING, encoding);
+        }
+        test_app()
+            .oneshot(builder.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn photos_json_is_compressed_when_accepted() {
+        let plain = request("/api/photos", None).await;
+        assert!(plain.headers().get(header::CONTENT_ENCODING).is_none());
+        let plain_len = to_bytes(plain.into_body(), usize::MAX).await.unwrap().len();
+
+        for encoding in ["gzip", "br"] {
+            let compressed = request("/api/photos", Some(encoding)).await;
+            assert_eq!(
+                compressed.headers().get(header::CONTENT_ENCODING).unwrap(),
+                encoding
+            );
+            let compressed_len = to_bytes(compressed.into_body(), usize::MAX)
+                .await
+                .unwrap()
+                .len();
+            assert!(compressed_len * 10 < plain_len);
+        }
+    }
+
+    #[tokio::test]
+    async fn images_are_not_recompressed() {
+        let response = request("/api/marker/image.jpg", Some("gzip, br")).await;
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+}
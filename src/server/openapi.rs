@@ -0,0 +1,192 @@
+//! OpenAPI 3.0 description of the HTTP API, generated from the handler
+//! annotations, and the Swagger UI page that renders it
+
+use axum::{
+    http::header,
+    response::{Html, IntoResponse, Response},
+};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityRequirement, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::handlers;
+
+const DOCS_HTML: &[u8] = include_bytes!("../../frontend/docs.html");
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "PhotoMap",
+        description = "Local API of the PhotoMap processor. It listens on localhost and has no authentication, unless `allow_lan` is set: then every /api, /photos and /convert-heic request needs the `access_token` setting as a bearer token, a `token` query parameter or the `photomap_token` cookie."
+    ),
+    modifiers(&LanTokenAddon),
+    paths(
+        handlers::health,
+        handlers::version,
+        handlers::get_all_photos,
+        handlers::search_photos,
+        handlers::get_photos_near,
+        handlers::get_photo_years,
+        handlers::get_duplicates,
+        handlers::resolve_duplicates,
+        handlers::set_photo_tags,
+        handlers::get_tags,
+        handlers::get_photo_info,
+        handlers::export_csv,
+        handlers::export_geojson,
+        handlers::export_zip,
+        handlers::get_clusters,
+        handlers::get_cluster_photos,
+        handlers::get_photo,
+        handlers::set_photo_location,
+        handlers::get_cache_stats,
+        handlers::get_disk_cache_stats,
+        handlers::get_library_stats,
+        handlers::get_last_run_stats,
+        handlers::get_no_gps_files,
+        handlers::refresh_locations,
+        handlers::search_place,
+        handlers::get_places,
+        handlers::get_albums,
+        handlers::get_trips,
+        handlers::get_settings,
+        handlers::update_settings,
+        handlers::set_folder,
+        handlers::get_folders,
+        handlers::add_folder,
+        handlers::add_folder_by_path,
+        handlers::remove_folder,
+        handlers::remove_folder_by_path,
+        handlers::set_folder_enabled,
+        handlers::select_folder_dialog,
+        handlers::processing_events_stream,
+        handlers::processing_events_ws,
+        handlers::initiate_processing,
+        handlers::reprocess_photos,
+        handlers::cancel_processing,
+        handlers::get_processing_status,
+        handlers::reveal_file,
+        handlers::shutdown_app,
+        handlers::serve_photo,
+        handlers::get_marker_image,
+        handlers::get_thumbnail_image,
+        handlers::get_gallery_image,
+        handlers::get_popup_image,
+        handlers::get_marker_batch,
+        handlers::convert_heic,
+    ),
+    components(schemas(
+        crate::database::ImageMetadata,
+        crate::database::ExifSource,
+        crate::database::GpsQuality,
+        crate::database::NoGpsEntry,
+        crate::database::StorageKind,
+        crate::database::LibraryStats,
+        crate::database::YearSummary,
+        crate::database::DateRange,
+        crate::database::CountryCount,
+        crate::database::LocationCount,
+        crate::database::YearCount,
+        crate::database::DayCount,
+        crate::settings::Settings,
+        crate::settings::FolderConfig,
+        crate::settings::FieldError,
+        crate::image_processing::DiskCacheStats,
+        crate::config::ProcessingConfig,
+        crate::image_processing::OutputFormat,
+        super::events::ProcessingEvent,
+        super::events::ProcessingData,
+        handlers::FolderInfo,
+        handlers::PlaceResult,
+        handlers::AddFolderRequest,
+        handlers::FolderEnabledRequest,
+        handlers::LocationRequest,
+        handlers::NoGpsFile,
+        handlers::SetTagsRequest,
+        handlers::ResolveDuplicatesRequest,
+        handlers::ExportZipRequest,
+    )),
+    tags(
+        (name = "photos", description = "Querying and editing the photo library"),
+        (name = "images", description = "Originals and generated markers, thumbnails and previews"),
+        (name = "folders", description = "Folders the library is built from"),
+        (name = "processing", description = "Scanning the folders and following its progress"),
+        (name = "library", description = "Statistics, places, albums and trips"),
+        (name = "settings", description = "Application settings"),
+        (name = "app", description = "Health and lifecycle of the server"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Bearer token scheme of LAN access, optional since localhost needs none
+struct LanTokenAddon;
+
+impl Modify for LanTokenAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "lan_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+        openapi.security = Some(vec![
+            SecurityRequirement::default(),
+            SecurityRequirement::new("lan_token", Vec::<String>::new()),
+        ]);
+    }
+}
+
+pub async fn openapi_json() -> Response {
+    match ApiDoc::openapi().to_json() {
+        Ok(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to serialize the OpenAPI document: {}", e);
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn api_docs() -> Html<&'static [u8]> {
+    Html(DOCS_HTML)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use utoipa::OpenApi;
+
+    #[test]
+    fn document_is_valid_openapi_3_0() {
+        let json = ApiDoc::openapi().to_json().unwrap();
+        let doc: openapiv3::OpenAPI = serde_json::from_str(&json).unwrap();
+
+        assert!(doc.openapi.starts_with("3.0"), "{}", doc.openapi);
+        for path in [
+            "/api/photos",
+            "/api/photos/years",
+            "/api/folders/{index}",
+            "/api/marker/{filename}",
+            "/api/settings",
+        ] {
+            assert!(doc.paths.paths.contains_key(path), "missing {}", path);
+        }
+
+        let photos = doc.paths.paths["/api/photos"].as_item().unwrap();
+        let params: Vec<_> = photos
+            .get
+            .as_ref()
+            .unwrap()
+            .parameters
+            .iter()
+            .filter_map(|p| p.as_item())
+            .map(|p| p.parameter_data_ref().name.as_str())
+            .collect();
+        for name in ["album", "start_date", "end_date"] {
+            assert!(params.contains(&name), "missing query parameter {}", name);
+        }
+
+        let schemas = &doc.components.as_ref().unwrap().schemas;
+        assert!(schemas.contains_key("ImageMetadata"));
+        assert!(schemas.contains_key("Settings"));
+        let security_schemes = &doc.components.as_ref().unwrap().security_schemes;
+        assert!(security_schemes.contains_key("lan_token"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Per-client rate limit of the routes that decode photos and encode images,
+//! so a script hammering them cannot saturate the CPU. The limit is set by
+//! `rate_limit_rps` and `rate_limit_burst`; a rate of 0 turns it off.
+//! Requests from this computer are never limited: the app's own map asks for
+//! one image per marker and would break on a busy view.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json, Router,
+};
+use tower::{Layer, Service};
+use tower_governor::{governor::GovernorConfigBuilder, GovernorError, GovernorLayer};
+
+/// Wraps `routes` in a token bucket per client IP refilled at `rps` requests per
+/// second and holding up to `burst`. Rejected requests get 429 with `Retry-After`;
+/// loopback clients pass unlimited.
+pub fn limit<S>(routes: Router<S>, rps: u32, burst: u32) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if rps == 0 {
+        return routes;
+    }
+    let Some(config) = GovernorConfigBuilder::default()
+        .period(Duration::from_secs(1) / rps)
+        .burst_size(burst.max(1))
+        .error_handler(too_many_requests)
+        .finish()
+    else {
+        return routes;
+    };
+    routes.layer(ExemptLoopback(GovernorLayer {
+        config: Arc::new(config),
+    }))
+}
+
+/// Applies the wrapped layer only to requests that do not come from this computer
+#[derive(Clone)]
+struct ExemptLoopback<L>(L);
+
+impl<S: Clone, L: Layer<S>> Layer<S> for ExemptLoopback<L> {
+    type Service = ExemptLoopbackService<S, L::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExemptLoopbackService {
+            limited: self.0.layer(inner.clone()),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ExemptLoopbackService<S, L> {
+    inner: S,
+    limited: L,
+}
+
+type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+impl<S, L> Service<Request> for ExemptLoopbackService<S, L>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    L: Service<Request, Response = S::Response, Error = S::Error>,
+    L::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<S::Response, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.inner.poll_ready(cx) {
+            Poll::Ready(Ok(())) => self.limited.poll_ready(cx),
+            other => other,
+        }
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let loopback = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .is_some_and(|ConnectInfo(addr)| addr.ip().to_canonical().is_loopback());
+        if loopback {
+            Box::pin(self.inner.call(request))
+        } else {
+            Box::pin(self.limited.call(request))
+        }
+    }
+}
+
+fn too_many_requests(error: GovernorError) -> axum::response::Response {
+    match error {
+        GovernorError::TooManyRequests { wait_time, .. } => {
+            // Waits below a second round down to 0, which clients treat as "retry now"
+            let retry_after = wait_time.max(1);
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, HeaderValue::from(retry_after))],
+                Json(serde_json::json!({
+                    "status": "error",
+                    "message": format!("Too many requests, retry in {}s", retry_after),
+                })),
+            )
+                .into_response()
+        }
+        GovernorError::UnableToExtractKey => {
+            eprintln!("❌ Rate limiter could not read the client address");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        GovernorError::Other { code, msg, .. } => (code, msg.unwrap_or_default()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::limit;
+    use axum::{
+        body::Body,
+        extract::ConnectInfo,
+        http::{header, Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    fn request_from(addr: &str) -> Request<Body> {
+        let mut request = Request::builder()
+            .uri("/api/marker/a.jpg")
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(addr.parse::<SocketAddr>().unwrap()));
+        request
+    }
+
+    async fn statuses(app: &Router, addr: &str, count: usize) -> Vec<StatusCode> {
+        let mut statuses = Vec::new();
+        for _ in 0..count {
+            let response = app.clone().oneshot(request_from(addr)).await.unwrap();
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                assert!(response.headers().contains_key(header::RETRY_AFTER));
+            }
+            statuses.push(response.status());
+        }
+        statuses
+    }
+
+    fn app() -> Router {
+        limit(
+            Router::new().route("/api/marker/*filename", get(|| async { "marker" })),
+            1,
+            5,
+        )
+    }
+
+    #[tokio::test]
+    async fn lan_clients_are_limited_after_the_burst() {
+        let app = app();
+        let statuses = statuses(&app, "192.168.1.20:50000", 8).await;
+        assert!(statuses[..5].iter().all(|status| *status == StatusCode::OK));
+        assert_eq!(statuses[7], StatusCode::TOO_MANY_REQUESTS);
+
+        // Each client has a bucket of its own
+        assert_eq!(
+            statuses(&app, "192.168.1.21:50000", 1).await,
+            [StatusCode::OK]
+        );
+    }
+
+    #[tokio::test]
+    async fn this_computer_is_never_limited() {
+        let app = app();
+        for addr in ["127.0.0.1:50000", "[::1]:50000", "[::ffff:127.0.0.1]:50000"] {
+            let statuses = statuses(&app, addr, 20).await;
+            assert!(
+                statuses.iter().all(|status| *status == StatusCode::OK),
+                "{addr}"
+            );
+        }
+    }
+}
@@ -1,7 +1,14 @@
 use super::events::ProcessingEvent;
 use crate::database::Database;
+use crate::image_cache::ImageCache;
+use crate::image_processing::DiskThumbnailCache;
+use crate::processing::ProcessingStats;
 use crate::settings::Settings;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 use tokio::sync::{broadcast, mpsc};
 
@@ -10,7 +17,231 @@ use tokio::sync::{broadcast, mpsc};
 pub struct AppState {
     pub db: Database,
     pub settings: Arc<Mutex<Settings>>,
+    pub image_cache: ImageCache,
+    // Markers and thumbnails kept across restarts, behind the in-memory cache
+    pub disk_cache: DiskThumbnailCache,
+    // Set by /api/cancel to stop the running processing job
+    pub cancel_processing: Arc<AtomicBool>,
+    // Tracks the active processing job so concurrent runs are rejected
+    pub processing: ProcessingState,
+    // Set while the native folder picker is open so a second one is not spawned
+    pub folder_dialog: FolderDialogState,
+    // Statistics of the last finished processing run, served at /api/stats/last-run
+    pub last_run: Arc<std::sync::Mutex<Option<LastRunStats>>>,
     pub event_sender: mpsc::Sender<ProcessingEvent>,
     pub event_broadcast: broadcast::Sender<ProcessingEvent>,
     pub shutdown_sender: broadcast::Sender<()>,
 }
+
+/// Details of the processing run currently in progress
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessingRun {
+    /// Unix time in seconds
+    pub started_at: u64,
+    pub folders: Vec<String>,
+}
+
+/// Totals and per-folder results of a finished processing run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LastRunStats {
+    /// Unix time in seconds
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub cancelled: bool,
+    pub total_files: usize,
+    pub processed: usize,
+    pub no_gps: usize,
+    pub heic_files: usize,
+    pub symlinked_files: usize,
+    pub failed: usize,
+    pub duration_secs: f64,
+    pub folders: Vec<FolderStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderStats {
+    pub path: String,
+    #[serde(flatten)]
+    pub stats: ProcessingStats,
+}
+
+impl LastRunStats {
+    /// Sums per-folder results into run totals
+    pub fn new(started_at: u64, folders: Vec<FolderStats>, cancelled: bool) -> Self {
+        let mut run = LastRunStats {
+            started_at,
+            finished_at: unix_now(),
+            cancelled,
+            ..Default::default()
+        };
+        for folder in &folders {
+            run.total_files += folder.stats.total_files;
+            run.processed += folder.stats.processed;
+            run.no_gps += folder.stats.no_gps;
+            run.heic_files += folder.stats.heic_files;
+            run.symlinked_files += folder.stats.symlinked_files;
+            run.failed += folder.stats.failed_files.len();
+            run.duration_secs += folder.stats.duration_secs;
+        }
+        run.folders = folders;
+        run
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Holds the active processing run, if any
+#[derive(Clone, Default)]
+pub struct ProcessingState {
+    run: Arc<std::sync::Mutex<Option<ProcessingRun>>>,
+}
+
+impl ProcessingState {
+    /// Registers a new run unless one is already active.
+    /// The run ends when the returned guard is dropped, including on panic.
+    pub fn try_start(&self, folders: &[PathBuf]) -> Option<RunGuard> {
+        let mut run = self.lock();
+        if run.is_some() {
+            return None;
+        }
+
+        *run = Some(ProcessingRun {
+            started_at: unix_now(),
+            folders: folders
+                .iter()
+                .map(|f| f.to_string_lossy().to_string())
+                .collect(),
+        });
+        Some(RunGuard {
+            state: self.clone(),
+        })
+    }
+
+    pub fn current(&self) -> Option<ProcessingRun> {
+        self.lock().clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<ProcessingRun>> {
+        // A panicking run must not lock out every later run
+        self.run.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Marks the run as finished when dropped
+pub struct RunGuard {
+    state: ProcessingState,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        *self.state.lock() = None;
+    }
+}
+
+/// Whether the native folder picker is open
+#[derive(Clone, Default)]
+pub struct FolderDialogState {
+    open: Arc<AtomicBool>,
+}
+
+impl FolderDialogState {
+    /// Marks the dialog as open unless it already is.
+    /// It counts as closed again when the returned guard is dropped.
+    pub fn try_open(&self) -> Option<FolderDialogGuard> {
+        self.open
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()?;
+        Some(FolderDialogGuard {
+            state: self.clone(),
+        })
+    }
+}
+
+/// Marks the folder picker as closed when dropped
+pub struct FolderDialogGuard {
+    state: FolderDialogState,
+}
+
+impl Drop for FolderDialogGuard {
+    fn drop(&mut self) {
+        self.state.open.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_run_is_rejected_until_guard_drops() {
+        let state = ProcessingState::default();
+        let guard = state.try_start(&[PathBuf::from("/photos")]).unwrap();
+        assert!(state.try_start(&[]).is_none());
+        assert_eq!(state.current().unwrap().folders, vec!["/photos"]);
+
+        drop(guard);
+        assert!(!state.is_running());
+        assert!(state.try_start(&[]).is_some());
+    }
+
+    #[test]
+    fn last_run_sums_folder_stats() {
+        let folder = |path: &str, total_files, processed, failed: usize| FolderStats {
+            path: path.to_string(),
+            stats: ProcessingStats {
+                total_files,
+                processed,
+                no_gps: total_files - processed,
+                failed_files: (0..failed)
+                    .map(|i| crate::processing::FailedFile {
+                        path: format!("{path}/broken{i}.jpg"),
+                        error: "Failed to read".to_string(),
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        };
+
+        let run = LastRunStats::new(
+            100,
+            vec![folder("/a", 10, 7, 1), folder("/b", 5, 5, 0)],
+            false,
+        );
+        assert_eq!(
+            (run.total_files, run.processed, run.no_gps, run.failed),
+            (15, 12, 3, 1)
+        );
+        assert!(run.finished_at >= run.started_at);
+
+        let json = serde_json::to_value(&run).unwrap();
+        assert_eq!(json["folders"][0]["path"], "/a");
+        assert_eq!(json["folders"][0]["total_files"], 10);
+        assert_eq!(
+            json["folders"][0]["failed_files"][0]["path"],
+            "/a/broken0.jpg"
+        );
+    }
+
+    #[test]
+    fn panicking_run_clears_state() {
+        let state = ProcessingState::default();
+        let guard = state.try_start(&[]).unwrap();
+        let result = std::thread::spawn(move || {
+            let _guard = guard;
+            panic!("processing failed");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert!(!state.is_running());
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::mpsc;
+
+use crate::database::{Database, PhotoMetadata};
+use crate::image_cache::{cache_key, CachedImage, ImageCache};
+use crate::image_processing::{
+    convert_heic_to_jpeg, create_scaled_image_in_memory, EncodeOptions, ImageType,
+};
+
+use super::etag;
+use super::events::{ProcessingData, ProcessingEvent};
+use super::state::AppState;
+
+/// Image sizes the map requests as soon as photos are loaded
+const WARMUP_TYPES: [ImageType; 2] = [ImageType::Marker, ImageType::Thumbnail];
+
+/// Number of progress events sent over a whole warm-up run
+const PROGRESS_STEPS: usize = 100;
+
+/// Threads reserved for warm-up, leaving the rest for request handling
+fn warmup_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| (n.get() / 2).max(1))
+        .unwrap_or(1)
+}
+
+/// Starts thumbnail pre-generation in the background when enabled in settings
+pub fn spawn_thumbnail_warmup(state: AppState) {
+    std::thread::spawn(move || {
+        let options = {
+            let settings = state.settings.blocking_lock();
+            if !settings.pregenerate_thumbnails {
+                return;
+            }
+            EncodeOptions::from_settings(&settings)
+        };
+
+        if let Err(e) =
+            pregenerate_thumbnails(&state.db, &state.image_cache, options, &state.event_sender)
+        {
+            tracing::warn!("Thumbnail warm-up failed: {}", e);
+        }
+    });
+}
+
+/// Generates marker and thumbnail images for every photo into the image cache.
+/// Stops early if the database is rebuilt while running. Returns the number of
+/// images generated.
+pub fn pregenerate_thumbnails(
+    db: &Database,
+    cache: &ImageCache,
+    options: EncodeOptions,
+    event_sender: &mpsc::Sender<ProcessingEvent>,
+) -> Result<usize> {
+    let generation = db.generation();
+    let photos = db.get_all_photos()?;
+    let total = photos.len() * WARMUP_TYPES.len();
+    if total == 0 {
+        return Ok(0);
+    }
+    let step = (total / PROGRESS_STEPS).max(1);
+
+    tracing::info!(
+        "Warming image cache: {} images on {} thread(s)",
+        total,
+        warmup_threads()
+    );
+
+    let done = AtomicUsize::new(0);
+    let generated = AtomicUsize::new(0);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(warmup_threads())
+        .build()?;
+
+    pool.install(|| {
+        photos
+            .par_iter()
+            .flat_map(|photo| WARMUP_TYPES.par_iter().map(move |t| (photo, *t)))
+            .for_each(|(photo, image_type)| {
+                if db.generation() != generation {
+                    return;
+                }
+
+                match warm_image(photo, image_type, options, generation, cache) {
+                    Ok(true) => {
+                        generated.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::warn!(
+                        "Failed to pre-generate {} for {}: {}",
+                        image_type.name(),
+                        photo.relative_path,
+                        e
+                    ),
+                }
+
+                let current = done.fetch_add(1, Ordering::Relaxed) + 1;
+                if current.is_multiple_of(step) || current == total {
+                    // Progress is best effort; never stall workers on a full channel
+                    let _ = event_sender.try_send(ProcessingEvent {
+                        event_type: "warmup_progress".to_string(),
+                        data: ProcessingData {
+                            total_files: Some(total),
+                            processed: Some(current),
+                            message: Some(format!("Warming cache {}/{}", current, total)),
+                            phase: Some("warming".to_string()),
+                            ..Default::default()
+                        },
+                    });
+                }
+            });
+    });
+
+    if db.generation() != generation {
+        tracing::info!("Thumbnail warm-up stopped: photos were reprocessed");
+        return Ok(generated.into_inner());
+    }
+
+    let generated = generated.into_inner();
+    tracing::info!("Image cache warmed: {} images generated", generated);
+    let _ = event_sender.blocking_send(ProcessingEvent {
+        event_type: "warmup_complete".to_string(),
+        data: ProcessingData {
+            total_files: Some(total),
+            processed: Some(total),
+            message: Some(format!("Cache warmed: {} images", total)),
+            phase: Some("warmed".to_string()),
+            ..Default::default()
+        },
+    });
+
+    Ok(generated)
+}
+
+/// Generates one image unless it is already cached; returns whether it was generated
+fn warm_image(
+    photo: &PhotoMetadata,
+    image_type: ImageType,
+    options: EncodeOptions,
+    generation: u64,
+    cache: &ImageCache,
+) -> Result<bool> {
+    let path = Path::new(&photo.file_path);
+    let Some(etag) = etag::image_etag(path, image_type.name(), options, generation) else {
+        anyhow::bail!("file not accessible");
+    };
+    let key = cache_key(&photo.file_path, &etag);
+    if cache.contains(&key) {
+        return Ok(false);
+    }
+
+    let (data, format) = if photo.is_heic {
+        convert_heic_to_jpeg(photo, image_type.name(), options)?
+    } else {
+        (
+            create_scaled_image_in_memory(path, image_type, options)?,
+            options.format,
+        )
+    };
+    cache.insert(key, CachedImage { data, format });
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_photo(dir: &Path, name: &str) -> PhotoMetadata {
+        let path = dir.join(name);
+        image::RgbImage::from_pixel(64, 48, image::Rgb([10, 100, 200]))
+            .save(&path)
+            .unwrap();
+        PhotoMetadata {
+            file_path: path.to_string_lossy().to_string(),
+            ..PhotoMetadata::sample(name, "2024-06-01 12:00:00", 48.8566, 2.3522)
+        }
+    }
+
+    #[test]
+    fn warms_markers_and_thumbnails_with_progress() {
+        let dir = std::env::temp_dir().join("photomap_test_warmup");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let db = Database::new().unwrap();
+        let photos: Vec<_> = (0..3)
+            .map(|i| test_photo(&dir, &format!("photo{i}.jpg")))
+            .collect();
+        db.insert_photos_batch(&photos).unwrap();
+
+        let cache = ImageCache::new(16 * 1024 * 1024);
+        let (tx, mut rx) = mpsc::channel(1024);
+        let options = EncodeOptions::default();
+
+        assert_eq!(
+            pregenerate_thumbnails(&db, &cache, options, &tx).unwrap(),
+            6
+        );
+        // A second run finds everything cached
+        assert_eq!(
+            pregenerate_thumbnails(&db, &cache, options, &tx).unwrap(),
+            0
+        );
+
+        let etag = etag::image_etag(
+            Path::new(&photos[0].file_path),
+            "marker",
+            options,
+            db.generation(),
+        )
+        .unwrap();
+        assert!(cache.contains(&cache_key(&photos[0].file_path, &etag)));
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        let last_progress = events
+            .iter()
+            .rfind(|e| e.event_type == "warmup_progress")
+            .unwrap();
+        assert_eq!(last_progress.data.processed, Some(6));
+        assert_eq!(
+            last_progress.data.message.as_deref(),
+            Some("Warming cache 6/6")
+        );
+        assert_eq!(events.last().unwrap().event_type, "warmup_complete");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,13 @@
+use crate::config::ProcessingConfig;
+use crate::constants::{
+    DEFAULT_EXPORT_MAX_PHOTOS, DEFAULT_IMAGE_CACHE_MB, DEFAULT_IMAGE_QUALITY,
+    DEFAULT_LOCATION_FORMAT, DEFAULT_MAX_GEOCODE_DISTANCE_KM, DEFAULT_PORT,
+    DEFAULT_RATE_LIMIT_BURST, DEFAULT_RATE_LIMIT_RPS, MAX_EXPORT_MAX_PHOTOS, MAX_IMAGE_CACHE_MB,
+};
+use crate::database::StorageKind;
+use crate::image_processing::{clamp_quality, OutputFormat};
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader};
@@ -17,38 +25,309 @@ pub fn normalize_folder_path(path: &str) -> String {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn parse_exclude_globs(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|glob| !glob.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Outcome of `Settings::merge_folders`, by picked folder
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct FolderMerge {
+    /// Appended to the folder list
+    pub added: Vec<String>,
+    /// Configured already
+    pub existing: Vec<String>,
+}
+
+/// A photo folder of the library
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct FolderConfig {
+    #[schema(example = "/home/me/Pictures")]
+    pub path: String,
+    /// Disabled folders are neither processed nor shown on the map, but keep their cache
+    #[serde(default = "default_folder_enabled")]
+    pub enabled: bool,
+}
+
+impl FolderConfig {
+    /// An enabled folder
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: normalize_folder_path(path),
+            enabled: true,
+        }
+    }
+}
+
+fn default_folder_enabled() -> bool {
+    true
+}
+
+/// Folder list entry as sent by clients: a path, a `FolderConfig`, or null
+/// for an empty slot of the former five-slot array
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FolderEntry {
+    Path(String),
+    Config(FolderConfig),
+}
+
+fn deserialize_folders<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<FolderConfig>, D::Error> {
+    let entries = Vec::<Option<FolderEntry>>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| match entry {
+            FolderEntry::Path(path) if path.trim().is_empty() => None,
+            FolderEntry::Path(path) => Some(FolderConfig::new(path.trim())),
+            FolderEntry::Config(folder) => Some(folder),
+        })
+        .collect())
+}
+
+/// Folders stored as `folder1`, `folder2`, ... with an optional `folderN_enabled`,
+/// in the order of their number; gaps are allowed. Files written before folders
+/// became a list have five `pathN` slots and maybe a `last_folder`.
+fn parse_folders(config_map: &HashMap<String, String>) -> Vec<FolderConfig> {
+    let unquote = |value: &String| value.trim_matches('"').trim().to_string();
+
+    let mut numbered: Vec<(usize, FolderConfig)> = config_map
+        .iter()
+        .filter_map(|(key, value)| {
+            let number = key.strip_prefix("folder")?.parse::<usize>().ok()?;
+            let path = unquote(value);
+            if path.is_empty() {
+                return None;
+            }
+            let enabled = config_map
+                .get(&format!("folder{}_enabled", number))
+                .and_then(|v| v.trim().parse::<bool>().ok())
+                .unwrap_or(true);
+            Some((
+                number,
+                FolderConfig {
+                    path: normalize_folder_path(&path),
+                    enabled,
+                },
+            ))
+        })
+        .collect();
+    if !numbered.is_empty() {
+        numbered.sort_by_key(|(number, _)| *number);
+        return numbered.into_iter().map(|(_, folder)| folder).collect();
+    }
+
+    // Former format
+    let mut folders: Vec<FolderConfig> = (1..=5)
+        .filter_map(|i| config_map.get(&format!("path{}", i)).map(unquote))
+        .filter(|path| !path.is_empty())
+        .map(|path| FolderConfig::new(&path))
+        .collect();
+    if folders.is_empty() {
+        if let Some(last_folder) = config_map.get("last_folder").map(unquote) {
+            if !last_folder.is_empty() {
+                folders.push(FolderConfig::new(&last_folder));
+            }
+        }
+    }
+    folders
+}
+
+/// `folderN` lines of the config file
+fn write_folders(folders: &[FolderConfig], content: &mut String) {
+    for (i, folder) in folders.iter().enumerate() {
+        content.push_str(&format!(
+            "folder{} = \"{}\"\n",
+            i + 1,
+            normalize_folder_path(&folder.path)
+        ));
+        content.push_str(&format!("folder{}_enabled = {}\n", i + 1, folder.enabled));
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct Settings {
-    pub folders: [Option<String>; 5], // Maximum 5 folder paths
+    /// Photo folders, in the order they were added. Older clients may send
+    /// plain paths with null for empty slots.
+    #[serde(default, deserialize_with = "deserialize_folders")]
+    pub folders: Vec<FolderConfig>,
     pub start_browser: bool,
+    /// Port of the HTTP server; takes effect on the next start, `--port` wins
+    #[serde(default = "default_port")]
+    pub port: u16,
     pub top: i32,
     pub left: i32,
     pub map_coords: bool,
     pub routes: bool,
     pub heatmap: bool,
+    #[serde(default = "default_image_quality")]
+    pub image_quality: u8,
+    #[serde(default)]
+    pub thumbnail_format: OutputFormat,
+    #[serde(default)]
+    pub pregenerate_thumbnails: bool,
+    #[serde(default = "default_image_cache_mb")]
+    pub image_cache_mb: u32,
+    /// Gitignore-style patterns excluded from processing in every folder
+    #[serde(default)]
+    pub exclude_globs: Vec<String>,
+    /// Subfolder levels scanned below each folder; None means unlimited
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    #[serde(default = "default_follow_symlinks")]
+    pub follow_symlinks: bool,
+    /// Directory name globs that are never scanned, at any level
+    #[serde(default = "default_exclude_dirs")]
+    pub exclude_dirs: Vec<String>,
+    /// Scan directories whose name starts with '.'
+    #[serde(default)]
+    pub include_hidden: bool,
+    /// Write manually set locations into the photo's EXIF, not only the database
+    #[serde(default)]
+    pub write_back: bool,
+    #[serde(default)]
+    pub processing: ProcessingConfig,
+    /// Photo database backend; takes effect on the next start
+    #[serde(default)]
+    pub storage: StorageKind,
+    /// Template of location names with `{name}`, `{admin1}` and `{country}`
+    #[serde(default = "default_location_format")]
+    pub location_format: String,
+    /// Photos farther than this from the nearest city get no location name
+    #[serde(default = "default_max_geocode_distance_km")]
+    pub max_geocode_distance_km: f64,
+    /// Origins besides localhost allowed to call the API, e.g. `http://*.local`;
+    /// takes effect on the next start
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    /// Image requests per second allowed per client, 0 for no limit;
+    /// takes effect on the next start
+    #[serde(default = "default_rate_limit_rps")]
+    pub rate_limit_rps: u32,
+    /// Image requests a client may send at once before the rate applies
+    #[serde(default = "default_rate_limit_burst")]
+    pub rate_limit_burst: u32,
+    /// Log status, latency and size of every response; takes effect on the next start
+    #[serde(default)]
+    pub access_log: bool,
+    /// Photos one ZIP export may hold
+    #[serde(default = "default_export_max_photos")]
+    pub export_max_photos: usize,
+    /// Listen on all interfaces instead of localhost and require `access_token`
+    /// on the API; takes effect on the next start
+    #[serde(default)]
+    pub allow_lan: bool,
+    /// Token LAN clients authenticate with, generated on the first start
+    #[serde(default)]
+    pub access_token: String,
+    /// Saved folder list while `--folder` replaces `folders` for this run;
+    /// `save` writes it instead until the user edits the folders
+    #[serde(skip)]
+    saved_folders: Option<Vec<FolderConfig>>,
+    /// File the settings were loaded from; `save` writes it back there
+    #[serde(skip)]
+    config_file: Option<PathBuf>,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+fn default_image_quality() -> u8 {
+    DEFAULT_IMAGE_QUALITY
+}
+
+fn default_image_cache_mb() -> u32 {
+    DEFAULT_IMAGE_CACHE_MB
+}
+
+fn default_follow_symlinks() -> bool {
+    true
+}
+
+fn default_location_format() -> String {
+    DEFAULT_LOCATION_FORMAT.to_string()
+}
+
+fn default_max_geocode_distance_km() -> f64 {
+    DEFAULT_MAX_GEOCODE_DISTANCE_KM
+}
+
+fn default_rate_limit_rps() -> u32 {
+    DEFAULT_RATE_LIMIT_RPS
+}
+
+fn default_rate_limit_burst() -> u32 {
+    DEFAULT_RATE_LIMIT_BURST
+}
+
+fn default_export_max_photos() -> usize {
+    DEFAULT_EXPORT_MAX_PHOTOS
+}
+
+fn default_exclude_dirs() -> Vec<String> {
+    vec!["node_modules".to_string(), "target".to_string()]
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
-            folders: [None, None, None, None, None],
+            folders: Vec::new(),
             start_browser: true,
+            port: DEFAULT_PORT,
             top: 12,
             left: 52,
             map_coords: true, // Show coordinates by default
             routes: false,    // Routes off by default
             heatmap: false,   // Heatmap off by default
+            image_quality: DEFAULT_IMAGE_QUALITY,
+            thumbnail_format: OutputFormat::Jpeg,
+            pregenerate_thumbnails: false, // Generate markers on demand by default
+            image_cache_mb: DEFAULT_IMAGE_CACHE_MB,
+            exclude_globs: Vec::new(),
+            max_depth: None,       // Scan all subfolders
+            follow_symlinks: true, // Symlinked year folders are part of the library
+            exclude_dirs: default_exclude_dirs(),
+            include_hidden: false, // Skip .git, .thumbnails and similar
+            write_back: false,     // Never modify photos unless asked to
+            processing: ProcessingConfig::default(),
+            storage: StorageKind::Memory, // Bincode caches unless SQLite is chosen
+            location_format: default_location_format(),
+            max_geocode_distance_km: DEFAULT_MAX_GEOCODE_DISTANCE_KM,
+            cors_allowed_origins: Vec::new(), // Only the app's own pages on localhost
+            rate_limit_rps: DEFAULT_RATE_LIMIT_RPS,
+            rate_limit_burst: DEFAULT_RATE_LIMIT_BURST,
+            access_log: false, // Only errors are logged per request
+            export_max_photos: DEFAULT_EXPORT_MAX_PHOTOS,
+            allow_lan: false,            // Only reachable from this computer
+            access_token: String::new(), // Generated by `load`
+            saved_folders: None,
+            config_file: None,
         }
     }
 }
 
 impl Settings {
     pub fn load() -> Result<Self> {
-        let config_path = Self::config_path();
-        let mut settings = Settings::default();
+        Self::load_from(Self::config_path())
+    }
+
+    /// Loads the settings from `config_path` and saves them back there
+    pub fn load_from(config_path: PathBuf) -> Result<Self> {
+        let mut settings = Settings {
+            config_file: Some(config_path.clone()),
+            ..Settings::default()
+        };
 
         if !config_path.exists() {
             // Create default settings file
+            settings.access_token = generate_access_token()?;
             settings
                 .save()
                 .context("Failed to create default settings file")?;
@@ -69,24 +348,7 @@ impl Settings {
             }
         }
 
-        // Load folders (path1-path5)
-        for i in 0..5 {
-            let key = format!("path{}", i + 1);
-            if let Some(path) = config_map.get(&key) {
-                let trimmed = path.trim_matches('"').trim();
-                if !trimmed.is_empty() {
-                    settings.folders[i] = Some(normalize_folder_path(trimmed));
-                }
-            }
-        }
-
-        // Backward compatibility: migrate last_folder to path1
-        if let Some(last_folder) = config_map.get("last_folder") {
-            let trimmed = last_folder.trim_matches('"').trim();
-            if !trimmed.is_empty() && settings.folders[0].is_none() {
-                settings.folders[0] = Some(normalize_folder_path(trimmed));
-            }
-        }
+        settings.folders = parse_folders(&config_map);
 
         if let Some(start_browser) = config_map.get("start_browser") {
             if let Ok(val) = start_browser.trim().parse::<bool>() {
@@ -94,6 +356,13 @@ impl Settings {
             }
         }
 
+        if let Some(port) = config_map.get("port") {
+            match port.trim().parse::<u16>() {
+                Ok(val) if val > 0 => settings.port = val,
+                _ => eprintln!("⚠️ Invalid port {}, using {}", port, DEFAULT_PORT),
+            }
+        }
+
         if let Some(top) = config_map.get("top") {
             if let Ok(val) = top.trim().parse::<i32>() {
                 settings.top = val;
@@ -125,12 +394,199 @@ impl Settings {
             }
         }
 
-        // If file exists but some fields are missing, save defaults back to file
-        let needs_save = !config_map.contains_key("top")
+        // Parse generated image encoding
+        if let Some(quality) = config_map.get("image_quality") {
+            if let Ok(val) = quality.trim().parse::<i64>() {
+                settings.image_quality = clamp_quality(val);
+            }
+        }
+
+        if let Some(format) = config_map.get("thumbnail_format") {
+            if let Some(val) = OutputFormat::parse(format.trim_matches('"')) {
+                settings.thumbnail_format = val;
+            }
+        }
+
+        if let Some(pregenerate) = config_map.get("pregenerate_thumbnails") {
+            if let Ok(val) = pregenerate.trim().parse::<bool>() {
+                settings.pregenerate_thumbnails = val;
+            }
+        }
+
+        if let Some(cache_mb) = config_map.get("image_cache_mb") {
+            if let Ok(val) = cache_mb.trim().parse::<u32>() {
+                settings.image_cache_mb = val;
+            }
+        }
+
+        // Exclude patterns are stored on one line separated by ';'
+        if let Some(globs) = config_map.get("exclude_globs") {
+            settings.exclude_globs = parse_exclude_globs(globs.trim_matches('"'));
+        }
+
+        // An empty value means no depth limit
+        if let Some(depth) = config_map.get("max_depth") {
+            settings.max_depth = depth.trim().parse::<usize>().ok();
+        }
+
+        if let Some(follow) = config_map.get("follow_symlinks") {
+            if let Ok(val) = follow.trim().parse::<bool>() {
+                settings.follow_symlinks = val;
+            }
+        }
+
+        if let Some(dirs) = config_map.get("exclude_dirs") {
+            settings.exclude_dirs = parse_exclude_globs(dirs.trim_matches('"'));
+        }
+
+        if let Some(hidden) = config_map.get("include_hidden") {
+            if let Ok(val) = hidden.trim().parse::<bool>() {
+                settings.include_hidden = val;
+            }
+        }
+
+        if let Some(write_back) = config_map.get("write_back") {
+            if let Ok(val) = write_back.trim().parse::<bool>() {
+                settings.write_back = val;
+            }
+        }
+
+        // An empty value means Rayon's default of one thread per core
+        if let Some(threads) = config_map.get("rayon_threads") {
+            settings.processing.rayon_threads = threads.trim().parse::<usize>().ok();
+        }
+
+        if let Some(quality) = config_map.get("thumbnail_quality") {
+            if let Ok(val) = quality.trim().parse::<u8>() {
+                settings.processing.thumbnail_jpeg_quality = val;
+            }
+        }
+
+        if let Some(quality) = config_map.get("marker_quality") {
+            if let Ok(val) = quality.trim().parse::<u8>() {
+                settings.processing.marker_jpeg_quality = val;
+            }
+        }
+
+        if let Some(import) = config_map.get("import_keywords") {
+            if let Ok(val) = import.trim().parse::<bool>() {
+                settings.processing.import_keywords = val;
+            }
+        }
+
+        if let Some(include) = config_map.get("include_suspicious_gps") {
+            if let Ok(val) = include.trim().parse::<bool>() {
+                settings.processing.include_suspicious_gps = val;
+            }
+        }
+
+        if let Some(storage) = config_map.get("storage") {
+            if let Some(val) = StorageKind::parse(storage.trim_matches('"')) {
+                settings.storage = val;
+            }
+        }
+
+        if let Some(format) = config_map.get("location_format") {
+            let trimmed = format.trim_matches('"').trim();
+            if !trimmed.is_empty() {
+                settings.location_format = trimmed.to_string();
+            }
+        }
+
+        if let Some(distance) = config_map.get("max_geocode_distance_km") {
+            if let Ok(val) = distance.trim().parse::<f64>() {
+                if val.is_finite() && val > 0.0 {
+                    settings.max_geocode_distance_km = val;
+                }
+            }
+        }
+
+        if let Some(origins) = config_map.get("cors_allowed_origins") {
+            settings.cors_allowed_origins = parse_exclude_globs(origins.trim_matches('"'));
+        }
+
+        if let Some(rps) = config_map.get("rate_limit_rps") {
+            if let Ok(val) = rps.trim().parse::<u32>() {
+                settings.rate_limit_rps = val;
+            }
+        }
+
+        if let Some(burst) = config_map.get("rate_limit_burst") {
+            if let Ok(val) = burst.trim().parse::<u32>() {
+                if val > 0 {
+                    settings.rate_limit_burst = val;
+                }
+            }
+        }
+
+        if let Some(access_log) = config_map.get("access_log") {
+            if let Ok(val) = access_log.trim().parse::<bool>() {
+                settings.access_log = val;
+            }
+        }
+
+        if let Some(max) = config_map.get("export_max_photos") {
+            if let Ok(val) = max.trim().parse::<usize>() {
+                if val > 0 {
+                    settings.export_max_photos = val;
+                }
+            }
+        }
+
+        if let Some(allow_lan) = config_map.get("allow_lan") {
+            if let Ok(val) = allow_lan.trim().parse::<bool>() {
+                settings.allow_lan = val;
+            }
+        }
+
+        if let Some(token) = config_map.get("access_token") {
+            settings.access_token = token.trim_matches('"').to_string();
+        }
+        let missing_token = settings.access_token.is_empty();
+        if missing_token {
+            settings.access_token = generate_access_token()?;
+        }
+
+        if let Err(e) = settings.processing.validate() {
+            eprintln!("⚠️ Invalid processing settings, using defaults: {}", e);
+            settings.processing = ProcessingConfig::default();
+        }
+
+        // If file exists but some fields are missing, save defaults back to file.
+        // Files with `pathN` or `last_folder` are rewritten with the folder list.
+        let needs_save = config_map.contains_key("path1")
+            || config_map.contains_key("last_folder")
+            || !config_map.contains_key("port")
+            || !config_map.contains_key("top")
             || !config_map.contains_key("left")
             || !config_map.contains_key("map_coords")
             || !config_map.contains_key("routes")
-            || !config_map.contains_key("heatmap");
+            || !config_map.contains_key("heatmap")
+            || !config_map.contains_key("image_quality")
+            || !config_map.contains_key("thumbnail_format")
+            || !config_map.contains_key("pregenerate_thumbnails")
+            || !config_map.contains_key("image_cache_mb")
+            || !config_map.contains_key("exclude_globs")
+            || !config_map.contains_key("max_depth")
+            || !config_map.contains_key("follow_symlinks")
+            || !config_map.contains_key("exclude_dirs")
+            || !config_map.contains_key("include_hidden")
+            || !config_map.contains_key("write_back")
+            || !config_map.contains_key("rayon_threads")
+            || !config_map.contains_key("thumbnail_quality")
+            || !config_map.contains_key("marker_quality")
+            || !config_map.contains_key("import_keywords")
+            || !config_map.contains_key("include_suspicious_gps")
+            || !config_map.contains_key("storage")
+            || !config_map.contains_key("location_format")
+            || !config_map.contains_key("max_geocode_distance_km")
+            || !config_map.contains_key("cors_allowed_origins")
+            || !config_map.contains_key("rate_limit_rps")
+            || !config_map.contains_key("rate_limit_burst")
+            || !config_map.contains_key("access_log")
+            || !config_map.contains_key("export_max_photos")
+            || !config_map.contains_key("allow_lan")
+            || missing_token;
         if needs_save {
             if let Err(e) = settings.save() {
                 eprintln!("Failed to save default settings: {}", e);
@@ -141,7 +597,7 @@ impl Settings {
     }
 
     pub fn save(&self) -> Result<()> {
-        let config_path = Self::config_path();
+        let config_path = self.config_file.clone().unwrap_or_else(Self::config_path);
 
         if let Some(parent) = config_path.parent() {
             std::fs::create_dir_all(parent).context("Creating config directory")?;
@@ -156,26 +612,270 @@ impl Settings {
         let mut content = String::new();
         content.push_str("# PhotoMap Configuration File\n");
 
-        // Save folders as path1-path5
-        for (i, folder) in self.folders.iter().enumerate() {
-            let value = folder
-                .as_deref()
-                .map(normalize_folder_path)
-                .unwrap_or_default();
-            content.push_str(&format!("path{} = \"{}\"\n", i + 1, value));
-        }
+        write_folders(self.folders_to_save(), &mut content);
 
         content.push_str(&format!("start_browser = {}\n", self.start_browser));
+        content.push_str(&format!("port = {}\n", self.port));
         content.push_str(&format!("top = {}\n", self.top));
         content.push_str(&format!("left = {}\n", self.left));
         content.push_str(&format!("map_coords = {}\n", self.map_coords));
         content.push_str(&format!("routes = {}\n", self.routes));
         content.push_str(&format!("heatmap = {}\n", self.heatmap));
+        content.push_str(&format!(
+            "image_quality = {}\n",
+            clamp_quality(self.image_quality as i64)
+        ));
+        content.push_str(&format!(
+            "thumbnail_format = {}\n",
+            self.thumbnail_format.name()
+        ));
+        content.push_str(&format!(
+            "pregenerate_thumbnails = {}\n",
+            self.pregenerate_thumbnails
+        ));
+        content.push_str(&format!("image_cache_mb = {}\n", self.image_cache_mb));
+        content.push_str(&format!(
+            "exclude_globs = \"{}\"\n",
+            self.exclude_globs.join(";")
+        ));
+        content.push_str(&format!(
+            "max_depth = {}\n",
+            self.max_depth.map(|d| d.to_string()).unwrap_or_default()
+        ));
+        content.push_str(&format!("follow_symlinks = {}\n", self.follow_symlinks));
+        content.push_str(&format!(
+            "exclude_dirs = \"{}\"\n",
+            self.exclude_dirs.join(";")
+        ));
+        content.push_str(&format!("include_hidden = {}\n", self.include_hidden));
+        content.push_str(&format!("write_back = {}\n", self.write_back));
+        content.push_str(&format!(
+            "rayon_threads = {}\n",
+            self.processing
+                .rayon_threads
+                .map(|n| n.to_string())
+                .unwrap_or_default()
+        ));
+        content.push_str(&format!(
+            "thumbnail_quality = {}\n",
+            self.processing.thumbnail_jpeg_quality
+        ));
+        content.push_str(&format!(
+            "marker_quality = {}\n",
+            self.processing.marker_jpeg_quality
+        ));
+        content.push_str(&format!(
+            "import_keywords = {}\n",
+            self.processing.import_keywords
+        ));
+        content.push_str(&format!(
+            "include_suspicious_gps = {}\n",
+            self.processing.include_suspicious_gps
+        ));
+        content.push_str(&format!("storage = {}\n", self.storage.name()));
+        content.push_str(&format!("location_format = \"{}\"\n", self.location_format));
+        content.push_str(&format!(
+            "max_geocode_distance_km = {}\n",
+            self.max_geocode_distance_km
+        ));
+        content.push_str(&format!(
+            "cors_allowed_origins = \"{}\"\n",
+            self.cors_allowed_origins.join(";")
+        ));
+        content.push_str(&format!("rate_limit_rps = {}\n", self.rate_limit_rps));
+        content.push_str(&format!("rate_limit_burst = {}\n", self.rate_limit_burst));
+        content.push_str(&format!("access_log = {}\n", self.access_log));
+        content.push_str(&format!("export_max_photos = {}\n", self.export_max_photos));
+        content.push_str(&format!("allow_lan = {}\n", self.allow_lan));
+        content.push_str(&format!("access_token = \"{}\"\n", self.access_token));
 
         std::fs::write(&config_path, content).context("Failed to write to config file")?;
         Ok(())
     }
 
+    /// Settings with the fields of a JSON object applied over these ones.
+    /// Nested objects such as `processing` are merged field by field too, and
+    /// unknown fields are ignored so that older or newer clients keep working.
+    /// Every field that cannot be read or fails validation is reported.
+    pub fn merged(&self, patch: &serde_json::Value) -> std::result::Result<Self, Vec<FieldError>> {
+        let Some(patch) = patch.as_object() else {
+            return Err(vec![FieldError::new("", "Expected a JSON object")]);
+        };
+        let current = serde_json::to_value(self).expect("settings serialize to JSON");
+
+        // Each field is applied alone first, so type errors name their field
+        let mut errors = Vec::new();
+        let mut merged = current.clone();
+        for (key, value) in patch {
+            let Some(old) = current.get(key) else {
+                tracing::debug!("Ignoring unknown setting {}", key);
+                continue;
+            };
+            let mut single = current.clone();
+            single[key] = merge_json(old, value);
+            match serde_json::from_value::<Settings>(single) {
+                Ok(_) => merged[key] = merge_json(old, value),
+                Err(e) => errors.push(FieldError::new(key, e.to_string())),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut merged: Settings =
+            serde_json::from_value(merged).map_err(|e| vec![FieldError::new("", e.to_string())])?;
+        merged.config_file = self.config_file.clone();
+        if !patch.contains_key("folders") {
+            merged.saved_folders = self.saved_folders.clone();
+        }
+        let errors = merged.validate(self);
+        if errors.is_empty() {
+            Ok(merged)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Problems of settings about to replace `previous`. Only newly added
+    /// folders must exist, so an unplugged drive does not block other changes.
+    pub fn validate(&self, previous: &Settings) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.port == 0 {
+            errors.push(FieldError::new("port", "must be between 1 and 65535"));
+        }
+        for (field, value) in [("top", self.top), ("left", self.left)] {
+            if value < 0 {
+                errors.push(FieldError::new(
+                    field,
+                    format!("must not be negative, got {}", value),
+                ));
+            }
+        }
+        if !(1..=100).contains(&self.image_quality) {
+            errors.push(FieldError::new(
+                "image_quality",
+                format!("must be between 1 and 100, got {}", self.image_quality),
+            ));
+        }
+        if self.image_cache_mb > MAX_IMAGE_CACHE_MB {
+            errors.push(FieldError::new(
+                "image_cache_mb",
+                format!(
+                    "must be at most {}, got {}",
+                    MAX_IMAGE_CACHE_MB, self.image_cache_mb
+                ),
+            ));
+        }
+        if !(1..=MAX_EXPORT_MAX_PHOTOS).contains(&self.export_max_photos) {
+            errors.push(FieldError::new(
+                "export_max_photos",
+                format!(
+                    "must be between 1 and {}, got {}",
+                    MAX_EXPORT_MAX_PHOTOS, self.export_max_photos
+                ),
+            ));
+        }
+        // The token travels in URLs, cookies and the INI file unescaped
+        let token_chars = |c: char| c.is_ascii_alphanumeric() || "-._~".contains(c);
+        if !self.access_token.chars().all(token_chars) {
+            errors.push(FieldError::new(
+                "access_token",
+                "may only contain letters, digits and - . _ ~",
+            ));
+        } else if self.allow_lan && self.access_token.is_empty() {
+            errors.push(FieldError::new(
+                "access_token",
+                "must not be empty while allow_lan is on",
+            ));
+        }
+        if !(self.max_geocode_distance_km > 0.0 && self.max_geocode_distance_km.is_finite()) {
+            errors.push(FieldError::new(
+                "max_geocode_distance_km",
+                format!("must be positive, got {}", self.max_geocode_distance_km),
+            ));
+        }
+        for folder in &self.folders {
+            let path = std::path::Path::new(&folder.path);
+            if !previous.has_folder(&folder.path) && !path.is_dir() {
+                errors.push(FieldError::new(
+                    "folders",
+                    format!("Folder does not exist: {}", folder.path),
+                ));
+            }
+        }
+        if let Err(e) = self.processing.validate() {
+            errors.push(FieldError::new("processing", e.to_string()));
+        }
+        errors
+    }
+
+    /// Appends picked folders to the folder list, keeping the configured ones
+    pub fn merge_folders(&mut self, picked: &[String]) -> FolderMerge {
+        self.keep_folders();
+        let mut merge = FolderMerge::default();
+        for path in picked {
+            let folder = normalize_folder_path(path);
+            if self.has_folder(&folder) {
+                merge.existing.push(folder);
+            } else {
+                self.folders.push(FolderConfig::new(&folder));
+                merge.added.push(folder);
+            }
+        }
+        merge
+    }
+
+    /// Uses `folders` for this run only (`--folder`); the config file keeps the
+    /// saved list until the user edits the folders
+    pub fn override_folders(&mut self, folders: Vec<FolderConfig>) {
+        let saved = std::mem::replace(&mut self.folders, folders);
+        self.saved_folders.get_or_insert(saved);
+    }
+
+    /// Whether the folders come from the command line instead of the config file
+    pub fn has_folder_override(&self) -> bool {
+        self.saved_folders.is_some()
+    }
+
+    /// Folder list the config file gets: the saved one during an override
+    fn folders_to_save(&self) -> &[FolderConfig] {
+        self.saved_folders.as_deref().unwrap_or(&self.folders)
+    }
+
+    /// Makes the current folder list the one `save` writes, ending an override.
+    /// Called when the user edits the folders.
+    pub fn keep_folders(&mut self) {
+        self.saved_folders = None;
+    }
+
+    /// Whether the folder is configured, enabled or not
+    pub fn has_folder(&self, path: &str) -> bool {
+        self.folders.iter().any(|f| f.path == path)
+    }
+
+    /// Paths of the folders that are processed and shown on the map
+    pub fn enabled_folders(&self) -> Vec<String> {
+        self.folders
+            .iter()
+            .filter(|f| f.enabled)
+            .map(|f| f.path.clone())
+            .collect()
+    }
+
+    /// Paths of the folders whose photos are kept off the map
+    pub fn disabled_folders(&self) -> Vec<String> {
+        self.folders
+            .iter()
+            .filter(|f| !f.enabled)
+            .map(|f| f.path.clone())
+            .collect()
+    }
+
+    /// Memory budget of the generated image cache in bytes
+    pub fn image_cache_bytes(&self) -> usize {
+        self.image_cache_mb as usize * 1024 * 1024
+    }
+
     pub fn config_path() -> PathBuf {
         let app_dir = crate::utils::get_app_data_dir();
 
@@ -186,6 +886,48 @@ impl Settings {
     }
 }
 
+/// 128 bits from the OS random source as hex
+fn generate_access_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).context("Failed to generate an access token")?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// A setting rejected by `Settings::merged`
+#[derive(Debug, Clone, PartialEq, Serialize, utoipa::ToSchema)]
+pub struct FieldError {
+    /// Name of the setting; empty when the request as a whole is wrong
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// `patch` applied over `base`: objects are merged key by key, anything else replaced
+fn merge_json(base: &serde_json::Value, patch: &serde_json::Value) -> serde_json::Value {
+    match (base, patch) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(patch)) => {
+            let mut merged = base.clone();
+            for (key, value) in patch {
+                let value = match base.get(key) {
+                    Some(old) => merge_json(old, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            serde_json::Value::Object(merged)
+        }
+        _ => patch.clone(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,6 +946,224 @@ mod tests {
         assert_eq!(normalize_folder_path("D:/Photo/Nested"), "D:/Photo/Nested");
     }
 
+    fn config_map(lines: &str) -> HashMap<String, String> {
+        lines
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn partial_update_keeps_other_fields() {
+        let mut settings = Settings::default();
+        settings.folders = vec![FolderConfig::new("Photos 2023")];
+        settings.processing.import_keywords = true;
+
+        let updated = settings
+            .merged(&serde_json::json!({
+                "start_browser": false,
+                "processing": { "marker_jpeg_quality": 60 }
+            }))
+            .unwrap();
+        assert!(!updated.start_browser);
+        assert_eq!(updated.folders, settings.folders);
+        assert_eq!(updated.processing.marker_jpeg_quality, 60);
+        assert!(updated.processing.import_keywords);
+        assert_eq!(updated.top, settings.top);
+    }
+
+    #[test]
+    fn invalid_fields_are_listed() {
+        let settings = Settings::default();
+        let fields = |patch: serde_json::Value| -> Vec<String> {
+            settings
+                .merged(&patch)
+                .unwrap_err()
+                .into_iter()
+                .map(|e| e.field)
+                .collect()
+        };
+
+        assert_eq!(fields(serde_json::json!({ "port": 0 })), ["port"]);
+        assert_eq!(fields(serde_json::json!({ "port": 70000 })), ["port"]);
+        assert_eq!(
+            fields(serde_json::json!({ "port": 8080, "top": -5, "image_quality": 0 })),
+            ["top", "image_quality"]
+        );
+        assert_eq!(
+            fields(serde_json::json!({ "folders": ["/photomap/definitely/missing"] })),
+            ["folders"]
+        );
+        assert_eq!(fields(serde_json::json!(["port"])), [""]);
+        assert_eq!(
+            fields(serde_json::json!({ "allow_lan": true, "access_token": "" })),
+            ["access_token"]
+        );
+        assert_eq!(
+            fields(serde_json::json!({ "access_token": "a b;c" })),
+            ["access_token"]
+        );
+        assert_eq!(
+            settings
+                .merged(&serde_json::json!({ "port": 8080 }))
+                .unwrap()
+                .port,
+            8080
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let settings = Settings::default();
+        let updated = settings
+            .merged(&serde_json::json!({ "heatmap": true, "theme": "dark", "path1": null }))
+            .unwrap();
+        assert!(updated.heatmap);
+        assert_eq!(updated.folders, settings.folders);
+    }
+
+    #[test]
+    fn generated_access_tokens_are_valid_and_distinct() {
+        let token = generate_access_token().unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(token.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(token, generate_access_token().unwrap());
+        let settings = Settings {
+            allow_lan: true,
+            access_token: token,
+            ..Settings::default()
+        };
+        assert!(settings.validate(&Settings::default()).is_empty());
+    }
+
+    #[test]
+    fn picked_folders_are_appended() {
+        let mut settings = Settings::default();
+        settings.folders = vec![
+            FolderConfig::new("Photos 2023"),
+            FolderConfig::new("Photos 2024"),
+        ];
+
+        // Output of the picker: one known folder, then more than five new ones
+        let picked: Vec<String> = ["Photos 2024", "a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        let merge = settings.merge_folders(&picked);
+
+        assert_eq!(merge.existing, ["Photos 2024"]);
+        assert_eq!(merge.added, ["a", "b", "c", "d", "e", "f"]);
+        let paths: Vec<&str> = settings.folders.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["Photos 2023", "Photos 2024", "a", "b", "c", "d", "e", "f"]
+        );
+        assert_eq!(settings.merge_folders(&[]), FolderMerge::default());
+    }
+
+    #[test]
+    fn five_slot_config_is_migrated_to_a_folder_list() {
+        let old = config_map(
+            "path1 = \"\"\npath2 = \"Photos 2023\"\npath3 = \"\"\npath4 = \"Photos 2024\"\npath5 = \"\"",
+        );
+        assert_eq!(
+            parse_folders(&old),
+            [
+                FolderConfig::new("Photos 2023"),
+                FolderConfig::new("Photos 2024")
+            ]
+        );
+        assert_eq!(
+            parse_folders(&config_map("last_folder = \"Camera\"")),
+            [FolderConfig::new("Camera")]
+        );
+        assert!(parse_folders(&config_map("path1 = \"\"")).is_empty());
+    }
+
+    #[test]
+    fn folder_list_is_written_without_a_cap() {
+        let mut folders: Vec<FolderConfig> = (1..=9)
+            .map(|i| FolderConfig::new(&format!("Trip {}", i)))
+            .collect();
+        folders[6].enabled = false;
+
+        let mut content = String::new();
+        write_folders(&folders, &mut content);
+        assert!(content.contains("folder9 = \"Trip 9\""));
+        assert!(content.contains("folder7_enabled = false"));
+        assert_eq!(parse_folders(&config_map(&content)), folders);
+
+        // Numbers order the folders; gaps and a missing flag are fine
+        let map = config_map("folder12 = \"b\"\nfolder3 = \"a\"\nfolder12_enabled = false");
+        let parsed = parse_folders(&map);
+        assert_eq!(parsed[0], FolderConfig::new("a"));
+        assert_eq!(parsed[1].path, "b");
+        assert!(!parsed[1].enabled);
+    }
+
+    #[test]
+    fn command_line_folders_are_not_saved_unless_edited() {
+        let mut settings = Settings {
+            folders: vec![FolderConfig::new("Photos 2023")],
+            ..Settings::default()
+        };
+        settings.override_folders(vec![FolderConfig::new("/mnt/card")]);
+        assert_eq!(settings.enabled_folders(), ["/mnt/card"]);
+        assert_eq!(
+            settings.folders_to_save(),
+            [FolderConfig::new("Photos 2023")]
+        );
+
+        // Unrelated updates keep the override, folder edits end it
+        let updated = settings
+            .merged(&serde_json::json!({ "heatmap": true }))
+            .unwrap();
+        assert_eq!(
+            updated.folders_to_save(),
+            [FolderConfig::new("Photos 2023")]
+        );
+        settings.merge_folders(&["/mnt/usb".to_string()]);
+        assert_eq!(
+            settings.folders_to_save(),
+            [
+                FolderConfig::new("/mnt/card"),
+                FolderConfig::new("/mnt/usb")
+            ]
+        );
+    }
+
+    #[test]
+    fn folders_are_read_from_json_in_either_shape() {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "folders": [null, "Photos 2023", "", {"path": "Photos 2024", "enabled": false}],
+            "start_browser": true, "top": 0, "left": 0,
+            "map_coords": true, "routes": false, "heatmap": false
+        }))
+        .unwrap();
+        assert_eq!(
+            settings.folders,
+            [
+                FolderConfig::new("Photos 2023"),
+                FolderConfig {
+                    path: "Photos 2024".to_string(),
+                    enabled: false
+                }
+            ]
+        );
+        assert_eq!(settings.enabled_folders(), ["Photos 2023"]);
+        assert_eq!(settings.disabled_folders(), ["Photos 2024"]);
+    }
+
+    #[test]
+    fn parses_exclude_globs_line() {
+        assert_eq!(
+            parse_exclude_globs(" Screenshots/ ;*.edited.jpg;; !keep/ "),
+            vec!["Screenshots/", "*.edited.jpg", "!keep/"]
+        );
+        assert!(parse_exclude_globs("").is_empty());
+    }
+
     #[test]
     fn test_settings_creation() {
         // Create a temp directory to act as HOME
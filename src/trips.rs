@@ -0,0 +1,190 @@
+//! Splits the timeline into trips: runs of photos where consecutive shots are
+//! close in both time and distance. Served at `/api/trips`.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::database::PhotoMetadata;
+use crate::exif_parser::generic::parse_timestamp;
+use crate::geocoding::haversine_km;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trip {
+    /// Position in the timeline, from 0
+    pub id: usize,
+    /// Capture time of the first and last photo; None for the undated bucket
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub photo_count: usize,
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lng: f64,
+    pub max_lng: f64,
+    /// Most frequent location name, ties by name
+    pub location: Option<String>,
+    /// Distinct location names in the order they were first visited
+    pub visited_locations: Vec<String>,
+    /// Relative paths in capture order
+    pub photos: Vec<String>,
+}
+
+impl Trip {
+    fn from_photos(id: usize, photos: &[(Option<i64>, &PhotoMetadata)]) -> Self {
+        let mut trip = Trip {
+            id,
+            start: None,
+            end: None,
+            photo_count: photos.len(),
+            min_lat: f64::INFINITY,
+            max_lat: f64::NEG_INFINITY,
+            min_lng: f64::INFINITY,
+            max_lng: f64::NEG_INFINITY,
+            location: None,
+            visited_locations: Vec::new(),
+            photos: Vec::with_capacity(photos.len()),
+        };
+        let mut locations: HashMap<&str, usize> = HashMap::new();
+        for (_, photo) in photos {
+            let (lat, lng) = (photo.coords.lat(), photo.coords.lng());
+            trip.min_lat = trip.min_lat.min(lat);
+            trip.max_lat = trip.max_lat.max(lat);
+            trip.min_lng = trip.min_lng.min(lng);
+            trip.max_lng = trip.max_lng.max(lng);
+            trip.photos.push(photo.relative_path.clone());
+            if let Some(location) = &photo.location {
+                let count = locations.entry(location).or_default();
+                if *count == 0 {
+                    trip.visited_locations.push(location.clone());
+                }
+                *count += 1;
+            }
+        }
+        if let (Some((Some(_), first)), Some((Some(_), last))) = (photos.first(), photos.last()) {
+            trip.start = Some(first.datetime.clone());
+            trip.end = Some(last.datetime.clone());
+        }
+        trip.location = locations
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(name, _)| name.to_string());
+        trip
+    }
+}
+
+/// Groups photos into trips in chronological order. A new trip starts when the
+/// next photo is more than `max_gap_days` later or `max_jump_km` away. Photos
+/// without a capture date end up in one undated trip at the end.
+pub fn detect_trips<'a>(
+    photos: impl IntoIterator<Item = &'a PhotoMetadata>,
+    max_gap_days: f64,
+    max_jump_km: f64,
+) -> Vec<Trip> {
+    let (mut dated, undated): (Vec<_>, Vec<_>) = photos
+        .into_iter()
+        .map(|photo| (parse_timestamp(&photo.datetime), photo))
+        .partition(|(time, _)| time.is_some());
+    dated.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then_with(|| a.1.relative_path.cmp(&b.1.relative_path))
+    });
+
+    let max_gap_secs = max_gap_days * 86_400.0;
+    let mut trips = Vec::new();
+    let mut start = 0;
+    for i in 1..=dated.len() {
+        let splits = dated.get(i).is_none_or(|(time, photo)| {
+            let (prev_time, prev) = dated[i - 1];
+            let gap = (time.unwrap() - prev_time.unwrap()) as f64;
+            let jump = haversine_km(
+                prev.coords.lat(),
+                prev.coords.lng(),
+                photo.coords.lat(),
+                photo.coords.lng(),
+            );
+            gap > max_gap_secs || jump > max_jump_km
+        });
+        if splits {
+            trips.push(Trip::from_photos(trips.len(), &dated[start..i]));
+            start = i;
+        }
+    }
+
+    if !undated.is_empty() {
+        let mut undated = undated;
+        undated.sort_by(|a, b| a.1.relative_path.cmp(&b.1.relative_path));
+        trips.push(Trip::from_photos(trips.len(), &undated));
+    }
+    trips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detect_trips;
+    use crate::database::PhotoMetadata;
+
+    fn paths(trip: &super::Trip) -> Vec<&str> {
+        trip.photos.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn gaps_in_time_and_distance_split_trips() {
+        let mut photos = vec![
+            // Versailles and Paris, with a gap of exactly three days kept in the same trip
+            PhotoMetadata::sample("p2.jpg", "2024-06-04 10:00:00", 48.86, 2.35),
+            PhotoMetadata::sample("p1.jpg", "2024-06-01 10:00:00", 48.85, 2.35),
+            PhotoMetadata::sample("v1.jpg", "2024-05-31 10:00:00", 48.80, 2.13),
+            // One second more than three days later
+            PhotoMetadata::sample("p3.jpg", "2024-06-07 10:00:01", 48.85, 2.34),
+            // Same day but ~340 km away in London
+            PhotoMetadata::sample("l1.jpg", "2024-06-07 12:00:00", 51.51, -0.13),
+            PhotoMetadata::sample("nodate.jpg", "Unknown Date", 0.0, 0.0),
+        ];
+        photos[0].location = Some("Paris, FR".to_string());
+        photos[1].location = Some("Paris, FR".to_string());
+        photos[2].location = Some("Versailles, FR".to_string());
+        let trips = detect_trips(&photos, 3.0, 300.0);
+
+        assert_eq!(trips.len(), 4);
+        assert_eq!(paths(&trips[0]), ["v1.jpg", "p1.jpg", "p2.jpg"]);
+        assert_eq!(trips[0].start.as_deref(), Some("2024-05-31 10:00:00"));
+        assert_eq!(trips[0].end.as_deref(), Some("2024-06-04 10:00:00"));
+        assert_eq!(trips[0].location.as_deref(), Some("Paris, FR"));
+        assert_eq!(trips[0].visited_locations, ["Versailles, FR", "Paris, FR"]);
+        assert_eq!((trips[0].min_lat, trips[0].max_lat), (48.80, 48.86));
+        assert_eq!(paths(&trips[1]), ["p3.jpg"]);
+        assert_eq!(paths(&trips[2]), ["l1.jpg"]);
+        assert_eq!(trips[2].photo_count, 1);
+        assert!(trips[2].location.is_none());
+
+        assert_eq!(trips.iter().map(|t| t.id).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        let undated = &trips[3];
+        assert_eq!(paths(undated), ["nodate.jpg"]);
+        assert!(undated.start.is_none() && undated.end.is_none());
+    }
+
+    #[test]
+    fn single_photo_is_one_trip() {
+        let photos = [PhotoMetadata::sample(
+            "a.jpg",
+            "2020-01-01 00:00:00",
+            10.0,
+            10.0,
+        )];
+        let trips = detect_trips(&photos, 3.0, 300.0);
+        assert_eq!(trips.len(), 1);
+        assert_eq!(trips[0].start, trips[0].end);
+        assert_eq!((trips[0].min_lng, trips[0].max_lng), (10.0, 10.0));
+        assert!(detect_trips(&[], 3.0, 300.0).is_empty());
+    }
+
+    #[test]
+    fn loose_thresholds_keep_one_trip() {
+        let photos = [
+            PhotoMetadata::sample("a.jpg", "2020-01-01 00:00:00", 48.85, 2.35),
+            PhotoMetadata::sample("b.jpg", "2020-03-01 00:00:00", 51.51, -0.13),
+        ];
+        assert_eq!(detect_trips(&photos, 90.0, 1000.0).len(), 1);
+        assert_eq!(detect_trips(&photos, 30.0, 1000.0).len(), 2);
+    }
+}
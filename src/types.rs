@@ -0,0 +1,174 @@
+//! Validated value types shared by processing, the database and the server
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Latitude and longitude in decimal degrees, always within valid ranges.
+/// Serializes as a flat `{"lat": .., "lng": ..}` object.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawGpsCoordinate")]
+pub struct GpsCoordinate {
+    lat: f64,
+    lng: f64,
+}
+
+/// Unvalidated shape used to run deserialized values through `GpsCoordinate::new`
+#[derive(Deserialize)]
+struct RawGpsCoordinate {
+    lat: f64,
+    lng: f64,
+}
+
+impl TryFrom<RawGpsCoordinate> for GpsCoordinate {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawGpsCoordinate) -> Result<Self> {
+        GpsCoordinate::new(raw.lat, raw.lng)
+    }
+}
+
+impl GpsCoordinate {
+    /// Rejects NaN, infinity, latitudes outside [-90, 90] and longitudes outside [-180, 180]
+    pub fn new(lat: f64, lng: f64) -> Result<Self> {
+        if !lat.is_finite() || !lng.is_finite() {
+            bail!("GPS coordinate is not a finite number: ({}, {})", lat, lng);
+        }
+        if !(-90.0..=90.0).contains(&lat) {
+            bail!("Latitude {} is outside [-90, 90]", lat);
+        }
+        if !(-180.0..=180.0).contains(&lng) {
+            bail!("Longitude {} is outside [-180, 180]", lng);
+        }
+        Ok(Self { lat, lng })
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub fn lng(&self) -> f64 {
+        self.lng
+    }
+}
+
+/// Map area as sent by Leaflet's `toBBoxString()`: "west,south,east,north".
+/// When west > east the box crosses the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+    pub const WORLD: BoundingBox = BoundingBox {
+        south: -90.0,
+        west: -180.0,
+        north: 90.0,
+        east: 180.0,
+    };
+
+    pub fn parse(value: &str) -> Result<Self> {
+        let parts: Vec<f64> = value
+            .split(',')
+            .map(|part| part.trim().parse::<f64>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| anyhow::anyhow!("Invalid bbox '{}': expected 4 numbers", value))?;
+        let [west, south, east, north] = parts[..] else {
+            bail!("Invalid bbox '{}': expected west,south,east,north", value);
+        };
+        if parts.iter().any(|v| !v.is_finite()) {
+            bail!("Invalid bbox '{}': values must be finite", value);
+        }
+        if south > north {
+            bail!("Invalid bbox '{}': south is above north", value);
+        }
+
+        // Leaflet keeps counting past ±180° when the map is panned around the world
+        let (west, east) = if east - west >= 360.0 {
+            (-180.0, 180.0)
+        } else {
+            (wrap_longitude(west), wrap_longitude(east))
+        };
+        Ok(Self {
+            south: south.max(-90.0),
+            west,
+            north: north.min(90.0),
+            east,
+        })
+    }
+
+    pub fn contains(&self, coords: GpsCoordinate) -> bool {
+        let lat_inside = (self.south..=self.north).contains(&coords.lat());
+        let lng_inside = if self.west <= self.east {
+            (self.west..=self.east).contains(&coords.lng())
+        } else {
+            coords.lng() >= self.west || coords.lng() <= self.east
+        };
+        lat_inside && lng_inside
+    }
+}
+
+/// Maps a longitude into [-180, 180]
+fn wrap_longitude(lng: f64) -> f64 {
+    if (-180.0..=180.0).contains(&lng) {
+        lng
+    } else {
+        (lng + 180.0).rem_euclid(360.0) - 180.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoundingBox, GpsCoordinate};
+
+    #[test]
+    fn parses_leaflet_bbox_including_antimeridian() {
+        let bbox = BoundingBox::parse("2.2,48.8,2.5,48.9").unwrap();
+        assert!(bbox.contains(GpsCoordinate::new(48.85, 2.35).unwrap()));
+        assert!(!bbox.contains(GpsCoordinate::new(48.85, 3.0).unwrap()));
+
+        // Panned east past 180°: 170..190 wraps to 170..-170
+        let pacific = BoundingBox::parse("170,-20,190,0").unwrap();
+        assert_eq!((pacific.west, pacific.east), (170.0, -170.0));
+        assert!(pacific.contains(GpsCoordinate::new(-10.0, 175.0).unwrap()));
+        assert!(pacific.contains(GpsCoordinate::new(-10.0, -175.0).unwrap()));
+        assert!(!pacific.contains(GpsCoordinate::new(-10.0, 0.0).unwrap()));
+
+        let world = BoundingBox::parse("-400,-100,400,100").unwrap();
+        assert_eq!(world, BoundingBox::WORLD);
+
+        for invalid in ["", "1,2,3", "a,b,c,d", "0,10,1,5", "0,0,NaN,1"] {
+            assert!(BoundingBox::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn accepts_boundaries_and_rejects_out_of_range() {
+        assert!(GpsCoordinate::new(90.0, 180.0).is_ok());
+        assert!(GpsCoordinate::new(-90.0, -180.0).is_ok());
+        assert!(GpsCoordinate::new(9999.0, 0.0).is_err());
+        assert!(GpsCoordinate::new(0.0, -180.5).is_err());
+        assert!(GpsCoordinate::new(f64::NAN, 0.0).is_err());
+        assert!(GpsCoordinate::new(0.0, f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn serializes_as_flat_lat_lng_and_validates_on_deserialize() {
+        let coords = GpsCoordinate::new(48.8566, 2.3522).unwrap();
+        let json = serde_json::to_string(&coords).unwrap();
+        assert_eq!(json, r#"{"lat":48.8566,"lng":2.3522}"#);
+        assert_eq!(
+            serde_json::from_str::<GpsCoordinate>(&json).unwrap(),
+            coords
+        );
+        assert!(serde_json::from_str::<GpsCoordinate>(r#"{"lat":91.0,"lng":0.0}"#).is_err());
+
+        let bytes = bincode::serialize(&coords).unwrap();
+        assert_eq!(
+            bincode::deserialize::<GpsCoordinate>(&bytes).unwrap(),
+            coords
+        );
+    }
+}
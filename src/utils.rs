@@ -2,6 +2,8 @@ mod app_paths;
 mod browser;
 mod folder_picker;
 
-pub use app_paths::{ensure_directory_exists, get_app_data_dir, get_config_path};
+pub use app_paths::{
+    ensure_directory_exists, get_app_data_dir, get_config_path, set_app_data_dir, set_config_path,
+};
 pub use browser::open_browser;
 pub use folder_picker::select_folders_native;
@@ -1,7 +1,27 @@
 use std::path::PathBuf;
+use std::sync::OnceLock;
+
+static APP_DATA_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Replaces the application data directory for this process (`--cache-dir`).
+/// Must be called before anything reads settings or caches.
+pub fn set_app_data_dir(path: PathBuf) {
+    let _ = APP_DATA_DIR_OVERRIDE.set(path);
+}
+
+/// Replaces the settings file for this process (`--config`). Caches stay in
+/// the application data directory. Must be called before settings are loaded.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH_OVERRIDE.set(path);
+}
 
 /// Returns the cross-platform directory for application data.
 pub fn get_app_data_dir() -> PathBuf {
+    if let Some(path) = APP_DATA_DIR_OVERRIDE.get() {
+        return path.clone();
+    }
+
     if cfg!(target_os = "macos") {
         let home_dir = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
         let mut path = PathBuf::from(home_dir);
@@ -41,6 +61,10 @@ pub fn ensure_directory_exists(path: &PathBuf) -> Result<(), std::io::Error> {
 
 /// Returns the path to the application configuration file.
 pub fn get_config_path() -> PathBuf {
+    if let Some(path) = CONFIG_PATH_OVERRIDE.get() {
+        return path.clone();
+    }
+
     let mut config_dir = get_app_data_dir();
     config_dir.push("photomap.ini");
     config_dir
@@ -0,0 +1,1005 @@
+//! End-to-end tests of the HTTP API against the real router.
+//!
+//! Each test processes its own synthetic library into a fresh `Database` and
+//! serves it in-process on a listener bound to an ephemeral port, with settings
+//! in its own temp directory. Nothing touches a running PhotoMap or the user's
+//! data directory. Every test runs once per storage backend.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use photomap_processor::database::Database;
+use photomap_processor::image_cache::ImageCache;
+use photomap_processor::image_processing::DiskThumbnailCache;
+use photomap_processor::processing::{self, WalkOptions};
+use photomap_processor::server::{
+    self,
+    state::{unix_now, AppState, FolderStats, LastRunStats},
+};
+use photomap_processor::settings::{self, FolderConfig, Settings};
+use photomap_processor::{geocoding, utils};
+
+/// Known positions of the synthetic library: (file, lat, lng, unix timestamp)
+const PHOTOS: [(&str, f64, f64, u64); 5] = [
+    ("paris.jpg", 48.8566, 2.3522, 1_717_243_200),
+    ("berlin.jpg", 52.5200, 13.4050, 1_719_835_200),
+    ("rome.jpg", 41.9028, 12.4964, 1_722_513_600),
+    ("madrid.jpg", 40.4168, -3.7038, 1_725_192_000),
+    ("trips/lisbon.jpg", 38.7223, -9.1393, 1_727_784_000),
+];
+
+/// Server running on its own task; stopped and cleaned up when dropped
+struct TestServer {
+    task: tokio::task::JoinHandle<()>,
+    base_url: String,
+    root: PathBuf,
+    client: reqwest::Client,
+}
+
+impl TestServer {
+    async fn start(name: &str, storage: &str) -> Self {
+        Self::start_with(name, storage, |_| {}).await
+    }
+
+    /// Starts a server whose library gets extra files from `setup` before processing
+    async fn start_with(name: &str, storage: &str, setup: impl FnOnce(&Path)) -> Self {
+        let root = std::env::temp_dir().join(format!(
+            "photomap_api_test_{name}_{storage}_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        let photos_dir = root.join("photos");
+        write_library(&photos_dir);
+        setup(&photos_dir);
+
+        // Folder caches are named after their folder, so the tests of this
+        // process share one data directory without sharing a cache file
+        utils::set_app_data_dir(
+            std::env::temp_dir().join(format!("photomap_api_test_data_{}", std::process::id())),
+        );
+        let data = root.join("data");
+        std::fs::create_dir_all(&data).unwrap();
+        // Missing settings are filled with defaults on load
+        let config = data.join("photomap.ini");
+        std::fs::write(&config, format!("storage = {storage}\n")).unwrap();
+        let mut settings = Settings::load_from(config).unwrap();
+        let folder = std::fs::canonicalize(&photos_dir).unwrap();
+        let folder = settings::normalize_folder_path(&folder.to_string_lossy());
+        settings.folders = vec![FolderConfig::new(&folder)];
+
+        let db = match storage {
+            "sqlite" => Database::open_sqlite(&data.join("library.sqlite")),
+            _ => Database::new(),
+        }
+        .unwrap();
+        let last_run = process_library(&db, &settings, &folder).await;
+
+        let (event_sender, mut events) = tokio::sync::mpsc::channel(100);
+        let event_broadcast = tokio::sync::broadcast::channel(100).0;
+        let forward = event_broadcast.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let _ = forward.send(event);
+            }
+        });
+        let state = AppState {
+            db,
+            image_cache: ImageCache::new(settings.image_cache_bytes()),
+            disk_cache: DiskThumbnailCache::new(data.join("thumbnails")),
+            settings: Arc::new(tokio::sync::Mutex::new(settings)),
+            cancel_processing: Arc::new(AtomicBool::new(false)),
+            processing: Default::default(),
+            folder_dialog: Default::default(),
+            last_run: Arc::new(std::sync::Mutex::new(Some(last_run))),
+            event_sender,
+            event_broadcast,
+            shutdown_sender: tokio::sync::broadcast::channel(1).0,
+        };
+
+        // The listener stays bound from here on, so no other process can take the port
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let task = tokio::spawn(async move {
+            server::serve(listener, state).await.unwrap();
+        });
+
+        TestServer {
+            task,
+            base_url,
+            root,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn wait_until_idle(&self) {
+        let deadline = Instant::now() + Duration::from_secs(60);
+        while Instant::now() < deadline {
+            if self.get_json("/api/processing-status").await["status"] != "running" {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("processing did not finish at {}", self.base_url);
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn get_json(&self, path: &str) -> serde_json::Value {
+        let response = self.client.get(self.url(path)).send().await.unwrap();
+        assert_eq!(response.status(), 200, "GET {path}");
+        response.json().await.unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Processes the folder like the app does at startup and saves its cache
+async fn process_library(db: &Database, settings: &Settings, folder: &str) -> LastRunStats {
+    let db = db.clone();
+    let walk_options = WalkOptions::from_settings(settings);
+    let processing_config = settings.processing;
+    let folder = folder.to_string();
+    tokio::task::spawn_blocking(move || {
+        geocoding::ReverseGeocoder::init();
+        let started_at = unix_now();
+        let stats = processing::process_photos_with_stats(
+            &db,
+            Path::new(&folder),
+            true,
+            false,
+            &walk_options,
+            &processing_config,
+            &AtomicBool::new(false),
+            None,
+        )
+        .unwrap();
+        db.save_to_disk(std::slice::from_ref(&folder), &walk_options)
+            .unwrap();
+        LastRunStats::new(
+            started_at,
+            vec![FolderStats {
+                path: folder,
+                stats,
+            }],
+            false,
+        )
+    })
+    .await
+    .unwrap()
+}
+
+/// Writes small JPEGs whose positions come from Google Takeout JSON sidecars
+fn write_library(dir: &Path) {
+    for (i, (name, lat, lng, timestamp)) in PHOTOS.iter().enumerate() {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image::RgbImage::from_pixel(64, 48, image::Rgb([i as u8 * 40, 120, 200]))
+            .save(&path)
+            .unwrap();
+        write_sidecar(&path, *lat, *lng, *timestamp);
+    }
+}
+
+fn write_sidecar(path: &Path, lat: f64, lng: f64, timestamp: u64) {
+    let sidecar = serde_json::json!({
+        "photoTakenTime": { "timestamp": timestamp.to_string() },
+        "geoDataExif": { "latitude": lat, "longitude": lng },
+    });
+    std::fs::write(
+        path.with_file_name(format!(
+            "{}.json",
+            path.file_name().unwrap().to_string_lossy()
+        )),
+        sidecar.to_string(),
+    )
+    .unwrap();
+}
+
+/// Encodes a small HEIC, or returns false when libheif was built without an HEVC encoder
+fn write_heic(path: &Path) -> bool {
+    use libheif_rs::{
+        Channel, ColorSpace, CompressionFormat, HeifContext, Image, LibHeif, RgbChroma,
+    };
+
+    let (width, height) = (64, 48);
+    let encoded = (|| -> libheif_rs::Result<()> {
+        let mut image = Image::new(width, height, ColorSpace::Rgb(RgbChroma::Rgb))?;
+        image.create_plane(Channel::Interleaved, width, height, 8)?;
+        let plane = image.planes_mut().interleaved.unwrap();
+        for y in 0..height as usize {
+            for pixel in plane.data[y * plane.stride..][..width as usize * 3].chunks_exact_mut(3) {
+                pixel.copy_from_slice(&[200, 80, 40]);
+            }
+        }
+        let mut encoder = LibHeif::new().encoder_for_format(CompressionFormat::Hevc)?;
+        let mut context = HeifContext::new()?;
+        context.encode_image(&image, &mut encoder, None)?;
+        context.write_to_file(path.to_str().unwrap())
+    })();
+    encoded.is_ok()
+}
+
+/// CRC-32 of a PNG chunk, over its type and data
+fn png_crc(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Writes a small PNG whose eXIf chunk holds a GPS position in whole degrees
+fn write_geotagged_png(path: &Path, lat: u32, lng: u32) {
+    use exif::experimental::Writer;
+    use exif::{Field, In, Rational, Tag, Value};
+
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let degrees = |num| Value::Rational(vec![Rational { num, denom: 1 }]);
+    let fields = [
+        field(Tag::GPSLatitude, degrees(lat)),
+        field(Tag::GPSLatitudeRef, Value::Ascii(vec![b"N".to_vec()])),
+        field(Tag::GPSLongitude, degrees(lng)),
+        field(Tag::GPSLongitudeRef, Value::Ascii(vec![b"E".to_vec()])),
+    ];
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = std::io::Cursor::new(Vec::new());
+    writer.write(&mut tiff, false).unwrap();
+    let tiff = tiff.into_inner();
+
+    let mut png = Vec::new();
+    image::RgbImage::from_pixel(64, 48, image::Rgb([40, 160, 80]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    // The eXIf chunk goes right after the signature and IHDR
+    let ihdr_end = 8 + 4 + 4 + 13 + 4;
+    let mut body = b"eXIf".to_vec();
+    body.extend_from_slice(&tiff);
+    let mut chunk = (tiff.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&png_crc(&body).to_be_bytes());
+    png.splice(ihdr_end..ihdr_end, chunk);
+    std::fs::write(path, png).unwrap();
+}
+
+#[tokio::test]
+async fn api_serves_processed_library_from_memory() {
+    serves_processed_library("memory").await;
+}
+
+#[tokio::test]
+async fn api_serves_processed_library_from_sqlite() {
+    serves_processed_library("sqlite").await;
+}
+
+async fn serves_processed_library(storage: &str) {
+    let server = TestServer::start("library", storage).await;
+
+    // Photos: all five, at the positions from their sidecars
+    let photos = server.get_json("/api/photos").await;
+    let photos = photos.as_array().unwrap();
+    assert_eq!(photos.len(), PHOTOS.len());
+    for (name, lat, lng, _) in PHOTOS {
+        let photo = photos
+            .iter()
+            .find(|p| p["relative_path"].as_str().unwrap().replace('\\', "/") == name)
+            .unwrap_or_else(|| panic!("{name} missing from /api/photos"));
+        assert!((photo["lat"].as_f64().unwrap() - lat).abs() < 1e-9);
+        assert!((photo["lng"].as_f64().unwrap() - lng).abs() < 1e-9);
+        assert_eq!(photo["source"], "GoogleTakeoutJson");
+    }
+    let paris = photos
+        .iter()
+        .find(|p| p["filename"] == "paris.jpg")
+        .unwrap();
+    assert_eq!(paris["datetime"], "2024-06-01 12:00:00");
+
+    // One photo by id; every response carries a request id for the logs
+    let response = server
+        .client
+        .get(server.url(&format!("/api/photos/{}", paris["id"].as_str().unwrap())))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("x-request-id"));
+    let photo: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(photo["filename"], "paris.jpg");
+    let response = server
+        .client
+        .get(server.url("/api/photos/0000000000000000"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    // Details: the synthetic JPEGs carry no EXIF, so there is nothing to show
+    let details = server.get_json("/api/photo-info/paris.jpg").await;
+    assert!(details["info"].as_object().unwrap().is_empty());
+    let response = server
+        .client
+        .get(server.url("/api/photo-info/missing.jpg"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    // Trips: the photos are a month apart, so each one is a trip of its own
+    let trips = server
+        .get_json("/api/trips?max_gap_days=3&max_jump_km=300")
+        .await;
+    let trips = trips.as_array().unwrap();
+    assert_eq!(trips.len(), PHOTOS.len());
+    assert_eq!(trips[0]["photos"][0], "paris.jpg");
+    assert_eq!(trips[0]["photo_count"], 1);
+    assert_eq!(trips[4]["id"], 4);
+    assert!(trips[0]["visited_locations"].is_array());
+    let hourly = server
+        .get_json("/api/trips?max_gap_hours=72&max_jump_km=300")
+        .await;
+    assert_eq!(hourly.as_array().unwrap().len(), PHOTOS.len());
+    let response = server
+        .client
+        .get(server.url("/api/trips?max_gap_hours=-1"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    // Albums: the first folder of the relative path, "/" for the root
+    let albums = server.get_json("/api/albums").await;
+    let albums = albums.as_array().unwrap();
+    assert_eq!(albums.len(), 2);
+    assert_eq!(albums[0]["name"], "/");
+    assert_eq!(albums[0]["count"], PHOTOS.len() - 1);
+    assert_eq!(albums[1]["name"], "trips");
+    assert_eq!(
+        albums[1]["cover_photo_relative_path"]
+            .as_str()
+            .unwrap()
+            .replace('\\', "/"),
+        "trips/lisbon.jpg"
+    );
+    let in_album = server.get_json("/api/photos?album=trips").await;
+    let in_album = in_album.as_array().unwrap();
+    assert_eq!(in_album.len(), 1);
+    assert_eq!(in_album[0]["album"], "trips");
+
+    // Health and version, for uptime checkers
+    let health = server.get_json("/health").await;
+    assert_eq!(health["status"], "ok");
+    assert_eq!(health["photos"], PHOTOS.len());
+    assert!(health["geocoder_ready"].is_boolean());
+    let version = server.get_json("/version").await;
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+
+    // API description
+    let spec = server.get_json("/api/openapi.json").await;
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3.0"));
+    assert!(spec["paths"]["/api/photos/near"]["get"].is_object());
+
+    // Search: by filename and by capture date
+    let found = server.get_json("/api/photos/search?q=PARIS").await;
+    assert_eq!(found["page"], 1);
+    let found = found["photos"].as_array().unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["filename"], "paris.jpg");
+    let found = server
+        .get_json("/api/photos/search?q=2024-06-01&per_page=1&page=2")
+        .await;
+    assert!(found["photos"].as_array().unwrap().len() <= 1);
+
+    // Nearby: Madrid and Lisbon are ~500 km apart, Paris is further away
+    let near = server
+        .get_json("/api/photos/near?lat=40.4168&lng=-3.7038&radius_m=600000&exclude=madrid.jpg")
+        .await;
+    let near = near.as_array().unwrap();
+    assert_eq!(near.len(), 1);
+    assert_eq!(near[0]["relative_path"], "trips/lisbon.jpg");
+    let km = near[0]["distance_m"].as_f64().unwrap() / 1000.0;
+    assert!((km - 503.0).abs() < 5.0, "{km}");
+
+    // Tags: set on one photo, then used as an AND filter
+    let response = server
+        .client
+        .post(server.url("/api/photos/tags"))
+        .json(&serde_json::json!({ "id": paris["id"], "tags": ["Eiffel", "city"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let tags = server.get_json("/api/tags").await;
+    assert_eq!(tags, serde_json::json!({ "city": 1, "eiffel": 1 }));
+    let tagged = server.get_json("/api/photos?tags=city,eiffel").await;
+    assert_eq!(tagged.as_array().unwrap().len(), 1);
+    assert_eq!(tagged[0]["tags"], serde_json::json!(["city", "eiffel"]));
+    let tagged = server.get_json("/api/photos?tags=city,beach").await;
+    assert!(tagged.as_array().unwrap().is_empty());
+
+    // Markers: a real JPEG rendered on demand
+    let marker_url = paris["marker_icon"].as_str().unwrap();
+    let response = server
+        .client
+        .get(server.url(marker_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/jpeg");
+    let marker = response.bytes().await.unwrap();
+    assert_eq!(&marker[..2], &[0xFF, 0xD8]);
+
+    let missing = server
+        .client
+        .get(server.url("/api/marker/missing.jpg"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+
+    let library = server.get_json("/api/stats").await;
+    assert_eq!(library["photos_with_gps"], PHOTOS.len());
+    assert!(library["total_size_bytes"].as_u64().unwrap() > 0);
+    let per_year = library["per_year"].as_array().unwrap();
+    let dated: u64 = per_year.iter().map(|y| y["count"].as_u64().unwrap()).sum();
+    assert_eq!(dated, PHOTOS.len() as u64);
+
+    // Years: all photos are from 2024; the date bounds are inclusive
+    let years = server.get_json("/api/photos/years").await;
+    assert_eq!(years.as_array().unwrap().len(), 1);
+    assert_eq!(years[0]["year"], 2024);
+    assert_eq!(years[0]["count"], PHOTOS.len());
+    assert!(years[0]["example_lat"].is_f64());
+    let summer = server
+        .get_json("/api/photos?start_date=2024-07-01&end_date=2024-08-31")
+        .await;
+    let mut summer: Vec<_> = summer
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["filename"].as_str().unwrap().to_string())
+        .collect();
+    summer.sort();
+    assert_eq!(summer, ["berlin.jpg", "rome.jpg"]);
+    let response = server
+        .client
+        .get(server.url("/api/photos?start_date=2024"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    // Startup processing is reported with its per-folder breakdown
+    let stats = server.get_json("/api/stats/last-run").await;
+    assert_eq!(stats["processed"], PHOTOS.len());
+    assert_eq!(stats["cancelled"], false);
+    assert_eq!(stats["folders"].as_array().unwrap().len(), 1);
+    assert_eq!(stats["folders"][0]["processed"], PHOTOS.len());
+    assert!(stats["folders"][0]["failed_files"]
+        .as_array()
+        .unwrap()
+        .is_empty());
+
+    // Processing geocoded the photos, so place search is ready
+    let places = server.get_json("/api/search-place?q=lisbon&limit=5").await;
+    assert_eq!(places[0]["name"], "Lisbon");
+    assert!(places.as_array().unwrap().len() <= 5);
+
+    // Status endpoint answers while idle
+    let status = server.get_json("/api/processing-status").await;
+    assert_eq!(status["status"], "idle");
+
+    let sqlite_file = server.root.join("data").join("library.sqlite");
+    assert_eq!(sqlite_file.exists(), storage == "sqlite");
+
+    // The startup folder is listed with its photos, then removed together with them
+    let folders = server.get_json("/api/folders").await;
+    assert_eq!(folders.as_array().unwrap().len(), 1);
+    assert_eq!(folders[0]["photo_count"], PHOTOS.len());
+    assert_eq!(folders[0]["exists"], true);
+    assert!(folders[0]["last_processed"].is_string());
+    assert_eq!(folders[0]["enabled"], true);
+
+    // A disabled folder leaves the map and comes back from its cache
+    let index = folders[0]["index"].as_u64().unwrap();
+    for enabled in [false, true] {
+        let response = server
+            .client
+            .post(server.url(&format!("/api/folders/{index}/enabled")))
+            .json(&serde_json::json!({ "enabled": enabled }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["status"], "success");
+        let photos = server.get_json("/api/photos").await;
+        let expected = if enabled { PHOTOS.len() } else { 0 };
+        assert_eq!(photos.as_array().unwrap().len(), expected);
+    }
+    let saved = std::fs::read_to_string(server.root.join("data").join("photomap.ini")).unwrap();
+    assert!(saved.contains("folder1_enabled = true"));
+
+    // Folders are added and removed by path, next to the others
+    let extra = server.root.join("extra");
+    std::fs::create_dir_all(&extra).unwrap();
+    let extra = extra.to_string_lossy().to_string();
+    let response = server
+        .client
+        .post(server.url("/api/folders/add"))
+        .json(&serde_json::json!({ "path": extra }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    server.wait_until_idle().await;
+    assert_eq!(
+        server
+            .get_json("/api/folders")
+            .await
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+    let response = server
+        .client
+        .post(server.url("/api/folders/add"))
+        .json(&serde_json::json!({ "path": server.root.join("missing") }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let response = server
+        .client
+        .post(server.url("/api/folders/remove"))
+        .json(&serde_json::json!({ "path": extra }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let response = server
+        .client
+        .post(server.url("/api/folders/remove"))
+        .json(&serde_json::json!({ "path": extra }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let folders = server.get_json("/api/folders").await;
+    assert_eq!(folders.as_array().unwrap().len(), 1);
+    assert_eq!(folders[0]["photo_count"], PHOTOS.len());
+
+    let response = server
+        .client
+        .delete(server.url(&format!("/api/folders/{index}")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(server
+        .get_json("/api/folders")
+        .await
+        .as_array()
+        .unwrap()
+        .is_empty());
+    assert!(server
+        .get_json("/api/photos")
+        .await
+        .as_array()
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn settings_update_is_persisted_with_memory() {
+    settings_update_is_persisted("memory").await;
+}
+
+#[tokio::test]
+async fn settings_update_is_persisted_with_sqlite() {
+    settings_update_is_persisted("sqlite").await;
+}
+
+async fn settings_update_is_persisted(storage: &str) {
+    let server = TestServer::start("settings", storage).await;
+
+    let mut settings = server.get_json("/api/settings").await;
+    assert_eq!(settings["heatmap"], false);
+    settings["heatmap"] = serde_json::json!(true);
+    settings["image_quality"] = serde_json::json!(70);
+
+    let response = server
+        .client
+        .post(server.url("/api/update_settings"))
+        .json(&settings)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let updated = server.get_json("/api/settings").await;
+    assert_eq!(updated["heatmap"], true);
+    assert_eq!(updated["image_quality"], 70);
+
+    let saved = std::fs::read_to_string(server.root.join("data").join("photomap.ini")).unwrap();
+    assert!(saved.contains("heatmap = true"));
+    assert!(saved.contains("image_quality = 70"));
+    assert!(saved.contains(&format!("storage = {storage}")));
+    assert!(saved.contains("folder1 = "));
+    assert!(!saved.contains("path1"));
+
+    // A partial update leaves the folders alone; invalid fields are listed
+    let response = server
+        .client
+        .post(server.url("/api/update_settings"))
+        .json(&serde_json::json!({ "start_browser": false }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let updated = server.get_json("/api/settings").await;
+    assert_eq!(updated["start_browser"], false);
+    assert_eq!(updated["folders"], settings["folders"]);
+
+    let response = server
+        .client
+        .post(server.url("/api/update_settings"))
+        .json(&serde_json::json!({ "port": 0, "heatmap": false }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "port");
+    assert_eq!(server.get_json("/api/settings").await["heatmap"], true);
+}
+
+#[tokio::test]
+async fn map_on_this_computer_is_not_rate_limited_with_memory() {
+    map_on_this_computer_is_not_rate_limited("memory").await;
+}
+
+#[tokio::test]
+async fn map_on_this_computer_is_not_rate_limited_with_sqlite() {
+    map_on_this_computer_is_not_rate_limited("sqlite").await;
+}
+
+async fn map_on_this_computer_is_not_rate_limited(storage: &str) {
+    let server = TestServer::start("rate_limit", storage).await;
+
+    // Twice the default burst of 50, as a map full of markers sends; the limit
+    // itself is covered by the unit tests with LAN addresses
+    let requests = (0..100).map(|_| {
+        server
+            .client
+            .get(server.url("/api/marker/missing.jpg"))
+            .send()
+    });
+    let responses = futures_util::future::join_all(requests).await;
+    assert!(responses
+        .iter()
+        .all(|response| response.as_ref().unwrap().status() == 404));
+}
+
+#[tokio::test]
+async fn selected_photos_are_exported_as_zip_with_memory() {
+    selected_photos_are_exported_as_zip("memory").await;
+}
+
+#[tokio::test]
+async fn selected_photos_are_exported_as_zip_with_sqlite() {
+    selected_photos_are_exported_as_zip("sqlite").await;
+}
+
+async fn selected_photos_are_exported_as_zip(storage: &str) {
+    let server = TestServer::start("export_zip", storage).await;
+
+    let photos = server.get_json("/api/photos").await;
+    let id_of = |relative_path: &str| {
+        photos
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|photo| photo["relative_path"] == relative_path)
+            .map(|photo| photo["id"].clone())
+            .unwrap()
+    };
+    // Repeated ids are exported once
+    let photo_ids = [
+        id_of("paris.jpg"),
+        id_of("trips/lisbon.jpg"),
+        id_of("paris.jpg"),
+    ];
+
+    let response = server
+        .client
+        .post(server.url("/api/photos/export/zip"))
+        .json(&serde_json::json!({ "photo_ids": photo_ids }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/zip");
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"photomap_export.zip\""
+    );
+
+    let data = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data.to_vec())).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort_unstable();
+    assert_eq!(names, ["paris.jpg", "trips/lisbon.jpg"]);
+
+    let mut exported = Vec::new();
+    std::io::Read::read_to_end(
+        &mut archive.by_name("trips/lisbon.jpg").unwrap(),
+        &mut exported,
+    )
+    .unwrap();
+    let original = std::fs::read(server.root.join("photos").join("trips/lisbon.jpg")).unwrap();
+    assert_eq!(exported, original);
+
+    // Empty selections and selections above `export_max_photos` are rejected
+    for photo_ids in [Vec::new(), vec![id_of("paris.jpg"); 501]] {
+        let response = server
+            .client
+            .post(server.url("/api/photos/export/zip"))
+            .json(&serde_json::json!({ "photo_ids": photo_ids }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+}
+
+#[tokio::test]
+async fn cluster_gallery_serves_jpeg_and_heic_with_memory() {
+    cluster_gallery_serves_jpeg_and_heic("memory").await;
+}
+
+#[tokio::test]
+async fn cluster_gallery_serves_jpeg_and_heic_with_sqlite() {
+    cluster_gallery_serves_jpeg_and_heic("sqlite").await;
+}
+
+async fn cluster_gallery_serves_jpeg_and_heic(storage: &str) {
+    let (lat, lng, timestamp) = (PHOTOS[0].1, PHOTOS[0].2, PHOTOS[0].3);
+    let mut has_heic = false;
+    let server = TestServer::start_with("cluster_gallery", storage, |dir| {
+        // A HEIC taken a day after paris.jpg, a few metres away
+        let path = dir.join("paris_day2.heic");
+        has_heic = write_heic(&path);
+        if has_heic {
+            write_sidecar(&path, lat + 0.0001, lng, timestamp + 86_400);
+        } else {
+            eprintln!("libheif has no HEVC encoder, only the JPEG is checked");
+        }
+    })
+    .await;
+
+    let cluster = format!("/api/cluster-photos?lat={lat}&lng={lng}&radius_m=1000");
+    let gallery = server.get_json(&cluster).await;
+    let expected: &[&str] = if has_heic {
+        &["paris_day2.heic", "paris.jpg"]
+    } else {
+        &["paris.jpg"]
+    };
+    assert_eq!(gallery["total"], expected.len());
+    let photos = gallery["photos"].as_array().unwrap();
+    let paths: Vec<&str> = photos
+        .iter()
+        .map(|photo| photo["relative_path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, expected, "newest first");
+
+    // Gallery images are GALLERY_SIZE squares, rendered rather than placeholders
+    for photo in photos {
+        let url = photo["gallery_url"].as_str().unwrap();
+        let response = server.client.get(server.url(url)).send().await.unwrap();
+        assert_eq!(response.status(), 200, "{url}");
+        assert!(
+            response.headers().contains_key("etag"),
+            "{url} is a placeholder"
+        );
+        let image = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!((image.width(), image.height()), (240, 240), "{url}");
+    }
+
+    // Pages hold `per_page` photos
+    let second = server
+        .get_json(&format!("{cluster}&page=2&per_page=1"))
+        .await;
+    assert_eq!(second["total"], expected.len());
+    assert_eq!(
+        second["photos"].as_array().unwrap().len(),
+        expected.len() - 1
+    );
+
+    let response = server
+        .client
+        .get(server.url(&format!(
+            "/api/cluster-photos?lat={lat}&lng={lng}&radius_m=0"
+        )))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn special_characters_round_trip_with_memory() {
+    special_characters_round_trip("memory").await;
+}
+
+#[tokio::test]
+async fn special_characters_round_trip_with_sqlite() {
+    special_characters_round_trip("sqlite").await;
+}
+
+async fn special_characters_round_trip(storage: &str) {
+    let server = TestServer::start_with("special_chars", storage, |dir| {
+        let path = dir.join("trips").join("café #2.jpg");
+        image::RgbImage::from_pixel(64, 48, image::Rgb([90, 160, 30]))
+            .save(&path)
+            .unwrap();
+        write_sidecar(&path, 45.4642, 9.19, 1_730_000_000);
+    })
+    .await;
+
+    let photos = server.get_json("/api/photos").await;
+    let cafe = photos
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["relative_path"] == "trips/café #2.jpg")
+        .expect("café #2.jpg missing from /api/photos");
+    let marker_url = cafe["marker_icon"].as_str().unwrap();
+    assert_eq!(marker_url, "/api/marker/trips/caf%C3%A9%20%232.jpg");
+
+    // The URL from the API works as is, and so does one with an encoded slash
+    for url in [marker_url, "/api/marker/trips%2Fcaf%C3%A9%20%232.jpg"] {
+        let response = server.client.get(server.url(url)).send().await.unwrap();
+        assert_eq!(response.status(), 200, "{url}");
+        assert!(
+            response.headers().contains_key("etag"),
+            "{url} is a placeholder"
+        );
+    }
+    let popup = cafe["url"].as_str().unwrap();
+    let response = server.client.get(server.url(popup)).send().await.unwrap();
+    assert_eq!(response.status(), 200, "{popup}");
+}
+
+#[tokio::test]
+async fn geotagged_png_is_shown_on_the_map_with_memory() {
+    geotagged_png_is_shown_on_the_map("memory").await;
+}
+
+#[tokio::test]
+async fn geotagged_png_is_shown_on_the_map_with_sqlite() {
+    geotagged_png_is_shown_on_the_map("sqlite").await;
+}
+
+async fn geotagged_png_is_shown_on_the_map(storage: &str) {
+    let server = TestServer::start_with("png", storage, |dir| {
+        write_geotagged_png(&dir.join("oslo.png"), 60, 11);
+    })
+    .await;
+
+    let photos = server.get_json("/api/photos").await;
+    let photos = photos.as_array().unwrap();
+    assert_eq!(photos.len(), PHOTOS.len() + 1);
+    let png = photos
+        .iter()
+        .find(|p| p["filename"] == "oslo.png")
+        .expect("oslo.png missing from /api/photos");
+    assert_eq!(
+        (png["lat"].as_f64(), png["lng"].as_f64()),
+        (Some(60.0), Some(11.0))
+    );
+    assert_eq!(png["source"], "Exif");
+
+    // Its marker is rendered from the PNG like for any JPEG
+    let marker_url = png["marker_icon"].as_str().unwrap();
+    let response = server
+        .client
+        .get(server.url(marker_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(
+        response.headers().contains_key("etag"),
+        "placeholder marker"
+    );
+    let marker = response.bytes().await.unwrap();
+    assert_eq!(&marker[..2], &[0xFF, 0xD8]);
+}
+
+#[tokio::test]
+async fn export_writes_geojson_and_exits_with_memory() {
+    export_writes_geojson_and_exits("memory").await;
+}
+
+#[tokio::test]
+async fn export_writes_geojson_and_exits_with_sqlite() {
+    export_writes_geojson_and_exits("sqlite").await;
+}
+
+async fn export_writes_geojson_and_exits(storage: &str) {
+    let root = std::env::temp_dir().join(format!(
+        "photomap_api_test_export_{storage}_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&root);
+    let photos_dir = root.join("photos");
+    write_library(&photos_dir);
+    std::fs::create_dir_all(root.join("data")).unwrap();
+    let config = root.join("custom.ini");
+    std::fs::write(&config, format!("storage = {storage}\n")).unwrap();
+    let output = root.join("library.geojson");
+
+    // Processes the folder, writes the file and exits without serving anything
+    let status = Command::new(env!("CARGO_BIN_EXE_photomap_processor"))
+        .arg("--folder")
+        .arg(&photos_dir)
+        .arg("--cache-dir")
+        .arg(root.join("data"))
+        .arg("--config")
+        .arg(&config)
+        .arg("--export")
+        .arg(format!("geojson:{}", output.display()))
+        .arg("--log-level")
+        .arg("warn")
+        .stdout(Stdio::null())
+        .status()
+        .expect("failed to run photomap_processor");
+    assert!(status.success());
+
+    let geojson: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+    assert_eq!(geojson["type"], "FeatureCollection");
+    let mut paths: Vec<String> = geojson["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|feature| {
+            feature["properties"]["relative_path"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    paths.sort();
+    let mut expected: Vec<String> = PHOTOS.iter().map(|(name, ..)| name.to_string()).collect();
+    expected.sort();
+    assert_eq!(paths, expected);
+
+    // Settings come from --config, and the folder was used for this run only
+    assert!(!root.join("data").join("photomap.ini").exists());
+    let settings = std::fs::read_to_string(&config).unwrap();
+    assert!(!settings.contains("photomap_api_test_export"), "{settings}");
+
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -1,91 +1,198 @@
 use anyhow::{Context, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, Write, Read, Seek};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
-fn main() -> Result<()> {
-    println!("🚀 Starting Exif Parser Test...");
+/// Compares the GPS parser of PhotoMap with exiftool on a folder of photos
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Folder to scan; a folder dialog opens when omitted
+    folder: Option<PathBuf>,
 
-    // 0. CHECK: exiftool must be installed!
-    println!("🔍 Checking for exiftool...");
-    
-    // Try local exiftool.exe first (portable mode)
-    let local_exiftool = std::env::current_exe()
-        .ok()
-        .and_then(|exe| exe.parent().map(|p| p.join("exiftool.exe")));
-    
-    let exiftool_found = if let Some(local_path) = &local_exiftool {
-        if local_path.exists() {
-            match std::process::Command::new(local_path).arg("-ver").output() {
-                Ok(output) if output.status.success() => {
-                    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                    println!("✅ Found exiftool version: {} (local)", version);
-                    true
-                }
-                _ => false
+    /// Write results.json instead of failures.txt and accuracy_issues.txt
+    #[arg(long)]
+    json: bool,
+
+    /// Largest accepted difference to exiftool, in degrees
+    #[arg(long, default_value_t = 0.0001)] // ~11 метров
+    tolerance: f64,
+
+    /// exiftool executable to use instead of a local exiftool.exe or the one on PATH
+    #[arg(long)]
+    exiftool_path: Option<PathBuf>,
+
+    /// Exit with code 1 when a larger share of the files misses GPS that exiftool finds
+    #[arg(long, default_value_t = 0.001)]
+    max_failure_rate: f64,
+}
+
+/// exiftool found GPS, our parser did not
+#[derive(Serialize)]
+struct Failure {
+    path: String,
+    make: String,
+    model: String,
+    exiftool_lat: f64,
+    exiftool_lng: f64,
+}
+
+/// Both found GPS, but further apart than the tolerance
+#[derive(Serialize)]
+struct AccuracyIssue {
+    path: String,
+    make: String,
+    model: String,
+    our_lat: f64,
+    our_lng: f64,
+    exiftool_lat: f64,
+    exiftool_lng: f64,
+    delta_lat: f64,
+    delta_lng: f64,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    failure_rate: f64,
+    mean_delta_lat: f64,
+    mean_delta_lon: f64,
+    /// 95th percentile of the larger delta of each accuracy issue
+    p95_delta: f64,
+}
+
+/// Layout of results.json
+#[derive(Serialize)]
+struct Results {
+    total: usize,
+    failures: Vec<Failure>,
+    accuracy_issues: Vec<AccuracyIssue>,
+    summary: Summary,
+}
+
+/// Failures and accuracy issues of each camera, keyed by (make, model)
+type CameraStats = HashMap<(String, String), (usize, usize)>;
+
+/// One camera in baseline.json
+#[derive(Serialize, Deserialize)]
+struct CameraBaseline {
+    make: String,
+    model: String,
+    failures: usize,
+    accuracy_issues: usize,
+}
+
+/// What exiftool reads from a file
+struct ExiftoolTags {
+    gps: Option<(f64, f64)>,
+    make: String,
+    model: String,
+}
+
+impl Summary {
+    fn new(total: usize, failures: &[Failure], issues: &[AccuracyIssue]) -> Self {
+        let mean = |delta: fn(&AccuracyIssue) -> f64| {
+            if issues.is_empty() {
+                0.0
+            } else {
+                issues.iter().map(delta).sum::<f64>() / issues.len() as f64
             }
-        } else {
-            false
+        };
+        let mut deltas: Vec<f64> = issues
+            .iter()
+            .map(|issue| issue.delta_lat.max(issue.delta_lng))
+            .collect();
+        deltas.sort_by(f64::total_cmp);
+        // Nearest rank
+        let p95_delta = match deltas.len() {
+            0 => 0.0,
+            n => deltas[((n as f64 * 0.95).ceil() as usize).clamp(1, n) - 1],
+        };
+
+        Summary {
+            failure_rate: if total == 0 {
+                0.0
+            } else {
+                failures.len() as f64 / total as f64
+            },
+            mean_delta_lat: mean(|issue| issue.delta_lat),
+            mean_delta_lon: mean(|issue| issue.delta_lng),
+            p95_delta,
         }
-    } else {
-        false
+    }
+}
+
+/// exiftool to run: the one given on the command line, a local exiftool.exe
+/// next to this tool (portable mode), or the one on PATH
+fn find_exiftool(exiftool_path: Option<PathBuf>) -> Option<(PathBuf, String)> {
+    let version = |program: &Path| match std::process::Command::new(program).arg("-ver").output() {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        _ => None,
     };
-    
-    // Fallback to PATH
-    if !exiftool_found {
-        match std::process::Command::new("exiftool").arg("-ver").output() {
-            Ok(output) if output.status.success() => {
-                let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                println!("✅ Found exiftool version: {} (system PATH)\n", version);
-            }
-            _ => {
-                eprintln!("\n❌ ERROR: exiftool is NOT installed!");
-                eprintln!("\nThis tool requires exiftool to work.");
-                eprintln!("\n📥 Installation options:");
-                eprintln!("\n  Option 1 (Portable - Recommended for Windows):");
-                eprintln!("    1. Download from https://exiftool.org/ → 'Windows Executable'");
-                eprintln!("    2. Extract exiftool.exe and exiftool_files/ folder");
-                eprintln!("    3. Put BOTH next to exif_parser_test.exe");
-                eprintln!("\n  Option 2 (System-wide):");
-                eprintln!("    Windows: Put in C:\\Windows\\ (requires admin)");
-                eprintln!("    macOS:   brew install exiftool");
-                eprintln!("    Linux:   sudo apt install libimage-exiftool-perl");
-                eprintln!("\n❓ Test installation: exiftool -ver\n");
-                std::process::exit(1);
-            }
+
+    if let Some(path) = exiftool_path {
+        return version(&path).map(|v| (path, v));
+    }
+    let local_exiftool = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|p| p.join("exiftool.exe")))
+        .filter(|path| path.exists());
+    if let Some(path) = local_exiftool {
+        if let Some(v) = version(&path) {
+            return Some((path, v));
         }
     }
+    let path = PathBuf::from("exiftool");
+    version(&path).map(|v| (path, v))
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    println!("🚀 Starting Exif Parser Test...");
+
+    // 0. CHECK: exiftool must be installed!
+    println!("🔍 Checking for exiftool...");
+    let Some((exiftool, version)) = find_exiftool(args.exiftool_path.clone()) else {
+        eprintln!("\n❌ ERROR: exiftool is NOT installed!");
+        eprintln!("\nThis tool requires exiftool to work.");
+        eprintln!("\n📥 Installation options:");
+        eprintln!("\n  Option 1 (Portable - Recommended for Windows):");
+        eprintln!("    1. Download from https://exiftool.org/ → 'Windows Executable'");
+        eprintln!("    2. Extract exiftool.exe and exiftool_files/ folder");
+        eprintln!("    3. Put BOTH next to exif_parser_test.exe");
+        eprintln!("\n  Option 2 (System-wide):");
+        eprintln!("    Windows: Put in C:\\Windows\\ (requires admin)");
+        eprintln!("    macOS:   brew install exiftool");
+        eprintln!("    Linux:   sudo apt install libimage-exiftool-perl");
+        eprintln!("\n  Option 3: pass its location with --exiftool-path");
+        eprintln!("\n❓ Test installation: exiftool -ver\n");
+        std::process::exit(1);
+    };
+    println!("✅ Found exiftool version: {} ({})\n", version, exiftool.display());
 
     // 1. Select folder
-    let folder = rfd::FileDialog::new()
-        .set_title("Select folder with photos")
-        .pick_folder()
-        .context("No folder selected")?;
+    let folder = match args.folder {
+        Some(folder) => folder,
+        None => rfd::FileDialog::new()
+            .set_title("Select folder with photos")
+            .pick_folder()
+            .context("No folder selected")?,
+    };
 
     println!("📂 Scanning folder: {}", folder.display());
     println!("🔍 Processing files...\n");
 
-    // 2. Open log files
-    let mut log_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("failures.txt")?;
-        
-    let mut accuracy_log = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open("accuracy_issues.txt")?;
-
     let mut count_processed = 0;
-    let mut count_failures = 0;
-    let mut count_accuracy_issues = 0;
-    
-    const COORD_TOLERANCE: f64 = 0.0001; // ~11 метров
+    let mut failures = Vec::new();
+    let mut accuracy_issues = Vec::new();
+    let mut cameras = CameraStats::new();
 
-    // 3. Process files on-the-fly
+    // 2. Process files on-the-fly
     for entry in WalkDir::new(&folder).into_iter().filter_map(|e| e.ok()) {
         if !entry.file_type().is_file() {
             continue;
@@ -108,81 +215,240 @@ fn main() -> Result<()> {
 
                 // Try our parser
                 let our_result = extract_gps_our(path);
-                
-                if our_result.is_none() {
-                    // Our parser failed - check if exiftool finds GPS
-                    if let Some(exiftool_gps) = extract_gps_exiftool(path) {
+                let Some(tags) = read_exiftool(&exiftool, path) else {
+                    continue;
+                };
+                cameras
+                    .entry((tags.make.clone(), tags.model.clone()))
+                    .or_insert((0, 0));
+
+                match (our_result, tags.gps) {
+                    (None, Some(exiftool_gps)) => {
                         // FAILURE: exiftool found GPS but we didn't
                         println!("\n⚠️  MISSING GPS: {}", path.display());
                         println!("  Our parser: ✗ FAILED");
                         println!("  exiftool: ✓ ({:.6}, {:.6})", exiftool_gps.0, exiftool_gps.1);
-                        writeln!(log_file, "{}", path.display())?;
-                        count_failures += 1;
+                        failures.push(Failure {
+                            path: path.display().to_string(),
+                            make: tags.make,
+                            model: tags.model,
+                            exiftool_lat: exiftool_gps.0,
+                            exiftool_lng: exiftool_gps.1,
+                        });
                     }
-                } else if let Some(our_gps) = our_result {
-                    // Our parser succeeded - verify accuracy against exiftool
-                    if let Some(exiftool_gps) = extract_gps_exiftool(path) {
+                    (Some(our_gps), Some(exiftool_gps)) => {
+                        // Our parser succeeded - verify accuracy against exiftool
                         let lat_diff = (our_gps.0 - exiftool_gps.0).abs();
                         let lon_diff = (our_gps.1 - exiftool_gps.1).abs();
-                        
-                        if lat_diff > COORD_TOLERANCE || lon_diff > COORD_TOLERANCE {
+
+                        if lat_diff > args.tolerance || lon_diff > args.tolerance {
                             // ACCURACY ISSUE: coordinates don't match
                             println!("\n⚠️  ACCURACY ISSUE: {}", path.display());
                             println!("  Our parser: ({:.6}, {:.6})", our_gps.0, our_gps.1);
                             println!("  exiftool:   ({:.6}, {:.6})", exiftool_gps.0, exiftool_gps.1);
                             println!("  Difference: Δlat={:.6}°, Δlon={:.6}°", lat_diff, lon_diff);
-                            writeln!(accuracy_log, "{} | Our: ({:.6}, {:.6}) | exiftool: ({:.6}, {:.6}) | Diff: ({:.6}, {:.6})",
-                                     path.display(), our_gps.0, our_gps.1, exiftool_gps.0, exiftool_gps.1, lat_diff, lon_diff)?;
-                            count_accuracy_issues += 1;
+                            accuracy_issues.push(AccuracyIssue {
+                                path: path.display().to_string(),
+                                make: tags.make,
+                                model: tags.model,
+                                our_lat: our_gps.0,
+                                our_lng: our_gps.1,
+                                exiftool_lat: exiftool_gps.0,
+                                exiftool_lng: exiftool_gps.1,
+                                delta_lat: lat_diff,
+                                delta_lng: lon_diff,
+                            });
                         }
                     }
+                    _ => {}
                 }
             }
         }
     }
 
+    // Group the problems by camera; cameras without any keep (0, 0) for the baseline
+    for failure in &failures {
+        cameras
+            .entry((failure.make.clone(), failure.model.clone()))
+            .or_default()
+            .0 += 1;
+    }
+    for issue in &accuracy_issues {
+        cameras
+            .entry((issue.make.clone(), issue.model.clone()))
+            .or_default()
+            .1 += 1;
+    }
+    write_camera_stats(&cameras)?;
+    compare_with_baseline(&cameras)?;
+
+    let summary = Summary::new(count_processed, &failures, &accuracy_issues);
     println!("\n\n✅ Scan complete.");
     println!("Total processed: {}", count_processed);
-    println!("Missing GPS (we failed, exiftool succeeded): {}", count_failures);
-    println!("Accuracy issues (coordinates mismatch): {}", count_accuracy_issues);
-    println!("\nSee failures.txt for missing GPS files.");
-    println!("See accuracy_issues.txt for coordinate mismatches.");
+    println!("Missing GPS (we failed, exiftool succeeded): {}", failures.len());
+    println!("Accuracy issues (coordinates mismatch): {}", accuracy_issues.len());
+    let failure_rate = summary.failure_rate;
+    println!("Failure rate: {:.4}%", failure_rate * 100.0);
+
+    // 3. Write the results
+    if args.json {
+        let results = Results {
+            total: count_processed,
+            failures,
+            accuracy_issues,
+            summary,
+        };
+        let file = File::create("results.json").context("Failed to create results.json")?;
+        serde_json::to_writer_pretty(file, &results).context("Failed to write results.json")?;
+        println!("\nSee results.json for details.");
+    } else {
+        write_text_logs(&failures, &accuracy_issues)?;
+        println!("\nSee failures.txt for missing GPS files.");
+        println!("See accuracy_issues.txt for coordinate mismatches.");
+        println!("See camera_stats.txt for the problems of each camera model.");
+        copy_failed_files(&failures)?;
+    }
+
+    if failure_rate > args.max_failure_rate {
+        eprintln!(
+            "\n❌ Failure rate {:.4}% is above the allowed {:.4}%",
+            failure_rate * 100.0,
+            args.max_failure_rate * 100.0
+        );
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn write_text_logs(failures: &[Failure], accuracy_issues: &[AccuracyIssue]) -> Result<()> {
+    let mut log_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open("failures.txt")?;
+    for failure in failures {
+        writeln!(log_file, "{}", failure.path)?;
+    }
+
+    let mut accuracy_log = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open("accuracy_issues.txt")?;
+    for issue in accuracy_issues {
+        writeln!(accuracy_log, "{} | Our: ({:.6}, {:.6}) | exiftool: ({:.6}, {:.6}) | Diff: ({:.6}, {:.6})",
+                 issue.path, issue.our_lat, issue.our_lng, issue.exiftool_lat, issue.exiftool_lng, issue.delta_lat, issue.delta_lng)?;
+    }
+    Ok(())
+}
+
+/// Camera name as people know it, e.g. "SONY ILCE-7M4" or "Apple iPhone 15"
+fn camera_name(make: &str, model: &str) -> String {
+    if model.to_lowercase().starts_with(&make.to_lowercase()) {
+        model.to_string()
+    } else {
+        format!("{} {}", make, model)
+    }
+}
+
+/// Cameras with most failures first, then most accuracy issues
+fn sorted_cameras(cameras: &CameraStats) -> Vec<(&(String, String), &(usize, usize))> {
+    let mut sorted: Vec<_> = cameras.iter().collect();
+    sorted.sort_by(|(a_camera, a), (b_camera, b)| {
+        b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a_camera.cmp(b_camera))
+    });
+    sorted
+}
+
+fn write_camera_stats(cameras: &CameraStats) -> Result<()> {
+    let mut report = File::create("camera_stats.txt").context("Failed to create camera_stats.txt")?;
+    writeln!(report, "{:<40} {:>10} {:>16}", "Camera", "Failures", "Accuracy issues")?;
+    for ((make, model), (failures, issues)) in sorted_cameras(cameras) {
+        writeln!(report, "{:<40} {:>10} {:>16}", camera_name(make, model), failures, issues)?;
+    }
+    Ok(())
+}
+
+/// Writes baseline.json on the first run; later runs report every camera
+/// that got more failures or accuracy issues than in the baseline
+fn compare_with_baseline(cameras: &CameraStats) -> Result<()> {
+    let baseline_path = Path::new("baseline.json");
+    if !baseline_path.exists() {
+        let baseline: Vec<CameraBaseline> = sorted_cameras(cameras)
+            .into_iter()
+            .map(|((make, model), (failures, issues))| CameraBaseline {
+                make: make.clone(),
+                model: model.clone(),
+                failures: *failures,
+                accuracy_issues: *issues,
+            })
+            .collect();
+        let file = File::create(baseline_path).context("Failed to create baseline.json")?;
+        serde_json::to_writer_pretty(file, &baseline).context("Failed to write baseline.json")?;
+        println!("\n📌 Baseline of {} camera models written to baseline.json", baseline.len());
+        return Ok(());
+    }
+
+    let file = File::open(baseline_path).context("Failed to open baseline.json")?;
+    let baseline: Vec<CameraBaseline> =
+        serde_json::from_reader(BufReader::new(file)).context("Failed to read baseline.json")?;
+    let baseline: HashMap<(String, String), (usize, usize)> = baseline
+        .into_iter()
+        .map(|camera| ((camera.make, camera.model), (camera.failures, camera.accuracy_issues)))
+        .collect();
+
+    // Models missing from the baseline are new and start from zero
+    let mut regressions = 0;
+    for (camera, (failures, issues)) in sorted_cameras(cameras) {
+        let (base_failures, base_issues) = baseline.get(camera).copied().unwrap_or((0, 0));
+        let name = camera_name(&camera.0, &camera.1);
+        if *failures > base_failures {
+            println!("⚠️  REGRESSION: {} failures {} → {}", name, base_failures, failures);
+            regressions += 1;
+        }
+        if *issues > base_issues {
+            println!("⚠️  REGRESSION: {} accuracy issues {} → {}", name, base_issues, issues);
+            regressions += 1;
+        }
+    }
+    if regressions == 0 {
+        println!("\n✅ No regressions against baseline.json");
+    }
+    Ok(())
+}
+
+/// Copies failed files to 'JPG for checks' directory
+fn copy_failed_files(failures: &[Failure]) -> Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    println!("\n📋 Copying failed files to 'JPG for checks' directory...");
+    let target_dir = PathBuf::from("JPG for checks");
     
-    // Copy failed files to 'JPG for checks' directory
-    if count_failures > 0 {
-        println!("\n📋 Copying failed files to 'JPG for checks' directory...");
-        let target_dir = PathBuf::from("JPG for checks");
-        
-        // Create target directory if it doesn't exist
-        std::fs::create_dir_all(&target_dir)
-            .with_context(|| "Failed to create 'JPG for checks' directory")?;
-        
-        // Read failures.txt and copy each file
-        let failures_content = std::fs::read_to_string("failures.txt")
-            .with_context(|| "Failed to read failures.txt")?;
-        
-        let mut copied = 0;
-        for line in failures_content.lines() {
-            let source_path = PathBuf::from(line.trim());
-            if source_path.exists() {
-                if let Some(filename) = source_path.file_name() {
-                    let target_path = target_dir.join(filename);
-                    match std::fs::copy(&source_path, &target_path) {
-                        Ok(_) => {
-                            copied += 1;
-                            println!("  ✓ Copied: {}", filename.to_string_lossy());
-                        }
-                        Err(e) => {
-                            println!("  ✗ Failed to copy {}: {}", filename.to_string_lossy(), e);
-                        }
+    // Create target directory if it doesn't exist
+    std::fs::create_dir_all(&target_dir)
+        .with_context(|| "Failed to create 'JPG for checks' directory")?;
+    
+    let mut copied = 0;
+    for failure in failures {
+        let source_path = PathBuf::from(&failure.path);
+        if source_path.exists() {
+            if let Some(filename) = source_path.file_name() {
+                let target_path = target_dir.join(filename);
+                match std::fs::copy(&source_path, &target_path) {
+                    Ok(_) => {
+                        copied += 1;
+                        println!("  ✓ Copied: {}", filename.to_string_lossy());
+                    }
+                    Err(e) => {
+                        println!("  ✗ Failed to copy {}: {}", filename.to_string_lossy(), e);
                     }
                 }
             }
         }
-        
-        println!("📦 Copied {} of {} failed files.", copied, count_failures);
     }
-
+    
+    println!("📦 Copied {} of {} failed files.", copied, failures.len());
     Ok(())
 }
 
@@ -397,46 +663,49 @@ fn try_extract_from_ifd(exif_data: &exif::Exif, coord_tag: exif::Tag, ref_tag: e
 
 
 // --- "Exiftool" Code (Gold Standard - 99.99% accuracy) ---
-fn extract_gps_exiftool(path: &Path) -> Option<(f64, f64)> {
+/// GPS and camera of a file from a single exiftool run; None if exiftool failed
+fn read_exiftool(exiftool: &Path, path: &Path) -> Option<ExiftoolTags> {
     use std::process::Command;
-    
-    // Run exiftool to extract GPS coordinates
-    let output = Command::new("exiftool")
+
+    let output = Command::new(exiftool)
+        .arg("-s") // Tag names instead of descriptions
         .arg("-GPSLatitude")
         .arg("-GPSLongitude")
-        .arg("-n")  // Numerical output for GPS coordinates
+        .arg("-Make")
+        .arg("-Model")
+        .arg("-n") // Numerical output for GPS coordinates
         .arg(path)
         .output()
         .ok()?;
-    
+
     if !output.status.success() {
         return None;
     }
-    
+
     let stdout = String::from_utf8_lossy(&output.stdout);
     let mut lat: Option<f64> = None;
     let mut lon: Option<f64> = None;
-    
+    let mut make = String::from("Unknown");
+    let mut model = String::from("Unknown");
+
+    // Format: "GPSLatitude                     : 48.8725955"
     for line in stdout.lines() {
-        let line_trimmed = line.trim();
-        
-        // Format: "GPS Latitude                    : 48.8725955"
-        if line_trimmed.starts_with("GPS Latitude") && line_trimmed.contains(':') {
-            if let Some(value_part) = line_trimmed.split(':').nth(1) {
-                let parsed = value_part.trim().parse();
-                lat = parsed.ok();
-            }
-        } else if line_trimmed.starts_with("GPS Longitude") && line_trimmed.contains(':') {
-            if let Some(value_part) = line_trimmed.split(':').nth(1) {
-                let parsed = value_part.trim().parse();
-                lon = parsed.ok();
-            }
+        let Some((tag, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match tag.trim() {
+            "GPSLatitude" => lat = value.parse().ok(),
+            "GPSLongitude" => lon = value.parse().ok(),
+            "Make" if !value.is_empty() => make = value.to_string(),
+            "Model" if !value.is_empty() => model = value.to_string(),
+            _ => {}
         }
     }
-    
-    if let (Some(lat_val), Some(lon_val)) = (lat, lon) {
-        return Some((lat_val, lon_val));
-    }
-    
-    None
+
+    Some(ExiftoolTags {
+        gps: lat.zip(lon),
+        make,
+        model,
+    })
 }
@@ -1,6 +1,7 @@
 use anyhow::{bail, Context, Result};
 use flate2::{write::GzEncoder, Compression, GzBuilder};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Write};
@@ -12,27 +13,34 @@ struct GeoLocation {
     lat: f64,
     lng: f64,
     country: String,
+    admin1: String,
 }
 
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 3 {
+    if args.len() != 3 && args.len() != 4 {
         let program = args
             .first()
             .map(String::as_str)
             .unwrap_or("geodata_builder");
-        eprintln!("Usage: {program} <geonames-cities5000.txt> <output-geodata.bin.gz>");
+        eprintln!(
+            "Usage: {program} <geonames-cities5000.txt> <output-geodata.bin.gz> [admin1CodesASCII.txt]"
+        );
         eprintln!();
         eprintln!("Example:");
-        eprintln!("  {program} cities5000.txt ../../src/geodata.bin.gz");
+        eprintln!("  {program} cities5000.txt ../../src/geodata.bin.gz admin1CodesASCII.txt");
         bail!("expected input and output paths");
     }
 
     let input_path = Path::new(&args[1]);
     let output_path = Path::new(&args[2]);
+    let admin1_names = match args.get(3) {
+        Some(path) => read_admin1_names(Path::new(path))?,
+        None => HashMap::new(),
+    };
 
-    let locations = read_geonames(input_path)?;
+    let locations = read_geonames(input_path, &admin1_names)?;
     write_geodata(output_path, &locations)?;
 
     println!(
@@ -44,7 +52,21 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn read_geonames(path: &Path) -> Result<Vec<GeoLocation>> {
+/// Maps "CC.code" keys of admin1CodesASCII.txt to region names
+fn read_admin1_names(path: &Path) -> Result<HashMap<String, String>> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut names = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let mut fields = line.split('\t');
+        if let (Some(key), Some(name)) = (fields.next(), fields.next()) {
+            names.insert(key.to_string(), name.trim().to_string());
+        }
+    }
+    Ok(names)
+}
+
+fn read_geonames(path: &Path, admin1_names: &HashMap<String, String>) -> Result<Vec<GeoLocation>> {
     let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
     let reader = BufReader::new(file);
     let mut locations = Vec::new();
@@ -53,7 +75,7 @@ fn read_geonames(path: &Path) -> Result<Vec<GeoLocation>> {
     for (line_index, line) in reader.lines().enumerate() {
         let line = line.with_context(|| format!("reading line {}", line_index + 1))?;
 
-        match parse_geonames_line(&line) {
+        match parse_geonames_line(&line, admin1_names) {
             Some(location) => locations.push(location),
             None => skipped += 1,
         }
@@ -70,7 +92,7 @@ fn read_geonames(path: &Path) -> Result<Vec<GeoLocation>> {
     Ok(locations)
 }
 
-fn parse_geonames_line(line: &str) -> Option<GeoLocation> {
+fn parse_geonames_line(line: &str, admin1_names: &HashMap<String, String>) -> Option<GeoLocation> {
     let fields: Vec<&str> = line.split('\t').collect();
     if fields.len() < 15 {
         return None;
@@ -80,6 +102,7 @@ fn parse_geonames_line(line: &str) -> Option<GeoLocation> {
     let lat: f64 = fields[4].parse().ok()?;
     let lng: f64 = fields[5].parse().ok()?;
     let country = fields[8].trim();
+    let admin1_code = fields[10].trim();
     let population: u64 = fields[14].parse().ok()?;
 
     if name.is_empty()
@@ -96,6 +119,11 @@ fn parse_geonames_line(line: &str) -> Option<GeoLocation> {
         lat,
         lng,
         country: country.to_string(),
+        // Regions without a name in admin1CodesASCII.txt stay empty
+        admin1: admin1_names
+            .get(&format!("{country}.{admin1_code}"))
+            .cloned()
+            .unwrap_or_default(),
     })
 }
 
@@ -126,10 +154,13 @@ mod tests {
     fn parses_geonames_city_row() {
         let line = "2950159\tBerlin\tBerlin\tBerlin\t52.52437\t13.41053\tP\tPPLC\tDE\t\t16\t\t\t\t3426354\t\t74\tEurope/Berlin\t2024-01-01";
 
-        let location = parse_geonames_line(line).expect("valid GeoNames row");
+        let admin1_names = HashMap::from([("DE.16".to_string(), "Berlin".to_string())]);
+
+        let location = parse_geonames_line(line, &admin1_names).expect("valid GeoNames row");
 
         assert_eq!(location.name, "Berlin");
         assert_eq!(location.country, "DE");
+        assert_eq!(location.admin1, "Berlin");
         assert_eq!(location.lat, 52.52437);
         assert_eq!(location.lng, 13.41053);
     }
@@ -138,7 +169,7 @@ mod tests {
     fn skips_rows_below_population_threshold() {
         let line = "123\tTiny place\tTiny place\tTiny place\t52.0\t13.0\tP\tPPL\tDE\t\t16\t\t\t\t4999\t\t74\tEurope/Berlin\t2024-01-01";
 
-        assert!(parse_geonames_line(line).is_none());
+        assert!(parse_geonames_line(line, &HashMap::new()).is_none());
     }
 
     #[test]
@@ -148,6 +179,7 @@ mod tests {
             lat: 52.52437,
             lng: 13.41053,
             country: "DE".to_string(),
+            admin1: "Berlin".to_string(),
         }];
 
         let path = env::temp_dir().join(format!(